@@ -1,5 +1,9 @@
+use crate::api::ensure_online;
+use crate::constants::{ARXIV_PAGE_SIZE, ARXIV_REQUEST_INTERVAL};
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
+use std::sync::Mutex;
+use std::time::Instant;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "lowercase")]
@@ -11,6 +15,11 @@ pub enum ArxivEntryChild {
     Link(ArxivLink),
     Published(String),
     Updated(String),
+    #[serde(rename = "arxiv:doi", alias = "doi")]
+    Doi(String),
+    #[serde(rename = "arxiv:comment", alias = "comment")]
+    Comment(String),
+    Category(ArxivCategory),
     #[serde(other)]
     Other,
 }
@@ -18,21 +27,104 @@ pub enum ArxivEntryChild {
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
 pub struct ArxivEntry {
     pub id: String,
+    /// Title with whitespace collapsed for display.
     pub title: String,
+    /// Title as it appears in the feed, line breaks included.
+    pub raw_title: String,
+    /// Abstract with whitespace collapsed for display.
     pub summary: String,
     pub authors: Vec<ArxivAuthor>,
     pub links: Vec<ArxivLink>,
     pub published: String,
+    pub updated: String,
+    pub doi: Option<String>,
+    /// Free-form author comment, e.g. page count or venue.
+    pub comment: Option<String>,
+    /// Subject categories such as `cs.LG`, primary category first.
+    pub categories: Vec<String>,
 }
 
-pub async fn search_arxiv(query: &str) -> Result<Vec<ArxivEntry>> {
+impl ArxivEntry {
+    /// The paper's abstract page: the `rel="alternate"` link, or the entry id.
+    pub fn abstract_url(&self) -> Option<String> {
+        self.links
+            .iter()
+            .find(|l| l.rel == "alternate" && !l.href.is_empty())
+            .map(|l| l.href.clone())
+            .or_else(|| self.id.starts_with("http").then(|| self.id.clone()))
+    }
+
+    /// The paper's PDF: the `/pdf/` link, or one built from the arXiv id.
+    pub fn pdf_url(&self) -> Option<String> {
+        self.links
+            .iter()
+            .find(|l| l.rel == "related" && l.href.contains("/pdf/"))
+            .map(|l| l.href.clone())
+            .or_else(|| {
+                let id = extract_id(&self.id);
+                (!id.is_empty()).then(|| format!("https://arxiv.org/pdf/{}", id))
+            })
+    }
+}
+
+/// One page of search results.
+#[derive(Debug, Clone, Default)]
+pub struct ArxivPage {
+    pub entries: Vec<ArxivEntry>,
+    /// Number of matches for the whole query, from `opensearch:totalResults`.
+    pub total: usize,
+}
+
+/// Waits until [`ARXIV_REQUEST_INTERVAL`] has passed since the previous query,
+/// as the arXiv API terms ask.
+async fn throttle() {
+    static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+    let wait = {
+        let mut last = LAST_REQUEST.lock().unwrap();
+        let now = Instant::now();
+        let slot = last.map_or(now, |t| (t + ARXIV_REQUEST_INTERVAL).max(now));
+        *last = Some(slot);
+        slot - now
+    };
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Fetches up to [`ARXIV_PAGE_SIZE`] results starting at offset `start`.
+pub async fn search_arxiv(query: &str, start: usize) -> Result<ArxivPage> {
     let url = format!(
-        "https://export.arxiv.org/api/query?search_query=all:{}&max_results=15",
-        urlencoding::encode(query)
+        "https://export.arxiv.org/api/query?search_query=all:{}&start={}&max_results={}",
+        urlencoding::encode(query),
+        start,
+        ARXIV_PAGE_SIZE
     );
+    ensure_online()?;
+    throttle().await;
     let client = reqwest::Client::new();
     let response = client.get(url).send().await?.text().await?;
-    
+    let entries = parse_entries(&response);
+    Ok(ArxivPage {
+        total: total_results(&response).unwrap_or(start + entries.len()),
+        entries,
+    })
+}
+
+/// Reads `<opensearch:totalResults>` from a feed.
+fn total_results(response: &str) -> Option<usize> {
+    let tag = response.find("<opensearch:totalResults")?;
+    let rest = &response[tag..];
+    let value = &rest[rest.find('>')? + 1..];
+    value[..value.find('<')?].trim().parse().ok()
+}
+
+/// Trims `text` and collapses each run of whitespace, line breaks included, to one space.
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Extracts the `<entry>` elements of an arXiv Atom feed; malformed entries are skipped.
+fn parse_entries(response: &str) -> Vec<ArxivEntry> {
     let mut entries = Vec::new();
     let mut start_pos = 0;
     while let Some(s) = response[start_pos..].find("<entry>") {
@@ -40,7 +132,7 @@ pub async fn search_arxiv(query: &str) -> Result<Vec<ArxivEntry>> {
         if let Some(e) = response[entry_start..].find("</entry>") {
             let entry_end = entry_start + e + "</entry>".len();
             let entry_xml = &response[entry_start..entry_end];
-            
+
             #[derive(Deserialize)]
             struct EntryWrapper {
                 #[serde(rename = "$value")]
@@ -53,12 +145,25 @@ pub async fn search_arxiv(query: &str) -> Result<Vec<ArxivEntry>> {
                     for child in wrapper.children {
                         match child {
                             ArxivEntryChild::Id(v) => entry.id = v,
-                            ArxivEntryChild::Title(v) => entry.title = v,
-                            ArxivEntryChild::Summary(v) => entry.summary = v,
+                            ArxivEntryChild::Title(v) => {
+                                entry.title = normalize_whitespace(&v);
+                                entry.raw_title = v;
+                            }
+                            ArxivEntryChild::Summary(v) => entry.summary = normalize_whitespace(&v),
                             ArxivEntryChild::Author(v) => entry.authors.push(v),
                             ArxivEntryChild::Link(v) => entry.links.push(v),
                             ArxivEntryChild::Published(v) => entry.published = v,
-                            _ => (),
+                            ArxivEntryChild::Updated(v) => entry.updated = v,
+                            ArxivEntryChild::Doi(v) => entry.doi = Some(v.trim().to_string()),
+                            ArxivEntryChild::Comment(v) => {
+                                entry.comment = Some(normalize_whitespace(&v))
+                            }
+                            ArxivEntryChild::Category(c) => {
+                                if !c.term.is_empty() && !entry.categories.contains(&c.term) {
+                                    entry.categories.push(c.term);
+                                }
+                            }
+                            ArxivEntryChild::Other => (),
                         }
                     }
                     entries.push(entry);
@@ -70,8 +175,7 @@ pub async fn search_arxiv(query: &str) -> Result<Vec<ArxivEntry>> {
             break;
         }
     }
-    
-    Ok(entries)
+    entries
 }
 
 
@@ -80,6 +184,12 @@ pub struct ArxivAuthor {
     pub name: String,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ArxivCategory {
+    #[serde(rename = "@term", default)]
+    pub term: String,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ArxivLink {
     #[serde(rename = "@href", default)]
@@ -92,6 +202,7 @@ pub async fn fetch_bibtex(id: &str) -> Result<String> {
     // arXiv IDs can have versions like 2101.00001v1, bibtex works with just the base id usually
     // but the full id works too.
     let url = format!("https://arxiv.org/bibtex/{}", id);
+    ensure_online()?;
     let client = reqwest::Client::new();
     let response = client.get(url).send().await?.text().await?;
     Ok(response)
@@ -100,3 +211,65 @@ pub async fn fetch_bibtex(id: &str) -> Result<String> {
 pub fn extract_id(arxiv_url: &str) -> String {
     arxiv_url.split('/').last().unwrap_or(arxiv_url).to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entries_metadata() {
+        let feed = r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:arxiv="http://arxiv.org/schemas/atom">
+<entry>
+  <id>http://arxiv.org/abs/2101.00001v2</id>
+  <updated>2021-02-03T00:00:00Z</updated>
+  <published>2021-01-01T00:00:00Z</published>
+  <title>A Paper</title>
+  <summary>Abstract.</summary>
+  <author><name>Ada Lovelace</name></author>
+  <arxiv:doi>10.1000/xyz</arxiv:doi>
+  <arxiv:comment>12 pages,
+ 3 figures</arxiv:comment>
+  <link href="http://arxiv.org/abs/2101.00001v2" rel="alternate" type="text/html"/>
+  <arxiv:primary_category term="cs.LG" scheme="http://arxiv.org/schemas/atom"/>
+  <category term="cs.LG" scheme="http://arxiv.org/schemas/atom"/>
+  <category term="stat.ML" scheme="http://arxiv.org/schemas/atom"/>
+</entry>
+</feed>"#;
+        let entries = parse_entries(feed);
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.title, "A Paper");
+        assert_eq!(entry.updated, "2021-02-03T00:00:00Z");
+        assert_eq!(entry.doi.as_deref(), Some("10.1000/xyz"));
+        assert_eq!(entry.comment.as_deref(), Some("12 pages, 3 figures"));
+        assert_eq!(entry.categories, vec!["cs.LG", "stat.ML"]);
+        assert_eq!(entry.authors[0].name, "Ada Lovelace");
+        assert_eq!(
+            entry.abstract_url().as_deref(),
+            Some("http://arxiv.org/abs/2101.00001v2")
+        );
+        // No PDF link in the feed: built from the id
+        assert_eq!(
+            entry.pdf_url().as_deref(),
+            Some("https://arxiv.org/pdf/2101.00001v2")
+        );
+        assert_eq!(ArxivEntry::default().pdf_url(), None);
+        assert_eq!(ArxivEntry::default().abstract_url(), None);
+    }
+
+    #[test]
+    fn test_parse_entries_normalizes_text() {
+        let feed = "<feed><entry>\n<id>http://arxiv.org/abs/1706.03762v7</id>\n<title>Attention Is\n  All You Need &amp; More</title>\n<summary>  The dominant\n  sequence models &lt;are&gt; complex.\n</summary>\n</entry></feed>";
+        let entry = &parse_entries(feed)[0];
+        assert_eq!(entry.title, "Attention Is All You Need & More");
+        assert_eq!(entry.raw_title, "Attention Is\n  All You Need & More");
+        assert_eq!(entry.summary, "The dominant sequence models <are> complex.");
+    }
+
+    #[test]
+    fn test_total_results() {
+        let feed = r#"<feed><opensearch:totalResults xmlns:opensearch="http://a9.com/-/spec/opensearch/1.1/">1234</opensearch:totalResults></feed>"#;
+        assert_eq!(total_results(feed), Some(1234));
+        assert_eq!(total_results("<feed></feed>"), None);
+    }
+}
@@ -0,0 +1,147 @@
+//! Optional log of the raw traffic with AI providers.
+//!
+//! Events use the [`AI_DEBUG_LOG_TARGET`] tracing target, which [`init_tracing`]
+//! routes to a daily-rotated file under the config directory instead of stderr.
+//! Providers only emit them while [`set_enabled`] is on.
+
+use crate::config::AppConfig;
+use crate::constants::{AI_DEBUG_LOG_DIR, AI_DEBUG_LOG_MAX_FILES, AI_DEBUG_LOG_TARGET};
+use regex::Regex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tracing_appender::rolling::{RollingFileAppender, RollingWriter};
+use tracing_subscriber::filter::{filter_fn, LevelFilter, Targets};
+use tracing_subscriber::fmt::writer::{EitherWriter, MakeWriter};
+use tracing_subscriber::prelude::*;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Turns request/response logging on or off, following `AppConfig::ai_debug_log`.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Folder holding the rotated `ai-debug.*.log` files.
+pub fn log_dir() -> std::path::PathBuf {
+    AppConfig::config_dir().join(AI_DEBUG_LOG_DIR)
+}
+
+/// The rotating log file, opened on the first AI debug event. Until logging is
+/// turned on nothing is written, so the folder is not created either.
+#[derive(Default)]
+struct LazyLogFile(OnceLock<Option<RollingFileAppender>>);
+
+impl<'a> MakeWriter<'a> for LazyLogFile {
+    type Writer = EitherWriter<RollingWriter<'a>, std::io::Sink>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        let appender = self.0.get_or_init(|| {
+            tracing_appender::rolling::Builder::new()
+                .rotation(tracing_appender::rolling::Rotation::DAILY)
+                .filename_prefix("ai-debug")
+                .filename_suffix("log")
+                .max_log_files(AI_DEBUG_LOG_MAX_FILES)
+                .build(log_dir())
+                .map_err(|e| eprintln!("AI debug log unavailable: {}", e))
+                .ok()
+        });
+        match appender {
+            Some(appender) => EitherWriter::A(appender.make_writer()),
+            None => EitherWriter::B(std::io::sink()),
+        }
+    }
+}
+
+/// Installs the global subscriber: regular events go to stderr as before, AI
+/// debug events only to the rotating log file.
+pub fn init_tracing() {
+    let file_layer = tracing_subscriber::fmt::layer()
+        .with_writer(LazyLogFile::default())
+        .with_ansi(false)
+        .with_filter(Targets::new().with_target(AI_DEBUG_LOG_TARGET, LevelFilter::DEBUG));
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_filter(filter_fn(|meta| meta.target() != AI_DEBUG_LOG_TARGET))
+                .with_filter(LevelFilter::INFO),
+        )
+        .with(file_layer)
+        .init();
+}
+
+fn bearer_regex() -> &'static Regex {
+    static BEARER_REGEX: OnceLock<Regex> = OnceLock::new();
+    BEARER_REGEX.get_or_init(|| Regex::new(r"(?i)(bearer\s+|\bsk-)[A-Za-z0-9._\-]+").unwrap())
+}
+
+/// Replaces `api_key` and anything that looks like a bearer token or `sk-` key.
+fn redact(text: &str, api_key: Option<&str>) -> String {
+    let text = match api_key {
+        Some(key) if !key.is_empty() => text.replace(key, "[REDACTED]"),
+        _ => text.to_string(),
+    };
+    bearer_regex()
+        .replace_all(&text, "${1}[REDACTED]")
+        .into_owned()
+}
+
+/// Logs an outgoing request body.
+pub fn log_request(provider: &str, url: &str, body: &serde_json::Value, api_key: Option<&str>) {
+    if !enabled() {
+        return;
+    }
+    tracing::debug!(
+        target: AI_DEBUG_LOG_TARGET,
+        "{} → POST {}\n{}",
+        provider,
+        redact(url, api_key),
+        redact(
+            &serde_json::to_string_pretty(body).unwrap_or_default(),
+            api_key
+        )
+    );
+}
+
+/// Logs one raw line of a streamed response (or an error body).
+pub fn log_response(provider: &str, raw: &[u8], api_key: Option<&str>) {
+    if !enabled() {
+        return;
+    }
+    let text = String::from_utf8_lossy(raw);
+    let text = text.trim_end();
+    if text.is_empty() {
+        return;
+    }
+    tracing::debug!(
+        target: AI_DEBUG_LOG_TARGET,
+        "{} ← {}",
+        provider,
+        redact(text, api_key)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_keys() {
+        assert_eq!(
+            redact("https://host/v1?key=abc123&x=1", Some("abc123")),
+            "https://host/v1?key=[REDACTED]&x=1"
+        );
+        assert_eq!(
+            redact(
+                "Authorization: Bearer tok.en-1 and sk-live_99, risk-free",
+                None
+            ),
+            "Authorization: Bearer [REDACTED] and sk-[REDACTED], risk-free"
+        );
+        assert_eq!(redact("nothing secret", Some("")), "nothing secret");
+    }
+}
@@ -1,14 +1,25 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use async_trait::async_trait;
 use std::sync::Arc;
-use crate::config::ProviderConfig;
-use futures::Stream;
+use crate::config::{ProviderConfig, ProviderKind};
+use crate::constants::{
+    AI_REQUEST_TIMEOUT, AI_SEED, DEFAULT_AI_CONNECT_TIMEOUT, REASONING_TRUNCATED_MARKER,
+    STREAM_LOG_SNIPPET_CHARS,
+};
+use futures::{Stream, StreamExt};
+use reqwest::Client;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 pub mod ollama;
 pub mod openai_compat;
 pub mod arxiv;
+pub mod debug_log;
+pub mod presets;
+pub mod prompt;
 
 use crate::api::ollama::OllamaProvider;
 use crate::api::openai_compat::OpenAiCompatibleProvider;
@@ -30,11 +41,70 @@ pub struct Message {
 #[derive(Error, Debug)]
 pub enum ApiError {
     #[error("HTTP client error: {0}")]
-    HttpClient(#[from] reqwest::Error),
+    HttpClient(reqwest::Error),
+    #[error("Request timed out: {0}")]
+    Timeout(reqwest::Error),
+    #[error("Could not connect to the server (is it running?): {0}")]
+    ConnectionRefused(reqwest::Error),
     #[error("API response error: {0}")]
     Response(String),
     #[error("Configuration error: {0}")]
     Config(String),
+    #[error("API key is missing")]
+    MissingApiKey,
+    #[error("Model {0} not found")]
+    ModelNotFound(String),
+    #[error("Offline mode is on; network features are disabled")]
+    Offline,
+}
+
+impl ApiError {
+    /// Short reason shown in the status bar, e.g. `AI: No API key`.
+    pub fn status(&self) -> &'static str {
+        match self {
+            ApiError::HttpClient(e) if e.is_decode() => "Unavailable",
+            ApiError::HttpClient(_) => "Offline",
+            ApiError::Timeout(_) => "Timed out",
+            ApiError::ConnectionRefused(_) => "Connection refused",
+            ApiError::MissingApiKey => "No API key",
+            ApiError::ModelNotFound(_) => "Model not found",
+            ApiError::Offline => "Offline mode",
+            ApiError::Response(_) | ApiError::Config(_) => "Unavailable",
+        }
+    }
+}
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Turns offline mode on or off, following `AppConfig::offline`. Every network
+/// request in this module checks it first with [`ensure_online`].
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Fails with [`ApiError::Offline`] while offline mode is on.
+pub fn ensure_online() -> Result<(), ApiError> {
+    if is_offline() {
+        Err(ApiError::Offline)
+    } else {
+        Ok(())
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            ApiError::Timeout(error)
+        } else if error.is_connect() {
+            ApiError::ConnectionRefused(error)
+        } else {
+            ApiError::HttpClient(error)
+        }
+    }
 }
 
 pub type AiStream = Pin<Box<dyn Stream<Item = Result<AiChunk, ApiError>> + Send>>;
@@ -43,6 +113,50 @@ pub type AiStream = Pin<Box<dyn Stream<Item = Result<AiChunk, ApiError>> + Send>
 pub enum AiChunk {
     Content(String),
     Reasoning(String),
+    /// Token counts reported at the end of a response.
+    Usage(TokenUsage),
+}
+
+/// Tokens consumed by one request.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    pub fn total(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Approximate cost from the provider's configured per-million-token prices.
+    pub fn cost(&self, provider: &ProviderConfig) -> Option<f64> {
+        let input = provider.input_price_per_mtok?;
+        let output = provider.output_price_per_mtok?;
+        Some(
+            (self.prompt_tokens as f64 * input + self.completion_tokens as f64 * output)
+                / 1_000_000.0,
+        )
+    }
+
+    /// One-line summary such as `1200 in · 350 out · ≈ $0.0042`.
+    pub fn summary(&self, provider: Option<&ProviderConfig>) -> String {
+        let mut text = format!(
+            "{} in · {} out tokens",
+            self.prompt_tokens, self.completion_tokens
+        );
+        if let Some(cost) = provider.and_then(|p| self.cost(p)) {
+            text.push_str(&format!(" · ≈ ${:.4}", cost));
+        }
+        text
+    }
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
 }
 
 pub struct ThinkingFilter {
@@ -123,7 +237,8 @@ impl ThinkingFilter {
                             if tag.as_bytes().starts_with(suffix) {
                                 let split_pos = bytes.len() - i;
                                 if self.buffer.is_char_boundary(split_pos) {
-                                    let reasoning: String = self.buffer.drain(..split_pos).collect();
+                                    let reasoning: String =
+                                        self.buffer.drain(..split_pos).collect();
                                     if !reasoning.is_empty() {
                                         chunks.push(AiChunk::Reasoning(reasoning));
                                     }
@@ -145,26 +260,423 @@ impl ThinkingFilter {
         }
         chunks
     }
+
+    /// Returns the text still held back because it looked like the start of a
+    /// tag, e.g. a lone `<` at the very end of a reply.
+    pub fn finish(&mut self) -> Vec<AiChunk> {
+        let rest = std::mem::take(&mut self.buffer);
+        if rest.is_empty() {
+            Vec::new()
+        } else if self.inside_think {
+            vec![AiChunk::Reasoning(rest)]
+        } else {
+            vec![AiChunk::Content(rest)]
+        }
+    }
+}
+
+/// Reasoning of one streamed reply, capped so a model that thinks for pages
+/// can't grow the reasoning view and memory without bound. The content of the
+/// reply is not affected.
+pub struct ReasoningBuffer {
+    /// Characters accepted so far.
+    len: usize,
+    /// Most characters accepted; 0 keeps everything.
+    max_chars: usize,
+    truncated: bool,
+}
+
+impl ReasoningBuffer {
+    pub fn new(max_chars: usize) -> Self {
+        Self {
+            len: 0,
+            max_chars,
+            truncated: false,
+        }
+    }
+
+    /// The part of `reasoning` to show: all of it while under the limit, the
+    /// rest up to the limit followed by [`REASONING_TRUNCATED_MARKER`] once
+    /// it is reached, and nothing after that.
+    pub fn push(&mut self, reasoning: &str) -> String {
+        if self.truncated {
+            return String::new();
+        }
+        let chars = reasoning.chars().count();
+        if self.max_chars == 0 || self.len + chars <= self.max_chars {
+            self.len += chars;
+            return reasoning.to_string();
+        }
+        let kept: String = reasoning.chars().take(self.max_chars - self.len).collect();
+        self.len = self.max_chars;
+        self.truncated = true;
+        kept + REASONING_TRUNCATED_MARKER
+    }
+
+    /// Whether reasoning has been dropped.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// A complete, non-streamed response.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatReply {
+    pub content: String,
+    /// Text the model produced inside `<think>` blocks or as reasoning deltas.
+    pub reasoning: String,
+    /// Token counts, if the provider reported them.
+    pub usage: Option<TokenUsage>,
 }
 
 #[async_trait]
 pub trait AiProvider: Send + Sync {
     fn name(&self) -> &str;
-    async fn chat_stream(&self, messages: Vec<Message>) -> Result<AiStream, ApiError>;
+    /// Streams a reply sampled with `seed`; the same seed and prompt give the same
+    /// reply on providers that honour it.
+    async fn chat_stream_seeded(
+        &self,
+        messages: Vec<Message>,
+        seed: u64,
+    ) -> Result<AiStream, ApiError>;
     async fn check_availability(&self) -> Result<(), ApiError>;
+
+    /// Streams a reply with the default, reproducible `AI_SEED`.
+    async fn chat_stream(&self, messages: Vec<Message>) -> Result<AiStream, ApiError> {
+        self.chat_stream_seeded(messages, AI_SEED).await
+    }
+
+    /// Drives `chat_stream` to completion, keeping content and reasoning apart.
+    #[allow(dead_code)]
+    async fn chat_with_reasoning(&self, messages: Vec<Message>) -> Result<ChatReply, ApiError> {
+        let mut stream = self.chat_stream(messages).await?;
+        let mut reply = ChatReply::default();
+        while let Some(chunk) = stream.next().await {
+            match chunk? {
+                AiChunk::Content(text) => reply.content.push_str(&text),
+                AiChunk::Reasoning(text) => reply.reasoning.push_str(&text),
+                AiChunk::Usage(usage) => reply.usage = Some(usage),
+            }
+        }
+        Ok(reply)
+    }
+
+    /// Returns the full completion for workflows that don't need streaming.
+    #[allow(dead_code)]
+    async fn chat(&self, messages: Vec<Message>) -> Result<String, ApiError> {
+        Ok(self.chat_with_reasoning(messages).await?.content)
+    }
+}
+
+/// A seed that differs on every call, for asking the model for another sample.
+pub fn random_seed() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    hasher.finish()
+}
+
+/// Connection and reply time limits for a provider's HTTP client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    pub connect: Duration,
+    /// Whole-reply limit for short requests, idle limit for streamed ones.
+    pub request: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: DEFAULT_AI_CONNECT_TIMEOUT,
+            request: AI_REQUEST_TIMEOUT,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn from_config(config: &ProviderConfig) -> Self {
+        let default = Self::default();
+        Self {
+            connect: config
+                .connect_timeout_secs
+                .map_or(default.connect, Duration::from_secs),
+            request: config
+                .request_timeout_secs
+                .map_or(default.request, Duration::from_secs),
+        }
+    }
+
+    /// A client that fails fast on dead endpoints but lets a stream run as long
+    /// as chunks keep arriving. Short requests add `RequestBuilder::timeout`.
+    pub(crate) fn client(&self) -> Client {
+        Client::builder()
+            .connect_timeout(self.connect)
+            .read_timeout(self.request)
+            .build()
+            .unwrap_or_default()
+    }
 }
 
 pub fn create_provider(config: &ProviderConfig) -> Arc<dyn AiProvider> {
-    match config.name.as_str() {
-        "Ollama" => Arc::new(OllamaProvider::new(
+    let timeouts = Timeouts::from_config(config);
+    match config.kind() {
+        ProviderKind::Ollama => Arc::new(OllamaProvider::new(
             config.active_model.clone(),
             config.base_url.clone(),
+            config.ollama_endpoint,
+            timeouts,
         )),
-        _ => Arc::new(OpenAiCompatibleProvider::new(
+        ProviderKind::OpenAiCompatible => Arc::new(OpenAiCompatibleProvider::new(
             config.name.clone(),
             config.active_model.clone(),
             config.base_url.clone(),
             config.api_key.clone(),
+            config.organization.clone(),
+            config.project.clone(),
+            timeouts,
         )),
     }
 }
+
+/// Turns a streamed response body into chunks by calling `on_line` on each
+/// `\n`-terminated line. Bytes are buffered across network chunks, so lines and
+/// multibyte characters split between them are reassembled, and a last line
+/// without a newline is still parsed when the body ends.
+pub(crate) fn line_stream<S, B, F>(body: S, mut on_line: F) -> AiStream
+where
+    S: Stream<Item = Result<B, ApiError>> + Send + 'static,
+    B: AsRef<[u8]> + Send + 'static,
+    F: FnMut(&[u8]) -> Vec<Result<AiChunk, ApiError>> + Send + 'static,
+{
+    let stream = body
+        .map(Some)
+        .chain(futures::stream::once(async { None }))
+        .scan(Vec::new(), move |buffer: &mut Vec<u8>, item| {
+            let chunks = match item {
+                Some(Ok(bytes)) => {
+                    buffer.extend_from_slice(bytes.as_ref());
+                    let mut chunks = Vec::new();
+                    while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=pos).collect();
+                        chunks.extend(on_line(&line));
+                    }
+                    chunks
+                }
+                Some(Err(e)) => vec![Err(e)],
+                None if buffer.is_empty() => Vec::new(),
+                None => on_line(&std::mem::take(buffer)),
+            };
+            futures::future::ready(Some(futures::stream::iter(chunks)))
+        })
+        .flatten();
+    Box::pin(stream)
+}
+
+/// Parses one streamed JSON object. Blank lines give `Ok(None)`, an
+/// `{"error": …}` object sent in place of a chunk becomes an error, and a line
+/// matching no known schema is logged and skipped.
+pub(crate) fn parse_stream_json<T: DeserializeOwned>(
+    provider: &str,
+    line: &[u8],
+) -> Result<Option<T>, ApiError> {
+    let line = line.trim_ascii();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if let Some(message) = stream_error(line) {
+        return Err(ApiError::Response(format!(
+            "{} stream error: {}",
+            provider, message
+        )));
+    }
+    match serde_json::from_slice::<T>(line) {
+        Ok(chunk) => Ok(Some(chunk)),
+        Err(e) => {
+            let text = String::from_utf8_lossy(line);
+            let snippet: String = text.chars().take(STREAM_LOG_SNIPPET_CHARS).collect();
+            tracing::warn!(
+                "Skipping unrecognized {} stream chunk ({}): {}",
+                provider,
+                e,
+                snippet
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// The message of an `{"error": "…"}` or `{"error": {"message": "…"}}` object.
+fn stream_error(line: &[u8]) -> Option<String> {
+    if !line.windows(7).any(|w| w == b"\"error\"") {
+        return None;
+    }
+    let value: serde_json::Value = serde_json::from_slice(line).ok()?;
+    Some(match value.get("error")? {
+        serde_json::Value::String(message) => message.clone(),
+        error => error
+            .get("message")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string()),
+    })
+}
+
+/// Drops trailing slashes and any of `endpoint_suffixes` pasted along with the
+/// server address, so appending `/path` never yields `//path` or a doubled path.
+pub(crate) fn normalize_base_url(url: &str, endpoint_suffixes: &[&str]) -> String {
+    let mut url = url.trim().trim_end_matches('/');
+    for suffix in endpoint_suffixes {
+        if let Some(stripped) = url.strip_suffix(suffix) {
+            url = stripped.trim_end_matches('/');
+        }
+    }
+    url.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ScriptedProvider(Vec<AiChunk>);
+
+    #[async_trait]
+    impl AiProvider for ScriptedProvider {
+        fn name(&self) -> &str {
+            "Scripted"
+        }
+
+        async fn chat_stream_seeded(
+            &self,
+            _messages: Vec<Message>,
+            _seed: u64,
+        ) -> Result<AiStream, ApiError> {
+            let chunks: Vec<Result<AiChunk, ApiError>> = self.0.iter().cloned().map(Ok).collect();
+            Ok(Box::pin(futures::stream::iter(chunks)))
+        }
+
+        async fn check_availability(&self) -> Result<(), ApiError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_reasoning_buffer_truncates() {
+        let mut buffer = ReasoningBuffer::new(10);
+        assert_eq!(buffer.push("Let me "), "Let me ");
+        assert_eq!(
+            buffer.push("think about it"),
+            format!("thi{}", REASONING_TRUNCATED_MARKER)
+        );
+        assert!(buffer.is_truncated());
+        assert_eq!(buffer.push("more and more"), "");
+
+        let mut unlimited = ReasoningBuffer::new(0);
+        assert_eq!(unlimited.push(&"é".repeat(50_000)).chars().count(), 50_000);
+        assert!(!unlimited.is_truncated());
+    }
+
+    #[test]
+    fn test_chat_collects_stream() {
+        let provider = ScriptedProvider(vec![
+            AiChunk::Reasoning("think ".to_string()),
+            AiChunk::Content("Hello, ".to_string()),
+            AiChunk::Reasoning("more".to_string()),
+            AiChunk::Content("world".to_string()),
+            AiChunk::Usage(TokenUsage {
+                prompt_tokens: 12,
+                completion_tokens: 4,
+            }),
+        ]);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let reply = rt
+            .block_on(provider.chat_with_reasoning(Vec::new()))
+            .unwrap();
+        assert_eq!(reply.content, "Hello, world");
+        assert_eq!(reply.reasoning, "think more");
+        assert_eq!(reply.usage.map(|u| u.total()), Some(16));
+        assert_eq!(
+            rt.block_on(provider.chat(Vec::new())).unwrap(),
+            "Hello, world"
+        );
+    }
+
+    #[test]
+    fn test_api_error_status() {
+        assert_eq!(ApiError::MissingApiKey.status(), "No API key");
+        assert_eq!(
+            ApiError::ModelNotFound("llama3".to_string()).status(),
+            "Model not found"
+        );
+        assert_eq!(
+            ApiError::Response("status 500".to_string()).status(),
+            "Unavailable"
+        );
+        assert_eq!(ApiError::Offline.status(), "Offline mode");
+
+        // Nothing listens on port 9 (discard), so the connection is refused
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let error = rt
+            .block_on(reqwest::get("http://127.0.0.1:9/"))
+            .unwrap_err();
+        assert_eq!(ApiError::from(error).status(), "Connection refused");
+
+        // A listener that never answers makes the request time out
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let error = rt
+            .block_on(async {
+                let client = reqwest::Client::builder()
+                    .timeout(std::time::Duration::from_millis(50))
+                    .build()
+                    .unwrap();
+                client.get(url).send().await
+            })
+            .unwrap_err();
+        assert_eq!(ApiError::from(error).status(), "Timed out");
+    }
+
+    #[test]
+    fn test_token_usage_summary() {
+        let usage = TokenUsage {
+            prompt_tokens: 2_000,
+            completion_tokens: 500,
+        };
+        let mut provider = crate::config::AppConfig::default().providers[0].clone();
+        assert_eq!(usage.summary(Some(&provider)), "2000 in · 500 out tokens");
+
+        provider.input_price_per_mtok = Some(1.0);
+        provider.output_price_per_mtok = Some(4.0);
+        assert_eq!(usage.cost(&provider), Some(0.004));
+        assert_eq!(
+            usage.summary(Some(&provider)),
+            "2000 in · 500 out tokens · ≈ $0.0040"
+        );
+    }
+
+    #[test]
+    fn test_timeouts_from_config() {
+        let mut provider = crate::config::AppConfig::default().providers[0].clone();
+        assert_eq!(Timeouts::from_config(&provider), Timeouts::default());
+
+        provider.connect_timeout_secs = Some(3);
+        provider.request_timeout_secs = Some(300);
+        assert_eq!(
+            Timeouts::from_config(&provider),
+            Timeouts {
+                connect: Duration::from_secs(3),
+                request: Duration::from_secs(300),
+            }
+        );
+    }
+
+    #[test]
+    fn test_random_seed_varies() {
+        assert_ne!(random_seed(), random_seed());
+    }
+}
@@ -1,5 +1,9 @@
-use crate::api::{AiChunk, AiProvider, AiStream, ApiError, Message};
-use crate::constants::{AI_REQUEST_TIMEOUT, AI_SEED, AI_TEMPERATURE, AI_TOP_P};
+use crate::api::{
+    debug_log, ensure_online, line_stream, normalize_base_url, parse_stream_json, AiChunk,
+    AiProvider, AiStream, ApiError, Message, MessageRole, ThinkingFilter, Timeouts, TokenUsage,
+};
+use crate::config::OllamaEndpoint;
+use crate::constants::{AI_TEMPERATURE, AI_TOP_P};
 use async_trait::async_trait;
 use futures::StreamExt;
 use reqwest::Client;
@@ -8,27 +12,130 @@ use serde_json::json;
 
 pub struct OllamaProvider {
     client: Client,
+    timeouts: Timeouts,
     pub model: String,
+    /// Server address without any path, e.g. `http://localhost:11434`.
     pub base_url: String,
+    pub endpoint: OllamaEndpoint,
 }
 
 impl OllamaProvider {
-    pub fn new(model: String, base_url: String) -> Self {
-        let client = Client::builder()
-            .timeout(AI_REQUEST_TIMEOUT)
-            .build()
-            .unwrap_or_default();
+    pub fn new(
+        model: String,
+        base_url: String,
+        endpoint: OllamaEndpoint,
+        timeouts: Timeouts,
+    ) -> Self {
         Self {
-            client,
+            client: timeouts.client(),
+            timeouts,
             model,
-            base_url,
+            base_url: normalize_base_url(
+                &base_url,
+                &["/api/chat", "/api/generate", "/api/tags", "/api", "/v1"],
+            ),
+            endpoint,
         }
     }
+
+    async fn post_stream(
+        &self,
+        path: &str,
+        body: serde_json::Value,
+        parse_line: fn(&[u8], &mut ThinkingFilter) -> Vec<Result<AiChunk, ApiError>>,
+    ) -> Result<AiStream, ApiError> {
+        ensure_online()?;
+        let url = format!("{}{}", self.base_url, path);
+        debug_log::log_request("Ollama", &url, &body, None);
+        let response = self.client.post(url).json(&body).send().await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            debug_log::log_response("Ollama", body.as_bytes(), None);
+            return Err(ApiError::Response(format!(
+                "Ollama {} error ({}): {}",
+                path, status, body
+            )));
+        }
+
+        let body = response
+            .bytes_stream()
+            .map(|item| item.map_err(ApiError::from));
+        // Models like deepseek-r1 put their reasoning inline in `<think>` tags
+        let mut filter = ThinkingFilter::new();
+        Ok(line_stream(body, move |line| {
+            debug_log::log_response("Ollama", line, None);
+            parse_line(line, &mut filter)
+        }))
+    }
+
+    async fn chat_endpoint(&self, messages: Vec<Message>, seed: u64) -> Result<AiStream, ApiError> {
+        self.post_stream(
+            "/api/chat",
+            json!({
+                "model": self.model,
+                "messages": messages,
+                "stream": true,
+                "options": {
+                    "temperature": AI_TEMPERATURE,
+                    "top_p": AI_TOP_P,
+                    "seed": seed
+                }
+            }),
+            parse_chat_line,
+        )
+        .await
+    }
+
+    async fn generate_endpoint(
+        &self,
+        messages: Vec<Message>,
+        seed: u64,
+    ) -> Result<AiStream, ApiError> {
+        self.post_stream(
+            "/api/generate",
+            json!({
+                "model": self.model,
+                "prompt": generate_prompt(&messages),
+                "raw": true,
+                "stream": true,
+                "options": {
+                    "temperature": AI_TEMPERATURE,
+                    "top_p": AI_TOP_P,
+                    "seed": seed
+                }
+            }),
+            parse_generate_line,
+        )
+        .await
+    }
 }
 
 #[derive(Deserialize)]
 struct OllamaChatResponse {
     message: OllamaMessage,
+    /// Set on the final line, which also carries the token counts.
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct OllamaGenerateResponse {
+    #[serde(default)]
+    response: String,
+    #[serde(default)]
+    thinking: Option<String>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    prompt_eval_count: Option<u64>,
+    #[serde(default)]
+    eval_count: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -55,8 +162,14 @@ impl AiProvider for OllamaProvider {
     }
 
     async fn check_availability(&self) -> Result<(), ApiError> {
+        ensure_online()?;
         let url = format!("{}/api/tags", self.base_url);
-        let response = self.client.get(url).send().await?;
+        let response = self
+            .client
+            .get(url)
+            .timeout(self.timeouts.request)
+            .send()
+            .await?;
 
         if !response.status().is_success() {
             return Err(ApiError::Response(format!(
@@ -73,67 +186,223 @@ impl AiProvider for OllamaProvider {
         {
             Ok(())
         } else {
-            Err(ApiError::Response(format!(
-                "Model {} not found in Ollama",
-                self.model
-            )))
+            Err(ApiError::ModelNotFound(self.model.clone()))
         }
     }
 
-    async fn chat_stream(&self, messages: Vec<Message>) -> Result<AiStream, ApiError> {
-        let url = format!("{}/api/chat", self.base_url);
-        let response = self
-            .client
-            .post(url)
-            .json(&json!({
-                "model": self.model,
-                "messages": messages,
-                "stream": true,
-                "options": {
-                    "temperature": AI_TEMPERATURE,
-                    "top_p": AI_TOP_P,
-                    "seed": AI_SEED
+    async fn chat_stream_seeded(
+        &self,
+        messages: Vec<Message>,
+        seed: u64,
+    ) -> Result<AiStream, ApiError> {
+        match self.endpoint {
+            OllamaEndpoint::Chat => self.chat_endpoint(messages, seed).await,
+            OllamaEndpoint::Generate => self.generate_endpoint(messages, seed).await,
+            OllamaEndpoint::Auto => match self.chat_endpoint(messages.clone(), seed).await {
+                Err(ApiError::Response(body)) if lacks_chat_template(&body) => {
+                    tracing::info!(
+                        "Model {} has no chat template, falling back to /api/generate",
+                        self.model
+                    );
+                    self.generate_endpoint(messages, seed).await
                 }
-            }))
-            .send()
-            .await?;
+                result => result,
+            },
+        }
+    }
+}
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let body = response.text().await.unwrap_or_default();
-            return Err(ApiError::Response(format!(
-                "Ollama chat error ({}): {}",
-                status, body
-            )));
+/// Whether an `/api/chat` error says the model can't be used for chat.
+fn lacks_chat_template(error: &str) -> bool {
+    let error = error.to_lowercase();
+    error.contains("does not support chat")
+        || error.contains("chat template")
+        || error.contains("no template")
+}
+
+/// Flattens chat messages into a plain transcript for `/api/generate`.
+fn generate_prompt(messages: &[Message]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        let speaker = match message.role {
+            MessageRole::System => {
+                prompt.push_str(&message.content);
+                prompt.push_str("\n\n");
+                continue;
+            }
+            MessageRole::User => "User",
+            MessageRole::Assistant => "Assistant",
+        };
+        prompt.push_str(&format!("{}: {}\n\n", speaker, message.content));
+    }
+    prompt.push_str("Assistant:");
+    prompt
+}
+
+fn usage_chunk(
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+) -> Result<AiChunk, ApiError> {
+    Ok(AiChunk::Usage(TokenUsage {
+        prompt_tokens: prompt_tokens.unwrap_or(0),
+        completion_tokens: completion_tokens.unwrap_or(0),
+    }))
+}
+
+/// Chunks for one streamed line: the explicit reasoning field as is, then the
+/// text split by `filter` into content and inline `<think>` reasoning. The
+/// final line flushes the filter and reports the token counts.
+fn line_chunks(
+    reasoning: Option<String>,
+    text: String,
+    done: bool,
+    usage: (Option<u64>, Option<u64>),
+    filter: &mut ThinkingFilter,
+) -> Vec<Result<AiChunk, ApiError>> {
+    let mut chunks: Vec<Result<AiChunk, ApiError>> = Vec::new();
+    if let Some(r) = reasoning {
+        chunks.push(Ok(AiChunk::Reasoning(r)));
+    }
+    if !text.is_empty() {
+        chunks.extend(filter.process(text).into_iter().map(Ok));
+    }
+    if done {
+        chunks.extend(filter.finish().into_iter().map(Ok));
+        chunks.push(usage_chunk(usage.0, usage.1));
+    }
+    chunks
+}
+
+fn parse_chat_line(line: &[u8], filter: &mut ThinkingFilter) -> Vec<Result<AiChunk, ApiError>> {
+    match parse_stream_json::<OllamaChatResponse>("Ollama", line) {
+        Ok(Some(chunk)) => line_chunks(
+            chunk.message.reasoning,
+            chunk.message.content,
+            chunk.done,
+            (chunk.prompt_eval_count, chunk.eval_count),
+            filter,
+        ),
+        Ok(None) => Vec::new(),
+        Err(e) => vec![Err(e)],
+    }
+}
+
+fn parse_generate_line(line: &[u8], filter: &mut ThinkingFilter) -> Vec<Result<AiChunk, ApiError>> {
+    match parse_stream_json::<OllamaGenerateResponse>("Ollama", line) {
+        Ok(Some(chunk)) => line_chunks(
+            chunk.thinking,
+            chunk.response,
+            chunk.done,
+            (chunk.prompt_eval_count, chunk.eval_count),
+            filter,
+        ),
+        Ok(None) => Vec::new(),
+        Err(e) => vec![Err(e)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lacks_chat_template() {
+        assert!(lacks_chat_template(
+            "Ollama /api/chat error (400 Bad Request): {\"error\":\"llama2:text does not support chat\"}"
+        ));
+        assert!(!lacks_chat_template(
+            "Ollama /api/chat error (404 Not Found): {\"error\":\"model 'x' not found\"}"
+        ));
+    }
+
+    #[test]
+    fn test_generate_prompt_and_lines() {
+        let prompt = generate_prompt(&[
+            Message {
+                role: MessageRole::System,
+                content: "Be brief.".to_string(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: "Fix this".to_string(),
+            },
+        ]);
+        assert_eq!(prompt, "Be brief.\n\nUser: Fix this\n\nAssistant:");
+
+        let filter = &mut ThinkingFilter::new();
+        let chunks = parse_generate_line(br#"{"response":"\\section","done":false}"#, filter);
+        assert!(matches!(&chunks[..], [Ok(AiChunk::Content(c))] if c == "\\section"));
+        let chunks = parse_generate_line(
+            br#"{"response":"","done":true,"prompt_eval_count":12,"eval_count":3}"#,
+            filter,
+        );
+        assert!(matches!(
+            &chunks[..],
+            [Ok(AiChunk::Usage(TokenUsage {
+                prompt_tokens: 12,
+                completion_tokens: 3
+            }))]
+        ));
+    }
+
+    #[test]
+    fn test_inline_think_tags_split_across_lines() {
+        let lines: [&[u8]; 6] = [
+            br#"{"message":{"content":"<thi"},"done":false}"#,
+            br#"{"message":{"content":"nk>Needs a sec"},"done":false}"#,
+            br#"{"message":{"content":"tion.</th"},"done":false}"#,
+            br#"{"message":{"content":"ink>\\section{A}"},"done":false}"#,
+            br#"{"message":{"content":" <","reasoning":"explicit"},"done":false}"#,
+            br#"{"message":{"content":""},"done":true,"prompt_eval_count":5,"eval_count":9}"#,
+        ];
+        let filter = &mut ThinkingFilter::new();
+        let (mut content, mut reasoning, mut usage) = (String::new(), String::new(), None);
+        for line in lines {
+            for chunk in parse_chat_line(line, filter) {
+                match chunk.unwrap() {
+                    AiChunk::Content(c) => content.push_str(&c),
+                    AiChunk::Reasoning(r) => reasoning.push_str(&r),
+                    AiChunk::Usage(u) => usage = Some(u.total()),
+                }
+            }
         }
+        assert_eq!(reasoning, "Needs a section.explicit");
+        // The trailing `<` is held back as a possible tag, then flushed at the end
+        assert_eq!(content, "\\section{A} <");
+        assert_eq!(usage, Some(14));
+    }
 
-        let stream = response
-            .bytes_stream()
-            .map(|item| item.map_err(ApiError::HttpClient))
-            .scan(Vec::new(), |buffer, item| {
-                let res = match item {
-                    Ok(bytes) => {
-                        buffer.extend_from_slice(&bytes);
-                        let mut chunks = Vec::new();
-                        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                            let line: Vec<u8> = buffer.drain(..=pos).collect();
-                            if let Ok(chunk) = serde_json::from_slice::<OllamaChatResponse>(&line) {
-                                if let Some(r) = chunk.message.reasoning {
-                                    chunks.push(Ok(AiChunk::Reasoning(r)));
-                                }
-                                if !chunk.message.content.is_empty() {
-                                    chunks.push(Ok(AiChunk::Content(chunk.message.content)));
-                                }
-                            }
-                        }
-                        Some(futures::stream::iter(chunks))
-                    }
-                    Err(e) => Some(futures::stream::iter(vec![Err(e)])),
-                };
-                futures::future::ready(res)
-            })
-            .flatten();
-
-        Ok(Box::pin(stream))
+    #[test]
+    fn test_base_url_normalized() {
+        for url in [
+            "http://localhost:11434",
+            "http://localhost:11434/",
+            "http://localhost:11434/api/",
+            "http://localhost:11434/v1",
+        ] {
+            let provider = OllamaProvider::new(
+                "llama3".to_string(),
+                url.to_string(),
+                OllamaEndpoint::Chat,
+                Timeouts::default(),
+            );
+            assert_eq!(provider.base_url, "http://localhost:11434");
+        }
+    }
+
+    #[test]
+    fn test_stream_error_object() {
+        let filter = &mut ThinkingFilter::new();
+        let chunks = parse_chat_line(
+            br#"{"error":"model runner has unexpectedly stopped"}"#,
+            filter,
+        );
+        assert!(matches!(
+            &chunks[..],
+            [Err(ApiError::Response(m))] if m == "Ollama stream error: model runner has unexpectedly stopped"
+        ));
+        // Unknown shapes and blank lines are skipped
+        assert!(parse_chat_line(b"{\"unexpected\":1}", filter).is_empty());
+        assert!(parse_chat_line(b"\n", filter).is_empty());
     }
 }
@@ -1,38 +1,94 @@
-use crate::api::{AiChunk, AiProvider, AiStream, ApiError, Message};
-use crate::constants::{AI_REQUEST_TIMEOUT, AI_SEED, AI_TEMPERATURE, AI_TOP_P};
+use crate::api::{
+    debug_log, ensure_online, line_stream, normalize_base_url, parse_stream_json, AiChunk,
+    AiProvider, AiStream, ApiError, Message, ThinkingFilter, Timeouts, TokenUsage,
+};
+use crate::constants::{AI_TEMPERATURE, AI_TOP_P};
 use async_trait::async_trait;
 use futures::StreamExt;
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, Url};
 use serde::Deserialize;
 use serde_json::json;
 
 pub struct OpenAiCompatibleProvider {
     client: Client,
+    timeouts: Timeouts,
     pub name: String,
     pub model: String,
+    /// API root including the version, e.g. `https://api.openai.com/v1`.
     pub base_url: String,
     pub api_key: Option<String>,
+    pub organization: Option<String>,
+    pub project: Option<String>,
 }
 
 impl OpenAiCompatibleProvider {
-    pub fn new(name: String, model: String, base_url: String, api_key: Option<String>) -> Self {
-        let client = Client::builder()
-            .timeout(AI_REQUEST_TIMEOUT)
-            .build()
-            .unwrap_or_default();
+    pub fn new(
+        name: String,
+        model: String,
+        base_url: String,
+        api_key: Option<String>,
+        organization: Option<String>,
+        project: Option<String>,
+        timeouts: Timeouts,
+    ) -> Self {
         Self {
-            client,
+            client: timeouts.client(),
+            timeouts,
             name,
             model,
-            base_url,
+            base_url: normalize_base_url(&base_url, &["/chat/completions", "/models"]),
             api_key,
+            organization,
+            project,
+        }
+    }
+
+    /// Adds bearer auth plus the OpenAI organization/project headers, each only when set,
+    /// so other OpenAI-compatible servers never see headers they don't expect.
+    fn authorize(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(ref key) = self.api_key {
+            request = request.bearer_auth(key);
+        }
+        if let Some(organization) = self.organization.as_deref().filter(|o| !o.is_empty()) {
+            request = request.header("OpenAI-Organization", organization);
+        }
+        if let Some(project) = self.project.as_deref().filter(|p| !p.is_empty()) {
+            request = request.header("OpenAI-Project", project);
+        }
+        request
+    }
+
+    /// Whether the server runs on this machine, like llama.cpp or LM Studio,
+    /// which don't ask for an API key.
+    fn is_local(&self) -> bool {
+        let Ok(url) = Url::parse(&self.base_url) else {
+            return false;
+        };
+        match url.host_str() {
+            Some("localhost") => true,
+            Some(host) => host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback()),
+            None => false,
         }
     }
 }
 
 #[derive(Deserialize)]
 struct OpenAiStreamResponse {
+    #[serde(default)]
     choices: Vec<OpenAiStreamChoice>,
+    /// Only present on the final chunk, with `stream_options.include_usage`.
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
 }
 
 #[derive(Deserialize)]
@@ -48,6 +104,27 @@ struct OpenAiDelta {
     reasoning_content: Option<String>,
 }
 
+/// Body of `GET /models`.
+#[derive(Deserialize, Default)]
+struct OpenAiModels {
+    #[serde(default)]
+    data: Vec<OpenAiModel>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiModel {
+    id: String,
+}
+
+impl OpenAiModels {
+    /// Whether `model` is available; an empty list can't rule anything out, and
+    /// a blank model leaves the choice to the server (local servers use whatever
+    /// model they have loaded).
+    fn lists(&self, model: &str) -> bool {
+        model.is_empty() || self.data.is_empty() || self.data.iter().any(|m| m.id == model)
+    }
+}
+
 #[async_trait]
 impl AiProvider for OpenAiCompatibleProvider {
     fn name(&self) -> &str {
@@ -55,96 +132,266 @@ impl AiProvider for OpenAiCompatibleProvider {
     }
 
     async fn check_availability(&self) -> Result<(), ApiError> {
-        if self.api_key.is_none() {
-            return Err(ApiError::Config("API Key is missing".to_string()));
+        ensure_online()?;
+        if self.api_key.is_none() && !self.is_local() {
+            return Err(ApiError::MissingApiKey);
         }
 
         let url = format!("{}/models", self.base_url);
-        let mut request = self.client.get(url);
+        let response = self
+            .authorize(self.client.get(url).timeout(self.timeouts.request))
+            .send()
+            .await?;
 
-        if let Some(ref key) = self.api_key {
-            request = request.bearer_auth(key);
+        if !response.status().is_success() {
+            return Err(ApiError::Response(format!(
+                "API returned status {}",
+                response.status()
+            )));
         }
 
-        let response = request.send().await?;
-
-        if response.status().is_success() {
+        // Servers that don't list models in the OpenAI format are given the benefit of the doubt
+        let models: OpenAiModels = response.json().await.unwrap_or_default();
+        if models.lists(&self.model) {
             Ok(())
         } else {
-            Err(ApiError::Response(format!(
-                "API returned status {}",
-                response.status()
-            )))
+            Err(ApiError::ModelNotFound(self.model.clone()))
         }
     }
 
-    async fn chat_stream(&self, messages: Vec<Message>) -> Result<AiStream, ApiError> {
+    async fn chat_stream_seeded(
+        &self,
+        messages: Vec<Message>,
+        seed: u64,
+    ) -> Result<AiStream, ApiError> {
+        ensure_online()?;
         let url = format!("{}/chat/completions", self.base_url);
-        let mut request = self.client.post(url);
+        let request = self.authorize(self.client.post(&url));
 
-        if let Some(ref key) = self.api_key {
-            request = request.bearer_auth(key);
-        }
-
-        let response = request
-            .json(&json!({
-                "model": self.model,
-                "messages": messages,
-                "stream": true,
-                "temperature": AI_TEMPERATURE,
-                "top_p": AI_TOP_P,
-                "seed": AI_SEED
-            }))
-            .send()
-            .await?;
+        let body = json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": true,
+            "stream_options": { "include_usage": true },
+            "temperature": AI_TEMPERATURE,
+            "top_p": AI_TOP_P,
+            "seed": seed
+        });
+        debug_log::log_request(&self.name, &url, &body, self.api_key.as_deref());
+        let response = request.json(&body).send().await?;
 
         if !response.status().is_success() {
             let status = response.status();
             let body = response.text().await.unwrap_or_default();
+            debug_log::log_response(&self.name, body.as_bytes(), self.api_key.as_deref());
             return Err(ApiError::Response(format!(
                 "API error ({}): {}",
                 status, body
             )));
         }
 
-        let stream = response
+        let name = self.name.clone();
+        let api_key = self.api_key.clone();
+        let body = response
             .bytes_stream()
-            .map(|item| item.map_err(ApiError::HttpClient))
-            .scan(Vec::new(), |buffer, item| {
-                let res = match item {
-                    Ok(bytes) => {
-                        buffer.extend_from_slice(&bytes);
-                        let mut chunks = Vec::new();
-                        while let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
-                            let line_bytes: Vec<u8> = buffer.drain(..=pos).collect();
-                            let line = String::from_utf8_lossy(&line_bytes);
-                            let line = line.trim();
-
-                            if line.is_empty() || line == "data: [DONE]" {
-                                continue;
-                            }
-
-                            if let Some(json_str) = line.strip_prefix("data: ") {
-                                if let Ok(chunk) = serde_json::from_str::<OpenAiStreamResponse>(json_str) {
-                                    if let Some(choice) = chunk.choices.first() {
-                                        if let Some(content) = &choice.delta.content {
-                                            chunks.push(Ok(AiChunk::Content(content.clone())));
-                                        }
-                                        if let Some(reasoning) = &choice.delta.reasoning_content {
-                                            chunks.push(Ok(AiChunk::Reasoning(reasoning.clone())));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Some(futures::stream::iter(chunks))
-                    }
-                    Err(e) => Some(futures::stream::iter(vec![Err(e)])),
-                };
-                futures::future::ready(res)
+            .map(|item| item.map_err(ApiError::from));
+        // Models like deepseek-r1 put their reasoning inline in `<think>` tags
+        let mut filter = ThinkingFilter::new();
+        Ok(line_stream(body, move |line| {
+            debug_log::log_response(&name, line, api_key.as_deref());
+            parse_sse_line(&name, line, &mut filter)
+        }))
+    }
+}
+
+/// Parses one line of the `text/event-stream` body, splitting the content by
+/// `filter` into text and inline `<think>` reasoning. The closing `[DONE]`
+/// flushes the filter.
+fn parse_sse_line(
+    provider: &str,
+    line: &[u8],
+    filter: &mut ThinkingFilter,
+) -> Vec<Result<AiChunk, ApiError>> {
+    let mut chunks = Vec::new();
+    let line = String::from_utf8_lossy(line);
+    let Some(json_str) = line.trim().strip_prefix("data:") else {
+        return chunks;
+    };
+    let json_str = json_str.trim_start();
+    if json_str == "[DONE]" {
+        return filter.finish().into_iter().map(Ok).collect();
+    }
+
+    let chunk = match parse_stream_json::<OpenAiStreamResponse>(provider, json_str.as_bytes()) {
+        Ok(Some(chunk)) => chunk,
+        Ok(None) => return chunks,
+        Err(e) => return vec![Err(e)],
+    };
+    if let Some(choice) = chunk.choices.first() {
+        if let Some(content) = &choice.delta.content {
+            chunks.extend(filter.process(content.clone()).into_iter().map(Ok));
+        }
+        if let Some(reasoning) = &choice.delta.reasoning_content {
+            chunks.push(Ok(AiChunk::Reasoning(reasoning.clone())));
+        }
+    }
+    if let Some(usage) = chunk.usage {
+        chunks.push(Ok(AiChunk::Usage(TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+        })));
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(organization: Option<&str>, project: Option<&str>) -> OpenAiCompatibleProvider {
+        OpenAiCompatibleProvider::new(
+            "OpenAI".to_string(),
+            "gpt-4o".to_string(),
+            "https://api.openai.com/v1".to_string(),
+            Some("sk-test".to_string()),
+            organization.map(str::to_string),
+            project.map(str::to_string),
+            Timeouts::default(),
+        )
+    }
+
+    #[test]
+    fn test_scope_headers_only_when_set() {
+        let scoped = provider(Some("org-1"), Some("proj-2"));
+        let request = scoped
+            .authorize(scoped.client.get("https://api.openai.com/v1/models"))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["OpenAI-Organization"], "org-1");
+        assert_eq!(request.headers()["OpenAI-Project"], "proj-2");
+        assert_eq!(request.headers()["Authorization"], "Bearer sk-test");
+
+        let plain = provider(None, Some(""));
+        let request = plain
+            .authorize(plain.client.get("https://api.openai.com/v1/models"))
+            .build()
+            .unwrap();
+        assert!(!request.headers().contains_key("OpenAI-Organization"));
+        assert!(!request.headers().contains_key("OpenAI-Project"));
+    }
+
+    #[test]
+    fn test_models_lists() {
+        let models: OpenAiModels =
+            serde_json::from_str(r#"{"object":"list","data":[{"id":"gpt-4o"},{"id":"o3-mini"}]}"#)
+                .unwrap();
+        assert!(models.lists("gpt-4o"));
+        assert!(!models.lists("gpt-5"));
+        assert!(OpenAiModels::default().lists("anything"));
+        assert!(models.lists(""));
+    }
+
+    #[test]
+    fn test_local_servers_need_no_key() {
+        for (url, local) in [
+            ("http://localhost:8080/v1", true),
+            ("http://127.0.0.1:1234/v1", true),
+            ("http://[::1]:1234/v1", true),
+            ("https://api.openai.com/v1", false),
+            ("http://192.168.1.20:8080/v1", false),
+            ("not a url", false),
+        ] {
+            let provider = OpenAiCompatibleProvider::new(
+                "Local".to_string(),
+                String::new(),
+                url.to_string(),
+                None,
+                None,
+                None,
+                Timeouts::default(),
+            );
+            assert_eq!(provider.is_local(), local, "{}", url);
+        }
+    }
+
+    #[test]
+    fn test_base_url_normalized() {
+        for url in [
+            "https://api.openai.com/v1",
+            "https://api.openai.com/v1/",
+            "https://api.openai.com/v1/chat/completions",
+        ] {
+            let provider = OpenAiCompatibleProvider::new(
+                "OpenAI".to_string(),
+                "gpt-4o".to_string(),
+                url.to_string(),
+                None,
+                None,
+                None,
+                Timeouts::default(),
+            );
+            assert_eq!(provider.base_url, "https://api.openai.com/v1");
+        }
+    }
+
+    #[test]
+    fn test_sse_line_split_across_chunks() {
+        // The delta and the "é" are both cut in half by the network chunking
+        let body = futures::stream::iter(vec![
+            Ok(b"data: {\"choices\":[{\"delta\":{\"content\":\"caf\xc3".to_vec()),
+            Ok(b"\xa9\"}}]}\n\ndata: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,".to_vec()),
+            Ok(b"\"completion_tokens\":1}}\ndata: [DONE]".to_vec()),
+        ]);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let mut filter = ThinkingFilter::new();
+        let chunks: Vec<_> = rt.block_on(
+            line_stream(body, move |line| {
+                parse_sse_line("OpenAI", line, &mut filter)
             })
-            .flatten();
+            .collect(),
+        );
+        assert!(matches!(
+            &chunks[..],
+            [Ok(AiChunk::Content(c)), Ok(AiChunk::Usage(usage))]
+                if c == "café" && usage.total() == 6
+        ));
+    }
+
+    #[test]
+    fn test_sse_think_tags_and_held_back_text() {
+        let filter = &mut ThinkingFilter::new();
+        let content = |text: &str| {
+            format!(
+                "data: {{\"choices\":[{{\"delta\":{{\"content\":\"{}\"}}}}]}}",
+                text
+            )
+        };
+        let mut chunks = parse_sse_line(
+            "OpenAI",
+            content("<think>plan</think>x <").as_bytes(),
+            filter,
+        );
+        chunks.extend(parse_sse_line("OpenAI", b"data: [DONE]", filter));
+        assert!(matches!(
+            &chunks[..],
+            [
+                Ok(AiChunk::Reasoning(r)),
+                Ok(AiChunk::Content(c)),
+                Ok(AiChunk::Content(rest)),
+            ] if r == "plan" && c == "x " && rest == "<"
+        ));
+    }
 
-        Ok(Box::pin(stream))
+    #[test]
+    fn test_sse_error_object() {
+        let chunks = parse_sse_line(
+            "OpenAI",
+            br#"data: {"error":{"message":"Rate limit reached","type":"requests"}}"#,
+            &mut ThinkingFilter::new(),
+        );
+        assert!(matches!(
+            &chunks[..],
+            [Err(ApiError::Response(m))] if m == "OpenAI stream error: Rate limit reached"
+        ));
     }
 }
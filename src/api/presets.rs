@@ -0,0 +1,152 @@
+//! Named system prompts for the AI assistant.
+//!
+//! The first preset is always "Default": the active provider's `system_prompt`
+//! override, or the built-in LaTeX editing prompt. Bundled presets follow, then
+//! the user's own `.txt` files from `<config_dir>/prompts/`.
+
+use crate::config::{AppConfig, ProviderConfig};
+use crate::constants::USER_PROMPTS_DIR;
+use std::path::Path;
+
+/// Name of the preset that falls back to the provider's own system prompt.
+pub const DEFAULT_PRESET: &str = "Default";
+
+const DEFAULT_SYSTEM_PROMPT: &str = "You are an expert LaTeX assistant. Your goal is to help users edit specific sections of their LaTeX documents. \
+\n\nCORE RULES:\n\
+- Output ONLY the modified LaTeX code for the provided snippet.\n\
+- Do NOT include markdown blocks like ```latex.\n\
+- Do NOT include conversational text, greetings, or explanations.\n\
+- If the user provides a small snippet, assume they want to edit it or add something relative to it.\n\
+- If adding a new environment (table, figure, etc.), ensure it is properly closed.\n\
+- Use ONLY standard LaTeX commands (article class). Avoid hallucinated commands like \\keywords (use \\paragraph{Keywords:} instead).\n\
+- Maintain the context of the surrounding code if applicable.";
+
+/// Shared by the bundled presets so their output can replace the selection as-is.
+const OUTPUT_RULES: &str = "\n\nOutput ONLY the resulting LaTeX, without markdown fences, \
+greetings or explanations.";
+
+/// (name, prompt) of the bundled presets, in display order. Each prompt is
+/// followed by [`OUTPUT_RULES`].
+const BUILTIN_PRESETS: &[(&str, &str)] = &[
+    (
+        "Fix LaTeX",
+        "You are an expert LaTeX assistant. Fix compilation errors, unbalanced braces and \
+         environments, and misused commands in the given LaTeX. Change nothing else: keep the \
+         wording, formatting and structure as they are.",
+    ),
+    (
+        "Improve Writing",
+        "You are an experienced academic editor. Improve the clarity, grammar and flow of the \
+         prose in the given LaTeX while keeping its meaning, its technical terms and every \
+         command, label, citation and math expression unchanged.",
+    ),
+    (
+        "Translate",
+        "You are a professional translator of scientific texts. Translate the prose in the given \
+         LaTeX into the language the user asks for (English if none is given). Leave commands, \
+         labels, citations, math and code untouched.",
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptPreset {
+    pub name: String,
+    pub prompt: String,
+}
+
+/// `.txt` files in `dir`, sorted by name. A missing directory yields no presets.
+fn presets_in(dir: &Path) -> Vec<PromptPreset> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut presets: Vec<PromptPreset> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "txt" {
+                return None;
+            }
+            let prompt = std::fs::read_to_string(&path).ok()?;
+            Some(PromptPreset {
+                name: path.file_stem()?.to_string_lossy().into_owned(),
+                prompt: prompt.trim().to_string(),
+            })
+        })
+        .filter(|preset| !preset.prompt.is_empty())
+        .collect();
+    presets.sort_by(|a, b| a.name.cmp(&b.name));
+    presets
+}
+
+fn builtin_presets(provider: Option<&ProviderConfig>) -> impl Iterator<Item = PromptPreset> {
+    let default = provider
+        .and_then(|p| p.system_prompt.clone())
+        .filter(|prompt| !prompt.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string());
+    std::iter::once(PromptPreset {
+        name: DEFAULT_PRESET.to_string(),
+        prompt: default,
+    })
+    .chain(BUILTIN_PRESETS.iter().map(|(name, prompt)| PromptPreset {
+        name: name.to_string(),
+        prompt: format!("{}{}", prompt, OUTPUT_RULES),
+    }))
+}
+
+/// "Default" for `provider`, the bundled presets, then the user's own.
+pub fn all_presets(provider: Option<&ProviderConfig>) -> Vec<PromptPreset> {
+    builtin_presets(provider)
+        .chain(presets_in(&AppConfig::config_dir().join(USER_PROMPTS_DIR)))
+        .collect()
+}
+
+/// The prompt of the preset called `name`, falling back to "Default" when it
+/// no longer exists.
+pub fn system_prompt(presets: &[PromptPreset], name: Option<&str>) -> String {
+    name.and_then(|name| presets.iter().find(|p| p.name == name))
+        .or_else(|| presets.first())
+        .map_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string(), |p| p.prompt.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_preset_uses_provider_override() {
+        let mut provider = AppConfig::default().providers[0].clone();
+        let presets: Vec<_> = builtin_presets(Some(&provider)).collect();
+        assert_eq!(presets[0].name, DEFAULT_PRESET);
+        assert_eq!(presets[0].prompt, DEFAULT_SYSTEM_PROMPT);
+        assert_eq!(presets.len(), BUILTIN_PRESETS.len() + 1);
+
+        provider.system_prompt = Some("Answer in French.".to_string());
+        let presets: Vec<_> = builtin_presets(Some(&provider)).collect();
+        assert_eq!(presets[0].prompt, "Answer in French.");
+    }
+
+    #[test]
+    fn test_system_prompt_falls_back_to_default() {
+        let presets: Vec<_> = builtin_presets(None).collect();
+        assert!(system_prompt(&presets, Some("Translate")).starts_with("You are a professional"));
+        assert_eq!(
+            system_prompt(&presets, Some("Removed")),
+            DEFAULT_SYSTEM_PROMPT
+        );
+        assert_eq!(system_prompt(&presets, None), DEFAULT_SYSTEM_PROMPT);
+    }
+
+    #[test]
+    fn test_presets_in_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("summarize.txt"), "Summarize.\n").unwrap();
+        std::fs::write(dir.path().join("empty.txt"), "  \n").unwrap();
+        std::fs::write(dir.path().join("notes.md"), "not a preset").unwrap();
+        std::fs::write(dir.path().join("check.txt"), "Check.").unwrap();
+
+        let presets = presets_in(dir.path());
+        let names: Vec<&str> = presets.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["check", "summarize"]);
+        assert_eq!(presets[1].prompt, "Summarize.");
+        assert!(presets_in(&dir.path().join("missing")).is_empty());
+    }
+}
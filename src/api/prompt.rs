@@ -0,0 +1,203 @@
+//! Builds the messages for an AI edit request from the chosen context mode.
+
+use crate::api::{Message, MessageRole};
+use crate::config::AiContextMode;
+use crate::constants::{AI_CHARS_PER_TOKEN, AI_RESPONSE_TOKEN_RESERVE};
+
+const SELECTION_START: &str = "<<<SELECTION>>>";
+const SELECTION_END: &str = "<<<END SELECTION>>>";
+const OMITTED_BEFORE: &str = "% [earlier text omitted]\n";
+const OMITTED_AFTER: &str = "\n% [later text omitted]";
+
+/// Messages for one edit request plus how they fit the model's context window.
+#[derive(Debug)]
+pub struct EditPrompt {
+    pub messages: Vec<Message>,
+    pub estimated_tokens: usize,
+    /// Document text around the selection was cut to fit the window.
+    pub truncated: bool,
+    /// The prompt is still likely too large for the window.
+    pub exceeds_window: bool,
+}
+
+/// Rough token count for `text`.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(AI_CHARS_PER_TOKEN)
+}
+
+/// The last `max_chars` characters of `text`.
+fn tail_chars(text: &str, max_chars: usize) -> &str {
+    let skip = text.chars().count().saturating_sub(max_chars);
+    text.char_indices()
+        .nth(skip)
+        .map_or("", |(i, _)| &text[i..])
+}
+
+/// The first `max_chars` characters of `text`.
+fn head_chars(text: &str, max_chars: usize) -> &str {
+    text.char_indices()
+        .nth(max_chars)
+        .map_or(text, |(i, _)| &text[..i])
+}
+
+/// Builds the system and user messages for editing `selection`.
+///
+/// `before` and `after` are the document text around the selection. In
+/// [`AiContextMode::WholeDocument`] the caller passes the entire document as
+/// `selection`. For [`AiContextMode::DocumentWithSelection`] the surrounding
+/// text is trimmed, keeping what is nearest the selection, so the prompt fits
+/// `context_window` tokens with room left for the reply.
+pub fn build_edit_prompt(
+    system_prompt: &str,
+    instruction: &str,
+    mode: AiContextMode,
+    before: &str,
+    selection: &str,
+    after: &str,
+    context_window: usize,
+) -> EditPrompt {
+    let budget_tokens = context_window.saturating_sub(AI_RESPONSE_TOKEN_RESERVE);
+    let mut truncated = false;
+
+    let user = match mode {
+        AiContextMode::Selection => format!(
+            "Edit the following LaTeX snippet based on these instructions: {}\n\nSnippet:\n{}",
+            instruction, selection
+        ),
+        AiContextMode::WholeDocument => format!(
+            "Edit the following LaTeX document based on these instructions: {}\n\n\
+             Output the complete revised document.\n\nDocument:\n{}",
+            instruction, selection
+        ),
+        AiContextMode::DocumentWithSelection => {
+            let header = format!(
+                "Edit only the part of the LaTeX document between {} and {} based on these \
+                 instructions: {}\n\nOutput ONLY the replacement for the marked part, without \
+                 the markers.\n\nDocument:\n",
+                SELECTION_START, SELECTION_END, instruction
+            );
+            let fixed_chars = system_prompt.chars().count()
+                + header.chars().count()
+                + SELECTION_START.len()
+                + selection.chars().count()
+                + SELECTION_END.len();
+            let available = (budget_tokens * AI_CHARS_PER_TOKEN).saturating_sub(fixed_chars);
+
+            let before_len = before.chars().count();
+            let after_len = after.chars().count();
+            let (mut before, mut after) = (before.to_string(), after.to_string());
+            if before_len + after_len > available {
+                truncated = true;
+                let available =
+                    available.saturating_sub(OMITTED_BEFORE.len() + OMITTED_AFTER.len());
+                // Split the room evenly, giving either side's unused share to the other
+                let half = available / 2;
+                let keep_before = before_len.min(half.max(available.saturating_sub(after_len)));
+                let keep_after = after_len.min(available - keep_before);
+                if keep_before < before_len {
+                    before = format!("{}{}", OMITTED_BEFORE, tail_chars(&before, keep_before));
+                }
+                if keep_after < after_len {
+                    after = format!("{}{}", head_chars(&after, keep_after), OMITTED_AFTER);
+                }
+            }
+            format!(
+                "{}{}{}{}{}{}",
+                header, before, SELECTION_START, selection, SELECTION_END, after
+            )
+        }
+    };
+
+    let estimated_tokens = estimate_tokens(&format!("{}{}", system_prompt, user));
+    EditPrompt {
+        messages: vec![
+            Message {
+                role: MessageRole::System,
+                content: system_prompt.to_string(),
+            },
+            Message {
+                role: MessageRole::User,
+                content: user,
+            },
+        ],
+        estimated_tokens,
+        truncated,
+        exceeds_window: estimated_tokens > budget_tokens,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+    }
+
+    #[test]
+    fn test_build_edit_prompt_modes() {
+        let prompt = build_edit_prompt(
+            "sys",
+            "fix",
+            AiContextMode::Selection,
+            "A ",
+            "B",
+            " C",
+            8192,
+        );
+        assert!(prompt.messages[1].content.ends_with("Snippet:\nB"));
+        assert!(!prompt.messages[1].content.contains("A "));
+
+        let prompt = build_edit_prompt(
+            "sys",
+            "fix",
+            AiContextMode::DocumentWithSelection,
+            "A ",
+            "B",
+            " C",
+            8192,
+        );
+        assert!(prompt.messages[1]
+            .content
+            .ends_with("A <<<SELECTION>>>B<<<END SELECTION>>> C"));
+        assert!(!prompt.truncated);
+        assert!(!prompt.exceeds_window);
+    }
+
+    #[test]
+    fn test_build_edit_prompt_truncates_context() {
+        let before = "b".repeat(20_000);
+        let after = "a".repeat(20_000);
+        let prompt = build_edit_prompt(
+            "sys",
+            "fix",
+            AiContextMode::DocumentWithSelection,
+            &before,
+            "SEL",
+            &after,
+            4096,
+        );
+        assert!(prompt.truncated);
+        assert!(!prompt.exceeds_window);
+        let content = &prompt.messages[1].content;
+        assert!(content.contains(OMITTED_BEFORE));
+        assert!(content.contains("b<<<SELECTION>>>SEL<<<END SELECTION>>>a"));
+        assert!(content.ends_with(OMITTED_AFTER));
+
+        // Whole-document prompts are never cut, only flagged
+        let whole = build_edit_prompt(
+            "sys",
+            "fix",
+            AiContextMode::WholeDocument,
+            "",
+            &before,
+            "",
+            4096,
+        );
+        assert!(!whole.truncated);
+        assert!(whole.exceeds_window);
+    }
+}
@@ -0,0 +1,142 @@
+//! Bibliography support: locating a document's `.bib` files and parsing them
+//! into searchable entries for the citation picker.
+
+use crate::utils::fuzzy_score;
+use biblatex::{Bibliography, ChunksExt};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// A bibliography entry reduced to what the citation picker shows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BibEntry {
+    pub key: String,
+    pub title: String,
+    pub authors: String,
+    pub year: String,
+}
+
+/// Parses BibTeX/BibLaTeX source into entries.
+pub fn parse_bib(src: &str) -> Result<Vec<BibEntry>, String> {
+    let bibliography = Bibliography::parse(src).map_err(|e| e.to_string())?;
+    let field = |entry: &biblatex::Entry, name: &str| {
+        entry
+            .get(name)
+            .map(|chunks| chunks.format_verbatim())
+            .unwrap_or_default()
+    };
+    Ok(bibliography
+        .iter()
+        .map(|entry| {
+            let date = field(entry, "year");
+            let date = if date.is_empty() {
+                field(entry, "date")
+            } else {
+                date
+            };
+            BibEntry {
+                key: entry.key.clone(),
+                title: field(entry, "title"),
+                authors: field(entry, "author"),
+                year: date.chars().take(4).collect(),
+            }
+        })
+        .collect())
+}
+
+fn bib_resource_regex() -> &'static Regex {
+    static BIB_RESOURCE_REGEX: OnceLock<Regex> = OnceLock::new();
+    BIB_RESOURCE_REGEX.get_or_init(|| {
+        Regex::new(r"\\(bibliography|addbibresource)(?:\[[^\]]*\])?\{([^}]+)\}").unwrap()
+    })
+}
+
+/// Finds the `.bib` files named by `\bibliography{a,b}` or `\addbibresource{c.bib}`,
+/// resolved against the document's directory.
+pub fn find_bib_files(text: &str, document_dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for caps in bib_resource_regex().captures_iter(text) {
+        for name in caps[2].split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let mut path = document_dir.join(name);
+            if path.extension().is_none() {
+                path.set_extension("bib");
+            }
+            if !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+/// Entries matching `query` against key, authors, title and year, best first.
+pub fn search<'a>(entries: &'a [BibEntry], query: &str) -> Vec<&'a BibEntry> {
+    let mut matches: Vec<(i32, &BibEntry)> = entries
+        .iter()
+        .filter_map(|entry| {
+            // Score each field separately so a match need not span fields
+            [&entry.key, &entry.authors, &entry.title, &entry.year]
+                .iter()
+                .filter_map(|text| fuzzy_score(query, text))
+                .max()
+                .map(|score| (score, entry))
+        })
+        .collect();
+    matches.sort_by(|a, b| b.0.cmp(&a.0));
+    matches.into_iter().map(|(_, entry)| entry).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BIB: &str = r#"
+@book{knuth84,
+  author = {Knuth, Donald E.},
+  title = {The {TeX}book},
+  year = {1984},
+}
+@article{lamport94,
+  author = {Lamport, Leslie},
+  title = {LaTeX: A Document Preparation System},
+  date = {1994-06},
+}
+"#;
+
+    #[test]
+    fn test_parse_bib() {
+        let entries = parse_bib(BIB).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].key, "knuth84");
+        assert_eq!(entries[0].title, "The TeXbook");
+        assert_eq!(entries[0].year, "1984");
+        assert!(entries[1].authors.contains("Lamport"));
+        assert_eq!(entries[1].year, "1994");
+    }
+
+    #[test]
+    fn test_find_bib_files() {
+        let text = r"\bibliography{refs, extra.bib} \addbibresource[label=x]{more.bib}";
+        let files = find_bib_files(text, Path::new("/doc"));
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("/doc/refs.bib"),
+                PathBuf::from("/doc/extra.bib"),
+                PathBuf::from("/doc/more.bib"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let entries = parse_bib(BIB).unwrap();
+        let keys: Vec<&str> = search(&entries, "lamport")
+            .iter()
+            .map(|e| e.key.as_str())
+            .collect();
+        assert_eq!(keys, vec!["lamport94"]);
+        assert_eq!(search(&entries, "1984")[0].key, "knuth84");
+        assert_eq!(search(&entries, "").len(), 2);
+    }
+}
@@ -1,14 +1,335 @@
+use crate::constants::{
+    COMPILE_TIMEOUT_SECS, CONFIG_DIR_ENV, CONFIG_DIR_NAME, DEFAULT_AUX_EXTENSIONS,
+    DEFAULT_COMPILE_QUEUE_DEPTH, DEFAULT_EDITOR_FONT, DEFAULT_EDITOR_FONT_SIZE,
+    DEFAULT_FORMAT_WRAP_COLUMN, DEFAULT_MAX_REASONING_CHARS, DEFAULT_PNG_COPY_DPI,
+    DEFAULT_PREVIEW_DEBOUNCE_MS, DEFAULT_PREVIEW_SCALE, DEFAULT_PROJECT_SEARCH_INCLUDE,
+    DEFAULT_SPELL_LANGUAGE, DEFAULT_TAB_WIDTH, DEFAULT_TODO_KEYWORDS,
+};
 use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ProviderConfig {
+    /// Unique among the configured providers; `active_provider` refers to it.
     pub name: String,
+    /// Which API the server speaks. Entries written before this field existed
+    /// leave it unset; see [`ProviderConfig::kind`].
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub provider_type: Option<ProviderKind>,
     pub api_key: Option<String>,
+    /// For OpenAI-compatible providers the API root including `/v1`; for Ollama
+    /// the bare server address. Trailing slashes are ignored.
     pub base_url: String,
     pub active_model: String,
     pub system_prompt: Option<String>,
+    /// Price per million prompt tokens, used for the cost estimate after a response.
+    #[serde(default)]
+    pub input_price_per_mtok: Option<f64>,
+    /// Price per million completion tokens.
+    #[serde(default)]
+    pub output_price_per_mtok: Option<f64>,
+    /// Model context window in tokens; see `DEFAULT_AI_CONTEXT_TOKENS`.
+    #[serde(default)]
+    pub context_window: Option<usize>,
+    /// Which Ollama endpoint to call; ignored by other providers.
+    #[serde(default)]
+    pub ollama_endpoint: OllamaEndpoint,
+    /// Sent as `OpenAI-Organization` by OpenAI-compatible providers when set.
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// Sent as `OpenAI-Project` by OpenAI-compatible providers when set.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Seconds to wait for the server to accept a connection; see `DEFAULT_AI_CONNECT_TIMEOUT`.
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// Seconds to wait for a whole reply to short requests, and at most between
+    /// two chunks of a streamed one; see `AI_REQUEST_TIMEOUT`.
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+}
+
+/// The API a provider speaks, which decides the client and the settings shown.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    Ollama,
+    /// `/chat/completions` in the OpenAI format: OpenAI, DeepSeek and most
+    /// hosted or local servers.
+    OpenAiCompatible,
+}
+
+impl ProviderKind {
+    pub const ALL: [ProviderKind; 2] = [ProviderKind::Ollama, ProviderKind::OpenAiCompatible];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ProviderKind::Ollama => "Ollama",
+            ProviderKind::OpenAiCompatible => "OpenAI-compatible",
+        }
+    }
+
+    /// Base URL a new provider of this kind starts with.
+    pub fn default_base_url(self) -> &'static str {
+        match self {
+            ProviderKind::Ollama => "http://localhost:11434",
+            ProviderKind::OpenAiCompatible => "http://localhost:8080/v1",
+        }
+    }
+}
+
+impl ProviderConfig {
+    /// A provider of `kind` with its default URL and no model chosen yet.
+    pub fn new(name: String, kind: ProviderKind) -> Self {
+        Self {
+            name,
+            provider_type: Some(kind),
+            api_key: None,
+            base_url: kind.default_base_url().to_string(),
+            active_model: String::new(),
+            system_prompt: None,
+            input_price_per_mtok: None,
+            output_price_per_mtok: None,
+            context_window: None,
+            ollama_endpoint: OllamaEndpoint::default(),
+            organization: None,
+            project: None,
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+        }
+    }
+
+    /// The configured type, or for older entries without one, Ollama if the
+    /// provider is named "Ollama" and OpenAI-compatible otherwise.
+    pub fn kind(&self) -> ProviderKind {
+        self.provider_type.unwrap_or(if self.name == "Ollama" {
+            ProviderKind::Ollama
+        } else {
+            ProviderKind::OpenAiCompatible
+        })
+    }
+}
+
+/// Ollama API used for completions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OllamaEndpoint {
+    /// `/api/chat`, falling back to `/api/generate` for models without a chat template.
+    #[default]
+    Auto,
+    /// Always `/api/chat`.
+    Chat,
+    /// Always `/api/generate` with a prompt assembled from the messages.
+    Generate,
+}
+
+impl OllamaEndpoint {
+    pub const ALL: [OllamaEndpoint; 3] = [
+        OllamaEndpoint::Auto,
+        OllamaEndpoint::Chat,
+        OllamaEndpoint::Generate,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            OllamaEndpoint::Auto => "Automatic",
+            OllamaEndpoint::Chat => "Chat (/api/chat)",
+            OllamaEndpoint::Generate => "Generate (/api/generate)",
+        }
+    }
+}
+
+/// What the AI is shown alongside the instruction.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AiContextMode {
+    /// Only the selected text (or the lines around the cursor), which is replaced.
+    #[default]
+    Selection,
+    /// The whole document, which is replaced.
+    WholeDocument,
+    /// The whole document with the selection marked; only the selection is replaced.
+    DocumentWithSelection,
+}
+
+impl AiContextMode {
+    pub const ALL: [AiContextMode; 3] = [
+        AiContextMode::Selection,
+        AiContextMode::WholeDocument,
+        AiContextMode::DocumentWithSelection,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AiContextMode::Selection => "Selection only",
+            AiContextMode::WholeDocument => "Whole document",
+            AiContextMode::DocumentWithSelection => "Document + selection highlighted",
+        }
+    }
+}
+
+/// TeX engine that typesets the document.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LatexEngine {
+    #[default]
+    Pdflatex,
+    Xelatex,
+    Lualatex,
+}
+
+impl LatexEngine {
+    pub const ALL: [LatexEngine; 3] = [
+        LatexEngine::Pdflatex,
+        LatexEngine::Xelatex,
+        LatexEngine::Lualatex,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LatexEngine::Pdflatex => "pdfLaTeX",
+            LatexEngine::Xelatex => "XeLaTeX",
+            LatexEngine::Lualatex => "LuaLaTeX",
+        }
+    }
+
+    /// Executable run for this engine.
+    pub fn program(self) -> &'static str {
+        match self {
+            LatexEngine::Pdflatex => "pdflatex",
+            LatexEngine::Xelatex => "xelatex",
+            LatexEngine::Lualatex => "lualatex",
+        }
+    }
+}
+
+/// How compiled documents are shown in the preview pane.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewBackend {
+    /// Each page converted to SVG with pdftocairo; supports dark mode and outline jumps.
+    #[default]
+    Svg,
+    /// The PDF handed to WebKit as is; faster for long documents and needs no poppler-utils.
+    Pdf,
+}
+
+impl PreviewBackend {
+    pub const ALL: [PreviewBackend; 2] = [PreviewBackend::Svg, PreviewBackend::Pdf];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PreviewBackend::Svg => "Rendered pages (SVG)",
+            PreviewBackend::Pdf => "Embedded PDF",
+        }
+    }
+}
+
+/// Whether WebKit's sandbox is turned off, which some environments (WSL,
+/// containers) need for the preview to render at all.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WebKitSandbox {
+    /// Disable it only where it is known not to work.
+    #[default]
+    Auto,
+    /// Always disable it.
+    Disabled,
+    /// Never disable it, even where detection says it is needed.
+    Enabled,
+}
+
+impl WebKitSandbox {
+    pub const ALL: [WebKitSandbox; 3] = [
+        WebKitSandbox::Auto,
+        WebKitSandbox::Disabled,
+        WebKitSandbox::Enabled,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            WebKitSandbox::Auto => "Automatic",
+            WebKitSandbox::Disabled => "Always Disable",
+            WebKitSandbox::Enabled => "Keep Enabled",
+        }
+    }
+
+    /// Whether to disable the sandbox; `detect` is only consulted for `Auto`.
+    pub fn should_disable(self, detect: impl FnOnce() -> bool) -> bool {
+        match self {
+            WebKitSandbox::Auto => detect(),
+            WebKitSandbox::Disabled => true,
+            WebKitSandbox::Enabled => false,
+        }
+    }
+}
+
+/// Whether the preview is drawn light or dark, independently of the editor.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewTheme {
+    /// Dark while the desktop uses a dark style.
+    #[default]
+    FollowSystem,
+    Light,
+    /// Dark background with the pages' colors inverted.
+    Dark,
+}
+
+impl PreviewTheme {
+    pub const ALL: [PreviewTheme; 3] = [
+        PreviewTheme::FollowSystem,
+        PreviewTheme::Light,
+        PreviewTheme::Dark,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PreviewTheme::FollowSystem => "Follow System",
+            PreviewTheme::Light => "Always Light",
+            PreviewTheme::Dark => "Always Dark",
+        }
+    }
+
+    /// Whether to render dark; `system_dark` is only consulted for `FollowSystem`.
+    pub fn is_dark(self, system_dark: bool) -> bool {
+        match self {
+            PreviewTheme::FollowSystem => system_dark,
+            PreviewTheme::Light => false,
+            PreviewTheme::Dark => true,
+        }
+    }
+}
+
+/// How wide preview pages are drawn.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewLayout {
+    /// The page's natural width times `preview_scale`, shrunk to fit the pane.
+    #[default]
+    Fixed,
+    /// As wide as the preview pane.
+    FitWidth,
+    /// The page's natural width, scrolling sideways in a narrow pane.
+    ActualSize,
+}
+
+impl PreviewLayout {
+    pub const ALL: [PreviewLayout; 3] = [
+        PreviewLayout::Fixed,
+        PreviewLayout::FitWidth,
+        PreviewLayout::ActualSize,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PreviewLayout::Fixed => "Fixed",
+            PreviewLayout::FitWidth => "Fit Width",
+            PreviewLayout::ActualSize => "Actual Size",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -17,14 +338,207 @@ pub struct AppConfig {
     pub providers: Vec<ProviderConfig>,
     #[serde(default)]
     pub ai_history: Vec<String>,
+    #[serde(default)]
+    pub preview_theme: PreviewTheme,
+    /// Only read from configs written before `preview_theme`, where turning it
+    /// off kept the preview light.
+    #[serde(default = "default_true", skip_serializing)]
+    preview_dark_mode: bool,
+    #[serde(default = "default_preview_debounce_ms")]
+    pub preview_debounce_ms: u64,
+    /// Compilations allowed to wait behind the running one; read at startup.
+    #[serde(default = "default_compile_queue_depth")]
+    pub compile_queue_depth: usize,
+    /// Compile into `<document_dir>/.latexrs-build/` and keep intermediate files.
+    #[serde(default)]
+    pub keep_build_files: bool,
+    /// Save the open file before each compile so it always matches the preview.
+    /// Never creates a file for an unsaved document.
+    #[serde(default)]
+    pub autosave_before_compile: bool,
+    /// Scale factor applied to preview pages (higher shows fine TikZ detail larger).
+    #[serde(default = "default_preview_scale")]
+    pub preview_scale: f64,
+    /// Whether pages keep their scaled width, fill the pane or show at 100%.
+    #[serde(default)]
+    pub preview_layout: PreviewLayout,
+    #[serde(default)]
+    pub preview_backend: PreviewBackend,
+    /// Cache the preamble as a pdflatex format so edits only retypeset the body.
+    #[serde(default)]
+    pub precompile_preamble: bool,
+    /// Shared macros injected after `\documentclass` in every preview compile.
+    #[serde(default)]
+    pub preview_preamble: String,
+    /// Engine used unless the document's `.latexrs.toml` names another.
+    #[serde(default)]
+    pub latex_engine: LatexEngine,
+    /// Let every document run shell commands through `\write18`.
+    #[serde(default)]
+    pub shell_escape: bool,
+    /// Folder holding pdflatex, biber and the other tools, for installs that
+    /// aren't on the `PATH` the app was started with. Programs missing from it
+    /// are still looked up on `PATH`.
+    #[serde(default)]
+    pub tex_bin_dir: Option<PathBuf>,
+    /// Skip compiling while braces or environments are unbalanced.
+    #[serde(default)]
+    pub lint_before_compile: bool,
+    /// Limit for each run of the engine and its helper tools, in seconds.
+    #[serde(default = "default_compile_timeout_secs")]
+    pub compile_timeout_secs: u64,
+    /// Folders whose `.latexrs.toml` may turn shell escape on. A folder is added
+    /// when the user enables it in Document Settings, so a downloaded project
+    /// can't enable it by itself.
+    #[serde(default)]
+    pub shell_escape_trusted_dirs: Vec<PathBuf>,
+    /// Wrap fragments without `\documentclass` in a minimal document for the preview.
+    #[serde(default = "default_true")]
+    pub wrap_fragments: bool,
+    /// Number the pages in the preview with an overlay.
+    #[serde(default)]
+    pub preview_page_numbers: bool,
+    /// Content-Security-Policy of preview pages; `None` uses the strict
+    /// `DEFAULT_PREVIEW_CSP`. Allowing scripts lets documents run code in the preview.
+    #[serde(default)]
+    pub preview_content_security_policy: Option<String>,
+    /// WebKit sandbox workaround for WSL and containers; read at startup.
+    #[serde(default)]
+    pub webkit_sandbox: WebKitSandbox,
+    /// Write each compile's errors and warnings to
+    /// `.latexrs-build/<name>.diagnostics.json` for external editors.
+    #[serde(default)]
+    pub write_diagnostics: bool,
+    /// Resolution of pages copied with Copy Page as PNG.
+    #[serde(default = "default_png_copy_dpi")]
+    pub png_copy_dpi: u32,
+    #[serde(default = "default_true")]
+    pub spell_check: bool,
+    /// Hunspell dictionary name, e.g. `en_US`.
+    #[serde(default = "default_spell_language")]
+    pub spell_language: String,
+    #[serde(default)]
+    pub ai_context_mode: AiContextMode,
+    /// Name of the system-prompt preset picked in the AI panel; `None` is "Default".
+    #[serde(default)]
+    pub ai_prompt_preset: Option<String>,
+    /// Start the AI reasoning box collapsed to its title.
+    #[serde(default)]
+    pub collapse_reasoning: bool,
+    /// Reasoning shown per reply, in characters; 0 shows all of it.
+    #[serde(default = "default_max_reasoning_chars")]
+    pub max_reasoning_chars: usize,
+    /// Write AI requests and raw responses to `<config_dir>/logs/`.
+    #[serde(default)]
+    pub ai_debug_log: bool,
+    /// Never touch the network: AI providers and arXiv refuse every request.
+    #[serde(default)]
+    pub offline: bool,
+    #[serde(default = "default_editor_font")]
+    pub editor_font: String,
+    /// Editor font size in points before zoom.
+    #[serde(default = "default_editor_font_size")]
+    pub editor_font_size: i32,
+    #[serde(default = "default_tab_width")]
+    pub tab_width: u32,
+    #[serde(default = "default_true")]
+    pub insert_spaces: bool,
+    #[serde(default = "default_true")]
+    pub show_line_numbers: bool,
+    #[serde(default)]
+    pub wrap_lines: bool,
+    /// GtkSourceView style scheme id; `None` follows the system light/dark theme.
+    #[serde(default)]
+    pub editor_color_scheme: Option<String>,
+    /// Column at which Format Document wraps prose; `0` leaves line lengths alone.
+    #[serde(default = "default_format_wrap_column")]
+    pub format_wrap_column: usize,
+    /// Whether Format Document aligns `&` columns in tables and align environments.
     #[serde(default = "default_true")]
-    pub preview_dark_mode: bool,
+    pub format_align_tables: bool,
+    /// Comment keywords (`% TODO`, `% FIXME`, …) collected in the TODO sidebar.
+    #[serde(default = "default_todo_keywords")]
+    pub todo_keywords: Vec<String>,
+    /// Globs of the files Search in Project looks through.
+    #[serde(default = "default_project_search_include")]
+    pub project_search_include: Vec<String>,
+    /// Globs of files and folders Search in Project skips, e.g. `build`.
+    #[serde(default)]
+    pub project_search_exclude: Vec<String>,
+    /// Extensions (without the dot) that Clean Auxiliary Files deletes.
+    #[serde(default = "default_aux_extensions")]
+    pub aux_extensions: Vec<String>,
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn default_preview_debounce_ms() -> u64 {
+    DEFAULT_PREVIEW_DEBOUNCE_MS
+}
+
+fn default_compile_queue_depth() -> usize {
+    DEFAULT_COMPILE_QUEUE_DEPTH
+}
+
+fn default_compile_timeout_secs() -> u64 {
+    COMPILE_TIMEOUT_SECS
+}
+
+fn default_preview_scale() -> f64 {
+    DEFAULT_PREVIEW_SCALE
+}
+
+fn default_png_copy_dpi() -> u32 {
+    DEFAULT_PNG_COPY_DPI
+}
+
+fn default_max_reasoning_chars() -> usize {
+    DEFAULT_MAX_REASONING_CHARS
+}
+
+fn default_spell_language() -> String {
+    DEFAULT_SPELL_LANGUAGE.to_string()
+}
+
+fn default_editor_font() -> String {
+    DEFAULT_EDITOR_FONT.to_string()
+}
+
+fn default_editor_font_size() -> i32 {
+    DEFAULT_EDITOR_FONT_SIZE
+}
+
+fn default_tab_width() -> u32 {
+    DEFAULT_TAB_WIDTH
+}
+
+fn default_format_wrap_column() -> usize {
+    DEFAULT_FORMAT_WRAP_COLUMN
+}
+
+fn default_todo_keywords() -> Vec<String> {
+    DEFAULT_TODO_KEYWORDS
+        .iter()
+        .map(|k| k.to_string())
+        .collect()
+}
+
+fn default_aux_extensions() -> Vec<String> {
+    DEFAULT_AUX_EXTENSIONS
+        .iter()
+        .map(|e| e.to_string())
+        .collect()
+}
+
+fn default_project_search_include() -> Vec<String> {
+    DEFAULT_PROJECT_SEARCH_INCLUDE
+        .iter()
+        .map(|g| g.to_string())
+        .collect()
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
@@ -32,52 +546,159 @@ impl Default for AppConfig {
             providers: vec![
                 ProviderConfig {
                     name: "Ollama".to_string(),
+                    provider_type: Some(ProviderKind::Ollama),
                     api_key: None,
                     base_url: "http://localhost:11434".to_string(),
                     active_model: "qwen2.5:0.5b".to_string(),
                     system_prompt: None,
+                    input_price_per_mtok: None,
+                    output_price_per_mtok: None,
+                    context_window: None,
+                    ollama_endpoint: OllamaEndpoint::Auto,
+                    organization: None,
+                    project: None,
+                    connect_timeout_secs: None,
+                    request_timeout_secs: None,
+                },
+                ProviderConfig {
+                    name: "Local (llama.cpp)".to_string(),
+                    provider_type: Some(ProviderKind::OpenAiCompatible),
+                    api_key: None,
+                    base_url: ProviderKind::OpenAiCompatible
+                        .default_base_url()
+                        .to_string(),
+                    // Blank: use whichever model the server has loaded
+                    active_model: String::new(),
+                    system_prompt: None,
+                    input_price_per_mtok: None,
+                    output_price_per_mtok: None,
+                    context_window: None,
+                    ollama_endpoint: OllamaEndpoint::Auto,
+                    organization: None,
+                    project: None,
+                    connect_timeout_secs: None,
+                    request_timeout_secs: None,
                 },
                 ProviderConfig {
                     name: "DeepSeek".to_string(),
+                    provider_type: Some(ProviderKind::OpenAiCompatible),
                     api_key: None,
                     base_url: "https://api.deepseek.com/v1".to_string(),
                     active_model: "deepseek-reasoner".to_string(),
                     system_prompt: None,
+                    input_price_per_mtok: None,
+                    output_price_per_mtok: None,
+                    context_window: None,
+                    ollama_endpoint: OllamaEndpoint::Auto,
+                    organization: None,
+                    project: None,
+                    connect_timeout_secs: None,
+                    request_timeout_secs: None,
                 },
                 ProviderConfig {
                     name: "OpenAI".to_string(),
+                    provider_type: Some(ProviderKind::OpenAiCompatible),
                     api_key: None,
                     base_url: "https://api.openai.com/v1".to_string(),
                     active_model: "gpt-4o".to_string(),
                     system_prompt: None,
+                    input_price_per_mtok: None,
+                    output_price_per_mtok: None,
+                    context_window: None,
+                    ollama_endpoint: OllamaEndpoint::Auto,
+                    organization: None,
+                    project: None,
+                    connect_timeout_secs: None,
+                    request_timeout_secs: None,
                 },
             ],
             ai_history: Vec::new(),
+            preview_theme: PreviewTheme::default(),
             preview_dark_mode: true,
+            preview_debounce_ms: DEFAULT_PREVIEW_DEBOUNCE_MS,
+            compile_queue_depth: DEFAULT_COMPILE_QUEUE_DEPTH,
+            keep_build_files: false,
+            autosave_before_compile: false,
+            preview_scale: DEFAULT_PREVIEW_SCALE,
+            preview_layout: PreviewLayout::default(),
+            preview_backend: PreviewBackend::default(),
+            precompile_preamble: false,
+            preview_preamble: String::new(),
+            latex_engine: LatexEngine::default(),
+            shell_escape: false,
+            tex_bin_dir: None,
+            lint_before_compile: false,
+            compile_timeout_secs: COMPILE_TIMEOUT_SECS,
+            shell_escape_trusted_dirs: Vec::new(),
+            wrap_fragments: true,
+            preview_page_numbers: false,
+            preview_content_security_policy: None,
+            webkit_sandbox: WebKitSandbox::default(),
+            write_diagnostics: false,
+            png_copy_dpi: DEFAULT_PNG_COPY_DPI,
+            spell_check: true,
+            spell_language: DEFAULT_SPELL_LANGUAGE.to_string(),
+            ai_context_mode: AiContextMode::default(),
+            ai_prompt_preset: None,
+            collapse_reasoning: false,
+            max_reasoning_chars: DEFAULT_MAX_REASONING_CHARS,
+            ai_debug_log: false,
+            offline: false,
+            editor_font: DEFAULT_EDITOR_FONT.to_string(),
+            editor_font_size: DEFAULT_EDITOR_FONT_SIZE,
+            tab_width: DEFAULT_TAB_WIDTH,
+            insert_spaces: true,
+            show_line_numbers: true,
+            wrap_lines: false,
+            editor_color_scheme: None,
+            format_wrap_column: DEFAULT_FORMAT_WRAP_COLUMN,
+            format_align_tables: true,
+            todo_keywords: default_todo_keywords(),
+            project_search_include: default_project_search_include(),
+            project_search_exclude: Vec::new(),
+            aux_extensions: default_aux_extensions(),
         }
     }
 }
 
+/// `override_dir` as given, else `<xdg_config_home>/latex-rs` when that is an
+/// absolute path (relative values are invalid per the XDG spec), else the
+/// platform default.
+fn resolve_config_dir(
+    override_dir: Option<OsString>,
+    xdg_config_home: Option<OsString>,
+) -> PathBuf {
+    if let Some(dir) = override_dir.filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    let base = xdg_config_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(dirs::config_dir)
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join(CONFIG_DIR_NAME)
+}
+
 impl AppConfig {
+    /// Directory holding `config.toml`, templates, prompt presets and logs.
+    ///
+    /// `LATEX_RS_CONFIG_DIR` overrides it outright; otherwise `XDG_CONFIG_HOME`
+    /// is honoured on every platform before falling back to the system default.
     pub fn config_dir() -> PathBuf {
-        let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
-        path.push("latex-rs");
-        path
+        resolve_config_dir(
+            std::env::var_os(CONFIG_DIR_ENV),
+            std::env::var_os("XDG_CONFIG_HOME"),
+        )
     }
 
     pub fn config_file() -> PathBuf {
-        let mut path = Self::config_dir();
-        path.push("config.toml");
-        path
+        Self::config_dir().join("config.toml")
     }
 
     pub fn load() -> Self {
-        let path = Self::config_file();
-        if path.exists() {
-            if let Ok(content) = fs::read_to_string(&path) {
-                if let Ok(config) = toml::from_str(&content) {
-                    return config;
-                }
+        if Self::config_file().exists() {
+            if let Ok(config) = Self::read() {
+                return config;
             }
         }
         let default = Self::default();
@@ -85,13 +706,35 @@ impl AppConfig {
         default
     }
 
+    /// Parses the config file, failing instead of falling back to the defaults
+    /// so a broken file edited by hand is not overwritten.
+    pub fn read() -> anyhow::Result<Self> {
+        Self::read_from(&Self::config_dir())
+    }
+
+    fn read_from(dir: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(dir.join("config.toml"))?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut config: Self = toml::from_str(content)?;
+        if !config.preview_dark_mode && config.preview_theme == PreviewTheme::FollowSystem {
+            config.preview_theme = PreviewTheme::Light;
+        }
+        Ok(config)
+    }
+
     pub fn save(&self) -> anyhow::Result<()> {
-        let dir = Self::config_dir();
+        self.save_to(&Self::config_dir())
+    }
+
+    fn save_to(&self, dir: &Path) -> anyhow::Result<()> {
         if !dir.exists() {
-            fs::create_dir_all(&dir)?;
+            fs::create_dir_all(dir)?;
         }
         let content = toml::to_string_pretty(self)?;
-        fs::write(Self::config_file(), content)?;
+        fs::write(dir.join("config.toml"), content)?;
         Ok(())
     }
 
@@ -100,4 +743,153 @@ impl AppConfig {
             .iter()
             .find(|p| p.name == self.active_provider)
     }
+
+    /// `name`, or `name 2`, `name 3`, … if a provider is already called that.
+    pub fn unique_provider_name(&self, name: &str) -> String {
+        let taken = |candidate: &str| self.providers.iter().any(|p| p.name == candidate);
+        if !taken(name) {
+            return name.to_string();
+        }
+        (2..)
+            .map(|n| format!("{} {}", name, n))
+            .find(|candidate| !taken(candidate))
+            .unwrap()
+    }
+
+    /// Appends a provider of `kind`, named `name` (or the kind's label when
+    /// blank) made unique, and returns its index.
+    pub fn add_provider(&mut self, name: &str, kind: ProviderKind) -> usize {
+        let name = match name.trim() {
+            "" => kind.label(),
+            name => name,
+        };
+        let name = self.unique_provider_name(name);
+        self.providers.push(ProviderConfig::new(name, kind));
+        self.providers.len() - 1
+    }
+
+    /// Removes the provider at `index`, keeping at least one. If it was the
+    /// active one, the provider now at its position (or the last) becomes active.
+    pub fn remove_provider(&mut self, index: usize) -> Option<ProviderConfig> {
+        if self.providers.len() <= 1 || index >= self.providers.len() {
+            return None;
+        }
+        let removed = self.providers.remove(index);
+        if removed.name == self.active_provider {
+            let next = index.min(self.providers.len() - 1);
+            self.active_provider = self.providers[next].name.clone();
+        }
+        Some(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_kind_inferred_for_old_entries() {
+        let mut provider = AppConfig::default().providers[0].clone();
+        provider.provider_type = None;
+        assert_eq!(provider.kind(), ProviderKind::Ollama);
+        provider.name = "Ollama (lab)".to_string();
+        assert_eq!(provider.kind(), ProviderKind::OpenAiCompatible);
+        provider.provider_type = Some(ProviderKind::Ollama);
+        assert_eq!(provider.kind(), ProviderKind::Ollama);
+    }
+
+    #[test]
+    fn test_preview_theme_migrates_dark_mode_switch() {
+        let saved = toml::to_string_pretty(&AppConfig::default()).unwrap();
+        assert!(!saved.contains("preview_dark_mode"));
+        assert_eq!(
+            AppConfig::parse(&saved).unwrap().preview_theme,
+            PreviewTheme::FollowSystem
+        );
+
+        let old = format!("preview_dark_mode = false\n{}", saved);
+        assert_eq!(
+            AppConfig::parse(&old).unwrap().preview_theme,
+            PreviewTheme::Light
+        );
+        assert!(PreviewTheme::Dark.is_dark(false));
+        assert!(!PreviewTheme::Light.is_dark(true));
+    }
+
+    #[test]
+    fn test_add_provider_keeps_names_unique() {
+        let mut config = AppConfig::default();
+        let first = config.add_provider("  ", ProviderKind::Ollama);
+        let second = config.add_provider("Ollama", ProviderKind::Ollama);
+        let third = config.add_provider("llama.cpp", ProviderKind::OpenAiCompatible);
+        assert_eq!(config.providers[first].name, "Ollama 2");
+        assert_eq!(config.providers[second].name, "Ollama 3");
+        assert_eq!(config.providers[third].name, "llama.cpp");
+        assert_eq!(config.providers[third].base_url, "http://localhost:8080/v1");
+        assert_eq!(config.providers[second].kind(), ProviderKind::Ollama);
+    }
+
+    #[test]
+    fn test_remove_provider_keeps_active_valid() {
+        let mut config = AppConfig::default();
+        let names = |c: &AppConfig| {
+            c.providers
+                .iter()
+                .map(|p| p.name.clone())
+                .collect::<Vec<_>>()
+        };
+        config.active_provider = "DeepSeek".to_string();
+
+        assert_eq!(config.remove_provider(0).unwrap().name, "Ollama");
+        assert_eq!(config.active_provider, "DeepSeek");
+
+        assert_eq!(config.remove_provider(1).unwrap().name, "DeepSeek");
+        assert_eq!(config.active_provider, "OpenAI");
+        assert_eq!(config.remove_provider(0).unwrap().name, "Local (llama.cpp)");
+        assert_eq!(names(&config), ["OpenAI"]);
+
+        assert!(config.remove_provider(0).is_none());
+        assert!(config.remove_provider(5).is_none());
+        assert!(config.get_active_provider().is_some());
+    }
+
+    #[test]
+    fn test_webkit_sandbox_override() {
+        assert!(WebKitSandbox::Auto.should_disable(|| true));
+        assert!(!WebKitSandbox::Auto.should_disable(|| false));
+        assert!(WebKitSandbox::Disabled.should_disable(|| false));
+        assert!(!WebKitSandbox::Enabled.should_disable(|| true));
+    }
+
+    #[test]
+    fn test_resolve_config_dir() {
+        assert_eq!(
+            resolve_config_dir(Some("/portable/cfg".into()), Some("/xdg".into())),
+            PathBuf::from("/portable/cfg")
+        );
+        assert_eq!(
+            resolve_config_dir(Some("".into()), Some("/xdg".into())),
+            PathBuf::from("/xdg/latex-rs")
+        );
+        assert_eq!(
+            resolve_config_dir(None, Some("relative".into())),
+            resolve_config_dir(None, None)
+        );
+    }
+
+    #[test]
+    fn test_config_dir_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().join("portable");
+
+        let mut config = AppConfig::default();
+        config.tab_width = 7;
+        config.ai_history.push("shorten the abstract".to_string());
+        config.save_to(&config_dir).unwrap();
+
+        let loaded = AppConfig::read_from(&config_dir).unwrap();
+        assert_eq!(loaded.tab_width, 7);
+        assert_eq!(loaded.ai_history, ["shorten the abstract"]);
+        assert!(config_dir.join("config.toml").exists());
+    }
 }
@@ -15,6 +15,13 @@ pub const APP_ID: &str = "com.github.latex-rs";
 /// Application name displayed in window title.
 pub const APP_NAME: &str = "LaTeX.rs Editor";
 
+/// Folder under the platform config directory holding `config.toml`.
+pub const CONFIG_DIR_NAME: &str = "latex-rs";
+
+/// Environment variable naming a config directory to use instead, e.g. for
+/// portable installs or tests.
+pub const CONFIG_DIR_ENV: &str = "LATEX_RS_CONFIG_DIR";
+
 // ============================================================================
 // Window Configuration
 // ============================================================================
@@ -29,6 +36,9 @@ pub const DEFAULT_WINDOW_HEIGHT: i32 = 800;
 // Editor Configuration
 // ============================================================================
 
+/// Folder under the config directory holding user document templates (`*.tex`).
+pub const USER_TEMPLATES_DIR: &str = "templates";
+
 // ============================================================================
 // LaTeX Compilation
 // ============================================================================
@@ -41,27 +51,128 @@ pub const MAX_LATEX_SIZE_BYTES: usize = 10 * 1024 * 1024;
 /// Prevents hung processes from blocking the application.
 pub const COMPILE_TIMEOUT_SECS: u64 = 30;
 
+/// Most LaTeX runs per compile, including those after bibliography and index tools.
+pub const MAX_LATEX_PASSES: usize = 3;
+
+/// Per-project compile settings, read from the document's folder.
+pub const PROJECT_FILE_NAME: &str = ".latexrs.toml";
+
+/// Lines at the top of a document searched for `% !TEX` magic comments.
+pub const MAGIC_COMMENT_LINES: usize = 20;
+
+/// Folder (next to the document) that holds kept build artifacts.
+pub const BUILD_DIR_NAME: &str = ".latexrs-build";
+
+/// Extensions removed by Clean Auxiliary Files until the user configures others.
+pub const DEFAULT_AUX_EXTENSIONS: &[&str] = &[
+    "aux",
+    "log",
+    "bbl",
+    "blg",
+    "bcf",
+    "run.xml",
+    "out",
+    "toc",
+    "lof",
+    "lot",
+    "fls",
+    "fdb_latexmk",
+    "synctex.gz",
+    "synctex",
+    "idx",
+    "ind",
+    "ilg",
+    "glo",
+    "gls",
+    "glg",
+    "nav",
+    "snm",
+    "xdv",
+];
+
+/// Extensions Clean Auxiliary Files never deletes, whatever is configured:
+/// sources, bibliographies and the output.
+pub const PROTECTED_EXTENSIONS: &[&str] = &["tex", "bib", "pdf", "sty", "cls"];
+
+/// Folder under the user cache directory holding precompiled preamble formats.
+pub const PREAMBLE_FORMAT_DIR: &str = "formats";
+
+/// Number of precompiled preamble formats kept; each can take several megabytes.
+pub const PREAMBLE_FORMAT_CACHE_MAX: usize = 4;
+
 /// Polling interval for process timeout checking (milliseconds).
 /// Balances responsiveness vs CPU usage.
 pub const PROCESS_POLL_INTERVAL_MS: u64 = 100;
 
+/// Fallback preview page width in CSS pixels when the PDF page size is unknown.
+pub const DEFAULT_PAGE_WIDTH_PX: f64 = 850.0;
+
+/// CSS pixels per PDF point (96 px/in ÷ 72 pt/in).
+pub const PDF_POINTS_TO_CSS_PX: f64 = 96.0 / 72.0;
+
+/// TeX scaled points per PDF point (65536 sp/pt × 72.27 pt/in ÷ 72 bp/in),
+/// the unit of positions in SyncTeX files.
+pub const SCALED_POINTS_PER_BP: f64 = 65536.0 * 72.27 / 72.0;
+
+/// Default preview scale factor (1.0 = page shown at its natural size).
+pub const DEFAULT_PREVIEW_SCALE: f64 = 1.0;
+
+/// Minimum preview scale factor.
+pub const MIN_PREVIEW_SCALE: f64 = 0.5;
+
+/// Maximum preview scale factor.
+pub const MAX_PREVIEW_SCALE: f64 = 3.0;
+
+/// Source lines shown above and below each error on the preview's error page.
+pub const ERROR_CONTEXT_LINES: usize = 2;
+
+/// Errors shown with their source lines on the preview's error page; TeX
+/// often reports many follow-on errors after the first.
+pub const MAX_ERROR_EXCERPTS: usize = 5;
+
+/// Delay after the cursor stops moving before the preview marks its page.
+pub const CURSOR_PAGE_DELAY_MS: u64 = 300;
+
+/// Default resolution of pages copied as PNG (dots per inch).
+pub const DEFAULT_PNG_COPY_DPI: u32 = 150;
+
+/// Resolution range offered in settings for pages copied as PNG.
+pub const MIN_PNG_COPY_DPI: u32 = 72;
+pub const MAX_PNG_COPY_DPI: u32 = 600;
+
 // ============================================================================
 // Compilation Queue
 // ============================================================================
 
-/// Buffer size for the compilation queue channel.
-/// Size of 1 ensures only one compilation runs at a time,
-/// with new requests replacing pending ones.
-pub const COMPILATION_QUEUE_BUFFER: usize = 1;
+/// Default delay (milliseconds) after the last keystroke before the preview recompiles.
+/// Keeps rapid typing from kicking off a compile per burst.
+pub const DEFAULT_PREVIEW_DEBOUNCE_MS: u64 = 400;
+
+/// Upper bound accepted for the preview debounce delay in settings (milliseconds).
+pub const MAX_PREVIEW_DEBOUNCE_MS: u64 = 5000;
+
+/// Default number of compilations allowed to wait behind the running one.
+/// One always jumps straight to the latest edit, which keeps slow machines responsive.
+pub const DEFAULT_COMPILE_QUEUE_DEPTH: usize = 1;
+
+/// Largest queue depth offered in settings.
+pub const MAX_COMPILE_QUEUE_DEPTH: usize = 8;
+
+/// Progress events buffered for a slow listener before the oldest are dropped.
+pub const COMPILE_PROGRESS_CAPACITY: usize = 32;
 
 // ============================================================================
 // ============================================================================
 // AI Configuration
 // ============================================================================
 
-/// HTTP request timeout for AI operations.
+/// HTTP request timeout for AI operations. Streamed replies have no overall
+/// limit; this is the longest pause allowed between two of their chunks.
 pub const AI_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// How long to wait for an AI server to accept a connection.
+pub const DEFAULT_AI_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 // AI model temperature for LaTeX generation (lower = more deterministic).
 pub const AI_TEMPERATURE: f64 = 0.2;
 
@@ -71,6 +182,49 @@ pub const AI_TOP_P: f64 = 0.9;
 /// AI model random seed for reproducible outputs.
 pub const AI_SEED: u64 = 42;
 
+/// Folder under the config directory holding user system-prompt presets (`*.txt`).
+pub const USER_PROMPTS_DIR: &str = "prompts";
+
+/// Rough characters per token used to estimate prompt size.
+pub const AI_CHARS_PER_TOKEN: usize = 4;
+
+/// Context window assumed when a provider does not configure one (tokens).
+pub const DEFAULT_AI_CONTEXT_TOKENS: usize = 8192;
+
+/// Part of the context window kept free for the model's reply (tokens).
+pub const AI_RESPONSE_TOKEN_RESERVE: usize = 2048;
+
+/// How often an unavailable AI provider is checked again (seconds).
+pub const AI_STATUS_RECHECK_SECS: u32 = 60;
+
+/// Reasoning kept per AI reply by default, in characters; the rest is dropped.
+pub const DEFAULT_MAX_REASONING_CHARS: usize = 20_000;
+
+/// Appended to the reasoning view once a reply's reasoning reaches the limit.
+pub const REASONING_TRUNCATED_MARKER: &str = "\n… (reasoning truncated)";
+
+/// Characters of an unrecognized streamed chunk quoted in the warning log.
+pub const STREAM_LOG_SNIPPET_CHARS: usize = 200;
+
+/// Tracing target of the optional AI request/response log.
+pub const AI_DEBUG_LOG_TARGET: &str = "ai_debug";
+
+/// Folder under the config directory holding the AI debug log files.
+pub const AI_DEBUG_LOG_DIR: &str = "logs";
+
+/// Number of daily AI debug log files kept before the oldest is deleted.
+pub const AI_DEBUG_LOG_MAX_FILES: usize = 7;
+
+// ============================================================================
+// arXiv Search
+// ============================================================================
+
+/// Results fetched per arXiv query; "Load more" fetches the next page.
+pub const ARXIV_PAGE_SIZE: usize = 15;
+
+/// Minimum gap between arXiv API requests, as its terms of use ask.
+pub const ARXIV_REQUEST_INTERVAL: Duration = Duration::from_secs(3);
+
 // ============================================================================
 // Security
 // ============================================================================
@@ -84,6 +238,10 @@ pub const WEBKIT_SANDBOX_DISABLE_VAR: &str = "WEBKIT_DISABLE_SANDBOX_THIS_IS_DAN
 /// WebKit sandbox disable environment variable (modern).
 pub const WEBKIT_SANDBOX_DISABLE_VAR_MODERN: &str = "WEBKIT_DISABLE_SANDBOX";
 
+/// Content-Security-Policy of preview pages: no scripts, inline styles only.
+pub const DEFAULT_PREVIEW_CSP: &str =
+    "default-src 'self'; script-src 'none'; style-src 'unsafe-inline';";
+
 // ============================================================================
 // UI Configuration
 // ============================================================================
@@ -94,6 +252,22 @@ pub const DEFAULT_EDITOR_FONT: &str = "Monospace";
 /// Default editor font size in points.
 pub const DEFAULT_EDITOR_FONT_SIZE: i32 = 12;
 
+/// Smallest and largest editor font sizes offered in settings (points).
+pub const MIN_EDITOR_FONT_SIZE: i32 = 6;
+pub const MAX_EDITOR_FONT_SIZE: i32 = 72;
+
+/// Default indent width in columns.
+pub const DEFAULT_TAB_WIDTH: u32 = 4;
+
+/// Largest indent width offered in settings.
+pub const MAX_TAB_WIDTH: u32 = 16;
+
+/// Default column at which Format Document wraps long lines.
+pub const DEFAULT_FORMAT_WRAP_COLUMN: usize = 80;
+
+/// Largest wrap column offered in settings.
+pub const MAX_FORMAT_WRAP_COLUMN: usize = 200;
+
 /// Default zoom level (1.0 = 100%).
 pub const DEFAULT_ZOOM_LEVEL: f64 = 1.0;
 
@@ -105,3 +279,55 @@ pub const MAX_ZOOM_LEVEL: f64 = 3.0;
 
 /// Zoom step for keyboard shortcuts.
 pub const ZOOM_STEP: f64 = 0.1;
+
+/// Spell-check language used until the user picks another.
+pub const DEFAULT_SPELL_LANGUAGE: &str = "en_US";
+
+/// Delay after the last edit before the document is spell checked again.
+pub const SPELL_CHECK_DELAY_MS: u64 = 500;
+
+/// Delay after the last edit before braces and environments are checked again.
+pub const LINT_DELAY_MS: u64 = 300;
+
+/// Source mark category of the gutter icons for structural problems.
+pub const LINT_MARK_CATEGORY: &str = "lint";
+
+/// Directories searched for Hunspell `.dic`/`.aff` dictionaries.
+pub const HUNSPELL_DICT_DIRS: &[&str] = &[
+    "/usr/share/hunspell",
+    "/usr/share/myspell",
+    "/usr/share/myspell/dicts",
+];
+
+/// Comment keywords listed in the TODO sidebar until the user configures others.
+pub const DEFAULT_TODO_KEYWORDS: &[&str] = &["TODO", "FIXME", "XXX"];
+
+/// Delay after the last edit before TODO comments are collected again.
+pub const TODO_SCAN_DELAY_MS: u64 = 300;
+
+/// How long the partner of a `\begin`/`\end` stays highlighted after a jump.
+pub const ENVIRONMENT_MATCH_FLASH_MS: u64 = 600;
+
+/// Lines longer than this many bytes (e.g. pasted minified output) make the
+/// editor wrap by character and drop syntax highlighting and spell checking,
+/// which otherwise slow GTK down badly on a single huge line.
+pub const LONG_LINE_BYTES: usize = 20_000;
+
+/// Cursor positions remembered for Navigate Back (`Alt+Left`).
+pub const NAVIGATION_HISTORY_MAX: usize = 50;
+
+/// Files searched by Search in Project until the user configures other globs.
+pub const DEFAULT_PROJECT_SEARCH_INCLUDE: &[&str] = &["*.tex"];
+
+/// Most matching lines Search in Project lists, so a common word in a large
+/// project does not build thousands of rows.
+pub const MAX_PROJECT_SEARCH_HITS: usize = 500;
+
+/// File extensions opened as documents when dropped on the window.
+pub const DROP_DOCUMENT_EXTENSIONS: &[&str] = &["tex", "txt"];
+
+/// Image extensions inserted as `\includegraphics` when dropped on the editor.
+pub const DROP_IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "pdf", "eps", "svg"];
+
+/// Longest caption-derived slug used in generated `\label`s.
+pub const MAX_LABEL_SLUG_LEN: usize = 40;
@@ -0,0 +1,233 @@
+//! Machine-readable compile diagnostics: errors and warnings pulled out of a
+//! pdflatex log and written as a JSON array for editors and other tools, or
+//! shown with the source lines around them on the preview's error page.
+
+use crate::constants::{ERROR_CONTEXT_LINES, MAX_ERROR_EXCERPTS};
+use crate::preview::{LineMap, Preview};
+use regex::Regex;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One problem reported by TeX, serialized as `{ file, line, col, severity, message }`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub file: String,
+    /// 1-based source line, when TeX reported one.
+    pub line: Option<usize>,
+    /// 1-based column where TeX stopped reading, only known for errors.
+    pub col: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// An error with the lines of the user's source around where TeX stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceExcerpt {
+    pub message: String,
+    /// 1-based line of the error in the user's source.
+    pub line: usize,
+    /// The error line and up to [`ERROR_CONTEXT_LINES`] on either side, with
+    /// their 1-based numbers.
+    pub lines: Vec<(usize, String)>,
+}
+
+/// Where a compilation writes its diagnostics.
+#[derive(Debug, Clone)]
+pub struct DiagnosticsOutput {
+    /// JSON file, overwritten after every compile.
+    pub path: PathBuf,
+    /// Reported as `file` in each diagnostic, usually the document's path.
+    pub source: String,
+}
+
+fn error_line_regex() -> &'static Regex {
+    static ERROR_LINE_REGEX: OnceLock<Regex> = OnceLock::new();
+    ERROR_LINE_REGEX.get_or_init(|| Regex::new(r"^l\.(\d+) ?(.*)$").unwrap())
+}
+
+fn warning_line_regex() -> &'static Regex {
+    static WARNING_LINE_REGEX: OnceLock<Regex> = OnceLock::new();
+    WARNING_LINE_REGEX.get_or_init(|| Regex::new(r"on input line (\d+)").unwrap())
+}
+
+/// Collects the errors (`! …` followed by an `l.<n>` context line) and the
+/// LaTeX/package warnings from a pdflatex log, errors first.
+pub fn parse_log(log: &str, source: &str) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = log.lines().collect();
+    let mut diagnostics = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let Some(message) = line.strip_prefix("! ") else {
+            continue;
+        };
+        // The context line follows within a few lines, before the next error
+        let context = lines[i + 1..]
+            .iter()
+            .take_while(|l| !l.starts_with("! "))
+            .find_map(|l| error_line_regex().captures(l));
+        let (line, col) = match context {
+            Some(caps) => (caps[1].parse().ok(), Some(caps[2].chars().count() + 1)),
+            None => (None, None),
+        };
+        diagnostics.push(Diagnostic {
+            file: source.to_string(),
+            line,
+            col,
+            severity: Severity::Error,
+            message: message.trim().to_string(),
+        });
+    }
+
+    for warning in Preview::extract_warnings(log) {
+        diagnostics.push(Diagnostic {
+            file: source.to_string(),
+            line: warning_line_regex()
+                .captures(&warning)
+                .and_then(|caps| caps[1].parse().ok()),
+            col: None,
+            severity: Severity::Warning,
+            message: warning,
+        });
+    }
+
+    diagnostics
+}
+
+/// Parses `log` and writes the result to `output.path` as a JSON array, with
+/// lines mapped back to the user's buffer through `line_map`.
+pub fn write(output: &DiagnosticsOutput, log: &str, line_map: LineMap) -> std::io::Result<()> {
+    let mut diagnostics = parse_log(log, &output.source);
+    for diagnostic in &mut diagnostics {
+        diagnostic.line = diagnostic.line.and_then(|line| line_map.to_user(line));
+        if diagnostic.line.is_none() {
+            diagnostic.col = None;
+        }
+    }
+    let json = serde_json::to_string_pretty(&diagnostics)?;
+    if let Some(parent) = output.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&output.path, json)
+}
+
+/// The first [`MAX_ERROR_EXCERPTS`] errors in `log` that point into `source`,
+/// with its surrounding lines. `line_map` relates the compiled lines to
+/// `source`; errors in injected lines or in other files' line ranges are skipped.
+pub fn source_excerpts(log: &str, source: &str, line_map: LineMap) -> Vec<SourceExcerpt> {
+    let source_lines: Vec<&str> = source.lines().collect();
+    parse_log(log, "")
+        .into_iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .filter_map(|diagnostic| {
+            let line = line_map.to_user(diagnostic.line?)?;
+            let index = line.checked_sub(1).filter(|i| *i < source_lines.len())?;
+            let first = index.saturating_sub(ERROR_CONTEXT_LINES);
+            let last = (index + ERROR_CONTEXT_LINES).min(source_lines.len() - 1);
+            Some(SourceExcerpt {
+                message: diagnostic.message,
+                line,
+                lines: (first..=last)
+                    .map(|i| (i + 1, source_lines[i].to_string()))
+                    .collect(),
+            })
+        })
+        .take(MAX_ERROR_EXCERPTS)
+        .collect()
+}
+
+/// Default diagnostics location for a saved document: `<build dir>/<stem>.diagnostics.json`.
+pub fn path_for(document: &Path, build_dir_name: &str) -> Option<PathBuf> {
+    let stem = document.file_stem()?.to_string_lossy();
+    Some(
+        document
+            .parent()?
+            .join(build_dir_name)
+            .join(format!("{}.diagnostics.json", stem)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_errors_and_warnings() {
+        let log = "(./doc.tex\n\
+! Undefined control sequence.\n\
+l.5 \\foo\n\
+         bar\n\
+LaTeX Warning: Reference `fig:x' on page 1 undefined on input line 12.\n\
+\n\
+! Missing $ inserted.\n\
+<inserted text> \n\
+                $\n\
+l.9 a^\n\
+      b\n\
+! Emergency stop.\n";
+        let diagnostics = parse_log(log, "paper.tex");
+        assert_eq!(diagnostics.len(), 4);
+        assert_eq!(
+            diagnostics[0],
+            Diagnostic {
+                file: "paper.tex".to_string(),
+                line: Some(5),
+                col: Some(5),
+                severity: Severity::Error,
+                message: "Undefined control sequence.".to_string(),
+            }
+        );
+        assert_eq!(diagnostics[1].line, Some(9));
+        assert_eq!(diagnostics[1].col, Some(3));
+        assert_eq!(diagnostics[2].message, "Emergency stop.");
+        assert_eq!(diagnostics[2].line, None);
+        assert_eq!(diagnostics[3].severity, Severity::Warning);
+        assert_eq!(diagnostics[3].line, Some(12));
+        assert_eq!(diagnostics[3].col, None);
+    }
+
+    #[test]
+    fn test_diagnostics_json_shape() {
+        let json = serde_json::to_value(parse_log("! Oops.\nl.3 x\n", "a.tex")).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                { "file": "a.tex", "line": 3, "col": 2, "severity": "error", "message": "Oops." }
+            ])
+        );
+    }
+
+    #[test]
+    fn test_source_excerpts() {
+        let source = "one\ntwo\nthree\n\\foo\nfive\nsix\nseven\n";
+        let log = "! Undefined control sequence.\nl.4 \\foo\n! Emergency stop.\n";
+        let excerpts = source_excerpts(log, source, LineMap::default());
+        assert_eq!(excerpts.len(), 1);
+        assert_eq!(excerpts[0].message, "Undefined control sequence.");
+        assert_eq!(excerpts[0].line, 4);
+        let numbers: Vec<usize> = excerpts[0].lines.iter().map(|(n, _)| *n).collect();
+        assert_eq!(numbers, vec![2, 3, 4, 5, 6]);
+        assert_eq!(excerpts[0].lines[2].1, "\\foo");
+
+        // Clipped at the start of the file; lines past its end are ignored
+        let excerpts = source_excerpts("! A.\nl.1 x\n! B.\nl.99 y\n", source, LineMap::default());
+        assert_eq!(excerpts.len(), 1);
+        assert_eq!(excerpts[0].lines.first().unwrap().0, 1);
+        assert_eq!(excerpts[0].lines.last().unwrap().0, 3);
+    }
+
+    #[test]
+    fn test_path_for() {
+        assert_eq!(
+            path_for(Path::new("/docs/paper.tex"), ".build"),
+            Some(PathBuf::from("/docs/.build/paper.diagnostics.json"))
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Line diffs between an AI suggestion and the text it replaces, so changes
+//! can be reviewed and applied hunk by hunk.
+
+use diffy::{Line, Patch};
+
+/// One line of a hunk as shown in the review dialog.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffHunk {
+    /// Zero-based index of the first original line covered by the hunk.
+    old_start: usize,
+    old_len: usize,
+    /// Unified-diff range header, e.g. `@@ -3,4 +3,5 @@`.
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+impl DiffHunk {
+    /// The hunk's lines with the new side applied.
+    fn replacement(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            DiffLine::Context(text) | DiffLine::Added(text) => Some(text.as_str()),
+            DiffLine::Removed(_) => None,
+        })
+    }
+}
+
+/// Splits `original` → `suggestion` into reviewable hunks.
+pub fn diff_hunks(original: &str, suggestion: &str) -> Vec<DiffHunk> {
+    let patch = diffy::create_patch(original, suggestion);
+    patch
+        .hunks()
+        .iter()
+        .map(|hunk| {
+            let old = hunk.old_range();
+            // Unified ranges are 1-based, except that an empty range names the line before it
+            let old_start = if old.is_empty() {
+                old.start()
+            } else {
+                old.start() - 1
+            };
+            DiffHunk {
+                old_start,
+                old_len: old.len(),
+                header: format!("@@ -{} +{} @@", old, hunk.new_range()),
+                lines: hunk
+                    .lines()
+                    .iter()
+                    .map(|line| match *line {
+                        Line::Context(text) => DiffLine::Context(text.to_string()),
+                        Line::Delete(text) => DiffLine::Removed(text.to_string()),
+                        Line::Insert(text) => DiffLine::Added(text.to_string()),
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+/// Rebuilds `original` with only the hunks whose `accepted` flag is set applied.
+///
+/// `hunks` must come from [`diff_hunks`] for the same `original`; missing
+/// flags count as rejected.
+pub fn apply_hunks(original: &str, hunks: &[DiffHunk], accepted: &[bool]) -> String {
+    let lines: Vec<&str> = original.split_inclusive('\n').collect();
+    let mut result = String::with_capacity(original.len());
+    let mut pos = 0;
+    for (i, hunk) in hunks.iter().enumerate() {
+        let end = (hunk.old_start + hunk.old_len).min(lines.len());
+        let start = hunk.old_start.clamp(pos, end);
+        result.extend(lines[pos..start].iter().copied());
+        if accepted.get(i).copied().unwrap_or(false) {
+            result.extend(hunk.replacement());
+        } else {
+            result.extend(lines[start..end].iter().copied());
+        }
+        pos = end;
+    }
+    result.extend(lines[pos..].iter().copied());
+    result
+}
+
+/// Turns a raw AI response into the text that should replace `original`.
+///
+/// Responses that are a unified diff applying cleanly to `original` are
+/// applied; anything else is treated as LaTeX and stripped of Markdown fences.
+pub fn resolve_suggestion(original: &str, response: &str) -> String {
+    if let Ok(patch) = Patch::from_str(response.trim_start()) {
+        if !patch.hunks().is_empty() {
+            if let Ok(patched) = diffy::apply(original, &patch) {
+                return patched;
+            }
+        }
+    }
+    crate::utils::extract_latex(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+
+    #[test]
+    fn test_apply_selected_hunks() {
+        let suggestion = "A\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nL\n";
+        let hunks = diff_hunks(ORIGINAL, suggestion);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!(hunks[0].header, "@@ -1,4 +1,4 @@");
+        assert!(hunks[0]
+            .lines
+            .contains(&DiffLine::Removed("a\n".to_string())));
+
+        assert_eq!(apply_hunks(ORIGINAL, &hunks, &[true, true]), suggestion);
+        assert_eq!(apply_hunks(ORIGINAL, &hunks, &[false, false]), ORIGINAL);
+        assert_eq!(
+            apply_hunks(ORIGINAL, &hunks, &[true, false]),
+            "A\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n"
+        );
+        // Missing flags count as rejected
+        assert_eq!(apply_hunks(ORIGINAL, &hunks, &[]), ORIGINAL);
+    }
+
+    #[test]
+    fn test_apply_hunks_insertions_and_missing_newline() {
+        let hunks = diff_hunks("", "new\n");
+        assert_eq!(apply_hunks("", &hunks, &[true]), "new\n");
+
+        let original = "x\ny";
+        let suggestion = "x\ny\nz";
+        let hunks = diff_hunks(original, suggestion);
+        assert_eq!(apply_hunks(original, &hunks, &[true]), suggestion);
+        assert_eq!(apply_hunks(original, &hunks, &[false]), original);
+    }
+
+    #[test]
+    fn test_resolve_suggestion() {
+        let patch = "--- original\n+++ modified\n@@ -1,2 +1,2 @@\n a\n-b\n+B\n";
+        assert_eq!(resolve_suggestion("a\nb\n", patch), "a\nB\n");
+        // Em dashes and stray rules are LaTeX, not patches
+        let latex = "Text --- more text\n";
+        assert_eq!(resolve_suggestion("x\n", latex), "Text --- more text");
+        assert_eq!(resolve_suggestion("x\n", "```latex\n\\foo\n```"), "\\foo");
+    }
+}
@@ -0,0 +1,339 @@
+//! LaTeX source formatter: re-indents nested environments, wraps long prose
+//! lines and aligns `&` columns. Verbatim-like environments are left untouched.
+
+/// Environments whose body is copied as-is.
+pub(crate) const VERBATIM_ENVIRONMENTS: &[&str] = &[
+    "verbatim",
+    "verbatim*",
+    "Verbatim",
+    "lstlisting",
+    "minted",
+    "comment",
+];
+
+/// Environments whose rows are aligned on `&`.
+const ALIGNMENT_ENVIRONMENTS: &[&str] = &[
+    "tabular",
+    "tabular*",
+    "tabularx",
+    "array",
+    "align",
+    "align*",
+    "alignat",
+    "alignat*",
+    "eqnarray",
+    "eqnarray*",
+    "matrix",
+    "pmatrix",
+    "bmatrix",
+    "vmatrix",
+    "cases",
+];
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// One level of indentation, e.g. four spaces or a tab.
+    pub indent: String,
+    /// Wrap prose lines longer than this many characters; `0` disables wrapping.
+    pub wrap_column: usize,
+    pub align_tables: bool,
+}
+
+/// The part of `line` before an unescaped `%`.
+fn strip_comment(line: &str) -> &str {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '%' if !escaped => return &line[..i],
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    line
+}
+
+/// Names of the environments opened (`\begin`) or closed (`\end`) in `code`, in order.
+fn environments<'a>(code: &'a str, command: &str) -> Vec<&'a str> {
+    let marker = format!("\\{}{{", command);
+    let mut names = Vec::new();
+    let mut rest = code;
+    while let Some(pos) = rest.find(&marker) {
+        rest = &rest[pos + marker.len()..];
+        if let Some(end) = rest.find('}') {
+            names.push(&rest[..end]);
+            rest = &rest[end + 1..];
+        }
+    }
+    names
+}
+
+/// Environments that change the indentation level; `document` is not indented.
+fn indenting(names: Vec<&str>) -> usize {
+    names.into_iter().filter(|n| *n != "document").count()
+}
+
+/// How many `\end{…}` commands `line` starts with.
+fn leading_ends(line: &str) -> usize {
+    let mut count = 0;
+    let mut rest = line.trim_start();
+    while let Some(after) = rest.strip_prefix("\\end{") {
+        let Some(close) = after.find('}') else { break };
+        if &after[..close] != "document" {
+            count += 1;
+        }
+        rest = after[close + 1..].trim_start();
+    }
+    count
+}
+
+/// Splits a row at `&` outside braces, ignoring `\&`.
+fn split_cells(row: &str) -> Vec<&str> {
+    let mut cells = Vec::new();
+    let mut depth = 0i32;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in row.char_indices() {
+        match c {
+            '\\' => {
+                escaped = !escaped;
+                continue;
+            }
+            '{' if !escaped => depth += 1,
+            '}' if !escaped => depth -= 1,
+            '&' if !escaped && depth == 0 => {
+                cells.push(row[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+        escaped = false;
+    }
+    cells.push(row[start..].trim());
+    cells
+}
+
+/// Pads cells so the `&` separators of consecutive rows line up. Rows without
+/// `&` (rules, comments) are kept as they are.
+fn align_rows(rows: &[&str]) -> Vec<String> {
+    let cells: Vec<Option<Vec<&str>>> = rows
+        .iter()
+        .map(|row| {
+            let row = row.trim();
+            (row.contains('&') && !row.contains('%')).then(|| split_cells(row))
+        })
+        .collect();
+
+    let mut widths: Vec<usize> = Vec::new();
+    for row in cells.iter().flatten() {
+        // The last cell is never padded, so it doesn't set a width
+        for (col, cell) in row.iter().enumerate().take(row.len() - 1) {
+            let width = cell.chars().count();
+            match widths.get_mut(col) {
+                Some(w) => *w = (*w).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    rows.iter()
+        .zip(cells)
+        .map(|(row, cells)| match cells {
+            None => row.trim().to_string(),
+            Some(cells) => {
+                let last = cells.len() - 1;
+                cells
+                    .iter()
+                    .enumerate()
+                    .map(|(col, cell)| {
+                        if col == last {
+                            cell.to_string()
+                        } else {
+                            format!("{:width$}", cell, width = widths[col])
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" & ")
+                    .trim_end()
+                    .to_string()
+            }
+        })
+        .collect()
+}
+
+/// Breaks `line` at spaces so each piece fits in `column` characters after `indent`.
+fn wrap_line(line: &str, indent: &str, column: usize, out: &mut Vec<String>) {
+    // Comments and inline verbatim would change meaning if split
+    let wrappable = column > 0 && !line.contains('%') && !line.contains("\\verb");
+    if !wrappable || indent.chars().count() + line.chars().count() <= column {
+        out.push(format!("{}{}", indent, line));
+        return;
+    }
+
+    let mut current = String::new();
+    for word in line.split_whitespace() {
+        // A line starting with `[` after `\\` would be read as its optional argument
+        let too_long =
+            indent.chars().count() + current.chars().count() + 1 + word.chars().count() > column;
+        if !current.is_empty() && too_long && !word.starts_with('[') {
+            out.push(format!("{}{}", indent, current));
+            current.clear();
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        out.push(format!("{}{}", indent, current));
+    }
+}
+
+/// Formats a whole document.
+pub fn format_latex(text: &str, options: &FormatOptions) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let indent = |depth: usize| options.indent.repeat(depth);
+    let mut out: Vec<String> = Vec::with_capacity(lines.len());
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let trimmed = lines[i].trim();
+        let code = strip_comment(trimmed);
+        let opened = environments(code, "begin");
+
+        if let Some(env) = opened
+            .iter()
+            .find(|env| VERBATIM_ENVIRONMENTS.contains(env))
+        {
+            let end_marker = format!("\\end{{{}}}", env);
+            out.push(format!("{}{}", indent(depth), trimmed));
+            i += 1;
+            if trimmed.contains(&end_marker) {
+                continue;
+            }
+            while i < lines.len() && !lines[i].contains(&end_marker) {
+                out.push(lines[i].to_string());
+                i += 1;
+            }
+            if i < lines.len() {
+                out.push(format!("{}{}", indent(depth), lines[i].trim()));
+                i += 1;
+            }
+            continue;
+        }
+
+        if options.align_tables {
+            let block_end = opened
+                .last()
+                .filter(|env| ALIGNMENT_ENVIRONMENTS.contains(env))
+                .filter(|env| !environments(code, "end").contains(*env))
+                .and_then(|env| {
+                    let end_marker = format!("\\end{{{}}}", env);
+                    let j = i
+                        + 1
+                        + lines[i + 1..]
+                            .iter()
+                            .position(|l| l.contains(&end_marker))?;
+                    // Nested environments are indented normally instead
+                    let nested = lines[i + 1..j]
+                        .iter()
+                        .any(|l| l.contains("\\begin{") || l.contains("\\end{"));
+                    (!nested).then_some(j)
+                });
+            if let Some(j) = block_end {
+                let inner = (depth + indenting(opened))
+                    .saturating_sub(indenting(environments(code, "end")));
+                let closes = leading_ends(lines[j]);
+                out.push(format!("{}{}", indent(depth), trimmed));
+                for row in align_rows(&lines[i + 1..j]) {
+                    if row.is_empty() {
+                        out.push(String::new());
+                    } else {
+                        out.push(format!("{}{}", indent(inner), row));
+                    }
+                }
+                let after = inner.saturating_sub(closes);
+                out.push(format!("{}{}", indent(after), lines[j].trim()));
+                let code = strip_comment(lines[j].trim());
+                depth = (after + indenting(environments(code, "begin")))
+                    .saturating_sub(indenting(environments(code, "end")) - closes);
+                i = j + 1;
+                continue;
+            }
+        }
+
+        if trimmed.is_empty() {
+            out.push(String::new());
+        } else {
+            let print_depth = depth.saturating_sub(leading_ends(trimmed));
+            wrap_line(trimmed, &indent(print_depth), options.wrap_column, &mut out);
+        }
+        depth = (depth + indenting(opened)).saturating_sub(indenting(environments(code, "end")));
+        i += 1;
+    }
+
+    let mut result = out.join("\n");
+    if text.ends_with('\n') {
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(wrap_column: usize) -> FormatOptions {
+        FormatOptions {
+            indent: "  ".to_string(),
+            wrap_column,
+            align_tables: true,
+        }
+    }
+
+    #[test]
+    fn test_format_indents_environments() {
+        let text = "\\begin{document}\n\\begin{itemize}\n\\item A\n   \\begin{enumerate}\n\\item B\n\\end{enumerate}\n\\end{itemize}\n\\end{document}\n";
+        assert_eq!(
+            format_latex(text, &options(0)),
+            "\\begin{document}\n\\begin{itemize}\n  \\item A\n  \\begin{enumerate}\n    \\item B\n  \\end{enumerate}\n\\end{itemize}\n\\end{document}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_verbatim() {
+        let text =
+            "\\begin{center}\n\\begin{verbatim}\n   keep   this\n\\end{verbatim}\n\\end{center}";
+        assert_eq!(
+            format_latex(text, &options(10)),
+            "\\begin{center}\n  \\begin{verbatim}\n   keep   this\n  \\end{verbatim}\n\\end{center}"
+        );
+    }
+
+    #[test]
+    fn test_format_wraps_prose() {
+        let text = "one two three four five six % keep\nalpha beta gamma delta";
+        assert_eq!(
+            format_latex(text, &options(12)),
+            "one two three four five six % keep\nalpha beta\ngamma delta"
+        );
+    }
+
+    #[test]
+    fn test_format_aligns_tables() {
+        let text = "\\begin{tabular}{ll}\n\\hline\nA & Long header \\\\\nLonger & x\\&y \\\\\n\\end{tabular}\nafter";
+        assert_eq!(
+            format_latex(text, &options(0)),
+            "\\begin{tabular}{ll}\n  \\hline\n  A      & Long header \\\\\n  Longer & x\\&y \\\\\n\\end{tabular}\nafter"
+        );
+
+        let plain = FormatOptions {
+            align_tables: false,
+            ..options(0)
+        };
+        assert_eq!(
+            format_latex(text, &plain),
+            "\\begin{tabular}{ll}\n  \\hline\n  A & Long header \\\\\n  Longer & x\\&y \\\\\n\\end{tabular}\nafter"
+        );
+    }
+}
@@ -1,28 +1,41 @@
 mod api;
+mod bib;
 mod config;
 mod constants;
+mod diagnostics;
+mod diff;
+mod format;
+mod navigation;
 mod preview;
+mod project;
+mod project_search;
 mod queue;
+mod spell;
 mod state;
+mod symbols;
+mod templates;
 mod ui;
 mod utils;
 
-use crate::api::{AiChunk, Message, MessageRole};
-use crate::config::AppConfig;
+use crate::api::prompt::build_edit_prompt;
+use crate::api::AiChunk;
+use crate::config::{AiContextMode, AppConfig, PreviewBackend, WebKitSandbox};
 use crate::constants::{
-    APP_ID, APP_NAME, DEFAULT_WINDOW_HEIGHT,
-    DEFAULT_WINDOW_WIDTH, DEFAULT_ZOOM_LEVEL, WEBKIT_SANDBOX_DISABLE_VAR,
+    AI_SEED, AI_STATUS_RECHECK_SECS, APP_ID, APP_NAME, DEFAULT_AI_CONTEXT_TOKENS,
+    DEFAULT_WINDOW_HEIGHT, DEFAULT_WINDOW_WIDTH, DEFAULT_ZOOM_LEVEL, WEBKIT_SANDBOX_DISABLE_VAR,
     WEBKIT_SANDBOX_DISABLE_VAR_MODERN, WSL_INTEROP_ENV,
 };
-use crate::preview::Preview;
+use crate::diagnostics::DiagnosticsOutput;
+use crate::preview::{CompileOptions, Preview};
 use crate::state::AppState;
-use crate::ui::{ai, editor, file_ops, header, layout, webview};
+use crate::ui::{ai, citation, diff_review, document_settings, editor, file_ops, header, insert, layout, palette, sidebar, statistics, symbol_picker, webview};
 use adw::prelude::*;
 use adw::{Application, ApplicationWindow};
 use futures::StreamExt;
-use gtk4::{gdk, glib, Box, Orientation};
+use gtk4::{gdk, gio, glib, Box, Orientation};
 use sourceview5::prelude::*;
 use std::cell::RefCell;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 /// Detects if running in an environment that requires WebKit sandbox to be disabled.
@@ -99,14 +112,147 @@ fn needs_webkit_sandbox_disabled() -> bool {
     false
 }
 
+/// What the process was asked to do on the command line.
+enum CliCommand {
+    /// Start the editor; any arguments are left for GTK.
+    Gui,
+    /// `--compile <in.tex> <out.pdf> [--diagnostics <out.json>]`: compile
+    /// without building the UI.
+    Compile {
+        input: PathBuf,
+        output: PathBuf,
+        diagnostics: Option<PathBuf>,
+    },
+}
+
+fn parse_cli_args(args: &[String]) -> Result<CliCommand, String> {
+    match args.get(1).map(String::as_str) {
+        Some("--compile") => match &args[2..] {
+            [input, output] => Ok(CliCommand::Compile {
+                input: PathBuf::from(input),
+                output: PathBuf::from(output),
+                diagnostics: None,
+            }),
+            [input, output, flag, json] if flag == "--diagnostics" => Ok(CliCommand::Compile {
+                input: PathBuf::from(input),
+                output: PathBuf::from(output),
+                diagnostics: Some(PathBuf::from(json)),
+            }),
+            _ => Err(format!(
+                "Usage: {} --compile <in.tex> <out.pdf> [--diagnostics <out.json>]",
+                args[0]
+            )),
+        },
+        _ => Ok(CliCommand::Gui),
+    }
+}
+
+/// Compiles `input` to `output` with the preview engine, printing warnings and
+/// errors to stderr and, if asked, writing them to a JSON diagnostics file.
+fn run_headless_compile(
+    input: &Path,
+    output: &Path,
+    diagnostics: Option<PathBuf>,
+) -> glib::ExitCode {
+    let latex = match crate::utils::open_file(input) {
+        Ok(file) => file.contents,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", input.display(), e);
+            return glib::ExitCode::FAILURE;
+        }
+    };
+
+    // A `% !TEX root` or the project's main file compiles that document
+    // instead, like the preview does
+    let magic = crate::project::MagicComments::parse(&latex);
+    let project = crate::project::ProjectSettings::load(input);
+    let main = magic
+        .root_for(input)
+        .or_else(|| project.main_file_for(input));
+    let latex = match &main {
+        Some(main) => match crate::utils::open_file(main) {
+            Ok(file) => file.contents,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", main.display(), e);
+                return glib::ExitCode::FAILURE;
+            }
+        },
+        None => latex,
+    };
+    let input_dir = main
+        .as_deref()
+        .unwrap_or(input)
+        .parent()
+        .map(Path::to_path_buf);
+
+    // Only the PDF is written, so skip the SVG page conversion. The magic
+    // comment's or project's engine and the project's timeout apply, but never
+    // its shell escape. Files are looked up next to the compiled document.
+    if let Ok(config) = AppConfig::read() {
+        crate::preview::set_tex_bin_dir(config.tex_bin_dir);
+    }
+    let defaults = CompileOptions::default();
+    let options = CompileOptions {
+        backend: PreviewBackend::Pdf,
+        engine: magic
+            .program
+            .or(project.engine)
+            .unwrap_or(defaults.engine),
+        timeout_secs: project.timeout_secs.unwrap_or(defaults.timeout_secs),
+        input_dir,
+        diagnostics: diagnostics.map(|path| DiagnosticsOutput {
+            path,
+            source: input.to_string_lossy().into_owned(),
+        }),
+        ..defaults
+    };
+    match Preview::new().compile_latex(&latex, &options) {
+        Ok(result) => {
+            for warning in &result.warnings {
+                eprintln!("{}", warning);
+            }
+            if let Err(e) = std::fs::write(output, &result.pdf_bytes) {
+                eprintln!("Failed to write {}: {}", output.display(), e);
+                return glib::ExitCode::FAILURE;
+            }
+            glib::ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            glib::ExitCode::FAILURE
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> glib::ExitCode {
-    // Initialize tracing for professional logging
-    tracing_subscriber::fmt::init();
+    // Initialize tracing for professional logging; AI traffic goes to its own file
+    api::debug_log::init_tracing();
+
+    let args: Vec<String> = std::env::args().collect();
+    match parse_cli_args(&args) {
+        Ok(CliCommand::Compile {
+            input,
+            output,
+            diagnostics,
+        }) => return run_headless_compile(&input, &output, diagnostics),
+        Ok(CliCommand::Gui) => {}
+        Err(usage) => {
+            eprintln!("{}", usage);
+            return glib::ExitCode::FAILURE;
+        }
+    }
 
     // Conditionally disable WebKit sandbox only in environments that require it
-    // (WSL, containers, etc.) to prevent "bwrap: setting up uid map: Permission denied"
-    if needs_webkit_sandbox_disabled() {
+    // (WSL, containers, etc.) to prevent "bwrap: setting up uid map: Permission denied".
+    // Read without `load` so a missing config file isn't written this early.
+    let sandbox = AppConfig::read()
+        .map(|config| config.webkit_sandbox)
+        .unwrap_or_default();
+    if sandbox != WebKitSandbox::Auto {
+        tracing::info!("WebKit sandbox setting: {}", sandbox.label());
+    }
+    if sandbox.should_disable(needs_webkit_sandbox_disabled) {
         // SAFETY: This is set early in main before any threads are spawned
         unsafe {
             std::env::set_var(WEBKIT_SANDBOX_DISABLE_VAR, "1");
@@ -117,14 +263,41 @@ async fn main() -> glib::ExitCode {
         );
     }
 
-    let app = Application::builder().application_id(APP_ID).build();
+    // HANDLES_OPEN turns file arguments (`latex-rs paper.tex`) into the `open`
+    // signal instead of an "unknown option" error
+    let app = Application::builder()
+        .application_id(APP_ID)
+        .flags(gio::ApplicationFlags::HANDLES_OPEN)
+        .build();
 
-    app.connect_activate(build_ui);
+    app.connect_activate(|app| build_ui(app, None));
+    app.connect_open(|app, files, _hint| {
+        // Only one document can be open at a time, so of several files the
+        // last one wins
+        let Some(file) = files.last() else {
+            app.activate();
+            return;
+        };
+        // A file manager hands files to the running instance; open them there
+        // rather than in a second window
+        match app.active_window() {
+            Some(window) => {
+                window.present();
+                let uri = file.uri().to_variant();
+                if let Err(e) = window.activate_action("win.open-file", Some(&uri)) {
+                    tracing::warn!("Could not open {}: {}", file.uri(), e);
+                }
+            }
+            None => build_ui(app, Some(file)),
+        }
+    });
 
     app.run()
 }
 
-fn build_ui(app: &Application) {
+/// Builds the editor window, opening `initial_file` if one was given on the
+/// command line.
+fn build_ui(app: &Application, initial_file: Option<&gio::File>) {
     let window = ApplicationWindow::builder()
         .application(app)
         .default_width(DEFAULT_WINDOW_WIDTH)
@@ -166,12 +339,19 @@ fn build_ui(app: &Application) {
         ai_entry,
         ai_spinner,
         ai_run_btn,
+        regenerate_btn,
         reasoning_revealer,
         reasoning_view,
         suggestion_revealer,
         accept_btn,
+        review_btn,
         reject_btn,
         clear_btn,
+        usage_label,
+        context_dropdown,
+        preset_dropdown,
+        reasoning_title,
+        reasoning_expander,
     ) = ai::create_ai_panel();
 
     // Sidebar & Content Split
@@ -182,11 +362,17 @@ fn build_ui(app: &Application) {
         sidebar_hub,
         sidebar_container,
         status_bar,
+        log_revealer,
         pos_label,
         word_count_label,
+        encoding_label,
         ai_status_label,
+        compile_status,
         arxiv_search,
         arxiv_list,
+        todo_list,
+        project_search,
+        project_hits,
     ) = layout::create_main_layout(&main_vbox);
 
     // Header Bar
@@ -223,6 +409,7 @@ fn build_ui(app: &Application) {
     
     let main_content = Box::new(Orientation::Vertical, 0);
     main_content.append(&outer_paned);
+    main_content.append(&log_revealer);
     main_content.append(&status_bar);
     
     content_stack.add_titled(&welcome_page, Some("welcome"), "Welcome");
@@ -290,23 +477,54 @@ fn build_ui(app: &Application) {
     ));
 
     let config = AppConfig::load();
-    let preview_generator = Preview::new();
-    let compilation_queue = crate::queue::CompilationQueue::new(preview_generator.clone());
+    api::debug_log::set_enabled(config.ai_debug_log);
+    api::set_offline(config.offline);
+    crate::preview::set_tex_bin_dir(config.tex_bin_dir.clone());
+    let preview_generator = Preview::from_config(&config);
+    let compilation_queue = crate::queue::CompilationQueue::new(
+        preview_generator.clone(),
+        config.compile_queue_depth,
+    );
 
     let state = Rc::new(RefCell::new(AppState {
         current_file: None,
+        project: Default::default(),
+        magic_comments: Default::default(),
+        navigation: Default::default(),
+        current_encoding: encoding_rs::UTF_8,
+        current_bom: false,
         ai_provider: None,
         ai_cancellation: None,
         is_ai_generating: false,
         pending_suggestion: None,
         original_text_selection: None,
+        last_ai_instruction: None,
+        session_token_usage: Default::default(),
         config,
         compilation_queue: Some(compilation_queue),
+        compile_job: None,
+        last_page_count: None,
+        last_pdf: None,
+        preview_debounce: None,
+        spell_checker: None,
         editor_zoom: DEFAULT_ZOOM_LEVEL,
         preview_zoom: DEFAULT_ZOOM_LEVEL,
+        math_scratch: false,
     }));
 
-    // Dependency check
+    // Dependency check; a misconfigured TeX folder is reported unless
+    // something more serious is missing
+    let engine = state.borrow().config.latex_engine.program();
+    let not_in_tex_dir = crate::preview::missing_from_tex_bin_dir(&[engine, "bibtex"]);
+    if !not_in_tex_dir.is_empty() {
+        let msg = format!(
+            "{} not found in the configured TeX programs folder; using PATH instead.",
+            not_in_tex_dir.join(", ")
+        );
+        banner.set_title(&msg);
+        banner.set_revealed(true);
+        tracing::warn!(msg);
+    }
     let missing_deps = crate::utils::check_dependencies();
     if !missing_deps.is_empty() {
         let msg = format!(
@@ -317,6 +535,12 @@ fn build_ui(app: &Application) {
         banner.set_revealed(true);
         tracing::warn!(msg);
     }
+    if !crate::preview::Preview::poppler_tools().pdftocairo && missing_deps.is_empty() {
+        banner.set_title(
+            "poppler-utils is not installed. The preview shows the PDF directly instead of rendered pages.",
+        );
+        banner.set_revealed(true);
+    }
 
     let validate_ai = Rc::new(glib::clone!(
         #[strong]
@@ -325,6 +549,8 @@ fn build_ui(app: &Application) {
         ai_btn,
         #[weak]
         ai_status_label,
+        #[weak]
+        ai_revealer,
         move || {
             let ctx = glib::MainContext::default();
             ctx.spawn_local(glib::clone!(
@@ -334,8 +560,18 @@ fn build_ui(app: &Application) {
                 ai_btn,
                 #[weak]
                 ai_status_label,
+                #[weak]
+                ai_revealer,
                 async move {
                     ai_btn.set_sensitive(false);
+                    if state.borrow().config.offline {
+                        state.borrow_mut().ai_provider = None;
+                        ai_btn.set_visible(false);
+                        ai_revealer.set_reveal_child(false);
+                        ai_status_label.set_text("AI: Offline mode");
+                        return;
+                    }
+                    ai_btn.set_visible(true);
                     ai_status_label.set_text("AI: Initializing...");
 
                     let config = state.borrow().config.clone();
@@ -361,11 +597,12 @@ fn build_ui(app: &Application) {
                                 );
                             }
                             Err(e) => {
+                                state.borrow_mut().ai_provider = None;
                                 ai_btn.set_tooltip_text(Some(&format!(
                                     "AI provider unavailable: {}. Check settings.",
                                     e
                                 )));
-                                ai_status_label.set_text("AI: Unavailable");
+                                ai_status_label.set_text(&format!("AI: {}", e.status()));
                                 tracing::error!("AI check failed: {}", e);
                             }
                         }
@@ -377,8 +614,66 @@ fn build_ui(app: &Application) {
         }
     ));
 
+    // Offline mode turns off everything that talks to the network: the AI
+    // assistant and the arXiv search
+    let apply_offline_mode = Rc::new(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        validate_ai,
+        #[weak]
+        sidebar_hub,
+        move || {
+            let offline = state.borrow().config.offline;
+            api::set_offline(offline);
+            sidebar::set_arxiv_visible(&sidebar_hub, !offline);
+            validate_ai();
+        }
+    ));
+
     // AI Initialization Check
-    validate_ai();
+    apply_offline_mode();
+
+    // Clicking the AI status checks again; an unavailable provider is also retried periodically
+    let status_click = gtk4::GestureClick::new();
+    status_click.connect_released(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        validate_ai,
+        move |_, _, _, _| {
+            if !state.borrow().is_ai_generating {
+                validate_ai();
+            }
+        }
+    ));
+    ai_status_label.add_controller(status_click);
+    ai_status_label.set_tooltip_text(Some("Click to check the AI provider again"));
+
+    glib::timeout_add_seconds_local(
+        AI_STATUS_RECHECK_SECS,
+        glib::clone!(
+            #[weak]
+            state,
+            #[weak]
+            validate_ai,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                let retry = {
+                    let s = state.borrow();
+                    s.ai_provider.is_none()
+                        && !s.is_ai_generating
+                        && !s.config.offline
+                        && s.config.get_active_provider().is_some()
+                };
+                if retry {
+                    validate_ai();
+                }
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
 
     // Sidebar logic
     sidebar_toggle.connect_active_notify(glib::clone!(
@@ -391,7 +686,7 @@ fn build_ui(app: &Application) {
 
     // Editor
     let style_manager = adw::StyleManager::default();
-    let (buffer, editor_view, editor_scroll) = editor::create_editor(&style_manager);
+    let (buffer, editor_view, editor_scroll) = editor::create_editor(&style_manager, state.clone());
 
     // Search Bar
     let (search_revealer, search_entry, search_count_label) = editor::create_search_bar();
@@ -403,7 +698,9 @@ fn build_ui(app: &Application) {
 
     // Preview
     let (web_view, preview_scroll) = webview::create_preview();
-    paned.set_end_child(Some(&preview_scroll));
+    let preview_overlay =
+        webview::overlay_copy_error_log(&preview_scroll, &compile_status, &toast_overlay);
+    paned.set_end_child(Some(&preview_overlay));
 
     // Search Logic
     let search_settings = sourceview5::SearchSettings::new();
@@ -441,6 +738,8 @@ fn build_ui(app: &Application) {
     search_entry.connect_next_match(glib::clone!(
         #[weak]
         search_context,
+        #[strong]
+        state,
         #[weak]
         editor_view,
         #[weak]
@@ -453,6 +752,7 @@ fn build_ui(app: &Application) {
                 buf.start_iter()
             };
             if let Some((start, end, _)) = search_context.forward(&iter) {
+                editor::record_jump(&buffer, &state);
                 buf.select_range(&start, &end);
                 editor_view.scroll_to_iter(&mut start.clone(), 0.0, false, 0.5, 0.5);
             }
@@ -462,6 +762,8 @@ fn build_ui(app: &Application) {
     search_entry.connect_previous_match(glib::clone!(
         #[weak]
         search_context,
+        #[strong]
+        state,
         #[weak]
         editor_view,
         #[weak]
@@ -474,6 +776,7 @@ fn build_ui(app: &Application) {
                 buf.start_iter()
             };
             if let Some((start, end, _)) = search_context.backward(&iter) {
+                editor::record_jump(&buffer, &state);
                 buf.select_range(&start, &end);
                 editor_view.scroll_to_iter(&mut start.clone(), 0.0, false, 0.5, 0.5);
             }
@@ -481,165 +784,10 @@ fn build_ui(app: &Application) {
     ));
 
     // Arxiv Search Logic
-    arxiv_search.connect_activate(glib::clone!(
-        #[weak]
-        arxiv_list,
-        #[weak]
-        toast_overlay,
-        #[weak]
-        window,
-        move |entry| {
-            let query = entry.text().to_string();
-            if query.is_empty() {
-                return;
-            }
-
-            // Clear previous results
-            while let Some(child) = arxiv_list.first_child() {
-                arxiv_list.remove(&child);
-            }
-
-            // Add loading indicator
-            let loading_label = gtk4::Label::new(Some("Searching arXiv..."));
-            loading_label.add_css_class("dim-label");
-            loading_label.set_margin_top(12);
-            let loading_row = gtk4::ListBoxRow::builder()
-                .child(&loading_label)
-                .selectable(false)
-                .activatable(false)
-                .build();
-            arxiv_list.append(&loading_row);
-
-            glib::MainContext::default().spawn_local(async move {
-                match crate::api::arxiv::search_arxiv(&query).await {
-                    Ok(entries) => {
-                        if loading_row.parent().is_some() {
-                            arxiv_list.remove(&loading_row);
-                        }
-                        if entries.is_empty() {
-                            let no_results = gtk4::Label::new(Some("No results found."));
-                            no_results.add_css_class("dim-label");
-                            no_results.set_margin_top(12);
-                            arxiv_list.append(&no_results);
-                        } else {
-                            for entry in entries {
-                                let authors = entry
-                                    .authors
-                                    .iter()
-                                    .map(|a| a.name.as_str())
-                                    .collect::<Vec<_>>()
-                                    .join(", ");
-                                
-                                let title = entry.title.trim().replace("\n", " ");
-                                // Escape for pango markup just in case
-                                let escaped_title = glib::markup_escape_text(&title);
-                                let escaped_authors = glib::markup_escape_text(&authors);
-
-                                let row = adw::ActionRow::builder()
-                                    .title(escaped_title.as_str())
-                                    .subtitle(escaped_authors.as_str())
-                                    .title_lines(2)
-                                    .build();
-
-                                let info_btn = gtk4::Button::builder()
-                                    .icon_name("info-symbolic")
-                                    .valign(gtk4::Align::Center)
-                                    .has_frame(false)
-                                    .tooltip_text("View Summary")
-                                    .build();
-
-                                let summary = entry.summary.trim().replace("\n", " ");
-                                 info_btn.connect_clicked(glib::clone!(
-                                     #[weak]
-                                     window,
-                                     move |_| {
-                                         let dialog = adw::AlertDialog::builder()
-                                             .heading("Paper Summary")
-                                             .body(&summary)
-                                             .build();
-                                         dialog.add_response("close", "Close");
-                                         dialog.set_default_response(Some("close"));
-                                         dialog.present(Some(&window));
-                                     }
-                                 ));
-
-                                let bib_btn = gtk4::Button::builder()
-                                    .icon_name("edit-copy-symbolic")
-                                    .valign(gtk4::Align::Center)
-                                    .has_frame(false)
-                                    .tooltip_text("Copy BibTeX")
-                                    .build();
-
-                                let entry_id = crate::api::arxiv::extract_id(&entry.id);
-                                bib_btn.connect_clicked(glib::clone!(
-                                    #[weak]
-                                    toast_overlay,
-                                    move |_| {
-                                        let id = entry_id.clone();
-                                        glib::MainContext::default().spawn_local(glib::clone!(
-                                            #[weak]
-                                            toast_overlay,
-                                            async move {
-                                                match crate::api::arxiv::fetch_bibtex(&id).await {
-                                                    Ok(bib) => {
-                                                        let display = gdk::Display::default().expect("Could not connect to a display.");
-                                                        let clipboard = display.clipboard();
-                                                        clipboard.set_text(&bib);
-                                                        toast_overlay.add_toast(adw::Toast::new("BibTeX copied to clipboard"));
-                                                    }
-                                                    Err(e) => {
-                                                        toast_overlay.add_toast(adw::Toast::new(&format!("Failed to fetch BibTeX: {}", e)));
-                                                    }
-                                                }
-                                            }
-                                        ));
-                                    }
-                                ));
-
-                                let pdf_btn = gtk4::Button::builder()
-                                    .icon_name("document-open-symbolic")
-                                    .valign(gtk4::Align::Center)
-                                    .has_frame(false)
-                                    .tooltip_text("Open PDF")
-                                    .build();
-
-                                let pdf_link = entry.links.iter()
-                                    .find(|l| l.rel == "related" && l.href.contains("pdf"))
-                                    .map(|l| l.href.clone())
-                                    .or_else(|| {
-                                        entry.links.iter()
-                                            .find(|l| l.rel == "alternate")
-                                            .map(|l| l.href.replace("abs", "pdf") + ".pdf")
-                                    });
-
-                                if let Some(url) = pdf_link {
-                                    pdf_btn.connect_clicked(move |_| {
-                                        let _ = gio::AppInfo::launch_default_for_uri(&url, None::<&gio::AppLaunchContext>);
-                                    });
-                                    row.add_suffix(&pdf_btn);
-                                }
-
-                                row.add_suffix(&info_btn);
-                                row.add_suffix(&bib_btn);
-                                arxiv_list.append(&row);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        if loading_row.parent().is_some() {
-                            arxiv_list.remove(&loading_row);
-                        }
-                        let error_label = gtk4::Label::new(Some(&format!("Error: {}", e)));
-                        error_label.add_css_class("error");
-                        arxiv_list.append(&error_label);
-                    }
-                }
-            });
-        }
-    ));
+    sidebar::arxiv::connect_arxiv_search(&arxiv_search, &arxiv_list, &window, &toast_overlay);
 
     // Zoom handlers
-    editor::connect_zoom_handlers(
+    let refresh_editor = editor::connect_zoom_handlers(
         &window,
         state.clone(),
         &editor_view,
@@ -648,19 +796,62 @@ fn build_ui(app: &Application) {
         &search_entry,
         &web_view,
     );
-    editor::connect_sidebar_activation(&outline_list, &buffer, &editor_view);
+    editor::connect_sidebar_activation(
+        &outline_list,
+        &buffer,
+        &editor_view,
+        &web_view,
+        state.clone(),
+    );
+    let refresh_todos = editor::connect_todo_list(&todo_list, &buffer, &editor_view, state.clone());
+    editor::connect_folding(&window, &buffer, &editor_view);
+    editor::connect_environment_jump(&window, &buffer, &editor_view, state.clone());
+    editor::connect_navigation_history(&window, &buffer, &editor_view, state.clone());
+    let recheck_spelling = editor::connect_spell_check(&buffer, state.clone());
+    editor::connect_long_line_guard(&buffer, &editor_view, state.clone());
+    editor::connect_lint(&buffer, &editor_view);
+    citation::connect_citation_picker(&window, &buffer, &editor_view, state.clone());
+    symbol_picker::connect_symbol_picker(&window, &buffer, &editor_view);
+    insert::connect_insert_dialogs(&window, &buffer, state.clone(), &toast_overlay);
+    statistics::connect_statistics_action(&window, &buffer, state.clone());
+    document_settings::connect_document_settings_action(&window, state.clone(), &toast_overlay);
+    editor::connect_format_action(&window, &buffer, &editor_view, state.clone());
+    editor::connect_comment_action(&window, &buffer);
 
     // Live preview handler
     webview::connect_live_preview(
         &buffer,
         &web_view,
         &outline_list,
+        &compile_status,
         state.clone(),
         &toast_overlay,
     );
+    webview::connect_recompile_action(
+        &window,
+        &buffer,
+        &web_view,
+        &outline_list,
+        &compile_status,
+        state.clone(),
+    );
+    webview::connect_compile_progress(&compile_status, &state);
+    webview::connect_cursor_page(&buffer, &web_view, state.clone());
+    webview::connect_explain_compile_action(&window, &buffer, state.clone());
+    webview::connect_math_scratch_action(
+        &window,
+        &buffer,
+        &web_view,
+        &outline_list,
+        &compile_status,
+        state.clone(),
+    );
 
     // Export PDF handler
     file_ops::connect_export_pdf(&export_btn, &window, &buffer, state.clone(), &toast_overlay);
+    file_ops::connect_build_folder_action(&window, state.clone(), &toast_overlay);
+    file_ops::connect_clean_action(&window, state.clone(), &toast_overlay);
+    file_ops::connect_copy_page_action(&window, &web_view, state.clone(), &toast_overlay);
 
     // File operations and status bar
     file_ops::connect_file_operations(
@@ -673,6 +864,38 @@ fn build_ui(app: &Application) {
         &view_title,
         &pos_label,
         &word_count_label,
+        &encoding_label,
+    );
+
+    // Search in Project: open hits in other files like dropped documents
+    sidebar::search::connect_project_search(
+        &project_search,
+        &project_hits,
+        &buffer,
+        &editor_view,
+        state.clone(),
+        &view_title,
+        &encoding_label,
+        Rc::new(glib::clone!(
+            #[strong]
+            update_view_state,
+            move || update_view_state(true)
+        )),
+    );
+
+    // Drag-and-drop: open dropped documents, insert dropped images
+    file_ops::connect_file_drop(
+        &window,
+        &editor_view,
+        &buffer,
+        state.clone(),
+        &view_title,
+        &encoding_label,
+        Rc::new(glib::clone!(
+            #[strong]
+            update_view_state,
+            move || update_view_state(true)
+        )),
     );
 
     // AI Assistant Toggle
@@ -683,10 +906,15 @@ fn build_ui(app: &Application) {
         ai_revealer,
         #[weak]
         ai_entry,
+        #[weak]
+        editor_view,
         move |_| {
             let is_revealed = ai_revealer.reveals_child();
             ai_revealer.set_reveal_child(!is_revealed);
-            if !is_revealed {
+            if is_revealed {
+                // Closing from the keyboard should leave the user typing in the document
+                editor_view.grab_focus();
+            } else {
                 ai_entry.grab_focus();
             }
         }
@@ -698,64 +926,194 @@ fn build_ui(app: &Application) {
         #[weak]
         window,
         #[strong]
-        validate_ai,
+        apply_offline_mode,
+        #[strong]
+        refresh_editor,
         #[weak]
         buffer,
         #[weak]
         web_view,
         #[weak]
         outline_list,
+        #[strong]
+        compile_status,
+        #[strong]
+        recheck_spelling,
+        #[strong]
+        refresh_todos,
         move |_| {
             let refresh_preview = {
                 let buffer = buffer.downgrade();
                 let web_view = web_view.downgrade();
                 let outline_list = outline_list.downgrade();
+                let compile_status = compile_status.clone();
+                let recheck_spelling = recheck_spelling.clone();
+                let refresh_todos = refresh_todos.clone();
                 let state = state.clone();
                 Rc::new(move || {
                     if let (Some(b), Some(wv), Some(ol)) = (buffer.upgrade(), web_view.upgrade(), outline_list.upgrade()) {
-                        crate::ui::webview::trigger_refresh(&b, &wv, &ol, state.clone());
+                        crate::ui::webview::trigger_refresh(&b, &wv, &ol, &compile_status, state.clone());
                     }
+                    recheck_spelling();
+                    refresh_todos();
                 })
             };
             crate::ui::settings::show_settings(
                 window.upcast_ref(), 
                 state.clone(), 
-                Some(validate_ai.clone()),
-                Some(refresh_preview)
+                Some(apply_offline_mode.clone()),
+                Some(refresh_preview),
+                Some(refresh_editor.clone())
             );
         }
     ));
 
+    // Refreshed on reload so presets added to the config folder show up
+    let refresh_presets = {
+        let state = state.clone();
+        let preset_dropdown = preset_dropdown.downgrade();
+        Rc::new(move || {
+            let Some(dropdown) = preset_dropdown.upgrade() else { return };
+            // Not borrowed across set_presets, whose selection change saves the config
+            let (presets, selected) = {
+                let s = state.borrow();
+                (api::presets::all_presets(s.config.get_active_provider()), s.config.ai_prompt_preset.clone())
+            };
+            ai::set_presets(&dropdown, &presets, selected.as_deref());
+        })
+    };
+    refresh_presets();
+    preset_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        state,
+        move |dropdown| {
+            let Some(item) = dropdown.selected_item().and_downcast::<gtk4::StringObject>() else { return };
+            let name = item.string().to_string();
+            let mut s = state.borrow_mut();
+            let preset = (name != api::presets::DEFAULT_PRESET).then_some(name);
+            if s.config.ai_prompt_preset != preset {
+                s.config.ai_prompt_preset = preset;
+                let _ = s.config.save();
+            }
+        }
+    ));
+
+    // Picks up changes made to config.toml outside the settings window
+    let reload_config_action = gio::SimpleAction::new("reload-config", None);
+    reload_config_action.connect_activate(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        apply_offline_mode,
+        #[strong]
+        refresh_editor,
+        #[weak]
+        buffer,
+        #[weak]
+        web_view,
+        #[weak]
+        outline_list,
+        #[strong]
+        compile_status,
+        #[strong]
+        recheck_spelling,
+        #[strong]
+        refresh_todos,
+        #[strong]
+        refresh_presets,
+        #[weak]
+        toast_overlay,
+        move |_, _| {
+            let config = match AppConfig::read() {
+                Ok(config) => config,
+                Err(e) => {
+                    toast_overlay.add_toast(adw::Toast::new(&format!("Could not reload settings: {}", e)));
+                    return;
+                }
+            };
+            let provider_changed = {
+                let mut s = state.borrow_mut();
+                let changed = config.get_active_provider() != s.config.get_active_provider()
+                    || config.offline != s.config.offline
+                    || s.ai_provider.is_none();
+                s.config = config;
+                changed
+            };
+            api::debug_log::set_enabled(state.borrow().config.ai_debug_log);
+            if provider_changed {
+                apply_offline_mode();
+            }
+            refresh_editor();
+            crate::ui::webview::trigger_refresh(&buffer, &web_view, &outline_list, &compile_status, state.clone());
+            recheck_spelling();
+            refresh_todos();
+            refresh_presets();
+            toast_overlay.add_toast(adw::Toast::new("Settings reloaded"));
+        }
+    ));
+    window.add_action(&reload_config_action);
+
+    let current_mode = state.borrow().config.ai_context_mode;
+    context_dropdown.set_selected(
+        AiContextMode::ALL
+            .iter()
+            .position(|m| *m == current_mode)
+            .unwrap_or(0) as u32,
+    );
+    context_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        state,
+        move |dropdown| {
+            if let Some(mode) = AiContextMode::ALL.get(dropdown.selected() as usize) {
+                let mut s = state.borrow_mut();
+                s.config.ai_context_mode = *mode;
+                let _ = s.config.save();
+            }
+        }
+    ));
+
     let ai_entry_weak = ai_entry.downgrade();
 
     let trigger_ai = {
         let state = state.clone();
         let buffer = buffer.downgrade();
         let ai_run_btn = ai_run_btn.downgrade();
+        let regenerate_btn = regenerate_btn.downgrade();
         let ai_spinner = ai_spinner.downgrade();
         let ai_entry = ai_entry.downgrade();
         let ai_status_label = ai_status_label.downgrade();
         let reasoning_revealer = reasoning_revealer.downgrade();
         let reasoning_view = reasoning_view.downgrade();
+        let reasoning_title = reasoning_title.downgrade();
+        let reasoning_expander = reasoning_expander.downgrade();
         let suggestion_revealer = suggestion_revealer.downgrade();
         let editor_view = editor_view.downgrade();
+        let usage_label = usage_label.downgrade();
+        let toast_overlay = toast_overlay.downgrade();
+
+        // `regenerate` re-sends the last completed instruction with a fresh seed
+        move |regenerate: bool| {
+            let (user_instruction, seed) = if regenerate {
+                let Some(instruction) = state.borrow().last_ai_instruction.clone() else { return };
+                (instruction, api::random_seed())
+            } else {
+                let ai_entry = if let Some(e) = ai_entry.upgrade() { e } else { return };
+                let ai_buffer = ai_entry.buffer();
+                let user_instruction = ai_buffer.text(&ai_buffer.start_iter(), &ai_buffer.end_iter(), false).to_string();
+                if user_instruction.trim().is_empty() {
+                    return;
+                }
 
-        move || {
-            let ai_entry = if let Some(e) = ai_entry.upgrade() { e } else { return };
-            let ai_buffer = ai_entry.buffer();
-            let user_instruction = ai_buffer.text(&ai_buffer.start_iter(), &ai_buffer.end_iter(), false).to_string();
-            if user_instruction.trim().is_empty() {
-                return;
-            }
-            
-            // Add to history if not duplicate of last
-            {
-                let mut s = state.borrow_mut();
-                if s.config.ai_history.last() != Some(&user_instruction) {
-                    s.config.ai_history.push(user_instruction.clone());
-                    let _ = s.config.save();
+                // Add to history if not duplicate of last
+                {
+                    let mut s = state.borrow_mut();
+                    if s.config.ai_history.last() != Some(&user_instruction) {
+                        s.config.ai_history.push(user_instruction.clone());
+                        let _ = s.config.save();
+                    }
                 }
-            }
+                (user_instruction, AI_SEED)
+            };
 
             let buffer = if let Some(b) = buffer.upgrade() { b } else { return };
             let ai_run_btn = if let Some(b) = ai_run_btn.upgrade() { b } else { return };
@@ -766,16 +1124,31 @@ fn build_ui(app: &Application) {
             let suggestion_revealer = if let Some(r) = suggestion_revealer.upgrade() { r } else { return };
             let editor_view = if let Some(v) = editor_view.upgrade() { v } else { return };
 
-            let (start, end) = buffer.selection_bounds().unwrap_or_else(|| {
-                let cursor = buffer.iter_at_mark(&buffer.mark("insert").unwrap());
-                let mut s = cursor.clone();
-                s.backward_visible_lines(5);
-                let mut e = cursor.clone();
-                e.forward_visible_lines(5);
-                (s, e)
-            });
+            // Put the original text back so the new answer replaces the old one
+            if regenerate && state.borrow().pending_suggestion.is_some() {
+                let original = state.borrow().original_text_selection.clone().unwrap_or_default();
+                if crate::ui::ai::restore_original_selection(&buffer, &original) {
+                    state.borrow_mut().pending_suggestion = None;
+                }
+            }
+
+            let context_mode = state.borrow().config.ai_context_mode;
+            let (start, end) = if context_mode == AiContextMode::WholeDocument {
+                buffer.bounds()
+            } else {
+                buffer.selection_bounds().unwrap_or_else(|| {
+                    let cursor = buffer.iter_at_mark(&buffer.mark("insert").unwrap());
+                    let mut s = cursor.clone();
+                    s.backward_visible_lines(5);
+                    let mut e = cursor.clone();
+                    e.forward_visible_lines(5);
+                    (s, e)
+                })
+            };
             
             let selected_text = buffer.text(&start, &end, false).to_string();
+            let text_before = buffer.text(&buffer.start_iter(), &start, false).to_string();
+            let text_after = buffer.text(&end, &buffer.end_iter(), false).to_string();
             let provider_opt = state.borrow().ai_provider.clone();
 
             if let Some(provider) = provider_opt {
@@ -795,6 +1168,9 @@ fn build_ui(app: &Application) {
 
                 ai_run_btn.set_sensitive(true);
                 ai_run_btn.set_label("Stop");
+                if let Some(btn) = regenerate_btn.upgrade() {
+                    btn.set_sensitive(false);
+                }
                 ai_run_btn.set_icon_name("process-stop-symbolic");
                 ai_run_btn.add_css_class("destructive-action");
                 ai_run_btn.remove_css_class("suggested-action");
@@ -803,11 +1179,28 @@ fn build_ui(app: &Application) {
                 ai_status_label.set_text("AI: Thinking...");
                 reasoning_view.buffer().set_text("");
                 reasoning_revealer.set_reveal_child(false);
+                // The box itself only appears once reasoning actually streams in
+                {
+                    let s = state.borrow();
+                    if let (Some(title), Some(p)) = (reasoning_title.upgrade(), s.config.get_active_provider()) {
+                        title.set_markup(&format!(
+                            "<b>Reasoning ({} · {})</b>",
+                            glib::markup_escape_text(&p.name),
+                            glib::markup_escape_text(&p.active_model)
+                        ));
+                    }
+                    if let Some(expander) = reasoning_expander.upgrade() {
+                        expander.set_expanded(!s.config.collapse_reasoning);
+                    }
+                }
                 suggestion_revealer.set_reveal_child(false);
                 
                 // Disable editing while generating
                 editor_view.set_editable(false);
 
+                let usage_label = usage_label.clone();
+                let toast_overlay = toast_overlay.clone();
+                let regenerate_btn = regenerate_btn.clone();
                 let ctx = glib::MainContext::default();
                 ctx.spawn_local(glib::clone!(
                     #[strong]
@@ -829,34 +1222,43 @@ fn build_ui(app: &Application) {
                     #[weak]
                     editor_view,
                     async move {
-                        let system_prompt = state.borrow().config.get_active_provider()
-                            .and_then(|p| p.system_prompt.clone())
-                            .unwrap_or_else(|| "You are an expert LaTeX assistant. Your goal is to help users edit specific sections of their LaTeX documents. \
-                                              \n\nCORE RULES:\n\
-                                              - Output ONLY the modified LaTeX code for the provided snippet.\n\
-                                              - Do NOT include markdown blocks like ```latex.\n\
-                                              - Do NOT include conversational text, greetings, or explanations.\n\
-                                              - If the user provides a small snippet, assume they want to edit it or add something relative to it.\n\
-                                              - If adding a new environment (table, figure, etc.), ensure it is properly closed.\n\
-                                              - Use ONLY standard LaTeX commands (article class). Avoid hallucinated commands like \\keywords (use \\paragraph{Keywords:} instead).\n\
-                                              - Maintain the context of the surrounding code if applicable.".to_string());
-
-                        let messages = vec![
-                            Message {
-                                role: MessageRole::System,
-                                content: system_prompt,
-                            },
-                            Message {
-                                role: MessageRole::User,
-                                content: format!("Edit the following LaTeX snippet based on these instructions: {}\n\nSnippet:\n{}", user_instruction, selected_text),
-                            }
-                        ];
+                        let context_window = state.borrow().config.get_active_provider()
+                            .and_then(|p| p.context_window)
+                            .unwrap_or(DEFAULT_AI_CONTEXT_TOKENS);
+                        let system_prompt = {
+                            let s = state.borrow();
+                            let presets = api::presets::all_presets(s.config.get_active_provider());
+                            api::presets::system_prompt(&presets, s.config.ai_prompt_preset.as_deref())
+                        };
+
+                        let prompt = build_edit_prompt(
+                            &system_prompt,
+                            &user_instruction,
+                            context_mode,
+                            &text_before,
+                            &selected_text,
+                            &text_after,
+                            context_window,
+                        );
+                        let warning = if prompt.exceeds_window {
+                            Some(format!(
+                                "The prompt is about {} tokens, more than the model's {}-token context window; the reply may be cut off",
+                                prompt.estimated_tokens, context_window
+                            ))
+                        } else if prompt.truncated {
+                            Some("The document was trimmed around the selection to fit the model's context window".to_string())
+                        } else {
+                            None
+                        };
+                        if let (Some(warning), Some(overlay)) = (warning, toast_overlay.upgrade()) {
+                            overlay.add_toast(adw::Toast::new(&warning));
+                        }
+                        let messages = prompt.messages;
 
                         let mut full_content = String::new();
-                        let mut full_reasoning = String::new();
+                        let mut reasoning_buffer = crate::api::ReasoningBuffer::new(state.borrow().config.max_reasoning_chars);
                         let mut success = false;
                         let mut ai_started_typing = false;
-                        let mut filter = crate::api::ThinkingFilter::new();
 
                         // buffer is already sourceview5::Buffer here because it was upgraded in trigger_ai?
                         // If it's not Option, then don't match it as Option.
@@ -874,7 +1276,7 @@ fn build_ui(app: &Application) {
                         let curr_mark = buffer.create_mark(None, &start, false);
                         let end_mark = buffer.create_mark(None, &end, false);
 
-                        match provider.chat_stream(messages).await {
+                        match provider.chat_stream_seeded(messages, seed).await {
                             Ok(mut stream) => {
                                 let mut cancelled = false;
                                 loop {
@@ -886,41 +1288,51 @@ fn build_ui(app: &Application) {
                                         chunk_opt = stream.next() => {
                                             match chunk_opt {
                                                 Some(Ok(chunk)) => {
-                                                    let processed_chunks = match chunk {
-                                                        AiChunk::Content(c) => filter.process(c),
-                                                        AiChunk::Reasoning(r) => vec![AiChunk::Reasoning(r)],
-                                                    };
-
-                                                    for p_chunk in processed_chunks {
-                                                        match p_chunk {
-                                                            AiChunk::Content(c) => {
-                                                                if !ai_started_typing {
-                                                                    buffer.begin_user_action();
-                                                                    // First time, delete the original selection
-                                                                    let mut s = buffer.iter_at_mark(&start_mark);
-                                                                    let mut e = buffer.iter_at_mark(&end_mark);
-                                                                    buffer.delete(&mut s, &mut e);
-                                                                    ai_started_typing = true;
-                                                                }
+                                                    // Providers have already split inline `<think>` reasoning from the content
+                                                    match chunk {
+                                                        AiChunk::Content(c) => {
+                                                            if !ai_started_typing {
+                                                                buffer.begin_user_action();
+                                                                // First time, delete the original selection
+                                                                let mut s = buffer.iter_at_mark(&start_mark);
+                                                                let mut e = buffer.iter_at_mark(&end_mark);
+                                                                buffer.delete(&mut s, &mut e);
+                                                                ai_started_typing = true;
+                                                            }
                                                                 
-                                                                let mut current_iter = buffer.iter_at_mark(&curr_mark);
-                                                                buffer.insert(&mut current_iter, &c);
-                                                                full_content.push_str(&c);
+                                                            let mut current_iter = buffer.iter_at_mark(&curr_mark);
+                                                            buffer.insert(&mut current_iter, &c);
+                                                            full_content.push_str(&c);
                                                                 
-                                                                // Apply highlighting to the new chunk
-                                                                let tag_start = buffer.iter_at_mark(&start_mark);
-                                                                let tag_end = buffer.iter_at_mark(&curr_mark);
-                                                                buffer.apply_tag_by_name("ai-suggestion", &tag_start, &tag_end);
+                                                            // Apply highlighting to the new chunk
+                                                            let tag_start = buffer.iter_at_mark(&start_mark);
+                                                            let tag_end = buffer.iter_at_mark(&curr_mark);
+                                                            buffer.apply_tag_by_name("ai-suggestion", &tag_start, &tag_end);
+                                                        }
+                                                        AiChunk::Reasoning(r) => {
+                                                            // Past the limit only the content keeps streaming
+                                                            let shown = reasoning_buffer.push(&r);
+                                                            if let (false, Some(view)) = (shown.is_empty(), reasoning_view.upgrade()) {
+                                                                let rb = view.buffer();
+                                                                rb.insert(&mut rb.end_iter(), &shown);
                                                             }
-                                                            AiChunk::Reasoning(r) => {
-                                                                full_reasoning.push_str(&r);
-                                                                if let Some(view) = reasoning_view.upgrade() {
-                                                                    let rb = view.buffer();
-                                                                    rb.insert(&mut rb.end_iter(), &r);
-                                                                }
-                                                                if let Some(rev) = reasoning_revealer.upgrade() {
-                                                                    rev.set_reveal_child(true);
-                                                                }
+                                                            if let Some(rev) = reasoning_revealer.upgrade() {
+                                                                rev.set_reveal_child(true);
+                                                            }
+                                                        }
+                                                        AiChunk::Usage(usage) => {
+                                                            let summary = {
+                                                                let mut s = state.borrow_mut();
+                                                                s.session_token_usage += usage;
+                                                                format!(
+                                                                    "{} (session: {} tokens)",
+                                                                    usage.summary(s.config.get_active_provider()),
+                                                                    s.session_token_usage.total()
+                                                                )
+                                                            };
+                                                            if let Some(l) = usage_label.upgrade() {
+                                                                l.set_text(&summary);
+                                                                l.set_visible(true);
                                                             }
                                                         }
                                                     }
@@ -947,13 +1359,8 @@ fn build_ui(app: &Application) {
                                         l.set_text("AI: Cancelled");
                                     }
                                 } else if success {
-                                    // Final check/cleanup
-                                    let final_text = if full_content.contains("---") || full_content.contains("@@") {
-                                         // If it looks like a patch, we might need to re-apply it properly
-                                         full_content.clone()
-                                    } else {
-                                        crate::utils::extract_latex(&full_content)
-                                    };
+                                    // Final check/cleanup: apply a returned diff or strip Markdown fences
+                                    let final_text = crate::diff::resolve_suggestion(&selected_text, &full_content);
 
                                     if final_text != full_content {
                                         // If extraction changed things, update the buffer one last time
@@ -969,7 +1376,11 @@ fn build_ui(app: &Application) {
                                         buffer.apply_tag_by_name("ai-suggestion", &tag_start, &tag_end);
                                     }
 
-                                    state.borrow_mut().pending_suggestion = Some(final_text.clone());
+                                    {
+                                        let mut s = state.borrow_mut();
+                                        s.pending_suggestion = Some(final_text.clone());
+                                        s.last_ai_instruction = Some(user_instruction.clone());
+                                    }
                                     
                                     if ai_started_typing {
                                         buffer.end_user_action();
@@ -996,6 +1407,9 @@ fn build_ui(app: &Application) {
                             btn.remove_css_class("destructive-action");
                             btn.add_css_class("suggested-action");
                         }
+                        if let Some(btn) = regenerate_btn.upgrade() {
+                            btn.set_sensitive(state.borrow().last_ai_instruction.is_some());
+                        }
 
                         if let Some(s) = ai_spinner.upgrade() {
                             s.stop();
@@ -1022,7 +1436,15 @@ fn build_ui(app: &Application) {
         #[strong]
         trigger_ai,
         move |_| {
-            trigger_ai();
+            trigger_ai(false);
+        }
+    ));
+
+    regenerate_btn.connect_clicked(glib::clone!(
+        #[strong]
+        trigger_ai,
+        move |_| {
+            trigger_ai(true);
         }
     ));
 
@@ -1071,13 +1493,16 @@ fn build_ui(app: &Application) {
                             glib::Propagation::Stop
                         }
                     }
-                    gdk::Key::Return => {
+                    // Enter sends, Shift+Enter starts a new line of the prompt
+                    gdk::Key::Return | gdk::Key::KP_Enter => {
                         let mask = controller.current_event_state();
-                        if mask.contains(gdk::ModifierType::CONTROL_MASK) {
-                            trigger_ai();
-                            glib::Propagation::Stop
-                        } else {
+                        if mask.contains(gdk::ModifierType::SHIFT_MASK) {
                             glib::Propagation::Proceed
+                        } else {
+                            if !state.borrow().is_ai_generating {
+                                trigger_ai(false);
+                            }
+                            glib::Propagation::Stop
                         }
                     }
                     _ => glib::Propagation::Proceed,
@@ -1114,6 +1539,22 @@ fn build_ui(app: &Application) {
         }
     ));
 
+    review_btn.connect_clicked(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        buffer,
+        #[strong]
+        state,
+        #[weak]
+        accept_btn,
+        #[weak]
+        reject_btn,
+        move |_| {
+            diff_review::show_diff_review(&window, &buffer, &state, &accept_btn, &reject_btn);
+        }
+    ));
+
     reject_btn.connect_clicked(glib::clone!(
         #[strong]
         state,
@@ -1147,6 +1588,98 @@ fn build_ui(app: &Application) {
         }
     ));
 
+    // Command palette: expose header actions as `win.*` actions with shortcuts
+    palette::add_button_action(&window, "new", &new_btn);
+    palette::add_button_action(&window, "open", &open_btn);
+    palette::add_button_action(&window, "save", &save_btn);
+    palette::add_button_action(&window, "export-pdf", &export_btn);
+    palette::add_button_action(&window, "settings", &settings_btn);
+    palette::add_button_action(&window, "toggle-ai", &ai_btn);
+    palette::add_button_action(&window, "ai-regenerate", &regenerate_btn);
+    palette::add_button_action(&window, "toggle-sidebar", &sidebar_toggle);
+    layout::connect_focus_mode(&window, &header_bar, &sidebar_toggle, &outer_paned, &paned);
+    palette::set_accels(app);
+    palette::create_command_palette(&window, &header_bar);
+
+    // Drain the compilation worker before closing so no pdflatex child is orphaned
+    window.connect_close_request(glib::clone!(
+        #[strong]
+        state,
+        move |window| {
+            let Some(queue) = state.borrow_mut().compilation_queue.take() else {
+                return glib::Propagation::Proceed;
+            };
+            glib::MainContext::default().spawn_local(glib::clone!(
+                #[weak]
+                window,
+                async move {
+                    queue.shutdown().await;
+                    window.close();
+                }
+            ));
+            glib::Propagation::Stop
+        }
+    ));
+
+    // Opens a file URI passed to the application (see `connect_open` in `main`)
+    let open_file_action = gio::SimpleAction::new("open-file", Some(glib::VariantTy::STRING));
+    open_file_action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        buffer,
+        #[strong]
+        state,
+        #[weak]
+        view_title,
+        #[weak]
+        encoding_label,
+        #[weak]
+        toast_overlay,
+        #[strong]
+        update_view_state,
+        move |_, parameter| {
+            let Some(uri) = parameter.and_then(|p| p.str()) else {
+                return;
+            };
+            let file = gio::File::for_uri(uri);
+            file_ops::confirm_discard_changes(
+                &window,
+                &buffer,
+                glib::clone!(
+                    #[weak]
+                    buffer,
+                    #[strong]
+                    state,
+                    #[weak]
+                    view_title,
+                    #[weak]
+                    encoding_label,
+                    #[weak]
+                    toast_overlay,
+                    #[strong]
+                    update_view_state,
+                    move || {
+                        if file_ops::open_external_file(
+                            &file,
+                            &buffer,
+                            &state,
+                            &view_title,
+                            &encoding_label,
+                            &toast_overlay,
+                        ) {
+                            update_view_state(true);
+                        }
+                    }
+                ),
+            );
+        }
+    ));
+    window.add_action(&open_file_action);
+    if let Some(file) = initial_file {
+        open_file_action.activate(Some(&file.uri().to_variant()));
+    }
+
     // Present window before starting background checks to avoid "GtkGizmo without allocation" warnings
     window.present();
 
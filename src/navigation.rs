@@ -0,0 +1,96 @@
+//! Back/forward history of cursor jumps, like a browser's: jumping records
+//! where the cursor was, Back returns there and Forward undoes a Back.
+
+use crate::constants::NAVIGATION_HISTORY_MAX;
+
+/// A cursor position as zero-based line and character offset within the line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: i32,
+    pub offset: i32,
+}
+
+#[derive(Debug, Default)]
+pub struct NavigationHistory {
+    back: Vec<Position>,
+    forward: Vec<Position>,
+}
+
+impl NavigationHistory {
+    /// Records `from` before a jump. A new jump drops the forward history, and
+    /// only the newest [`NAVIGATION_HISTORY_MAX`] positions are kept.
+    pub fn push(&mut self, from: Position) {
+        self.forward.clear();
+        // Repeated jumps from the same line (e.g. stepping through search
+        // matches) only need one entry
+        if self.back.last().is_some_and(|last| last.line == from.line) {
+            self.back.pop();
+        }
+        self.back.push(from);
+        if self.back.len() > NAVIGATION_HISTORY_MAX {
+            self.back.remove(0);
+        }
+    }
+
+    /// The position to go back to from `current`, which Forward then returns to.
+    pub fn back(&mut self, current: Position) -> Option<Position> {
+        let target = self.back.pop()?;
+        self.forward.push(current);
+        Some(target)
+    }
+
+    /// The position to go forward to from `current`, undoing a [`Self::back`].
+    pub fn forward(&mut self, current: Position) -> Option<Position> {
+        let target = self.forward.pop()?;
+        self.back.push(current);
+        Some(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: i32) -> Position {
+        Position { line, offset: 0 }
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut history = NavigationHistory::default();
+        assert_eq!(history.back(at(0)), None);
+
+        // Jump 1 → 40 → 90
+        history.push(at(1));
+        history.push(at(40));
+        assert_eq!(history.back(at(90)), Some(at(40)));
+        assert_eq!(history.back(at(40)), Some(at(1)));
+        assert_eq!(history.back(at(1)), None);
+        assert_eq!(history.forward(at(1)), Some(at(40)));
+        assert_eq!(history.forward(at(40)), Some(at(90)));
+        assert_eq!(history.forward(at(90)), None);
+
+        // A new jump after going back forgets the forward entries
+        history.back(at(90));
+        history.push(at(40));
+        assert_eq!(history.forward(at(7)), None);
+    }
+
+    #[test]
+    fn test_push_merges_same_line_and_is_bounded() {
+        let mut history = NavigationHistory::default();
+        history.push(Position { line: 3, offset: 2 });
+        history.push(Position { line: 3, offset: 9 });
+        assert_eq!(history.back(at(50)), Some(Position { line: 3, offset: 9 }));
+        assert_eq!(history.back(at(3)), None);
+
+        for line in 0..NAVIGATION_HISTORY_MAX as i32 + 10 {
+            history.push(at(line));
+        }
+        let mut count = 0;
+        while history.back(at(-1)).is_some() {
+            count += 1;
+        }
+        assert_eq!(count, NAVIGATION_HISTORY_MAX);
+    }
+}
@@ -1,14 +1,591 @@
-use crate::constants::{COMPILE_TIMEOUT_SECS, MAX_LATEX_SIZE_BYTES, PROCESS_POLL_INTERVAL_MS};
+use crate::config::{AppConfig, LatexEngine, PreviewBackend, PreviewLayout};
+use crate::constants::{
+    COMPILE_TIMEOUT_SECS, DEFAULT_PAGE_WIDTH_PX, DEFAULT_PREVIEW_CSP, DEFAULT_PREVIEW_SCALE,
+    MAX_LATEX_PASSES, MAX_LATEX_SIZE_BYTES, PDF_POINTS_TO_CSS_PX, PREAMBLE_FORMAT_CACHE_MAX,
+    PREAMBLE_FORMAT_DIR, PROCESS_POLL_INTERVAL_MS, SCALED_POINTS_PER_BP,
+};
+use crate::diagnostics::{self, DiagnosticsOutput, SourceExcerpt};
+use crate::utils::{lint_latex, LintIssue};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use horrorshow::helper::doctype;
 use horrorshow::{html, Raw};
 use html_escape::encode_text;
+use regex::Regex;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::sync::OnceLock;
-use tempfile::tempdir;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tempfile::{tempdir, TempDir};
+use thiserror::Error;
 
 #[derive(Clone, Debug)]
-pub struct Preview;
+pub struct Preview {
+    /// Applied in order to every successfully rendered page.
+    post_processors: Vec<Arc<dyn PreviewPostProcessor>>,
+    /// Content-Security-Policy of every generated page.
+    csp: Arc<str>,
+}
+
+/// Rejected [`Preview::with_content_security_policy`] value.
+#[derive(Debug, Error, PartialEq)]
+#[error("The preview Content-Security-Policy must not be empty")]
+pub struct EmptyCspError;
+
+/// Edits a rendered preview page before it is shown, e.g. to add an overlay.
+///
+/// The page's Content-Security-Policy still applies, so processors can add
+/// markup and inline `<style>` but not scripts. Error pages are left alone.
+pub trait PreviewPostProcessor: std::fmt::Debug + Send + Sync {
+    fn process(&self, html: &mut String, options: &CompileOptions);
+}
+
+/// Numbers each SVG page in its bottom-right corner when
+/// [`CompileOptions::page_numbers`] is set. Registered by [`Preview::new`].
+#[derive(Debug)]
+pub struct PageNumberOverlay;
+
+impl PreviewPostProcessor for PageNumberOverlay {
+    fn process(&self, html: &mut String, options: &CompileOptions) {
+        if options.page_numbers {
+            insert_into_head(
+                html,
+                "<style>
+                    body { counter-reset: preview-page; }
+                    .page { counter-increment: preview-page; }
+                    .page::after {
+                        content: counter(preview-page);
+                        position: absolute;
+                        right: 8px;
+                        bottom: 6px;
+                        font: 12px sans-serif;
+                        color: #888;
+                        pointer-events: none;
+                    }
+                </style>",
+            );
+        }
+    }
+}
+
+/// Inserts `snippet` just before `</head>`, or at the start of a page without one.
+pub fn insert_into_head(html: &mut String, snippet: &str) {
+    let pos = html.find("</head>").unwrap_or(0);
+    html.insert_str(pos, snippet);
+}
+
+static TEX_BIN_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Runs TeX and poppler programs from `dir` when they are there, following
+/// `AppConfig::tex_bin_dir`; programs it lacks are still looked up on `PATH`.
+/// GUI launches on macOS, for one, don't get the shell's `PATH`.
+pub fn set_tex_bin_dir(dir: Option<PathBuf>) {
+    *TEX_BIN_DIR.lock().unwrap() = dir;
+}
+
+/// `program` inside `dir` if it exists there, else the bare name for a `PATH` lookup.
+fn resolve_program(dir: Option<&Path>, program: &str) -> PathBuf {
+    dir.map(|dir| dir.join(format!("{}{}", program, std::env::consts::EXE_SUFFIX)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(program))
+}
+
+/// Programs of `programs` that the configured TeX folder does not contain;
+/// empty when no folder is configured.
+pub fn missing_from_tex_bin_dir<'a>(programs: &[&'a str]) -> Vec<&'a str> {
+    let dir = TEX_BIN_DIR.lock().unwrap().clone();
+    let Some(dir) = dir else {
+        return Vec::new();
+    };
+    programs
+        .iter()
+        .copied()
+        .filter(|program| resolve_program(Some(&dir), program) == Path::new(program))
+        .collect()
+}
+
+/// A command running `program` as resolved by [`set_tex_bin_dir`]. The folder
+/// also goes first on the child's `PATH`, so tools that call other tools (e.g.
+/// `makeglossaries` running `makeindex`) find them there too.
+pub fn tool_command(program: &str) -> Command {
+    let dir = TEX_BIN_DIR.lock().unwrap().clone();
+    let mut cmd = Command::new(resolve_program(dir.as_deref(), program));
+    if let Some(dir) = dir {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        if let Ok(path) =
+            std::env::join_paths(std::iter::once(dir).chain(std::env::split_paths(&path)))
+        {
+            cmd.env("PATH", path);
+        }
+    }
+    cmd
+}
+
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+/// Why a compilation or export failed.
+///
+/// `Display` gives the sanitized, human-readable text shown to the user.
+#[derive(Error, Debug)]
+pub enum CompileError {
+    /// An external program (pdflatex, pdftocairo, …) is not installed.
+    #[error("{0} was not found. Is it installed and on your PATH?")]
+    BinaryNotFound(String),
+    /// An external program ran longer than the allowed number of seconds.
+    #[error("Command timed out after {0} seconds")]
+    Timeout(u64),
+    #[error(
+        "Document too large ({:.2} MB). Maximum allowed size is {:.2} MB.",
+        *.size as f64 / BYTES_PER_MB,
+        *.max as f64 / BYTES_PER_MB
+    )]
+    TooLarge { size: usize, max: usize },
+    /// LaTeX ran but produced no PDF; `log` holds the sanitized log and output,
+    /// `excerpts` the source lines around the errors it reports.
+    #[error("LaTeX failed to generate a PDF.\n\n{log}")]
+    LatexFailed {
+        log: String,
+        excerpts: Vec<SourceExcerpt>,
+    },
+    #[error("pdftocairo failed to convert page {page}.\n\nStderr:\n{stderr}")]
+    ConversionFailed { page: usize, stderr: String },
+    /// The source failed the structure check that runs before compiling when
+    /// [`CompileOptions::lint_first`] is set, so TeX was never started.
+    #[error("Unbalanced braces or environments.\n\n{}", describe_issues(.issues))]
+    Unbalanced { issues: Vec<LintIssue> },
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The compilation was cancelled, e.g. because the window closed.
+    #[error("Compilation cancelled")]
+    Cancelled,
+}
+
+impl CompileError {
+    /// Short summary for status labels and error page headings.
+    pub fn title(&self) -> &'static str {
+        match self {
+            CompileError::BinaryNotFound(_) => "LaTeX Tools Missing",
+            CompileError::Timeout(_) => "Compilation Timed Out",
+            CompileError::TooLarge { .. } => "Document Too Large",
+            CompileError::LatexFailed { .. } => "Compilation Error",
+            CompileError::ConversionFailed { .. } => "Preview Conversion Failed",
+            CompileError::Unbalanced { .. } => "Unbalanced Source",
+            CompileError::Io(_) => "File Error",
+            CompileError::Cancelled => "Cancelled",
+        }
+    }
+}
+
+/// One `Line N: message` line per issue, with 1-based line numbers.
+fn describe_issues(issues: &[LintIssue]) -> String {
+    issues
+        .iter()
+        .map(|issue| format!("Line {}: {}", issue.line + 1, issue.message))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// HTML produced for the preview pane along with whether compilation succeeded.
+#[derive(Debug, Clone)]
+pub struct RenderOutput {
+    /// Complete HTML page to load into the WebView.
+    pub html: String,
+    /// `false` when `html` is an error page.
+    pub success: bool,
+    /// Sanitized compilation log (or the error text when no log was produced).
+    pub log: String,
+    /// [`CompileError::title`] of the failure, `None` on success.
+    pub error_title: Option<&'static str>,
+    /// Pages in the compiled PDF, `None` on failure.
+    pub page_count: Option<usize>,
+    /// The compiled PDF, `None` on failure.
+    pub pdf_bytes: Option<Vec<u8>>,
+}
+
+/// Successful compilation result: one SVG per page plus the LaTeX log.
+#[derive(Debug, Clone)]
+pub struct CompileOutput {
+    /// Empty with [`PreviewBackend::Pdf`] or when pdftocairo is missing.
+    pub svgs: Vec<String>,
+    /// The compiled PDF.
+    pub pdf_bytes: Vec<u8>,
+    /// Sanitized contents of `doc.log` from the final pass.
+    pub log: String,
+    /// LaTeX and package warnings found in `log`, one per entry.
+    pub warnings: Vec<String>,
+    /// Page count and dimensions reported by `pdfinfo`.
+    pub pdf_info: PdfInfo,
+    /// Where each outline section landed in the PDF, via SyncTeX.
+    pub section_anchors: Vec<SectionAnchor>,
+}
+
+/// Helper programs run between LaTeX passes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuxiliaryTool {
+    Biber,
+    Bibtex,
+    Makeindex,
+    Makeglossaries,
+}
+
+impl AuxiliaryTool {
+    pub fn program(self) -> &'static str {
+        match self {
+            AuxiliaryTool::Biber => "biber",
+            AuxiliaryTool::Bibtex => "bibtex",
+            AuxiliaryTool::Makeindex => "makeindex",
+            AuxiliaryTool::Makeglossaries => "makeglossaries",
+        }
+    }
+
+    /// The argument naming the build's `doc` files.
+    fn arg(self) -> &'static str {
+        match self {
+            AuxiliaryTool::Makeindex => "doc.idx",
+            _ => "doc",
+        }
+    }
+}
+
+/// One program run by [`Preview::compile_latex`], as predicted by [`Preview::plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedStep {
+    pub program: &'static str,
+    /// What the run is for.
+    pub purpose: String,
+    /// Why the run may be skipped, for steps decided only once earlier ones ran.
+    pub only_if: Option<&'static str>,
+}
+
+/// The tools a compilation is expected to run, in order, without running them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompilePlan {
+    pub steps: Vec<PlannedStep>,
+}
+
+impl CompilePlan {
+    fn push(&mut self, program: &'static str, purpose: impl Into<String>) {
+        self.steps.push(PlannedStep {
+            program,
+            purpose: purpose.into(),
+            only_if: None,
+        });
+    }
+
+    fn push_if(&mut self, program: &'static str, purpose: &str, only_if: &'static str) {
+        self.steps.push(PlannedStep {
+            program,
+            purpose: purpose.to_string(),
+            only_if: Some(only_if),
+        });
+    }
+
+    /// Numbered steps, one per line.
+    pub fn describe(&self) -> String {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let mut line = format!("{}. {} — {}", i + 1, step.program, step.purpose);
+                if let Some(condition) = step.only_if {
+                    line.push_str(&format!(" (only if {})", condition));
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Rendered position of a section heading, used to scroll the preview to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SectionAnchor {
+    /// Index into `utils::extract_sections`, matching the outline row.
+    pub index: usize,
+    /// One-based PDF page number.
+    pub page: usize,
+    /// Baseline distance from the top of the page, in PDF points.
+    pub y_pt: f64,
+}
+
+/// A position SyncTeX recorded for a line of the compiled document.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SynctexRecord {
+    line: usize,
+    /// One-based PDF page number.
+    page: usize,
+    /// Distance from the top of the page, in PDF points.
+    y_pt: f64,
+}
+
+/// Page dimensions in PDF points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSize {
+    pub width_pt: f64,
+    pub height_pt: f64,
+}
+
+impl PageSize {
+    /// Parses the `612 x 792 pts (letter)` part of a `pdfinfo` size line.
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts = text.split_whitespace();
+        let width_pt = parts.next()?.parse().ok()?;
+        if parts.next()? != "x" {
+            return None;
+        }
+        let height_pt = parts.next()?.parse().ok()?;
+        Some(Self {
+            width_pt,
+            height_pt,
+        })
+    }
+}
+
+/// Document metadata gathered from `pdfinfo`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfInfo {
+    pub page_count: usize,
+    /// Size of the first page, used when per-page sizes are unavailable.
+    pub page_size: Option<PageSize>,
+    /// Size of each page, in order. May be empty.
+    pub page_sizes: Vec<PageSize>,
+}
+
+impl Default for PdfInfo {
+    fn default() -> Self {
+        Self {
+            page_count: 1,
+            page_size: None,
+            page_sizes: Vec::new(),
+        }
+    }
+}
+
+impl PdfInfo {
+    /// Size of the given zero-based page, falling back to the first page's size.
+    pub fn size_of(&self, page: usize) -> Option<PageSize> {
+        self.page_sizes.get(page).copied().or(self.page_size)
+    }
+}
+
+/// Shared flag asking an in-flight compilation to stop.
+///
+/// Child processes are polled, so a cancelled compile kills and reaps its
+/// running child within one poll interval instead of leaving it orphaned.
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// A step of a running compilation, reported through [`ProgressSink`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileProgress {
+    Started,
+    /// A run of the engine; `pass` counts from 1.
+    LatexPass {
+        engine: &'static str,
+        pass: usize,
+    },
+    Tool(AuxiliaryTool),
+    /// Converting `page` of `total` to SVG.
+    ConvertingPage {
+        page: usize,
+        total: usize,
+    },
+}
+
+impl CompileProgress {
+    /// Status text such as `Running pdflatex (pass 2)…`.
+    pub fn describe(&self) -> String {
+        match self {
+            CompileProgress::Started => "Compiling…".to_string(),
+            CompileProgress::LatexPass { engine, pass: 1 } => format!("Running {}…", engine),
+            CompileProgress::LatexPass { engine, pass } => {
+                format!("Running {} (pass {})…", engine, pass)
+            }
+            CompileProgress::Tool(tool) => format!("Running {}…", tool.program()),
+            CompileProgress::ConvertingPage { page, total } => {
+                format!("Converting page {}/{}…", page, total)
+            }
+        }
+    }
+}
+
+/// Where a compilation reports its [`CompileProgress`]. The default reports
+/// nowhere; [`crate::queue::CompilationQueue`] passes its broadcast channel.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressSink(Option<tokio::sync::broadcast::Sender<CompileProgress>>);
+
+impl ProgressSink {
+    pub fn new(sender: tokio::sync::broadcast::Sender<CompileProgress>) -> Self {
+        Self(Some(sender))
+    }
+
+    fn report(&self, progress: CompileProgress) {
+        if let Some(sender) = &self.0 {
+            // Nobody listening is fine
+            let _ = sender.send(progress);
+        }
+    }
+}
+
+/// Per-compile settings supplied by the caller.
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    /// Persistent directory to compile in, leaving intermediate files behind.
+    /// `None` compiles in a throwaway temp directory.
+    pub build_dir: Option<PathBuf>,
+    /// Scale factor applied to the natural page width in the preview.
+    pub preview_scale: f64,
+    /// How page widths relate to the pane; only [`PreviewLayout::Fixed`] uses
+    /// `preview_scale`.
+    pub preview_layout: PreviewLayout,
+    /// [`PreviewBackend::Pdf`] skips the SVG conversion entirely.
+    pub backend: PreviewBackend,
+    /// Dump the preamble into a cached format and only typeset the body.
+    pub precompile_preamble: bool,
+    /// Extra preamble (shared macros) injected after `\documentclass`.
+    pub preview_preamble: String,
+    /// Wrap input without a `\documentclass` in a minimal document.
+    pub wrap_fragments: bool,
+    /// Also write the log's errors and warnings as JSON for other tools.
+    pub diagnostics: Option<DiagnosticsOutput>,
+    /// Show page numbers over the preview pages; see [`PageNumberOverlay`].
+    pub page_numbers: bool,
+    pub engine: LatexEngine,
+    /// Allow `\write18`. Off unless the user turned it on, since it lets the
+    /// document run arbitrary commands.
+    pub shell_escape: bool,
+    /// Limit for each run of the engine and its helper tools.
+    pub timeout_secs: u64,
+    /// Folder searched for `\input` files, images and bibliographies, e.g. the
+    /// project folder when compiling its main file.
+    pub input_dir: Option<PathBuf>,
+    /// Set to abort the compilation and kill whatever tool is running.
+    pub cancel: CancelFlag,
+    /// Told about each pass, helper tool and page conversion as it starts.
+    pub progress: ProgressSink,
+    /// Check braces and environments with [`lint_latex`] first and fail with
+    /// [`CompileError::Unbalanced`] instead of running TeX on a broken source.
+    pub lint_first: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            build_dir: None,
+            preview_scale: DEFAULT_PREVIEW_SCALE,
+            preview_layout: PreviewLayout::default(),
+            backend: PreviewBackend::default(),
+            precompile_preamble: false,
+            preview_preamble: String::new(),
+            wrap_fragments: true,
+            diagnostics: None,
+            page_numbers: false,
+            engine: LatexEngine::default(),
+            shell_escape: false,
+            timeout_secs: COMPILE_TIMEOUT_SECS,
+            input_dir: None,
+            cancel: CancelFlag::default(),
+            progress: ProgressSink::default(),
+            lint_first: false,
+        }
+    }
+}
+
+/// Relates lines of the compiled `doc.tex` to the user's buffer when extra
+/// lines were injected into it. Lines are 1-based.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LineMap {
+    /// First injected line of `doc.tex`.
+    start: usize,
+    /// Number of injected lines.
+    count: usize,
+}
+
+impl LineMap {
+    /// The `doc.tex` line holding the user's `line`.
+    pub fn to_compiled(&self, line: usize) -> usize {
+        if line >= self.start {
+            line + self.count
+        } else {
+            line
+        }
+    }
+
+    /// The user's line for a `doc.tex` line, or `None` inside the injected lines.
+    pub fn to_user(&self, line: usize) -> Option<usize> {
+        if line < self.start {
+            Some(line)
+        } else if line < self.start + self.count {
+            None
+        } else {
+            Some(line - self.count)
+        }
+    }
+}
+
+/// Working directory for a single compilation.
+enum BuildDir {
+    /// Deleted when dropped.
+    Temp(TempDir),
+    /// Kept after compilation so `.aux`, `.bbl`, `.log` can be inspected.
+    Kept(PathBuf),
+}
+
+impl BuildDir {
+    fn prepare(options: &CompileOptions) -> Result<Self, CompileError> {
+        match &options.build_dir {
+            Some(path) => {
+                fs::create_dir_all(path)?;
+                // A PDF left over from a previous run would mask a failed compile.
+                let _ = fs::remove_file(path.join("doc.pdf"));
+                // Files from an earlier document would trigger needless tool
+                // runs, bring back its bibliography or misplace its sections.
+                for name in [
+                    "doc.aux",
+                    "doc.bcf",
+                    "doc.bbl",
+                    "doc.idx",
+                    "doc.glo",
+                    "doc.synctex",
+                ] {
+                    let _ = fs::remove_file(path.join(name));
+                }
+                Ok(BuildDir::Kept(path.clone()))
+            }
+            None => Ok(BuildDir::Temp(tempdir()?)),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            BuildDir::Temp(dir) => dir.path(),
+            BuildDir::Kept(path) => path,
+        }
+    }
+}
+
+/// Which poppler-utils programs are installed, probed once per process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PopplerTools {
+    /// Converts pages to SVG; without it the preview embeds the PDF instead.
+    pub pdftocairo: bool,
+    /// Reports page sizes; without it pages are counted from the PDF itself.
+    pub pdfinfo: bool,
+}
 
 #[derive(Debug)]
 struct PdfLatexCapabilities {
@@ -24,7 +601,83 @@ impl Default for Preview {
 
 impl Preview {
     pub fn new() -> Self {
-        Preview
+        Preview {
+            post_processors: vec![Arc::new(PageNumberOverlay)],
+            csp: Arc::from(DEFAULT_PREVIEW_CSP),
+        }
+    }
+
+    /// A preview using the configured CSP. An invalid policy is logged and the
+    /// strict default kept.
+    pub fn from_config(config: &AppConfig) -> Self {
+        let Some(csp) = &config.preview_content_security_policy else {
+            return Self::new();
+        };
+        Self::new()
+            .with_content_security_policy(csp)
+            .unwrap_or_else(|e| {
+                tracing::warn!("{}; using the default", e);
+                Self::new()
+            })
+    }
+
+    /// Replaces the strict default Content-Security-Policy of preview pages.
+    ///
+    /// Loosening it is a security trade-off: `script-src` lets scripts from
+    /// compiled documents (e.g. in embedded SVG) run inside the preview, and
+    /// relaxing `default-src` lets pages load remote content.
+    pub fn with_content_security_policy(mut self, csp: &str) -> Result<Self, EmptyCspError> {
+        let csp = csp.trim();
+        if csp.trim_matches(';').trim().is_empty() {
+            return Err(EmptyCspError);
+        }
+        self.csp = Arc::from(csp);
+        Ok(self)
+    }
+
+    /// The page CSP with `source` also allowed by `directive`. A directive the
+    /// policy already has is extended, since browsers ignore a repeated one;
+    /// otherwise it is appended.
+    fn csp_allowing(&self, directive: &str, source: &str) -> String {
+        let mut found = false;
+        let mut directives: Vec<String> = self
+            .csp
+            .split(';')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| {
+                let mut parts = d.split_whitespace();
+                let name = parts.next().unwrap_or_default();
+                if found || !name.eq_ignore_ascii_case(directive) {
+                    return d.to_string();
+                }
+                found = true;
+                // 'none' cannot be combined with other sources
+                let sources: Vec<&str> = parts
+                    .filter(|s| !s.eq_ignore_ascii_case("'none'") && *s != source)
+                    .chain([source])
+                    .collect();
+                format!("{} {}", name, sources.join(" "))
+            })
+            .collect();
+        if !found {
+            directives.push(format!("{} {}", directive, source));
+        }
+        format!("{};", directives.join("; "))
+    }
+
+    /// Adds `processor` after the built-in ones.
+    #[allow(dead_code)]
+    pub fn with_post_processor(mut self, processor: impl PreviewPostProcessor + 'static) -> Self {
+        self.post_processors.push(Arc::new(processor));
+        self
+    }
+
+    fn post_process(&self, mut html: String, options: &CompileOptions) -> String {
+        for processor in &self.post_processors {
+            processor.process(&mut html, options);
+        }
+        html
     }
 
     /// Detects pdflatex capabilities (supported security flags)
@@ -44,17 +697,43 @@ impl Preview {
         })
     }
 
-    /// Creates a secure pdflatex command with appropriate security flags
-    fn secure_pdflatex_command(
+    /// Detects pdftocairo and pdfinfo once, so a missing poppler-utils is known
+    /// before a compile instead of failing after pdflatex has already run.
+    pub fn poppler_tools() -> PopplerTools {
+        static TOOLS: OnceLock<PopplerTools> = OnceLock::new();
+        *TOOLS.get_or_init(|| {
+            let installed = |program: &str| tool_command(program).arg("-v").output().is_ok();
+            let tools = PopplerTools {
+                pdftocairo: installed("pdftocairo"),
+                pdfinfo: installed("pdfinfo"),
+            };
+            if !tools.pdftocairo {
+                tracing::warn!("pdftocairo not found; the preview will embed the PDF directly");
+            }
+            tools
+        })
+    }
+
+    /// Creates a command running `options.engine` with appropriate security flags.
+    ///
+    /// `format_dir` is searched for the format named on a `%&` first line.
+    fn secure_latex_command(
         &self,
         temp_dir: &std::path::Path,
         input_path: &std::path::Path,
+        format_dir: Option<&Path>,
+        options: &CompileOptions,
     ) -> Command {
         let caps = Self::pdflatex_capabilities();
-        let mut cmd = Command::new("pdflatex");
+        let mut cmd = tool_command(options.engine.program());
 
-        // Essential security: disable shell escape
-        cmd.arg("-no-shell-escape");
+        // Essential security: disable shell escape unless the user allowed it
+        if options.shell_escape {
+            cmd.arg("-shell-escape");
+        } else {
+            cmd.arg("-no-shell-escape");
+        }
+        Self::add_input_dir(&mut cmd, options);
 
         // Restrict file access if supported
         if caps.supports_openin_any {
@@ -67,8 +746,18 @@ impl Preview {
         // Run in temp directory to further restrict access
         cmd.current_dir(temp_dir);
 
-        // Standard arguments
+        if let Some(format_dir) = format_dir {
+            cmd.arg("-parse-first-line");
+            // The trailing empty entry keeps kpathsea's default format path
+            if let Ok(path) = std::env::join_paths([format_dir, Path::new("")]) {
+                cmd.env("TEXFORMATS", path);
+            }
+        }
+
+        // Standard arguments; SyncTeX data maps source lines to preview positions.
+        // It is written uncompressed so `locate_sections` can read it directly
         cmd.arg("-interaction=nonstopmode")
+            .arg("-synctex=-1")
             .arg("-output-directory")
             .arg(temp_dir)
             .arg(input_path);
@@ -76,225 +765,1016 @@ impl Preview {
         cmd
     }
 
+    /// Lets TeX and the bibliography tools find files in `options.input_dir`.
+    fn add_input_dir(cmd: &mut Command, options: &CompileOptions) {
+        if let Some(dir) = &options.input_dir {
+            // The trailing empty entry keeps kpathsea's default search path
+            if let Ok(path) = std::env::join_paths([dir.as_path(), Path::new("")]) {
+                cmd.env("TEXINPUTS", &path).env("BIBINPUTS", &path);
+            }
+        }
+    }
+
     fn sanitize_paths(text: &str, temp_dir: &str, input_path: &str) -> String {
         text.replace(temp_dir, "[TEMP_DIR]")
             .replace(input_path, "[TEMP_DIR]/doc.tex")
     }
 
+    /// Runs `cmd` to completion, killing and reaping it if it outlives
+    /// `timeout_secs` or `cancel` is set.
     fn run_command_with_timeout(
         cmd: &mut std::process::Command,
         timeout_secs: u64,
-    ) -> Result<std::process::Output, String> {
+        cancel: &CancelFlag,
+    ) -> Result<std::process::Output, CompileError> {
         use std::time::{Duration, Instant};
-        let mut child = cmd
-            .spawn()
-            .map_err(|e| format!("Failed to spawn command: {}", e))?;
+        let mut child = cmd.spawn().map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                CompileError::BinaryNotFound(cmd.get_program().to_string_lossy().into_owned())
+            } else {
+                CompileError::Io(e)
+            }
+        })?;
         let start = Instant::now();
         let timeout = Duration::from_secs(timeout_secs);
 
         while start.elapsed() < timeout {
+            if cancel.is_cancelled() {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(CompileError::Cancelled);
+            }
             match child.try_wait() {
                 Ok(Some(_status)) => {
-                    let output = child
-                        .wait_with_output()
-                        .map_err(|e| format!("Failed to get output: {}", e))?;
-                    return Ok(output);
+                    return Ok(child.wait_with_output()?);
                 }
                 Ok(None) => {
                     std::thread::sleep(Duration::from_millis(PROCESS_POLL_INTERVAL_MS));
                     continue;
                 }
-                Err(e) => return Err(format!("Error waiting for child: {}", e)),
+                Err(e) => return Err(CompileError::Io(e)),
             }
         }
         // Timeout reached
         let _ = child.kill();
         let _ = child.wait();
-        Err(format!("Command timed out after {} seconds", timeout_secs))
+        Err(CompileError::Timeout(timeout_secs))
+    }
+
+    /// Sends `cmd`'s terminal output to the file `path`. A pipe would do, but
+    /// TeX can fill it while [`Self::run_command_with_timeout`] only polls.
+    fn stdout_to_file(cmd: &mut Command, path: &Path) -> Result<(), CompileError> {
+        cmd.stdout(fs::File::create(path)?);
+        Ok(())
     }
 
     pub fn render(&self, content: &str, dark_mode: bool) -> String {
-        match self.compile_latex(content) {
-            Ok(svgs) => self.wrap_svgs(svgs, dark_mode),
-            Err(e) => self.wrap_error(&e),
-        }
+        self.render_with_status(content, dark_mode, &CompileOptions::default())
+            .html
     }
 
-    /// Compiles LaTeX string directly to a PDF file at the specified destination.
+    /// Compiles `latex` with the default options and returns one standalone SVG
+    /// document per page, without the HTML page [`Preview::render`] wraps them in.
     #[allow(dead_code)]
-    pub fn export_pdf(&self, latex: &str, destination: &std::path::Path) -> Result<(), String> {
-        // Security: Validate input size
-        if latex.len() > MAX_LATEX_SIZE_BYTES {
-            return Err("Document too large".to_string());
+    pub fn compile_to_svgs(&self, latex: &str) -> Result<Vec<String>, CompileError> {
+        let options = CompileOptions {
+            backend: PreviewBackend::Svg,
+            ..CompileOptions::default()
+        };
+        let output = self.compile_latex(latex, &options)?;
+        // compile_latex only skips the conversion when pdftocairo is missing
+        if output.svgs.is_empty() {
+            return Err(CompileError::BinaryNotFound("pdftocairo".to_string()));
+        }
+        Ok(output.svgs)
+    }
+
+    /// Like [`Preview::render`], but takes explicit compile options and also reports
+    /// whether compilation succeeded.
+    pub fn render_with_status(
+        &self,
+        content: &str,
+        dark_mode: bool,
+        options: &CompileOptions,
+    ) -> RenderOutput {
+        match self.compile_latex(content, options) {
+            Ok(output) => RenderOutput {
+                page_count: Some(output.pdf_info.page_count),
+                // No SVG pages means the PDF backend or a missing pdftocairo
+                html: self.post_process(
+                    if output.svgs.is_empty() {
+                        self.wrap_pdf(&output.pdf_bytes, dark_mode)
+                    } else {
+                        self.wrap_svgs(
+                            output.svgs,
+                            dark_mode,
+                            &output.pdf_info,
+                            &output.section_anchors,
+                            options.preview_scale,
+                            options.preview_layout,
+                        )
+                    },
+                    options,
+                ),
+                success: true,
+                log: output.log,
+                error_title: None,
+                pdf_bytes: Some(output.pdf_bytes),
+            },
+            Err(e) => RenderOutput {
+                html: self.wrap_error(&e, dark_mode),
+                success: false,
+                log: e.to_string(),
+                error_title: Some(e.title()),
+                page_count: None,
+                pdf_bytes: None,
+            },
+        }
+    }
+
+    /// Renders a single formula with the bundled KaTeX, without running pdflatex.
+    ///
+    /// Surrounding `$…$`, `$$…$$`, `\(…\)` or `\[…\]` delimiters are optional.
+    /// KaTeX emits MathML, which WebKit draws natively, so the page needs no
+    /// scripts, stylesheets or fonts. Parse errors are shown in place of the formula.
+    pub fn render_math_snippet(&self, tex: &str, dark_mode: bool) -> String {
+        let body_content = Self::math_snippet_body(tex);
+        let body_class = if dark_mode { "dark-mode" } else { "" };
+
+        format!(
+            "{}",
+            html! {
+                : doctype::HTML;
+                html {
+                     head {
+                         meta(charset="utf-8");
+                         meta(http-equiv="Content-Security-Policy", content=&*self.csp);
+                         meta(http-equiv="X-Frame-Options", content="DENY");
+                         meta(http-equiv="X-Content-Type-Options", content="nosniff");
+                         style {
+                             : Raw("
+                                 body {
+                                     background-color: #f0f0f0;
+                                     color: #000;
+                                     display: flex;
+                                     justify-content: center;
+                                     padding: 40px 20px;
+                                 }
+                                 .math { font-size: 1.6em; overflow-x: auto; }
+                                 .hint { color: #777; font-family: sans-serif; }
+                                 .error { color: #a94442; white-space: pre-wrap; }
+
+                                 body.dark-mode { background-color: #1e1e1e; color: #eee; }
+                                 body.dark-mode .error { color: #ff9999; }
+                             ")
+                         }
+                     }
+                    body(class=body_class) {
+                        : Raw(&body_content);
+                    }
+                }
+            }
+        )
+    }
+
+    /// Rendered MathML for `tex`, a hint when it is empty, or the parse error.
+    fn math_snippet_body(tex: &str) -> String {
+        let source = Self::strip_math_delimiters(tex);
+        if source.is_empty() {
+            "<p class=\"hint\">Select a formula to preview it.</p>".to_string()
+        } else {
+            let opts = katex::Opts::builder()
+                .display_mode(true)
+                .output_type(katex::OutputType::Mathml)
+                .build()
+                .expect("valid KaTeX options");
+            match katex::render_with_opts(source, &opts) {
+                Ok(mathml) => format!("<div class=\"math\">{}</div>", mathml),
+                Err(e) => format!(
+                    "<pre class=\"error\">{}</pre>",
+                    encode_text(&Self::katex_error_message(&e.to_string()))
+                ),
+            }
+        }
+    }
+
+    /// `tex` without whitespace and one pair of inline or display math delimiters.
+    fn strip_math_delimiters(tex: &str) -> &str {
+        let tex = tex.trim();
+        for (open, close) in [("$$", "$$"), ("\\[", "\\]"), ("\\(", "\\)"), ("$", "$")] {
+            if let Some(inner) = tex
+                .strip_prefix(open)
+                .and_then(|rest| rest.strip_suffix(close))
+            {
+                return inner.trim();
+            }
         }
+        tex
+    }
+
+    /// The KaTeX parse error without the JS engine's wrapping and the
+    /// position marker, e.g. `Undefined control sequence: \foo`.
+    fn katex_error_message(error: &str) -> String {
+        const MARKER: &str = "KaTeX parse error: ";
+        let Some(pos) = error.find(MARKER) else {
+            return error.to_string();
+        };
+        let message = &error[pos + MARKER.len()..];
+        let end = [" at position ", " at end of input"]
+            .iter()
+            .filter_map(|suffix| message.find(suffix))
+            .min()
+            .unwrap_or(message.len());
+        // The JS error arrives Debug-formatted, with backslashes doubled
+        message[..end]
+            .trim_end_matches("\"))")
+            .replace("\\\\", "\\")
+    }
 
-        let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+    /// Compiles LaTeX string directly to a PDF file at the specified destination,
+    /// with the engine, shell escape and timeout from `options`.
+    pub fn export_pdf(
+        &self,
+        latex: &str,
+        destination: &std::path::Path,
+        options: &CompileOptions,
+    ) -> Result<(), CompileError> {
+        // Security: Validate input size
+        Self::check_size(latex)?;
+
+        let dir = tempdir()?;
         let input_path = dir.path().join("doc.tex");
         let temp_dir_path = dir.path().to_string_lossy().to_string();
         let input_path_str = input_path.to_string_lossy().to_string();
 
-        fs::write(&input_path, latex).map_err(|e| {
-            Self::sanitize_paths(
-                &format!("Failed to write tex file: {}", e),
-                &temp_dir_path,
-                &input_path_str,
-            )
-        })?;
-
-        let mut cmd = self.secure_pdflatex_command(dir.path(), &input_path);
+        fs::write(&input_path, latex)?;
 
+        let mut cmd = self.secure_latex_command(dir.path(), &input_path, None, options);
         let output =
-            Self::run_command_with_timeout(&mut cmd, COMPILE_TIMEOUT_SECS).map_err(|e| {
-                Self::sanitize_paths(
-                    &format!("Failed to run pdflatex: {}", e),
-                    &temp_dir_path,
-                    &input_path_str,
-                )
-            })?;
+            Self::run_command_with_timeout(&mut cmd, options.timeout_secs, &options.cancel)?;
 
         let pdf_path = dir.path().join("doc.pdf");
         if !pdf_path.exists() {
+            let log = fs::read_to_string(dir.path().join("doc.log"))
+                .unwrap_or_else(|_| String::from_utf8_lossy(&output.stderr).into_owned());
+            return Err(CompileError::LatexFailed {
+                excerpts: diagnostics::source_excerpts(&log, latex, LineMap::default()),
+                log: Self::sanitize_paths(&log, &temp_dir_path, &input_path_str),
+            });
+        }
+
+        fs::copy(&pdf_path, destination)?;
+        Ok(())
+    }
+
+    /// Rasterizes one page (1-based) of an already compiled PDF to PNG at `dpi`.
+    pub fn render_page_png(
+        &self,
+        pdf_bytes: &[u8],
+        page: usize,
+        dpi: u32,
+    ) -> Result<Vec<u8>, CompileError> {
+        let dir = tempdir()?;
+        let pdf_path = dir.path().join("doc.pdf");
+        fs::write(&pdf_path, pdf_bytes)?;
+
+        // With -singlefile pdftocairo writes `page.png` rather than `page-N.png`
+        let mut cmd = tool_command("pdftocairo");
+        cmd.arg("-png")
+            .arg("-singlefile")
+            .arg("-r")
+            .arg(dpi.to_string())
+            .arg("-f")
+            .arg(page.to_string())
+            .arg("-l")
+            .arg(page.to_string())
+            .arg(&pdf_path)
+            .arg(dir.path().join("page"));
+
+        let output =
+            Self::run_command_with_timeout(&mut cmd, COMPILE_TIMEOUT_SECS, &CancelFlag::default())?;
+        let png_path = dir.path().join("page.png");
+        if !output.status.success() || !png_path.exists() {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(format!("LaTeX failed to generate a PDF.\n{}", stderr));
+            return Err(CompileError::ConversionFailed {
+                page,
+                stderr: Self::sanitize_paths(
+                    &stderr,
+                    &dir.path().to_string_lossy(),
+                    &pdf_path.to_string_lossy(),
+                ),
+            });
         }
+        Ok(fs::read(png_path)?)
+    }
 
-        fs::copy(&pdf_path, destination)
-            .map_err(|e| format!("Failed to copy PDF to destination: {}", e))?;
+    /// Rejects documents over [`MAX_LATEX_SIZE_BYTES`] to prevent DoS.
+    fn check_size(latex: &str) -> Result<(), CompileError> {
+        if latex.len() > MAX_LATEX_SIZE_BYTES {
+            return Err(CompileError::TooLarge {
+                size: latex.len(),
+                max: MAX_LATEX_SIZE_BYTES,
+            });
+        }
         Ok(())
     }
 
-    fn get_pdf_page_count(&self, pdf_path: &std::path::Path) -> usize {
-        let mut cmd = Command::new("pdfinfo");
-        cmd.arg(pdf_path);
-        if let Ok(output) = cmd.output() {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            for line in stdout.lines() {
-                if line.starts_with("Pages:") {
-                    return line
-                        .split_whitespace()
-                        .last()
-                        .and_then(|s| s.parse().ok())
-                        .unwrap_or(1);
-                }
+    /// Queries `pdfinfo` for the page count and page dimensions.
+    ///
+    /// A plain call reports `Pages:` and the first page's `Page size:`; for
+    /// multi-page documents a second `-f 1 -l N` call lists each page's size so
+    /// mixed portrait/landscape documents keep their shapes. Both calls obey
+    /// the compile's timeout and cancel flag; only cancellation is an error.
+    fn get_pdf_info(
+        &self,
+        pdf_path: &std::path::Path,
+        options: &CompileOptions,
+    ) -> Result<PdfInfo, CompileError> {
+        if !Self::poppler_tools().pdfinfo {
+            return Ok(PdfInfo {
+                page_count: fs::read(pdf_path).map_or(1, |bytes| Self::count_pdf_pages(&bytes)),
+                ..PdfInfo::default()
+            });
+        }
+        let mut info = Self::run_pdfinfo(pdf_path, None, options)?.unwrap_or_default();
+
+        if info.page_count > 1 {
+            if let Some(per_page) = Self::run_pdfinfo(pdf_path, Some(info.page_count), options)? {
+                info.page_sizes = per_page.page_sizes;
+            }
+        }
+        Ok(info)
+    }
+
+    /// Runs `pdfinfo` on `pdf_path`, listing pages 1 to `last_page` if given.
+    /// The report goes to a file beside the PDF rather than a pipe, which a
+    /// long page list could fill while the process is only polled.
+    fn run_pdfinfo(
+        pdf_path: &Path,
+        last_page: Option<usize>,
+        options: &CompileOptions,
+    ) -> Result<Option<PdfInfo>, CompileError> {
+        let report_path = pdf_path.with_extension("pdfinfo");
+        let Ok(report) = fs::File::create(&report_path) else {
+            return Ok(None);
+        };
+        let mut cmd = tool_command("pdfinfo");
+        if let Some(last_page) = last_page {
+            cmd.arg("-f").arg("1").arg("-l").arg(last_page.to_string());
+        }
+        cmd.arg(pdf_path).stdout(report);
+        let result =
+            Self::run_command_with_timeout(&mut cmd, options.timeout_secs, &options.cancel);
+        let report = fs::read_to_string(&report_path).ok();
+        let _ = fs::remove_file(&report_path);
+        match result {
+            Ok(_) => Ok(report.map(|report| Self::parse_pdf_info(&report))),
+            Err(CompileError::Cancelled) => Err(CompileError::Cancelled),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Rough page count from the raw PDF: the number of `/Type /Page` objects.
+    /// Only used when `pdfinfo` is unavailable; never less than one.
+    fn count_pdf_pages(pdf: &[u8]) -> usize {
+        static PAGE_REGEX: OnceLock<regex::bytes::Regex> = OnceLock::new();
+        let re = PAGE_REGEX.get_or_init(|| regex::bytes::Regex::new(r"/Type\s*/Page[^s]").unwrap());
+        re.find_iter(pdf).count().max(1)
+    }
+
+    /// Finds where each section heading was rendered from the `doc.synctex` the
+    /// engine wrote, read once per compile rather than running `synctex view`
+    /// per heading. Sections SyncTeX did not record are skipped.
+    fn locate_sections(dir: &Path, latex: &str, line_map: LineMap) -> Vec<SectionAnchor> {
+        let Ok(synctex) = fs::read_to_string(dir.join("doc.synctex")) else {
+            return Vec::new();
+        };
+        let records = Self::parse_synctex(&synctex, "doc.tex");
+        crate::utils::extract_sections(latex)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, section)| {
+                let line = line_map.to_compiled(section.line as usize + 1);
+                // Like `synctex view`, fall back to the next line with output
+                // when the heading's own line has none
+                let record = records
+                    .iter()
+                    .filter(|r| r.line >= line)
+                    .min_by_key(|r| r.line)?;
+                Some(SectionAnchor {
+                    index,
+                    page: record.page,
+                    y_pt: record.y_pt,
+                })
+            })
+            .collect()
+    }
+
+    /// Positions an uncompressed SyncTeX file records for lines of the input
+    /// whose file name is `input`, in file order, so the first record of a
+    /// line is where its output starts.
+    fn parse_synctex(synctex: &str, input: &str) -> Vec<SynctexRecord> {
+        let mut tags = HashSet::new();
+        let mut unit = 1.0;
+        let mut magnification = 1.0;
+        let mut y_offset = 0.0;
+        let mut page = 0;
+        let mut records = Vec::new();
+        for line in synctex.lines() {
+            if let Some(rest) = line.strip_prefix("Input:") {
+                if let Some((tag, path)) = rest.split_once(':') {
+                    if Path::new(path).file_name() == Some(std::ffi::OsStr::new(input)) {
+                        tags.insert(tag.to_string());
+                    }
+                }
+            } else if let Some(value) = line.strip_prefix("Unit:") {
+                unit = value.trim().parse().unwrap_or(1.0);
+            } else if let Some(value) = line.strip_prefix("Magnification:") {
+                magnification = value.trim().parse::<f64>().unwrap_or(1000.0) / 1000.0;
+            } else if let Some(value) = line.strip_prefix("Y Offset:") {
+                y_offset = value.trim().parse().unwrap_or(0.0);
+            } else if let Some(value) = line.strip_prefix('{') {
+                page = value.trim().parse().unwrap_or(0);
+            } else if page > 0 && line.starts_with(['[', '(', 'h', 'v', 'x', 'k', 'g', '$']) {
+                // `<type><tag>,<line>[,<column>]:<h>,<v>[:...]`
+                let Some((link, position)) = line[1..].split_once(':') else {
+                    continue;
+                };
+                let mut link = link.split(',');
+                let (Some(tag), Some(Ok(source_line))) =
+                    (link.next(), link.next().map(str::parse::<usize>))
+                else {
+                    continue;
+                };
+                let Some(Ok(v)) = position
+                    .split(':')
+                    .next()
+                    .and_then(|hv| hv.split(',').nth(1))
+                    .map(str::parse::<f64>)
+                else {
+                    continue;
+                };
+                if tags.contains(tag) {
+                    records.push(SynctexRecord {
+                        line: source_line,
+                        page,
+                        y_pt: (v * unit * magnification + y_offset) / SCALED_POINTS_PER_BP,
+                    });
+                }
+            }
+        }
+        records
+    }
+
+    /// Parses `pdfinfo` output. Understands both the single `Page size:` line and
+    /// the per-page `Page    N size:` lines emitted with `-f`/`-l`.
+    fn parse_pdf_info(pdfinfo: &str) -> PdfInfo {
+        let mut info = PdfInfo::default();
+        for line in pdfinfo.lines() {
+            if let Some(pages) = line.strip_prefix("Pages:") {
+                info.page_count = pages.trim().parse().unwrap_or(1);
+            } else if let Some(size) = line.strip_prefix("Page size:") {
+                info.page_size = PageSize::parse(size);
+            } else if let Some(rest) = line.strip_prefix("Page ") {
+                if let Some((_, size)) = rest.split_once(" size:") {
+                    if let Some(size) = PageSize::parse(size) {
+                        info.page_sizes.push(size);
+                    }
+                }
+            }
+        }
+        if info.page_size.is_none() {
+            info.page_size = info.page_sizes.first().copied();
+        }
+        info
+    }
+
+    /// Builds the `doc.tex` source: `preamble` goes on the line after
+    /// `\documentclass{…}`, and a fragment without a document class is wrapped
+    /// in a minimal `article` when `wrap_fragments` is set.
+    fn prepare_source<'a>(
+        latex: &'a str,
+        preamble: &str,
+        wrap_fragments: bool,
+    ) -> (Cow<'a, str>, LineMap) {
+        let mut preamble = preamble.trim_end().to_string();
+        if !preamble.is_empty() {
+            preamble.push('\n');
+        }
+
+        static CLASS_REGEX: OnceLock<Regex> = OnceLock::new();
+        let class_regex =
+            CLASS_REGEX.get_or_init(|| Regex::new(r"(?m)^[ \t]*\\documentclass").unwrap());
+        match class_regex.find(latex) {
+            Some(_) if preamble.is_empty() => (Cow::Borrowed(latex), LineMap::default()),
+            Some(class) => {
+                // Options may span lines; the class name closes the command
+                let close = latex[class.end()..]
+                    .find('}')
+                    .map_or(class.end(), |i| class.end() + i);
+                let insert_at = latex[close..]
+                    .find('\n')
+                    .map_or(latex.len(), |i| close + i + 1);
+                let mut source = latex[..insert_at].to_string();
+                if !source.ends_with('\n') {
+                    source.push('\n');
+                }
+                let start = source.matches('\n').count() + 1;
+                source.push_str(&preamble);
+                source.push_str(&latex[insert_at..]);
+                (
+                    Cow::Owned(source),
+                    LineMap {
+                        start,
+                        count: preamble.matches('\n').count(),
+                    },
+                )
+            }
+            None if wrap_fragments => {
+                let (header, footer) = Self::fragment_wrapper(latex, &preamble);
+                let source = format!("{}{}\n{}", header, latex, footer);
+                (
+                    Cow::Owned(source),
+                    LineMap {
+                        start: 1,
+                        count: header.matches('\n').count(),
+                    },
+                )
+            }
+            None => (Cow::Borrowed(latex), LineMap::default()),
+        }
+    }
+
+    /// The lines placed before and after a fragment to make it a document.
+    /// Bare math such as `\frac{1}{2}` is additionally put in display math.
+    fn fragment_wrapper(fragment: &str, preamble: &str) -> (String, String) {
+        let mut header = format!(
+            "\\documentclass{{article}}\n\\usepackage{{amsmath,amssymb}}\n{}",
+            preamble
+        );
+        if fragment.contains("\\begin{document}") {
+            return (header, String::new());
+        }
+        header.push_str("\\pagestyle{empty}\n\\begin{document}\n");
+
+        let has_math_mode = ["$", "\\(", "\\[", "\\begin{"]
+            .iter()
+            .any(|marker| fragment.contains(marker));
+        let looks_like_math = ["^", "_", "\\frac", "\\sqrt", "\\sum", "\\int"]
+            .iter()
+            .any(|marker| fragment.contains(marker));
+        if !has_math_mode && looks_like_math && !fragment.contains("\n\n") {
+            header.push_str("\\[\n");
+            (header, "\\]\n\\end{document}\n".to_string())
+        } else {
+            (header, "\\end{document}\n".to_string())
+        }
+    }
+
+    /// Splits `latex` at the line starting with `\begin{document}` into the
+    /// preamble (whole lines, including a `\documentclass`) and the body.
+    fn split_preamble(latex: &str) -> Option<(&str, &str)> {
+        let mut offset = 0;
+        for line in latex.split_inclusive('\n') {
+            if line.trim_start().starts_with("\\begin{document}") {
+                let preamble = &latex[..offset];
+                return preamble
+                    .contains("\\documentclass")
+                    .then_some((preamble, &latex[offset..]));
+            }
+            offset += line.len();
+        }
+        None
+    }
+
+    /// Format name derived from the preamble text, so any edit to it selects a new format.
+    fn format_name(preamble: &str) -> String {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        preamble.hash(&mut hasher);
+        format!("preamble-{:016x}", hasher.finish())
+    }
+
+    /// The document to compile against the format `name`: a `%&name` line, blank
+    /// lines standing in for the rest of the preamble so line numbers in errors
+    /// and SyncTeX still match the editor, then the body.
+    fn with_format_line(name: &str, preamble: &str, body: &str) -> String {
+        format!(
+            "%&{}{}{}",
+            name,
+            "\n".repeat(preamble.matches('\n').count()),
+            body
+        )
+    }
+
+    fn format_cache_dir() -> PathBuf {
+        dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("latex-rs")
+            .join(PREAMBLE_FORMAT_DIR)
+    }
+
+    /// Name and directory of the format holding `preamble`, dumping it with
+    /// `pdflatex -ini` on first use. `None` when the preamble can't be dumped
+    /// (each preamble is only tried once) or the compile was cancelled.
+    fn cached_preamble_format(preamble: &str, cancel: &CancelFlag) -> Option<(String, PathBuf)> {
+        static FAILED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+        let failed = FAILED.get_or_init(Default::default);
+
+        let name = Self::format_name(preamble);
+        let cache = Self::format_cache_dir();
+        if cache.join(format!("{}.fmt", name)).exists() {
+            return Some((name, cache));
+        }
+        if failed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&name)
+        {
+            return None;
+        }
+
+        match Self::dump_format(&name, preamble, &cache, cancel) {
+            Ok(()) => {
+                Self::prune_format_cache(&cache);
+                Some((name, cache))
+            }
+            Err(CompileError::Cancelled) => None,
+            Err(e) => {
+                tracing::warn!(
+                    "Preamble could not be precompiled, compiling normally: {}",
+                    e
+                );
+                failed
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(name);
+                None
+            }
+        }
+    }
+
+    /// Runs `pdflatex -ini "&pdflatex preamble.tex"` with `\dump` appended to the
+    /// preamble, writing `<cache>/<name>.fmt`.
+    fn dump_format(
+        name: &str,
+        preamble: &str,
+        cache: &Path,
+        cancel: &CancelFlag,
+    ) -> Result<(), CompileError> {
+        fs::create_dir_all(cache)?;
+        // Dump in a scratch directory so a concurrent compile never loads half a format
+        let scratch = tempfile::tempdir_in(cache)?;
+        fs::write(
+            scratch.path().join("preamble.tex"),
+            format!("{}\\dump\n", preamble),
+        )?;
+
+        let mut cmd = tool_command("pdflatex");
+        cmd.current_dir(scratch.path())
+            .arg("-ini")
+            .arg("-no-shell-escape")
+            .arg("-interaction=nonstopmode")
+            .arg(format!("-jobname={}", name))
+            .arg("&pdflatex preamble.tex");
+        let terminal_path = scratch.path().join("terminal.txt");
+        Self::stdout_to_file(&mut cmd, &terminal_path)?;
+        let output = Self::run_command_with_timeout(&mut cmd, COMPILE_TIMEOUT_SECS, cancel)?;
+
+        let dumped = scratch.path().join(format!("{}.fmt", name));
+        if !output.status.success() || !dumped.exists() {
+            let log = fs::read_to_string(scratch.path().join(format!("{}.log", name)))
+                .or_else(|_| fs::read_to_string(&terminal_path))
+                .unwrap_or_default();
+            return Err(CompileError::LatexFailed {
+                log,
+                excerpts: Vec::new(),
+            });
+        }
+        fs::rename(dumped, cache.join(format!("{}.fmt", name)))?;
+        Ok(())
+    }
+
+    /// Deletes all but the [`PREAMBLE_FORMAT_CACHE_MAX`] newest formats.
+    fn prune_format_cache(cache: &Path) {
+        let Ok(entries) = fs::read_dir(cache) else {
+            return;
+        };
+        let mut formats: Vec<(std::time::SystemTime, PathBuf)> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "fmt" {
+                    return None;
+                }
+                Some((path.metadata().ok()?.modified().ok()?, path))
+            })
+            .collect();
+        formats.sort_by(|a, b| b.0.cmp(&a.0));
+        for (_, path) in formats.into_iter().skip(PREAMBLE_FORMAT_CACHE_MAX) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Runs a bibliography or index tool in the build directory.
+    /// We don't fail the compile if the tool fails, just log it and continue.
+    fn run_auxiliary_tool(dir: &Path, tool: AuxiliaryTool, options: &CompileOptions) {
+        options.progress.report(CompileProgress::Tool(tool));
+        let mut cmd = tool_command(tool.program());
+        cmd.current_dir(dir).arg(tool.arg());
+        Self::add_input_dir(&mut cmd, options);
+        if let Err(e) =
+            Self::run_command_with_timeout(&mut cmd, options.timeout_secs, &options.cancel)
+        {
+            tracing::warn!("{} failed: {}", tool.program(), e);
+        }
+    }
+
+    /// Whether a BibTeX run is needed: the `.aux` names a bibliography database
+    /// and either cites something or the log reports undefined references.
+    fn needs_bibtex(aux: &str, log: &str) -> bool {
+        aux.contains("\\bibdata")
+            && (aux.contains("\\citation") || log.contains("undefined references"))
+    }
+
+    /// Whether `log` asks for another LaTeX pass, e.g. for changed labels.
+    fn log_requests_rerun(log: &str) -> bool {
+        log.contains("Run LaTeX again")
+            || log.contains("Rerun to get")
+            || log.contains("Label(s) may have changed")
+    }
+
+    /// The helper tools the document's source calls for. [`Self::compile_latex`]
+    /// decides from the files the first pass writes (`.bcf`, `.aux`, `.idx`,
+    /// `.glo`); this reads the commands that make LaTeX write them.
+    fn tools_for_source(latex: &str) -> Vec<(AuxiliaryTool, &'static str)> {
+        static REGEXES: OnceLock<[Regex; 5]> = OnceLock::new();
+        let [biblatex, bibtex_backend, bibliography, makeindex, makeglossaries] = REGEXES
+            .get_or_init(|| {
+                [
+                    Regex::new(r"\\usepackage(\[[^\]]*\])?\{biblatex\}|\\addbibresource\b")
+                        .unwrap(),
+                    Regex::new(r"\\usepackage\[[^\]]*backend\s*=\s*bibtex").unwrap(),
+                    Regex::new(r"\\bibliography\s*\{").unwrap(),
+                    Regex::new(r"\\makeindex\b").unwrap(),
+                    Regex::new(r"\\makeglossaries\b").unwrap(),
+                ]
+            });
+        let code = crate::utils::strip_comments(latex);
+
+        let mut tools = Vec::new();
+        if biblatex.is_match(&code) {
+            if bibtex_backend.is_match(&code) {
+                tools.push((AuxiliaryTool::Bibtex, "biblatex with backend=bibtex"));
+            } else {
+                tools.push((AuxiliaryTool::Biber, "biblatex (\\addbibresource)"));
+            }
+        } else if bibliography.is_match(&code) {
+            tools.push((AuxiliaryTool::Bibtex, "\\bibliography"));
+        }
+        if makeindex.is_match(&code) {
+            tools.push((AuxiliaryTool::Makeindex, "\\makeindex"));
+        }
+        if makeglossaries.is_match(&code) {
+            tools.push((AuxiliaryTool::Makeglossaries, "\\makeglossaries"));
+        }
+        tools
+    }
+
+    /// The programs [`Self::compile_latex`] is expected to run for `latex` with
+    /// `options`, in order, without running anything.
+    ///
+    /// Helper tools are predicted from the source, while the compile itself
+    /// checks the files LaTeX writes, so a document that e.g. cites nothing may
+    /// skip a listed BibTeX run.
+    pub fn plan(latex: &str, options: &CompileOptions) -> CompilePlan {
+        let (source, _) =
+            Self::prepare_source(latex, &options.preview_preamble, options.wrap_fragments);
+        let engine = options.engine.program();
+        let tools = Self::tools_for_source(&source);
+
+        let mut plan = CompilePlan::default();
+        let mut first_pass = String::from("typesets the document");
+        if options.precompile_preamble && options.engine == LatexEngine::Pdflatex {
+            first_pass.push_str(" using the cached preamble format");
+        }
+        if !tools.is_empty() {
+            first_pass.push_str(" and records citations and index entries");
+        }
+        if options.shell_escape {
+            first_pass.push_str(", with shell escape");
+        }
+        plan.push(engine, first_pass);
+
+        if tools.is_empty() {
+            plan.push_if(
+                engine,
+                "resolves cross-references",
+                "the log asks for a rerun",
+            );
+        } else {
+            for (tool, reason) in &tools {
+                plan.push(tool.program(), format!("needed for {}", reason));
             }
+            plan.push(engine, "includes the generated bibliography and indexes");
+        }
+        for _ in 2..MAX_LATEX_PASSES {
+            plan.push_if(
+                engine,
+                "settles references that moved",
+                "the log still asks for a rerun",
+            );
+        }
+
+        if options.backend == PreviewBackend::Svg {
+            plan.push_if(
+                "pdftocairo",
+                "converts each page to SVG for the preview",
+                "pdftocairo is installed",
+            );
         }
-        1
+        plan
     }
 
-    fn compile_latex(&self, latex: &str) -> Result<Vec<String>, String> {
+    /// Compiles `latex` to PDF and, for the SVG backend, converts each page to SVG.
+    ///
+    /// This is the whole compilation engine and has no GUI dependencies, so it
+    /// can be driven from the command line (`--compile`) as well as the preview.
+    pub fn compile_latex(
+        &self,
+        latex: &str,
+        options: &CompileOptions,
+    ) -> Result<CompileOutput, CompileError> {
         // Security: Validate input size to prevent DoS
-        if latex.len() > MAX_LATEX_SIZE_BYTES {
-            return Err(format!(
-                "Document too large ({:.2} MB). Maximum allowed size is {:.2} MB.",
-                latex.len() as f64 / (1024.0 * 1024.0),
-                MAX_LATEX_SIZE_BYTES as f64 / (1024.0 * 1024.0)
-            ));
+        Self::check_size(latex)?;
+        if options.lint_first {
+            let issues = lint_latex(latex);
+            if !issues.is_empty() {
+                return Err(CompileError::Unbalanced { issues });
+            }
         }
+        options.progress.report(CompileProgress::Started);
 
-        let dir = tempdir().map_err(|e| format!("Failed to create temp dir: {}", e))?;
+        let dir = BuildDir::prepare(options)?;
         let input_path = dir.path().join("doc.tex");
         let temp_dir_path = dir.path().to_string_lossy().to_string();
         let input_path_str = input_path.to_string_lossy().to_string();
 
-        fs::write(&input_path, latex).map_err(|e| {
-            Self::sanitize_paths(
-                &format!("Failed to write tex file: {}", e),
-                &temp_dir_path,
-                &input_path_str,
-            )
-        })?;
+        // Shared preview macros and any fragment wrapper go in first, so a
+        // cached format includes them too
+        let (injected, line_map) =
+            Self::prepare_source(latex, &options.preview_preamble, options.wrap_fragments);
+
+        // With a cached preamble format only the body is typeset on each compile
+        let mut format = None;
+        let mut source = Cow::Borrowed(injected.as_ref());
+        // Preamble formats are dumped with pdflatex, so other engines can't load them
+        if options.precompile_preamble && options.engine == LatexEngine::Pdflatex {
+            if let Some((preamble, body)) = Self::split_preamble(&injected) {
+                if let Some((name, format_dir)) =
+                    Self::cached_preamble_format(preamble, &options.cancel)
+                {
+                    source = Cow::Owned(Self::with_format_line(&name, preamble, body));
+                    format = Some((name, format_dir));
+                }
+            }
+        }
+        fs::write(&input_path, source.as_bytes())?;
 
         // Smart multi-pass compilation
         let mut passes = 0;
-        let max_passes = 3;
         let mut needs_rerun = true;
 
-        while needs_rerun && passes < max_passes {
+        while needs_rerun && passes < MAX_LATEX_PASSES {
             passes += 1;
+            options.progress.report(CompileProgress::LatexPass {
+                engine: options.engine.program(),
+                pass: passes,
+            });
 
             // Run pdflatex
-            let mut cmd = self.secure_pdflatex_command(dir.path(), &input_path);
+            let format_dir = format.as_ref().map(|(_, dir)| dir.as_path());
+            let mut cmd = self.secure_latex_command(dir.path(), &input_path, format_dir, options);
+            // The terminal output is the only record of errors TeX hits before
+            // opening the log, such as an unloadable format
+            let terminal_path = dir.path().join("doc.stdout");
+            Self::stdout_to_file(&mut cmd, &terminal_path)?;
             let output =
-                Self::run_command_with_timeout(&mut cmd, COMPILE_TIMEOUT_SECS).map_err(|e| {
-                    Self::sanitize_paths(
-                        &format!(
-                            "Failed to run pdflatex (Pass {}): {}. Is it installed?",
-                            passes, e
-                        ),
-                        &temp_dir_path,
-                        &input_path_str,
-                    )
-                })?;
+                Self::run_command_with_timeout(&mut cmd, options.timeout_secs, &options.cancel)?;
+            let terminal = fs::read_to_string(&terminal_path).unwrap_or_default();
+            let _ = fs::remove_file(&terminal_path);
 
             let pdf_path = dir.path().join("doc.pdf");
             let log_path = dir.path().join("doc.log");
             let log =
                 fs::read_to_string(&log_path).unwrap_or_else(|_| "No log file found".to_string());
 
-            // Check if we need to run Biber (only on first pass if detected)
+            // A format dumped by an older TeX installation can't be loaded; start over without it
+            if let Some((name, format_dir)) = &format {
+                if terminal.contains("Fatal format file error") {
+                    tracing::warn!("Cached preamble format {} is unusable, discarding it", name);
+                    let _ = fs::remove_file(format_dir.join(format!("{}.fmt", name)));
+                    format = None;
+                    fs::write(&input_path, injected.as_bytes())?;
+                    passes = 0;
+                    continue;
+                }
+            }
+
+            // Run bibliography/index tools after the first pass, then rerun LaTeX
             if passes == 1 {
+                let mut ran_tool = false;
+
                 let bcf_path = dir.path().join("doc.bcf");
+                let aux = fs::read_to_string(dir.path().join("doc.aux")).unwrap_or_default();
                 if bcf_path.exists() || log.contains("Please (re)run Biber") {
-                    let mut biber_cmd = Command::new("biber");
-                    biber_cmd.current_dir(dir.path()).arg("doc");
-                    // We don't fail if biber fails, just log it and continue
-                    let _ = Self::run_command_with_timeout(&mut biber_cmd, COMPILE_TIMEOUT_SECS);
+                    Self::run_auxiliary_tool(dir.path(), AuxiliaryTool::Biber, options);
+                    ran_tool = true;
+                } else if Self::needs_bibtex(&aux, &log) {
+                    // Classic BibTeX: the .aux lists \citation and \bibdata entries
+                    Self::run_auxiliary_tool(dir.path(), AuxiliaryTool::Bibtex, options);
+                    ran_tool = true;
+                }
+
+                if dir.path().join("doc.idx").exists() {
+                    Self::run_auxiliary_tool(dir.path(), AuxiliaryTool::Makeindex, options);
+                    ran_tool = true;
+                }
+                if dir.path().join("doc.glo").exists() {
+                    Self::run_auxiliary_tool(dir.path(), AuxiliaryTool::Makeglossaries, options);
+                    ran_tool = true;
+                }
+
+                if ran_tool {
                     needs_rerun = true;
                     continue;
                 }
 
-                if log.contains("Run LaTeX again")
-                    || log.contains("Rerun to get")
-                    || log.contains("Label(s) may have changed")
-                {
+                if Self::log_requests_rerun(&log) {
                     needs_rerun = true;
                     continue;
                 }
             } else {
                 // Subsequent passes
-                if log.contains("Run LaTeX again")
-                    || log.contains("Rerun to get")
-                    || log.contains("Label(s) may have changed")
-                {
-                    needs_rerun = true;
-                } else {
-                    needs_rerun = false;
-                }
+                needs_rerun = Self::log_requests_rerun(&log);
             }
 
             // If it's the last pass or we don't need a rerun, check if PDF exists
-            if !needs_rerun || passes == max_passes {
+            if !needs_rerun || passes == MAX_LATEX_PASSES {
                 if !pdf_path.exists() {
                     let stderr = Self::sanitize_paths(
                         &String::from_utf8_lossy(&output.stderr),
                         &temp_dir_path,
                         &input_path_str,
                     );
-                    let stdout = Self::sanitize_paths(
-                        &String::from_utf8_lossy(&output.stdout),
-                        &temp_dir_path,
-                        &input_path_str,
-                    );
+                    let stdout = Self::sanitize_paths(&terminal, &temp_dir_path, &input_path_str);
                     let log_sanitized = Self::sanitize_paths(&log, &temp_dir_path, &input_path_str);
+                    Self::write_diagnostics(options, &log_sanitized, line_map);
 
-                    return Err(format!(
-                        "LaTeX failed to generate a PDF.\n\n--- LOG ---\n{}\n\n--- STDERR ---\n{}\n\n--- STDOUT ---\n{}",
-                        log_sanitized, stderr, stdout
-                    ));
+                    return Err(CompileError::LatexFailed {
+                        excerpts: diagnostics::source_excerpts(&log_sanitized, latex, line_map),
+                        log: format!(
+                            "--- LOG ---\n{}\n\n--- STDERR ---\n{}\n\n--- STDOUT ---\n{}",
+                            log_sanitized, stderr, stdout
+                        ),
+                    });
                 }
             }
         }
 
+        let log = fs::read_to_string(dir.path().join("doc.log"))
+            .unwrap_or_else(|_| "No log file found".to_string());
+        let log = Self::sanitize_paths(&log, &temp_dir_path, &input_path_str);
+
+        let warnings = Self::extract_warnings(&log);
+        Self::write_diagnostics(options, &log, line_map);
+
         let pdf_path = dir.path().join("doc.pdf");
-        let page_count = self.get_pdf_page_count(&pdf_path);
+        let pdf_bytes = fs::read(&pdf_path)?;
+        let pdf_info = self.get_pdf_info(&pdf_path, options)?;
+        let page_count = pdf_info.page_count;
         let mut svgs = Vec::new();
+        let section_anchors = Self::locate_sections(dir.path(), latex, line_map);
+
+        if options.backend == PreviewBackend::Pdf || !Self::poppler_tools().pdftocairo {
+            return Ok(CompileOutput {
+                svgs,
+                pdf_bytes,
+                log,
+                warnings,
+                pdf_info,
+                section_anchors,
+            });
+        }
 
         // Convert PDF to SVG page by page
         for page in 1..=page_count {
+            options.progress.report(CompileProgress::ConvertingPage {
+                page,
+                total: page_count,
+            });
             let svg_filename = format!("output-{}.svg", page);
             let svg_path = dir.path().join(&svg_filename);
 
-            let mut cmd = Command::new("pdftocairo");
+            let mut cmd = tool_command("pdftocairo");
             cmd.arg("-svg")
                 .arg("-f")
                 .arg(page.to_string())
@@ -303,26 +1783,17 @@ impl Preview {
                 .arg(&pdf_path)
                 .arg(&svg_path);
 
-            let cairo_output = Self::run_command_with_timeout(&mut cmd, COMPILE_TIMEOUT_SECS)
-                .map_err(|e| {
-                    Self::sanitize_paths(
-                        &format!(
-                            "Failed to run pdftocairo for page {}: {}. Is poppler-utils installed?",
-                            page, e
-                        ),
-                        &temp_dir_path,
-                        &input_path_str,
-                    )
-                })?;
+            let cairo_output =
+                Self::run_command_with_timeout(&mut cmd, options.timeout_secs, &options.cancel)?;
 
             if !cairo_output.status.success() {
                 let cairo_stderr = String::from_utf8_lossy(&cairo_output.stderr);
                 let cairo_stderr_sanitized =
                     Self::sanitize_paths(&cairo_stderr, &temp_dir_path, &input_path_str);
-                return Err(format!(
-                    "pdftocairo failed to convert page {} to SVG.\n\nStderr:\n{}",
-                    page, cairo_stderr_sanitized
-                ));
+                return Err(CompileError::ConversionFailed {
+                    page,
+                    stderr: cairo_stderr_sanitized,
+                });
             }
 
             if let Ok(content) = fs::read_to_string(&svg_path) {
@@ -331,23 +1802,175 @@ impl Preview {
         }
 
         if svgs.is_empty() {
-            let log_path = dir.path().join("doc.log");
-            let log =
-                fs::read_to_string(log_path).unwrap_or_else(|_| "No log file found".to_string());
-            return Err(format!(
-                "No SVG pages were generated (Page count was {}).\n\n--- LOG ---\n{}",
-                page_count, log
-            ));
+            return Err(CompileError::ConversionFailed {
+                page: 1,
+                stderr: format!(
+                    "No SVG pages were generated (page count was {})",
+                    page_count
+                ),
+            });
+        }
+
+        Ok(CompileOutput {
+            svgs,
+            pdf_bytes,
+            log,
+            warnings,
+            pdf_info,
+            section_anchors,
+        })
+    }
+
+    /// Writes the requested diagnostics file; a failure only costs the JSON, not the preview.
+    fn write_diagnostics(options: &CompileOptions, log: &str, line_map: LineMap) {
+        if let Some(output) = &options.diagnostics {
+            if let Err(e) = diagnostics::write(output, log, line_map) {
+                tracing::warn!("Failed to write {}: {}", output.path.display(), e);
+            }
+        }
+    }
+
+    /// Collects `LaTeX Warning:` and `Package … Warning:` messages from a log,
+    /// joining the continuation lines TeX wraps them onto.
+    pub(crate) fn extract_warnings(log: &str) -> Vec<String> {
+        let mut warnings: Vec<String> = Vec::new();
+        let mut in_warning = false;
+        for line in log.lines() {
+            let is_start = line.starts_with("LaTeX Warning:")
+                || line.starts_with("LaTeX Font Warning:")
+                || (line.starts_with("Package ") && line.contains(" Warning:"))
+                || (line.starts_with("Class ") && line.contains(" Warning:"));
+            if is_start {
+                warnings.push(line.trim().to_string());
+                in_warning = true;
+            } else if in_warning && !line.trim().is_empty() {
+                // Package warnings continue on lines indented with `(pkg)`
+                let continuation = line.trim_start();
+                let continuation = match continuation.strip_prefix('(') {
+                    Some(rest) => rest.split_once(')').map_or(rest, |(_, text)| text),
+                    None => continuation,
+                };
+                if let Some(last) = warnings.last_mut() {
+                    last.push(' ');
+                    last.push_str(continuation.trim());
+                }
+            } else {
+                in_warning = false;
+            }
+        }
+        warnings
+    }
+
+    /// Inline style sizing one page to its real width and aspect ratio, scaled by `scale`.
+    fn page_style(size: Option<PageSize>, scale: f64, layout: PreviewLayout) -> String {
+        // The width comes from `page_layout_css` instead
+        if layout == PreviewLayout::FitWidth {
+            return size.map_or_else(String::new, |size| {
+                format!("aspect-ratio: {} / {};", size.width_pt, size.height_pt)
+            });
+        }
+        match size {
+            Some(size) => format!(
+                "width: {:.0}px; aspect-ratio: {} / {};",
+                size.width_pt * PDF_POINTS_TO_CSS_PX * scale,
+                size.width_pt,
+                size.height_pt
+            ),
+            None => format!("width: {:.0}px;", DEFAULT_PAGE_WIDTH_PX * scale),
         }
+    }
+
+    /// An invisible marker at a section's rendered position, targeted by
+    /// `section-{index}` when the outline scrolls the preview.
+    fn anchor_html(anchor: &SectionAnchor, size: Option<PageSize>) -> String {
+        let top = size.map_or(0.0, |size| anchor.y_pt / size.height_pt * 100.0);
+        format!(
+            "<span class=\"anchor\" id=\"section-{}\" style=\"top: {:.2}%;\"></span>",
+            anchor.index, top
+        )
+    }
+
+    /// Width rules for `layout`. With [`PreviewLayout::Fixed`], scales above 1.0
+    /// drop the `max-width` cap so pages can grow past the pane; actual size
+    /// never shrinks and lines pages up on the left once they overflow, so
+    /// both edges can be scrolled to.
+    fn page_layout_css(scale: f64, layout: PreviewLayout) -> String {
+        match layout {
+            PreviewLayout::Fixed => {
+                let max_width = if scale > 1.0 { "none" } else { "95%" };
+                format!(".page {{ max-width: {}; }}", max_width)
+            }
+            PreviewLayout::FitWidth => ".page { width: 100%; max-width: none; }".to_string(),
+            PreviewLayout::ActualSize => {
+                "body { align-items: flex-start; } .page { max-width: none; margin-inline: auto; }"
+                    .to_string()
+            }
+        }
+    }
+
+    fn pdf_data_url(pdf_bytes: &[u8]) -> String {
+        format!("data:application/pdf;base64,{}", BASE64.encode(pdf_bytes))
+    }
 
-        Ok(svgs)
+    /// Preview page that hands the PDF itself to WebKit's built-in viewer.
+    fn wrap_pdf(&self, pdf_bytes: &[u8], dark_mode: bool) -> String {
+        let data_url = Self::pdf_data_url(pdf_bytes);
+        // The PDF is embedded as a data URL
+        let csp = self.csp_allowing("object-src", "data:");
+        let body_class = if dark_mode { "dark-mode" } else { "" };
+        format!(
+            "{}",
+            html! {
+                : doctype::HTML;
+                html {
+                     head {
+                         meta(charset="utf-8");
+                         meta(http-equiv="Content-Security-Policy", content=&csp);
+                         meta(http-equiv="X-Frame-Options", content="DENY");
+                         meta(http-equiv="X-Content-Type-Options", content="nosniff");
+                         style {
+                             : Raw("
+                                 html, body { margin: 0; height: 100%; background-color: #f0f0f0; }
+                                 embed { display: block; width: 100%; height: 100%; border: none; }
+                                 body.dark-mode { background-color: #1e1e1e; }
+                             ")
+                         }
+                     }
+                    body(class=body_class) {
+                        // The URL is base64, so it needs no escaping
+                        : Raw(&format!("<embed src=\"{}\" type=\"application/pdf\">", data_url));
+                    }
+                }
+            }
+        )
     }
 
-    fn wrap_svgs(&self, svgs: Vec<String>, dark_mode: bool) -> String {
+    fn wrap_svgs(
+        &self,
+        svgs: Vec<String>,
+        dark_mode: bool,
+        pdf_info: &PdfInfo,
+        anchors: &[SectionAnchor],
+        scale: f64,
+        layout: PreviewLayout,
+    ) -> String {
+        let scale = if layout == PreviewLayout::ActualSize {
+            1.0
+        } else {
+            scale
+        };
+        let page_css = Self::page_layout_css(scale, layout);
         let mut body_content = String::new();
-        for svg in svgs {
-            body_content.push_str("<div class=\"page\">");
+        for (i, svg) in svgs.into_iter().enumerate() {
+            body_content.push_str(&format!(
+                "<div class=\"page\" id=\"page-{}\" style=\"{}\">",
+                i + 1,
+                Self::page_style(pdf_info.size_of(i), scale, layout)
+            ));
             body_content.push_str(&svg);
+            for anchor in anchors.iter().filter(|a| a.page == i + 1) {
+                body_content.push_str(&Self::anchor_html(anchor, pdf_info.size_of(i)));
+            }
             body_content.push_str("</div>");
         }
 
@@ -360,8 +1983,7 @@ impl Preview {
                 html {
                      head {
                          meta(charset="utf-8");
-                         meta(http-equiv="Content-Security-Policy",
-                              content="default-src 'self'; script-src 'none'; style-src 'unsafe-inline';");
+                         meta(http-equiv="Content-Security-Policy", content=&*self.csp);
                          meta(http-equiv="X-Frame-Options", content="DENY");
                          meta(http-equiv="X-Content-Type-Options", content="nosniff");
                          style {
@@ -375,11 +1997,19 @@ impl Preview {
                                      gap: 20px;
                                  }
                                  .page {
+                                     position: relative;
                                      background: white;
                                      box-shadow: 0 4px 8px rgba(0,0,0,0.1);
                                      margin-bottom: 20px;
-                                     width: 850px;
-                                     max-width: 95%;
+                                 }
+                                 .page.current {
+                                     outline: 3px solid rgba(53, 132, 228, 0.6);
+                                     outline-offset: 2px;
+                                 }
+                                 .anchor {
+                                     position: absolute;
+                                     left: 0;
+                                     scroll-margin-top: 60px;
                                  }
                                  svg { 
                                      display: block; 
@@ -387,12 +2017,9 @@ impl Preview {
                                      height: auto; 
                                  }
 
-                                 @media (prefers-color-scheme: dark) {
-                                     body {
-                                         background-color: #1e1e1e;
-                                     }
+                                 body.dark-mode {
+                                     background-color: #1e1e1e;
                                  }
-
                                  body.dark-mode .page {
                                      background: #1e1e1e;
                                      border: 1px solid #333;
@@ -402,6 +2029,9 @@ impl Preview {
                                  }
                              ")
                          }
+                         style {
+                             : Raw(&page_css)
+                         }
                      }
                     body(class=body_class) {
                         : Raw(&body_content);
@@ -411,7 +2041,137 @@ impl Preview {
         )
     }
 
-    fn wrap_error(&self, error: &str) -> String {
+    /// Extracts the names of packages/files LaTeX reported as missing.
+    ///
+    /// Recognizes `! LaTeX Error: File `foo.sty' not found.` and
+    /// `! I can't find file `bar'.` and returns each file name once in order of
+    /// first appearance, with `.sty` added when the log gives no extension.
+    fn find_missing_packages(log: &str) -> Vec<String> {
+        static MISSING_REGEX: OnceLock<Regex> = OnceLock::new();
+        let re = MISSING_REGEX.get_or_init(|| {
+            Regex::new(r"File [`']([^`'\s]+)' not found|I can't find file [`']([^`'\s]+)'").unwrap()
+        });
+
+        let mut packages: Vec<String> = Vec::new();
+        for caps in re.captures_iter(log) {
+            let Some(file) = caps.get(1).or_else(|| caps.get(2)) else {
+                continue;
+            };
+            let name = if Path::new(file.as_str()).extension().is_some() {
+                file.as_str().to_string()
+            } else {
+                format!("{}.sty", file.as_str())
+            };
+            if !packages.contains(&name) {
+                packages.push(name);
+            }
+        }
+        packages
+    }
+
+    /// Builds an HTML hint telling the user how to install the packages
+    /// providing the missing `files` (e.g. `siunitx.sty`).
+    fn missing_packages_hint(files: &[String]) -> String {
+        if files.is_empty() {
+            return String::new();
+        }
+        let names: Vec<&str> = files
+            .iter()
+            .map(|f| f.trim_end_matches(".sty").trim_end_matches(".cls"))
+            .collect();
+        let apt = files
+            .iter()
+            .map(|f| format!("<code>apt-file search {}</code>", encode_text(f)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let fedora = files
+            .iter()
+            .map(|f| format!("'tex({})'", f))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "<div class=\"hint\"><h2>Missing LaTeX packages: {}</h2>\
+             TeX Live: <code>tlmgr install {}</code>\n\
+             Debian/Ubuntu: {} (then install the listed texlive-* packages)\n\
+             Fedora: <code>sudo dnf install {}</code></div>",
+            encode_text(&names.join(", ")),
+            encode_text(&names.join(" ")),
+            apt,
+            encode_text(&fedora)
+        )
+    }
+
+    /// HTML hint suggesting a fix for `error`, or an empty string.
+    fn error_hint(error: &CompileError) -> String {
+        let (heading, body) = match error {
+            CompileError::LatexFailed { log, .. } => {
+                return Self::missing_packages_hint(&Self::find_missing_packages(log))
+            }
+            CompileError::BinaryNotFound(program) if program == "pdftocairo" => (
+                "poppler-utils is not installed",
+                "The preview converts pages with pdftocairo from poppler-utils.",
+            ),
+            CompileError::BinaryNotFound(_) => (
+                "A TeX distribution is not installed",
+                "Install TeX Live (e.g. texlive-latex-extra) and make sure pdflatex is on your PATH.",
+            ),
+            CompileError::Unbalanced { .. } => (
+                "The document was not compiled",
+                "Fix the problems marked in the editor's gutter, or turn off Check Structure Before Compiling in Settings.",
+            ),
+            CompileError::Timeout(_) => (
+                "LaTeX did not finish in time",
+                "Look for an infinite loop or a prompt waiting for input, such as a \\read from the terminal.",
+            ),
+            _ => return String::new(),
+        };
+        format!(
+            "<div class=\"hint\"><h2>{}</h2>{}</div>",
+            encode_text(heading),
+            encode_text(body)
+        )
+    }
+
+    /// HTML showing each error of a failed LaTeX run with the source lines
+    /// around it, the error line marked; empty for other errors.
+    fn error_excerpts(error: &CompileError) -> String {
+        let CompileError::LatexFailed { excerpts, .. } = error else {
+            return String::new();
+        };
+        let mut html = String::new();
+        for excerpt in excerpts {
+            let width = excerpt.lines.last().map_or(1, |(n, _)| n.to_string().len());
+            html.push_str(&format!(
+                "<div class=\"excerpt\"><h2>Line {}: {}</h2>",
+                excerpt.line,
+                encode_text(&excerpt.message)
+            ));
+            for (number, text) in &excerpt.lines {
+                let (class, marker) = if *number == excerpt.line {
+                    ("source error-line", "&gt;")
+                } else {
+                    ("source", " ")
+                };
+                html.push_str(&format!(
+                    "<div class=\"{}\">{} {:>width$} | {}</div>",
+                    class,
+                    marker,
+                    number,
+                    encode_text(text),
+                    width = width
+                ));
+            }
+            html.push_str("</div>");
+        }
+        html
+    }
+
+    fn wrap_error(&self, error: &CompileError, dark_mode: bool) -> String {
+        let hint = Self::error_hint(error);
+        let excerpts = Self::error_excerpts(error);
+        let body_class = if dark_mode { "dark-mode" } else { "" };
+        let title = error.title();
+        let message = error.to_string();
         format!(
             "{}",
             html! {
@@ -419,22 +2179,31 @@ impl Preview {
                 html {
                      head {
                          meta(charset="utf-8");
-                         meta(http-equiv="Content-Security-Policy",
-                              content="default-src 'self'; script-src 'none'; style-src 'unsafe-inline';");
+                         meta(http-equiv="Content-Security-Policy", content=&*self.csp);
                          meta(http-equiv="X-Frame-Options", content="DENY");
                          meta(http-equiv="X-Content-Type-Options", content="nosniff");
                          style {
                              : Raw("
                                  body { font-family: monospace; padding: 20px; white-space: pre-wrap; background: #fff1f1; color: #a94442; }
-                                 @media (prefers-color-scheme: dark) {
-                                     body { background: #2a0f0f; color: #ff9999; }
-                                 }
+                                 .hint { background: #fff8e1; color: #5d4037; border: 1px solid #ffcc80; border-radius: 6px; padding: 12px; margin-bottom: 20px; }
+                                 .hint h2 { margin: 0 0 8px 0; font-size: 1.1em; }
+                                 .excerpt { background: #ffffff; border: 1px solid #ebccd1; border-radius: 6px; padding: 12px; margin-bottom: 20px; }
+                                 .excerpt h2 { margin: 0 0 8px 0; font-size: 1.1em; }
+                                 .source { color: #555555; }
+                                 .source.error-line { background: #f2dede; color: #a94442; font-weight: bold; }
+                                 body.dark-mode { background: #2a0f0f; color: #ff9999; }
+                                 body.dark-mode .hint { background: #3e2f10; color: #ffe0b2; border-color: #8d6e63; }
+                                 body.dark-mode .excerpt { background: #1e1e1e; border-color: #5c2b2b; }
+                                 body.dark-mode .source { color: #bbbbbb; }
+                                 body.dark-mode .source.error-line { background: #4a1c1c; color: #ff9999; }
                              ")
                          }
                      }
-                     body {
-                         h1 { : "Compilation Error" }
-                          : &*encode_text(error);
+                     body(class=body_class) {
+                         h1 { : title }
+                          : Raw(&hint);
+                          : Raw(&excerpts);
+                          : &*encode_text(&message);
                      }
                 }
             }
@@ -455,6 +2224,588 @@ mod tests {
         assert_eq!(sanitized, "Error in [TEMP_DIR]/doc.tex: missing package");
     }
 
+    #[test]
+    fn test_find_missing_packages() {
+        let log = "! LaTeX Error: File `tikz-cd.sty' not found.\n\
+                   Type X to quit or <RETURN> to proceed,\n\
+                   ! LaTeX Error: File `tikz-cd.sty' not found.\n\
+                   ! I can't find file `foo'.\n\
+                   ! LaTeX Error: File `revtex4-2.cls' not found.";
+        assert_eq!(
+            Preview::find_missing_packages(log),
+            vec!["tikz-cd.sty", "foo.sty", "revtex4-2.cls"]
+        );
+        assert!(Preview::find_missing_packages("Output written on doc.pdf").is_empty());
+    }
+
+    #[test]
+    fn test_missing_packages_hint() {
+        let files = ["siunitx.sty".to_string(), "revtex4-2.cls".to_string()];
+        let hint = Preview::missing_packages_hint(&files);
+        assert!(hint.contains("tlmgr install siunitx revtex4-2"));
+        assert!(hint.contains("search siunitx.sty</code>, <code>apt-file search revtex4-2.cls"));
+        assert!(hint.contains("dnf install 'tex(siunitx.sty)' 'tex(revtex4-2.cls)'"));
+        assert!(Preview::missing_packages_hint(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_needs_bibtex() {
+        let aux = "\\relax\n\\citation{knuth84}\n\\bibstyle{plain}\n\\bibdata{refs}\n";
+        assert!(Preview::needs_bibtex(aux, ""));
+        assert!(Preview::needs_bibtex(
+            "\\bibdata{refs}\n",
+            "LaTeX Warning: There were undefined references."
+        ));
+        assert!(!Preview::needs_bibtex("\\bibdata{refs}\n", ""));
+        assert!(!Preview::needs_bibtex("\\citation{knuth84}\n", ""));
+    }
+
+    #[test]
+    fn test_plan() {
+        let programs = |plan: &CompilePlan| -> Vec<&str> {
+            plan.steps.iter().map(|step| step.program).collect()
+        };
+        let options = CompileOptions {
+            backend: PreviewBackend::Pdf,
+            ..CompileOptions::default()
+        };
+
+        let plain = Preview::plan("Hello", &options);
+        assert_eq!(programs(&plain), ["pdflatex", "pdflatex", "pdflatex"]);
+        assert!(plain.steps[0].only_if.is_none());
+        assert!(plain.steps[1].only_if.is_some());
+
+        let latex = r"\documentclass{article}
+\usepackage{biblatex}
+\addbibresource{refs.bib}
+\makeindex
+% \makeglossaries
+\begin{document}\end{document}";
+        let plan = Preview::plan(latex, &options);
+        assert_eq!(
+            programs(&plan),
+            ["pdflatex", "biber", "makeindex", "pdflatex", "pdflatex"]
+        );
+        assert!(plan.steps[3].only_if.is_none());
+        assert!(plan
+            .describe()
+            .starts_with("1. pdflatex — typesets the document"));
+
+        let bibtex = Preview::plan(
+            "\\documentclass{article}\\begin{document}\\bibliography{refs}\\end{document}",
+            &CompileOptions {
+                engine: LatexEngine::Lualatex,
+                ..CompileOptions::default()
+            },
+        );
+        assert_eq!(
+            programs(&bibtex),
+            ["lualatex", "bibtex", "lualatex", "lualatex", "pdftocairo"]
+        );
+        assert_eq!(
+            Preview::tools_for_source("\\usepackage[backend=bibtex]{biblatex}")[0].0,
+            AuxiliaryTool::Bibtex
+        );
+    }
+
+    #[test]
+    fn test_compile_error_variants() {
+        let mut cmd = Command::new("latex-rs-no-such-binary");
+        match Preview::run_command_with_timeout(&mut cmd, 1, &CancelFlag::default()) {
+            Err(CompileError::BinaryNotFound(program)) => {
+                assert_eq!(program, "latex-rs-no-such-binary")
+            }
+            other => panic!("expected BinaryNotFound, got {:?}", other),
+        }
+
+        let too_large = Preview::check_size(&"x".repeat(MAX_LATEX_SIZE_BYTES + 1)).unwrap_err();
+        assert!(matches!(too_large, CompileError::TooLarge { .. }));
+        assert!(matches!(
+            Preview::new().compile_to_svgs(&"x".repeat(MAX_LATEX_SIZE_BYTES + 1)),
+            Err(CompileError::TooLarge { .. })
+        ));
+        assert!(too_large
+            .to_string()
+            .starts_with("Document too large (10.00 MB)"));
+        assert_eq!(
+            CompileError::Timeout(30).to_string(),
+            "Command timed out after 30 seconds"
+        );
+
+        let options = CompileOptions {
+            lint_first: true,
+            ..CompileOptions::default()
+        };
+        match Preview::new().compile_latex("\\begin{itemize}\n\\item {x\n", &options) {
+            Err(error @ CompileError::Unbalanced { .. }) => assert_eq!(
+                error.to_string(),
+                "Unbalanced braces or environments.\n\n\
+                 Line 1: `\\begin{itemize}` is never closed\n\
+                 Line 2: Unclosed `{`"
+            ),
+            other => panic!("expected Unbalanced, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_progress_sink() {
+        let (sender, mut receiver) = tokio::sync::broadcast::channel(8);
+        let options = CompileOptions {
+            progress: ProgressSink::new(sender),
+            ..CompileOptions::default()
+        };
+        // An oversized document is rejected before it counts as started
+        let _ = Preview::new().compile_latex(&"x".repeat(MAX_LATEX_SIZE_BYTES + 1), &options);
+        assert!(receiver.try_recv().is_err());
+
+        options
+            .progress
+            .report(CompileProgress::Tool(AuxiliaryTool::Biber));
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            CompileProgress::Tool(AuxiliaryTool::Biber)
+        );
+        assert_eq!(
+            CompileProgress::LatexPass {
+                engine: "pdflatex",
+                pass: 2
+            }
+            .describe(),
+            "Running pdflatex (pass 2)…"
+        );
+        assert_eq!(
+            CompileProgress::ConvertingPage { page: 3, total: 7 }.describe(),
+            "Converting page 3/7…"
+        );
+        ProgressSink::default().report(CompileProgress::Started);
+    }
+
+    #[test]
+    fn test_resolve_program() {
+        let dir = tempdir().unwrap();
+        let pdflatex = dir
+            .path()
+            .join(format!("pdflatex{}", std::env::consts::EXE_SUFFIX));
+        fs::write(&pdflatex, "").unwrap();
+        assert_eq!(resolve_program(Some(dir.path()), "pdflatex"), pdflatex);
+        assert_eq!(
+            resolve_program(Some(dir.path()), "biber"),
+            PathBuf::from("biber")
+        );
+        assert_eq!(resolve_program(None, "pdflatex"), PathBuf::from("pdflatex"));
+    }
+
+    #[test]
+    fn test_error_excerpts() {
+        let error = CompileError::LatexFailed {
+            log: String::new(),
+            excerpts: diagnostics::source_excerpts(
+                "! Undefined control sequence.\nl.9 \\foo\n",
+                "a\nb\nc\nd\ne\nf\ng\nh\n\\foo<x>\nj\n",
+                LineMap::default(),
+            ),
+        };
+        let html = Preview::error_excerpts(&error);
+        assert!(html.contains("<h2>Line 9: Undefined control sequence.</h2>"));
+        assert!(html.contains("<div class=\"source error-line\">&gt;  9 | \\foo&lt;x&gt;</div>"));
+        assert!(html.contains("<div class=\"source\">  10 | j</div>"));
+        assert_eq!(Preview::error_excerpts(&CompileError::Timeout(1)), "");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_cancel_kills_and_reaps_child() {
+        let dir = tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("echo $$ > {}; exec sleep 30", pid_file.display()));
+
+        let cancel = CancelFlag::default();
+        let canceller = cancel.clone();
+        let start = std::time::Instant::now();
+        let handle =
+            std::thread::spawn(move || Preview::run_command_with_timeout(&mut cmd, 60, &canceller));
+        while !fs::read_to_string(&pid_file).is_ok_and(|pid| pid.ends_with('\n')) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        cancel.cancel();
+
+        assert!(matches!(
+            handle.join().unwrap(),
+            Err(CompileError::Cancelled)
+        ));
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+        // A zombie keeps its /proc entry until reaped
+        let pid = fs::read_to_string(&pid_file).unwrap();
+        assert!(!Path::new(&format!("/proc/{}", pid.trim())).exists());
+    }
+
+    #[test]
+    fn test_extract_warnings() {
+        let log = "(./doc.aux)\n\
+                   LaTeX Warning: Reference `fig:a' on page 1 undefined on input line 5.\n\
+                   \n\
+                   Package hyperref Warning: Token not allowed in a PDF string,\n\
+                   (hyperref)                removing `math shift' on input line 7.\n\
+                   \n\
+                   Overfull \\hbox (1.2pt too wide) in paragraph\n";
+        assert_eq!(
+            Preview::extract_warnings(log),
+            vec![
+                "LaTeX Warning: Reference `fig:a' on page 1 undefined on input line 5.",
+                "Package hyperref Warning: Token not allowed in a PDF string, removing `math shift' on input line 7.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_pdf_info() {
+        let info = "Pages:           2\nPage size:       595.276 x 841.89 pts (A4)\n";
+        let parsed = Preview::parse_pdf_info(info);
+        assert_eq!(parsed.page_count, 2);
+        assert_eq!(
+            parsed.page_size,
+            Some(PageSize {
+                width_pt: 595.276,
+                height_pt: 841.89
+            })
+        );
+
+        let per_page = "Pages:           2\n\
+                        Page    1 size: 612 x 792 pts (letter)\n\
+                        Page    2 size: 792 x 612 pts\n";
+        let parsed = Preview::parse_pdf_info(per_page);
+        assert_eq!(parsed.page_sizes.len(), 2);
+        assert_eq!(parsed.size_of(1).unwrap().width_pt, 792.0);
+        assert_eq!(parsed.size_of(0), parsed.page_size);
+
+        assert_eq!(Preview::parse_pdf_info(""), PdfInfo::default());
+    }
+
+    #[test]
+    fn test_kept_build_dir_drops_previous_document() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["doc.pdf", "doc.aux", "doc.bcf", "doc.bbl", "doc.log"] {
+            fs::write(dir.path().join(name), "old").unwrap();
+        }
+        let options = CompileOptions {
+            build_dir: Some(dir.path().to_path_buf()),
+            ..CompileOptions::default()
+        };
+        let build = BuildDir::prepare(&options).unwrap();
+        assert_eq!(build.path(), dir.path());
+        for name in ["doc.pdf", "doc.aux", "doc.bcf", "doc.bbl"] {
+            assert!(!dir.path().join(name).exists(), "{} was kept", name);
+        }
+        assert!(dir.path().join("doc.log").exists());
+    }
+
+    #[test]
+    fn test_parse_synctex() {
+        let synctex = "SyncTeX Version:1\n\
+                       Input:1:/tmp/build/./doc.tex\n\
+                       Input:2:/usr/share/texmf/article.cls\n\
+                       Output:pdf\n\
+                       Magnification:1000\n\
+                       Unit:1\n\
+                       X Offset:0\n\
+                       Y Offset:0\n\
+                       Content:\n\
+                       !120\n\
+                       {1\n\
+                       [1,5:4736286,6578176:22609920,36044800,0\n\
+                       h2,40:0,0:0,0,0\n\
+                       x1,5:4736286,6578176\n\
+                       ]\n\
+                       }1\n\
+                       {2\n\
+                       (1,9:4736286,13156352:100,200,0\n\
+                       $1,9:4736286,14000000\n\
+                       )\n\
+                       }2\n\
+                       Postamble:\n";
+        let records = Preview::parse_synctex(synctex, "doc.tex");
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].line, 5);
+        assert_eq!(records[0].page, 1);
+        assert!((records[0].y_pt - 100.0).abs() < 0.001);
+        assert_eq!(records[2].line, 9);
+        assert_eq!(records[2].page, 2);
+        assert!((records[2].y_pt - 200.0).abs() < 0.001);
+        assert!(Preview::parse_synctex(synctex, "other.tex").is_empty());
+    }
+
+    #[derive(Debug)]
+    struct Watermark;
+
+    impl PreviewPostProcessor for Watermark {
+        fn process(&self, html: &mut String, _options: &CompileOptions) {
+            insert_into_head(html, "<style>.wm{}</style>");
+        }
+    }
+
+    #[test]
+    fn test_post_processors() {
+        let page = "<html><head><title>x</title></head><body></body></html>";
+        let preview = Preview::new().with_post_processor(Watermark);
+
+        let html = preview.post_process(page.to_string(), &CompileOptions::default());
+        assert_eq!(
+            html,
+            "<html><head><title>x</title><style>.wm{}</style></head><body></body></html>"
+        );
+
+        let options = CompileOptions {
+            page_numbers: true,
+            ..CompileOptions::default()
+        };
+        let html = preview.post_process(page.to_string(), &options);
+        let overlay = html.find("counter(preview-page)").unwrap();
+        assert!(overlay < html.find(".wm{}").unwrap());
+        assert!(html.ends_with("</head><body></body></html>"));
+    }
+
+    #[test]
+    fn test_content_security_policy() {
+        assert_eq!(
+            Preview::new()
+                .with_content_security_policy(" ; ")
+                .unwrap_err(),
+            EmptyCspError
+        );
+
+        let preview = Preview::new()
+            .with_content_security_policy("default-src 'self'; script-src 'self'")
+            .unwrap();
+        assert_eq!(
+            preview.csp_allowing("object-src", "data:"),
+            "default-src 'self'; script-src 'self'; object-src data:;"
+        );
+        assert_eq!(
+            Preview::new().csp_allowing("object-src", "data:"),
+            "default-src 'self'; script-src 'none'; style-src 'unsafe-inline'; object-src data:;"
+        );
+
+        let preview = Preview::new()
+            .with_content_security_policy("object-src 'none'; img-src data:; OBJECT-SRC https:")
+            .unwrap();
+        assert_eq!(
+            preview.csp_allowing("object-src", "data:"),
+            "object-src data:; img-src data:; OBJECT-SRC https:;"
+        );
+        let preview = Preview::new()
+            .with_content_security_policy("object-src 'self' data:")
+            .unwrap();
+        assert_eq!(
+            preview.csp_allowing("object-src", "data:"),
+            "object-src 'self' data:;"
+        );
+    }
+
+    #[test]
+    fn test_anchor_html() {
+        let anchor = SectionAnchor {
+            index: 3,
+            page: 1,
+            y_pt: 396.0,
+        };
+        let letter = PageSize {
+            width_pt: 612.0,
+            height_pt: 792.0,
+        };
+        assert_eq!(
+            Preview::anchor_html(&anchor, Some(letter)),
+            "<span class=\"anchor\" id=\"section-3\" style=\"top: 50.00%;\"></span>"
+        );
+    }
+
+    #[test]
+    fn test_count_pdf_pages() {
+        let pdf = b"<< /Type /Pages /Count 2 >> << /Type /Page /Parent 1 0 R >> << /Type/Page>>";
+        assert_eq!(Preview::count_pdf_pages(pdf), 2);
+        assert_eq!(Preview::count_pdf_pages(b"not a pdf"), 1);
+    }
+
+    #[test]
+    fn test_pdf_data_url() {
+        assert_eq!(
+            Preview::pdf_data_url(b"%PDF-1.5"),
+            "data:application/pdf;base64,JVBERi0xLjU="
+        );
+    }
+
+    #[test]
+    fn test_strip_math_delimiters() {
+        assert_eq!(Preview::strip_math_delimiters(" $x^2$ "), "x^2");
+        assert_eq!(Preview::strip_math_delimiters("$$ a + b $$"), "a + b");
+        assert_eq!(
+            Preview::strip_math_delimiters("\\[\\frac{1}{2}\\]"),
+            "\\frac{1}{2}"
+        );
+        assert_eq!(Preview::strip_math_delimiters("\\(y\\)"), "y");
+        assert_eq!(Preview::strip_math_delimiters("e^{i\\pi}"), "e^{i\\pi}");
+    }
+
+    #[test]
+    fn test_math_snippet_body() {
+        let html = Preview::math_snippet_body("$\\frac{a}{b}$");
+        assert!(html.contains("<math"));
+        assert!(html.contains("<mfrac>"));
+
+        let html = Preview::math_snippet_body("\\frac{a}{");
+        assert!(html.contains("class=\"error\""));
+        assert!(!html.contains("<math"));
+    }
+
+    #[test]
+    fn test_katex_error_message() {
+        assert_eq!(
+            Preview::katex_error_message(
+                r#"failed to execute js (detail: String("ParseError: KaTeX parse error: Undefined control sequence: \\foo at position 1: \\foo"))"#
+            ),
+            "Undefined control sequence: \\foo"
+        );
+        assert_eq!(
+            Preview::katex_error_message("engine failed"),
+            "engine failed"
+        );
+    }
+
+    #[test]
+    fn test_prepare_source_injects_preamble() {
+        let latex =
+            "\\documentclass[\n  a4paper]{article}\n\\begin{document}\nHi\n\\end{document}\n";
+        let (source, map) = Preview::prepare_source(
+            latex,
+            "\\newcommand{\\R}{\\mathbb{R}}\n\\usepackage{amssymb}",
+            true,
+        );
+        assert_eq!(
+            source,
+            "\\documentclass[\n  a4paper]{article}\n\\newcommand{\\R}{\\mathbb{R}}\n\\usepackage{amssymb}\n\\begin{document}\nHi\n\\end{document}\n"
+        );
+        assert_eq!(map.to_user(2), Some(2));
+        assert_eq!(map.to_user(3), None);
+        assert_eq!(map.to_user(6), Some(4));
+        assert_eq!(map.to_compiled(4), 6);
+
+        // An empty preamble leaves a full document untouched
+        let (source, map) = Preview::prepare_source(latex, "  \n", true);
+        assert_eq!(source, latex);
+        assert_eq!(map, LineMap::default());
+    }
+
+    #[test]
+    fn test_prepare_source_wraps_fragments() {
+        let (source, map) = Preview::prepare_source("\\frac{1}{2}", "\\def\\y{1}", true);
+        assert_eq!(
+            source,
+            "\\documentclass{article}\n\\usepackage{amsmath,amssymb}\n\\def\\y{1}\n\\pagestyle{empty}\n\\begin{document}\n\\[\n\\frac{1}{2}\n\\]\n\\end{document}\n"
+        );
+        assert_eq!(map.to_user(7), Some(1));
+
+        // Environments and prose are not put in math mode
+        let (source, map) =
+            Preview::prepare_source("\\begin{equation}\nx^2\n\\end{equation}", "", true);
+        assert!(source.ends_with(
+            "\\begin{document}\n\\begin{equation}\nx^2\n\\end{equation}\n\\end{document}\n"
+        ));
+        assert_eq!(map.to_user(6), Some(2));
+
+        // A body with \begin{document} only gets a class
+        let (source, _) =
+            Preview::prepare_source("\\begin{document}\nHi\n\\end{document}", "", true);
+        assert!(source.starts_with(
+            "\\documentclass{article}\n\\usepackage{amsmath,amssymb}\n\\begin{document}"
+        ));
+
+        let (source, map) = Preview::prepare_source("\\frac{1}{2}", "\\def\\y{1}", false);
+        assert_eq!(source, "\\frac{1}{2}");
+        assert_eq!(map, LineMap::default());
+    }
+
+    #[test]
+    fn test_split_preamble() {
+        let latex = "\\documentclass{article}\n\\usepackage{tikz}\n  \\begin{document}\nHi\n\\end{document}\n";
+        let (preamble, body) = Preview::split_preamble(latex).unwrap();
+        assert_eq!(preamble, "\\documentclass{article}\n\\usepackage{tikz}\n");
+        assert_eq!(body, "  \\begin{document}\nHi\n\\end{document}\n");
+
+        // A commented-out begin doesn't count, and a fragment has no preamble
+        assert!(
+            Preview::split_preamble("\\documentclass{article}\n% \\begin{document}\n").is_none()
+        );
+        assert!(Preview::split_preamble("\\begin{document}\nx").is_none());
+    }
+
+    #[test]
+    fn test_with_format_line_keeps_line_numbers() {
+        let latex = "\\documentclass{article}\n\\usepackage{amsmath}\n\\begin{document}\nBody\n";
+        let (preamble, body) = Preview::split_preamble(latex).unwrap();
+        let name = Preview::format_name(preamble);
+        assert!(name.starts_with("preamble-"));
+        assert_eq!(name, Preview::format_name(preamble));
+        assert_ne!(name, Preview::format_name("\\documentclass{book}\n"));
+
+        let source = Preview::with_format_line(&name, preamble, body);
+        assert!(source.starts_with(&format!("%&{}\n", name)));
+        assert_eq!(source.lines().nth(2), Some("\\begin{document}"));
+        assert_eq!(source.lines().count(), latex.lines().count());
+    }
+
+    #[test]
+    fn test_compile_recovers_from_unusable_format() {
+        let latex = "\\documentclass{article}\n% unusable format\n\\begin{document}\nBody\n\\end{document}\n";
+        let options = CompileOptions {
+            precompile_preamble: true,
+            backend: PreviewBackend::Pdf,
+            ..CompileOptions::default()
+        };
+        let (injected, _) =
+            Preview::prepare_source(latex, &options.preview_preamble, options.wrap_fragments);
+        let (preamble, _) = Preview::split_preamble(&injected).unwrap();
+        let cache = Preview::format_cache_dir();
+        fs::create_dir_all(&cache).unwrap();
+        let format = cache.join(format!("{}.fmt", Preview::format_name(preamble)));
+        fs::write(&format, "not a format").unwrap();
+
+        let output = Preview::new().compile_latex(latex, &options).unwrap();
+        assert!(output.pdf_bytes.starts_with(b"%PDF"));
+        assert!(fs::read(&format).map_or(true, |bytes| bytes != b"not a format"));
+    }
+
+    #[test]
+    fn test_page_style() {
+        // US letter is 612pt wide = 816 CSS px
+        let letter = PageSize {
+            width_pt: 612.0,
+            height_pt: 792.0,
+        };
+        assert_eq!(
+            Preview::page_style(Some(letter), 1.0, PreviewLayout::Fixed),
+            "width: 816px; aspect-ratio: 612 / 792;"
+        );
+        assert_eq!(
+            Preview::page_style(None, 2.0, PreviewLayout::Fixed),
+            "width: 1700px;"
+        );
+        assert_eq!(
+            Preview::page_layout_css(2.0, PreviewLayout::Fixed),
+            ".page { max-width: none; }"
+        );
+
+        // Fit width leaves the width to the stylesheet
+        assert_eq!(
+            Preview::page_style(Some(letter), 2.0, PreviewLayout::FitWidth),
+            "aspect-ratio: 612 / 792;"
+        );
+        assert!(Preview::page_layout_css(1.0, PreviewLayout::FitWidth).contains("width: 100%"));
+        assert!(
+            Preview::page_layout_css(3.0, PreviewLayout::ActualSize).contains("max-width: none")
+        );
+    }
+
     #[test]
     fn test_render_multi_page() {
         let preview = Preview::new();
@@ -474,4 +2825,21 @@ Page 2
         let page_count = result.matches("class=\"page\"").count();
         assert_eq!(page_count, 2);
     }
+
+    #[test]
+    fn test_render_with_index() {
+        let preview = Preview::new();
+        let latex = r#"
+\documentclass{article}
+\usepackage{makeidx}
+\makeindex
+\begin{document}
+Knuth\index{Knuth} wrote \TeX\index{TeX}.
+\printindex
+\end{document}
+"#;
+        let result = preview.render(latex, false);
+        // The index only starts its own page once makeindex has produced doc.ind
+        assert_eq!(result.matches("class=\"page\"").count(), 2);
+    }
 }
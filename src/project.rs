@@ -0,0 +1,265 @@
+//! Per-project compile settings, kept in a `.latexrs.toml` next to the
+//! document so they travel with the project. Each field that is set overrides
+//! the matching global setting in `AppConfig`.
+//!
+//! `% !TEX` magic comments in the document itself override both.
+
+use crate::config::LatexEngine;
+use crate::constants::{MAGIC_COMMENT_LINES, PROJECT_FILE_NAME, PROTECTED_EXTENSIONS};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProjectSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine: Option<LatexEngine>,
+    /// Only honoured when turned on for folders in
+    /// `AppConfig::shell_escape_trusted_dirs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell_escape: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Document compiled for the preview instead of the open file, e.g. the
+    /// root of a book while editing a chapter. Relative to the project folder.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_file: Option<PathBuf>,
+}
+
+/// Where the settings for `document` live: `.latexrs.toml` in its folder.
+pub fn path_for(document: &Path) -> Option<PathBuf> {
+    Some(document.parent()?.join(PROJECT_FILE_NAME))
+}
+
+impl ProjectSettings {
+    /// Settings for `document`; none when there is no sidecar file. A broken
+    /// file is logged and ignored rather than blocking the document.
+    pub fn load(document: &Path) -> Self {
+        let Some(path) = path_for(document) else {
+            return Self::default();
+        };
+        let Ok(content) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        toml::from_str(&content).unwrap_or_else(|e| {
+            tracing::warn!("Ignoring {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    /// Writes the settings next to `document`, removing the file once nothing
+    /// is overridden any more.
+    pub fn save(&self, document: &Path) -> anyhow::Result<()> {
+        let path = path_for(document).ok_or_else(|| anyhow::anyhow!("Document has no folder"))?;
+        if *self == Self::default() {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// The main file to compile instead of `document`, if it names another file.
+    pub fn main_file_for(&self, document: &Path) -> Option<PathBuf> {
+        let main = document.parent()?.join(self.main_file.as_ref()?);
+        (main != document).then_some(main)
+    }
+}
+
+/// Directives from `% !TEX root = …` and `% !TEX program = …` lines near the
+/// top of a document, as written by TeXShop, TeXstudio and VS Code.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MagicComments {
+    /// Document to compile instead, relative to the one containing the comment.
+    pub root: Option<PathBuf>,
+    pub program: Option<LatexEngine>,
+}
+
+impl MagicComments {
+    /// Reads the directives in the first [`MAGIC_COMMENT_LINES`] lines of
+    /// `text`; the first of each kind wins. Malformed or unknown directives are
+    /// logged and skipped.
+    pub fn parse(text: &str) -> Self {
+        let mut magic = Self::default();
+        for line in text.lines().take(MAGIC_COMMENT_LINES) {
+            let Some(directive) = line
+                .trim_start()
+                .strip_prefix('%')
+                .and_then(|rest| rest.trim_start().strip_prefix('!'))
+                .map(str::trim_start)
+                .filter(|rest| rest.get(..3).is_some_and(|t| t.eq_ignore_ascii_case("tex")))
+            else {
+                continue;
+            };
+            let Some((key, value)) = directive[3..].split_once('=') else {
+                tracing::warn!("Ignoring malformed magic comment: {}", line.trim());
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match key.trim().to_ascii_lowercase().as_str() {
+                "root" if magic.root.is_none() => magic.root = Some(PathBuf::from(value)),
+                // TeXShop spells it `TS-program`
+                "program" | "ts-program" if magic.program.is_none() => {
+                    magic.program = LatexEngine::ALL
+                        .into_iter()
+                        .find(|engine| engine.program().eq_ignore_ascii_case(value));
+                    if magic.program.is_none() {
+                        tracing::warn!("Ignoring unsupported TeX program: {}", value);
+                    }
+                }
+                _ => {}
+            }
+        }
+        magic
+    }
+
+    /// The root to compile instead of `document`, if it names another file.
+    pub fn root_for(&self, document: &Path) -> Option<PathBuf> {
+        let root = document.parent()?.join(self.root.as_ref()?);
+        (root != document).then_some(root)
+    }
+}
+
+/// Files directly inside `dirs` whose names end in one of `extensions` (given
+/// without the dot, e.g. `aux` or `synctex.gz`), sorted. Files with a
+/// [`PROTECTED_EXTENSIONS`] extension are never included.
+pub fn auxiliary_files(dirs: &[PathBuf], extensions: &[String]) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let has = |ext: &str| {
+                name.strip_suffix(ext)
+                    .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+            };
+            !PROTECTED_EXTENSIONS.iter().any(|ext| has(ext))
+                && extensions
+                    .iter()
+                    .any(|ext| has(ext.trim_start_matches('.').to_lowercase().as_str()))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_and_cleanup() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("chapter.tex");
+        assert_eq!(ProjectSettings::load(&document), ProjectSettings::default());
+
+        let settings = ProjectSettings {
+            engine: Some(LatexEngine::Xelatex),
+            timeout_secs: Some(120),
+            main_file: Some(PathBuf::from("book.tex")),
+            ..ProjectSettings::default()
+        };
+        settings.save(&document).unwrap();
+        let saved = fs::read_to_string(dir.path().join(PROJECT_FILE_NAME)).unwrap();
+        assert!(saved.contains("engine = \"xelatex\""));
+        assert!(!saved.contains("shell_escape"));
+        assert_eq!(ProjectSettings::load(&document), settings);
+
+        ProjectSettings::default().save(&document).unwrap();
+        assert!(!dir.path().join(PROJECT_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn test_broken_file_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(PROJECT_FILE_NAME), "engine = 42").unwrap();
+        let settings = ProjectSettings::load(&dir.path().join("paper.tex"));
+        assert_eq!(settings, ProjectSettings::default());
+    }
+
+    #[test]
+    fn test_main_file_for() {
+        let settings = ProjectSettings {
+            main_file: Some(PathBuf::from("book.tex")),
+            ..ProjectSettings::default()
+        };
+        assert_eq!(
+            settings.main_file_for(Path::new("/work/ch1.tex")),
+            Some(PathBuf::from("/work/book.tex"))
+        );
+        assert_eq!(settings.main_file_for(Path::new("/work/book.tex")), None);
+        assert_eq!(
+            ProjectSettings::default().main_file_for(Path::new("/work/ch1.tex")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_magic_comments() {
+        let magic = MagicComments::parse(
+            "% !TEX root = ../main.tex\n%!TeX TS-program = XeLaTeX\n% !TEX program = lualatex\n",
+        );
+        assert_eq!(magic.root, Some(PathBuf::from("../main.tex")));
+        assert_eq!(magic.program, Some(LatexEngine::Xelatex));
+        assert_eq!(
+            magic.root_for(Path::new("/work/chapters/ch1.tex")),
+            Some(PathBuf::from("/work/chapters/../main.tex"))
+        );
+
+        // Malformed, unknown and empty directives are skipped
+        let magic = MagicComments::parse(
+            "% !TEX root\n% !TEX program = context\n% !TEX root =  \n% TEX root = a.tex\n",
+        );
+        assert_eq!(magic, MagicComments::default());
+
+        // Only the top of the document is searched
+        let late = format!("{}% !TEX root = main.tex", "\n".repeat(MAGIC_COMMENT_LINES));
+        assert_eq!(MagicComments::parse(&late).root, None);
+    }
+
+    #[test]
+    fn test_auxiliary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let build = dir.path().join(".latexrs-build");
+        fs::create_dir(&build).unwrap();
+        for name in [
+            "paper.tex",
+            "paper.pdf",
+            "refs.bib",
+            "paper.aux",
+            "paper.synctex.gz",
+            "notes.log.tex",
+            ".aux",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        fs::write(build.join("paper.LOG"), "").unwrap();
+        fs::write(build.join("paper.pdf"), "").unwrap();
+
+        let extensions: Vec<String> = ["aux", ".log", "synctex.gz", "pdf"]
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        let dirs = [dir.path().to_path_buf(), build.clone()];
+        assert_eq!(
+            auxiliary_files(&dirs, &extensions),
+            [
+                build.join("paper.LOG"),
+                dir.path().join("paper.aux"),
+                dir.path().join("paper.synctex.gz"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Plain-text search through the files of a project folder, for the
+//! "Search in Project" sidebar tab.
+
+use crate::constants::{MAX_LATEX_SIZE_BYTES, MAX_PROJECT_SEARCH_HITS};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A line containing the search text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub path: PathBuf,
+    /// 0-based line and character offset of the first match on it.
+    pub line: i32,
+    pub column: i32,
+    /// The line without surrounding whitespace, for display.
+    pub text: String,
+}
+
+/// Whether `path` (relative, `/`-separated) matches the glob `pattern`. `*` and
+/// `?` stay within one folder and `**` spans any number of them. A pattern
+/// without `/` is matched against the file name alone, so `*.tex` finds files
+/// in every folder.
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    let path = if pattern.contains('/') {
+        path
+    } else {
+        path.rsplit('/').next().unwrap_or(path)
+    };
+    let pattern: Vec<char> = pattern.chars().collect();
+    let path: Vec<char> = path.chars().collect();
+    matches(&pattern, &path)
+}
+
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may also stand for no folder at all
+            (0..=text.len()).any(|i| matches(rest, &text[i..]))
+                || rest.first() == Some(&'/') && matches(&rest[1..], text)
+        }
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| matches(rest, &text[i..])),
+        ['?', rest @ ..] => text.first().is_some_and(|&c| c != '/') && matches(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+    }
+}
+
+/// Files below `dir` matching an `include` glob and no `exclude` glob, in no
+/// particular order. Hidden files and folders, such as `.git` and the build
+/// folder, are skipped, as are symlinks.
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    include: &[String],
+    exclude: &[String],
+    files: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        if exclude.iter().any(|glob| glob_matches(glob, &relative)) {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            collect_files(root, &path, include, exclude, files);
+        } else if file_type.is_file() && include.iter().any(|glob| glob_matches(glob, &relative)) {
+            files.push(path);
+        }
+    }
+}
+
+/// Lines of the files under `root` that contain `query`, ignoring case, sorted
+/// by path and line. At most [`MAX_PROJECT_SEARCH_HITS`] are returned, and
+/// files too large to compile are skipped.
+pub fn search_project(
+    root: &Path,
+    query: &str,
+    include: &[String],
+    exclude: &[String],
+) -> Vec<SearchHit> {
+    let mut hits = Vec::new();
+    if query.is_empty() {
+        return hits;
+    }
+    let needle = query.to_lowercase();
+    let mut files = Vec::new();
+    collect_files(root, root, include, exclude, &mut files);
+    files.sort();
+
+    for path in files {
+        if fs::metadata(&path).map_or(true, |m| m.len() > MAX_LATEX_SIZE_BYTES as u64) {
+            continue;
+        }
+        let Ok(bytes) = fs::read(&path) else {
+            continue;
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        for (line, content) in text.lines().enumerate() {
+            let lower = content.to_lowercase();
+            let Some(byte) = lower.find(&needle) else {
+                continue;
+            };
+            hits.push(SearchHit {
+                path: path.clone(),
+                line: line as i32,
+                column: lower[..byte].chars().count() as i32,
+                text: content.trim().to_string(),
+            });
+            if hits.len() >= MAX_PROJECT_SEARCH_HITS {
+                return hits;
+            }
+        }
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*.tex", "main.tex"));
+        assert!(glob_matches("*.tex", "chapters/intro.tex"));
+        assert!(!glob_matches("*.tex", "refs.bib"));
+        assert!(glob_matches("chapters/*.tex", "chapters/intro.tex"));
+        assert!(!glob_matches("chapters/*.tex", "chapters/old/intro.tex"));
+        assert!(glob_matches("chapters/**/*.tex", "chapters/old/intro.tex"));
+        assert!(glob_matches("chapters/**/*.tex", "chapters/intro.tex"));
+        assert!(glob_matches("build/**", "build/x/main.tex"));
+        assert!(glob_matches("ch?.tex", "ch1.tex"));
+        assert!(!glob_matches("ch?.tex", "ch10.tex"));
+    }
+
+    #[test]
+    fn test_search_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("chapters")).unwrap();
+        fs::create_dir_all(root.join("old")).unwrap();
+        fs::create_dir_all(root.join(".latexrs-build")).unwrap();
+        fs::write(
+            root.join("main.tex"),
+            "\\input{chapters/intro}\nSee Lemma 2.\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("chapters/intro.tex"),
+            "  \\begin{lemma}\\label{lem}\n",
+        )
+        .unwrap();
+        fs::write(root.join("old/draft.tex"), "lemma\n").unwrap();
+        fs::write(root.join(".latexrs-build/doc.tex"), "lemma\n").unwrap();
+        fs::write(root.join("refs.bib"), "lemma\n").unwrap();
+
+        let include = vec!["*.tex".to_string()];
+        let exclude = vec!["old".to_string()];
+        let hits = search_project(root, "LEMMA", &include, &exclude);
+        assert_eq!(
+            hits,
+            [
+                SearchHit {
+                    path: root.join("chapters/intro.tex"),
+                    line: 0,
+                    column: 9,
+                    text: "\\begin{lemma}\\label{lem}".to_string(),
+                },
+                SearchHit {
+                    path: root.join("main.tex"),
+                    line: 1,
+                    column: 4,
+                    text: "See Lemma 2.".to_string(),
+                },
+            ]
+        );
+        assert!(search_project(root, "", &include, &exclude).is_empty());
+    }
+}
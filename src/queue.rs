@@ -1,79 +1,291 @@
-use crate::constants::COMPILATION_QUEUE_BUFFER;
-use crate::preview::Preview;
+use crate::constants::COMPILE_PROGRESS_CAPACITY;
+use crate::preview::{CancelFlag, CompileOptions, CompileProgress, Preview, ProgressSink};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, oneshot, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, oneshot, Mutex, Notify};
 use tokio::task::JoinHandle;
 
+/// Outcome of a single compilation job.
+#[derive(Debug, Clone)]
+pub struct CompileOutcome {
+    /// Rendered preview HTML (an error page if compilation failed).
+    pub html: String,
+    /// Whether the document compiled successfully.
+    pub success: bool,
+    /// Sanitized compilation log.
+    pub log: String,
+    /// Short description of the failure, `None` on success.
+    pub error_title: Option<&'static str>,
+    /// Pages in the compiled PDF, `None` on failure.
+    pub page_count: Option<usize>,
+    /// The compiled PDF, `None` on failure.
+    pub pdf_bytes: Option<Vec<u8>>,
+    /// Wall-clock time spent compiling.
+    pub elapsed: Duration,
+}
+
+/// Identifies a submission to [`CompilationQueue::enqueue_cancellable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JobId(u64);
+
+/// A queued compilation request and the channel its outcome is sent back on.
+type CompileJob = (
+    JobId,
+    String,
+    bool,
+    CompileOptions,
+    oneshot::Sender<CompileOutcome>,
+);
+
+/// Holds up to `capacity` waiting values in order; when full, storing a new one
+/// drops the oldest, so the newest value is never the one lost.
+struct LatestSlot<T> {
+    values: std::sync::Mutex<VecDeque<T>>,
+    capacity: usize,
+    /// Signalled whenever a value is stored (or the worker must wake to exit).
+    ready: Notify,
+}
+
+impl<T> LatestSlot<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            values: std::sync::Mutex::new(VecDeque::new()),
+            capacity: capacity.max(1),
+            ready: Notify::new(),
+        }
+    }
+
+    /// Stores `value`, returning the one it superseded, and wakes the reader.
+    fn put(&self, value: T) -> Option<T> {
+        let superseded = {
+            let mut values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+            let superseded = if values.len() >= self.capacity {
+                values.pop_front()
+            } else {
+                None
+            };
+            values.push_back(value);
+            superseded
+        };
+        self.ready.notify_one();
+        superseded
+    }
+
+    /// Removes the oldest waiting value.
+    fn take(&self) -> Option<T> {
+        self.values
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front()
+    }
+
+    /// Removes the waiting values matching `matches`, returning whether any did.
+    fn remove(&self, matches: impl Fn(&T) -> bool) -> bool {
+        let mut values = self.values.lock().unwrap_or_else(|e| e.into_inner());
+        let before = values.len();
+        values.retain(|value| !matches(value));
+        values.len() != before
+    }
+
+    fn clear(&self) {
+        self.values
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
 /// A compilation queue that ensures only one LaTeX compilation runs at a time.
 /// This prevents resource conflicts and temp file corruption from concurrent compilations.
+///
+/// Up to `depth` jobs wait behind the running one. When they are all taken, a newer
+/// request drops the oldest waiting job, so the final preview always reflects the
+/// latest edit. A depth of 1 skips straight to the newest edit, which suits slow
+/// machines; a larger depth also shows the intermediate states, making the preview
+/// feel smoother when compiles are quick, at the cost of lagging further behind
+/// typing when they are not.
 #[derive(Clone)]
 pub struct CompilationQueue {
-    sender: mpsc::Sender<(String, bool, oneshot::Sender<String>)>,
+    pending: Arc<LatestSlot<CompileJob>>,
     /// Shared reference to the worker handle for graceful shutdown.
     /// Wrapped in Arc<Mutex> to allow cloning while maintaining single ownership semantics.
     worker_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+    /// Stops the worker for good; see [`Self::shutdown`].
+    cancel: CancelFlag,
+    /// The job being compiled and the flag that aborts it, killing its child
+    /// process.
+    running: Arc<std::sync::Mutex<Option<(JobId, CancelFlag)>>>,
+    next_id: Arc<AtomicU64>,
+    /// Progress of whichever job is running; see [`Self::subscribe`].
+    progress: broadcast::Sender<CompileProgress>,
 }
 
 impl CompilationQueue {
     /// Creates a new compilation queue with a dedicated worker task.
     ///
     /// The worker processes compilation requests sequentially, ensuring thread safety
-    /// for temporary file operations.
-    pub fn new(preview: Preview) -> Self {
-        let (sender, mut receiver) =
-            mpsc::channel::<(String, bool, oneshot::Sender<String>)>(COMPILATION_QUEUE_BUFFER);
+    /// for temporary file operations. `depth` is the number of jobs allowed to
+    /// wait (`AppConfig::compile_queue_depth`); values below 1 are treated as 1.
+    pub fn new(preview: Preview, depth: usize) -> Self {
+        let pending = Arc::new(LatestSlot::<CompileJob>::new(depth));
+        let cancel = CancelFlag::default();
+        let running = Arc::new(std::sync::Mutex::new(None));
+        let (progress, _) = broadcast::channel(COMPILE_PROGRESS_CAPACITY);
 
+        let worker_pending = pending.clone();
+        let worker_cancel = cancel.clone();
+        let worker_running = running.clone();
+        let worker_progress = ProgressSink::new(progress.clone());
         let handle = tokio::spawn(async move {
-            while let Some((latex, dark_mode, result_sender)) = receiver.recv().await {
+            loop {
+                if worker_cancel.is_cancelled() {
+                    break;
+                }
+                // Several jobs may be waiting, so only sleep once they are drained
+                let Some((id, latex, dark_mode, mut options, result_sender)) =
+                    worker_pending.take()
+                else {
+                    worker_pending.ready.notified().await;
+                    continue;
+                };
+                let job_cancel = CancelFlag::default();
+                *lock_running(&worker_running) = Some((id, job_cancel.clone()));
+                // Shutdown may have slipped in between the checks
+                if worker_cancel.is_cancelled() {
+                    job_cancel.cancel();
+                }
+                options.cancel = job_cancel.clone();
+                options.progress = worker_progress.clone();
                 let preview = preview.clone();
                 let start = std::time::Instant::now();
-                let html = tokio::task::spawn_blocking(move || preview.render(&latex, dark_mode))
-                    .await
-                    .unwrap_or_else(|e| format!("Render Task Error: {}", e));
+                let output = tokio::task::spawn_blocking(move || {
+                    preview.render_with_status(&latex, dark_mode, &options)
+                })
+                .await
+                .unwrap_or_else(|e| crate::preview::RenderOutput {
+                    html: format!("Render Task Error: {}", e),
+                    success: false,
+                    log: String::new(),
+                    error_title: Some("Render Task Error"),
+                    page_count: None,
+                    pdf_bytes: None,
+                });
                 let elapsed = start.elapsed();
+                lock_running(&worker_running).take();
+                if job_cancel.is_cancelled() {
+                    // Dropping the sender tells the caller it was abandoned
+                    tracing::debug!("Compilation {:?} cancelled", id);
+                    continue;
+                }
                 tracing::info!(
                     "LaTeX compilation completed in {:.2}s",
                     elapsed.as_secs_f64()
                 );
                 // Ignore send error if receiver dropped (job cancelled)
-                let _ = result_sender.send(html);
+                let _ = result_sender.send(CompileOutcome {
+                    html: output.html,
+                    success: output.success,
+                    log: output.log,
+                    error_title: output.error_title,
+                    page_count: output.page_count,
+                    pdf_bytes: output.pdf_bytes,
+                    elapsed,
+                });
             }
+            // Drop any waiting jobs so their callers see `None`
+            worker_pending.clear();
             tracing::debug!("Compilation worker shutting down");
         });
 
         Self {
-            sender,
+            pending,
             worker_handle: Arc::new(Mutex::new(Some(handle))),
+            cancel,
+            running,
+            next_id: Arc::new(AtomicU64::new(0)),
+            progress,
         }
     }
 
+    /// Receives the steps of every compilation the worker runs from now on,
+    /// one at a time, e.g. for a live status label. A listener that falls more
+    /// than [`COMPILE_PROGRESS_CAPACITY`] events behind skips the oldest.
+    pub fn subscribe(&self) -> broadcast::Receiver<CompileProgress> {
+        self.progress.subscribe()
+    }
+
     /// Enqueues a LaTeX document for compilation.
     ///
-    /// If the queue is full (another compilation is pending), the new request is dropped
-    /// to prevent queue buildup during rapid typing.
+    /// If the queue is already full, the oldest waiting job is dropped and its caller
+    /// receives `None`; this keeps rapid typing from building up a backlog while
+    /// guaranteeing the most recent edit is compiled.
     ///
-    /// Returns `Some(html)` with the rendered result, or `None` if the request was dropped
-    /// or the worker is unavailable.
-    pub async fn enqueue(&self, latex: String, dark_mode: bool) -> Option<String> {
+    /// Returns `Some(outcome)` with the rendered result, or `None` if the request was
+    /// superseded by a newer one or the queue has been shut down.
+    pub async fn enqueue(
+        &self,
+        latex: String,
+        dark_mode: bool,
+        options: CompileOptions,
+    ) -> Option<CompileOutcome> {
+        self.enqueue_cancellable(latex, dark_mode, options).1.await
+    }
+
+    /// Like [`Self::enqueue`], but also returns an id that [`Self::cancel`]
+    /// takes to abandon this submission. The job is queued right away; the
+    /// future only waits for its outcome.
+    pub fn enqueue_cancellable(
+        &self,
+        latex: String,
+        dark_mode: bool,
+        options: CompileOptions,
+    ) -> (JobId, impl Future<Output = Option<CompileOutcome>>) {
+        let id = JobId(self.next_id.fetch_add(1, Ordering::Relaxed));
         let (result_sender, result_receiver) = oneshot::channel();
-        // Try to send, if channel is full, drop the new job (keep the pending one)
-        if self.sender.try_send((latex, dark_mode, result_sender)).is_err() {
-            // Channel full, ignore new job
-            tracing::debug!("Compilation queue full, dropping new job");
-            return None;
+        // After shutdown the sender is dropped, so the caller sees `None`
+        if !self.cancel.is_cancelled()
+            && self
+                .pending
+                .put((id, latex, dark_mode, options, result_sender))
+                .is_some()
+        {
+            tracing::debug!("Dropped oldest pending compilation for a newer request");
+        }
+        // None if superseded, cancelled or the worker stopped
+        (id, async move { result_receiver.await.ok() })
+    }
+
+    /// Abandons the submission `id`: a waiting job is dropped and a running one
+    /// is aborted, killing its child process. Its caller receives `None`. Does
+    /// nothing for jobs that already finished.
+    pub fn cancel(&self, id: JobId) {
+        if self.pending.remove(|job| job.0 == id) {
+            tracing::debug!("Dropped pending compilation {:?}", id);
+            return;
+        }
+        if let Some((_, flag)) = lock_running(&self.running)
+            .as_ref()
+            .filter(|(running, _)| *running == id)
+        {
+            flag.cancel();
         }
-        result_receiver.await.ok() // Returns None if sender dropped (should not happen)
     }
 
     /// Gracefully shuts down the compilation worker.
     ///
     /// This should be called during application shutdown to ensure clean termination.
-    /// After calling this, the queue will no longer accept new compilations.
-    #[allow(dead_code)]
+    /// Any running compilation is cancelled and its child process killed and reaped;
+    /// after calling this, the queue will no longer accept new compilations.
     pub async fn shutdown(&self) {
-        // Drop the sender to signal the worker to stop
-        // Note: We can't drop self.sender directly, but closing the channel
-        // will cause receiver.recv() to return None
+        // Kill the in-flight job's child, then wake the worker if it is idle
+        self.cancel.cancel();
+        if let Some((_, flag)) = lock_running(&self.running).as_ref() {
+            flag.cancel();
+        }
+        self.pending.ready.notify_one();
 
         // Wait for the worker to finish current work
         let handle = self.worker_handle.lock().await.take();
@@ -86,9 +298,16 @@ impl CompilationQueue {
     }
 }
 
+fn lock_running(
+    running: &std::sync::Mutex<Option<(JobId, CancelFlag)>>,
+) -> std::sync::MutexGuard<'_, Option<(JobId, CancelFlag)>> {
+    running.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::MAX_LATEX_SIZE_BYTES;
     use tokio::runtime::Runtime;
 
     #[allow(dead_code)]
@@ -112,4 +331,151 @@ mod tests {
                                            // The queue logic is tested in integration tests
         });
     }
+
+    #[test]
+    fn test_shutdown_stops_idle_worker() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let queue = CompilationQueue::new(Preview::new(), 1);
+            tokio::time::timeout(Duration::from_secs(5), queue.shutdown())
+                .await
+                .expect("shutdown should not wait for another job");
+
+            // New work is refused rather than queued forever
+            let outcome = queue
+                .enqueue("x".to_string(), false, CompileOptions::default())
+                .await;
+            assert!(outcome.is_none());
+        });
+    }
+
+    #[test]
+    fn test_latest_slot_replaces_pending() {
+        let slot = LatestSlot::new(1);
+        assert_eq!(slot.put(1), None);
+        assert_eq!(slot.put(2), Some(1));
+        assert_eq!(slot.take(), Some(2));
+        assert_eq!(slot.take(), None);
+    }
+
+    #[test]
+    fn test_latest_slot_drops_oldest_when_full() {
+        let slot = LatestSlot::new(2);
+        assert_eq!(slot.put(1), None);
+        assert_eq!(slot.put(2), None);
+        assert_eq!(slot.put(3), Some(1));
+        assert_eq!(slot.take(), Some(2));
+        assert_eq!(slot.take(), Some(3));
+        assert_eq!(slot.take(), None);
+
+        // A depth of 0 still keeps the latest value
+        let slot = LatestSlot::new(0);
+        assert_eq!(slot.put(1), None);
+        assert_eq!(slot.put(2), Some(1));
+    }
+
+    #[test]
+    fn test_enqueue_keeps_latest() {
+        // On a current-thread runtime the worker cannot start until both requests are queued
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let queue = CompilationQueue::new(Preview::new(), 1);
+            let (first, second) = tokio::join!(
+                queue.enqueue("first".to_string(), false, CompileOptions::default()),
+                queue.enqueue("second".to_string(), false, CompileOptions::default()),
+            );
+            assert!(first.is_none(), "older request should be superseded");
+            assert!(second.is_some(), "latest request should be compiled");
+            queue.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn test_enqueue_threads_dark_mode_to_render() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let queue = CompilationQueue::new(Preview::new(), 1);
+            // Rejected by the size check, so no TeX installation is needed
+            let oversized = "x".repeat(MAX_LATEX_SIZE_BYTES + 1);
+            for dark_mode in [false, true] {
+                let outcome = queue
+                    .enqueue(oversized.clone(), dark_mode, CompileOptions::default())
+                    .await
+                    .expect("a lone request is compiled");
+                assert!(!outcome.success);
+                assert_eq!(outcome.error_title, Some("Document Too Large"));
+                assert_eq!(outcome.html.contains("dark-mode"), dark_mode);
+            }
+            queue.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn test_enqueue_with_depth_compiles_backlog() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let queue = CompilationQueue::new(Preview::new(), 2);
+            let (first, second, third) = tokio::join!(
+                queue.enqueue("first".to_string(), false, CompileOptions::default()),
+                queue.enqueue("second".to_string(), false, CompileOptions::default()),
+                queue.enqueue("third".to_string(), false, CompileOptions::default()),
+            );
+            assert!(first.is_none(), "oldest request should be dropped");
+            assert!(second.is_some() && third.is_some());
+            queue.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn test_cancel_pending_job() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let queue = CompilationQueue::new(Preview::new(), 2);
+            let (first_id, first) =
+                queue.enqueue_cancellable("first".to_string(), false, CompileOptions::default());
+            let (second_id, second) =
+                queue.enqueue_cancellable("second".to_string(), false, CompileOptions::default());
+            assert_ne!(first_id, second_id);
+            // Still waiting, since the worker hasn't run yet
+            queue.cancel(first_id);
+            let (first, second) = tokio::join!(first, second);
+            assert!(first.is_none(), "cancelled request should not be compiled");
+            assert!(second.is_some());
+
+            // Cancelling a finished job is harmless
+            queue.cancel(second_id);
+            queue.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn test_subscribe_reports_progress() {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let queue = CompilationQueue::new(Preview::new(), 1);
+            let mut progress = queue.subscribe();
+            let outcome = queue
+                .enqueue("\\relax".to_string(), false, CompileOptions::default())
+                .await;
+            assert!(outcome.is_some());
+            assert_eq!(progress.recv().await.unwrap(), CompileProgress::Started);
+            assert_eq!(
+                progress.recv().await.unwrap(),
+                CompileProgress::LatexPass {
+                    engine: "pdflatex",
+                    pass: 1
+                }
+            );
+            queue.shutdown().await;
+        });
+    }
 }
@@ -0,0 +1,276 @@
+//! LaTeX-aware spell checking.
+//!
+//! Prose is tokenized while skipping control sequences, math, comments and
+//! command arguments that are not prose (environment names, labels, citation
+//! keys, file names). Words are checked against a Hunspell dictionary.
+
+use crate::constants::HUNSPELL_DICT_DIRS;
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// Commands whose first (mandatory) argument is an identifier, not prose.
+const NON_PROSE_ARGUMENT_COMMANDS: &[&str] = &[
+    "begin",
+    "end",
+    "label",
+    "ref",
+    "eqref",
+    "pageref",
+    "autoref",
+    "cref",
+    "Cref",
+    "cite",
+    "citep",
+    "citet",
+    "nocite",
+    "usepackage",
+    "documentclass",
+    "includegraphics",
+    "input",
+    "include",
+    "bibliography",
+    "bibliographystyle",
+    "url",
+    "href",
+];
+
+/// Environments whose body is math and must not be spell checked.
+const MATH_ENVIRONMENTS: &[&str] = &[
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "eqnarray*",
+    "displaymath",
+    "math",
+];
+
+/// Byte offset just past `pat` in `text[from..]`, or the end of `text`.
+fn skip_past(text: &str, from: usize, pat: &str) -> usize {
+    text[from..]
+        .find(pat)
+        .map_or(text.len(), |i| from + i + pat.len())
+}
+
+/// Skips a balanced `open`...`close` group starting at `pos` (after optional
+/// whitespace), returning the group's contents and the offset after it.
+fn skip_group(text: &str, pos: usize, open: char, close: char) -> Option<(&str, usize)> {
+    let start = pos + (text[pos..].len() - text[pos..].trim_start().len());
+    if !text[start..].starts_with(open) {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, c) in text[start..].char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                let end = start + i;
+                return Some((&text[start + 1..end], end + 1));
+            }
+        }
+    }
+    Some((&text[start + 1..], text.len()))
+}
+
+/// Byte ranges of the prose words in a LaTeX document.
+pub fn prose_words(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut pos = 0;
+
+    while let Some(c) = text[pos..].chars().next() {
+        match c {
+            '%' => pos = skip_past(text, pos, "\n"),
+            '$' if text[pos + 1..].starts_with('$') => pos = skip_past(text, pos + 2, "$$"),
+            '$' => pos = skip_past(text, pos + 1, "$"),
+            '\\' => {
+                let rest = &text[pos + 1..];
+                let name_len = rest
+                    .find(|c: char| !c.is_ascii_alphabetic() && c != '@')
+                    .unwrap_or(rest.len());
+                // A single non-letter command like \% or \( when there is no name
+                let name_len = if name_len == 0 {
+                    rest.chars().next().map_or(0, char::len_utf8)
+                } else {
+                    name_len
+                };
+                let name = &rest[..name_len];
+                pos += 1 + name_len;
+
+                match name {
+                    "(" => pos = skip_past(text, pos, "\\)"),
+                    "[" => pos = skip_past(text, pos, "\\]"),
+                    _ if NON_PROSE_ARGUMENT_COMMANDS.contains(&name) => {
+                        if let Some((_, after)) = skip_group(text, pos, '[', ']') {
+                            pos = after;
+                        }
+                        if let Some((arg, after)) = skip_group(text, pos, '{', '}') {
+                            pos = after;
+                            if name == "begin" && MATH_ENVIRONMENTS.contains(&arg) {
+                                pos = skip_past(text, pos, &format!("\\end{{{}}}", arg));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            c if c.is_alphabetic() => {
+                let start = pos;
+                let mut end = pos;
+                let mut chars = text[pos..].char_indices().peekable();
+                while let Some((i, c)) = chars.next() {
+                    let is_word_char = c.is_alphabetic()
+                        || (c == '\'' && chars.peek().is_some_and(|(_, n)| n.is_alphabetic()));
+                    if !is_word_char {
+                        break;
+                    }
+                    end = pos + i + c.len_utf8();
+                }
+                words.push(start..end);
+                pos = end;
+            }
+            c => pos += c.len_utf8(),
+        }
+    }
+    words
+}
+
+/// Byte range of the paragraphs of `text` that `range` touches, widened to the
+/// blank lines around them. Math and arguments cannot span a blank line, so
+/// the words found in it are the same as in the whole document.
+pub fn enclosing_paragraphs(text: &str, range: Range<usize>) -> Range<usize> {
+    let mut start = text[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    while start > 0 {
+        let previous = text[..start - 1].rfind('\n').map_or(0, |i| i + 1);
+        if text[previous..start].trim().is_empty() {
+            break;
+        }
+        start = previous;
+    }
+    let mut end = text[range.end..]
+        .find('\n')
+        .map_or(text.len(), |i| range.end + i);
+    while end < text.len() {
+        let next = text[end + 1..]
+            .find('\n')
+            .map_or(text.len(), |i| end + 1 + i);
+        if text[end + 1..next].trim().is_empty() {
+            break;
+        }
+        end = next;
+    }
+    start..end
+}
+
+/// Dictionaries (`xx_YY.dic` with a matching `.aff`) installed on the system.
+pub fn available_languages() -> Vec<String> {
+    let mut languages: Vec<String> = HUNSPELL_DICT_DIRS
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "dic" || !path.with_extension("aff").exists() {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().into_owned())
+        })
+        .collect();
+    languages.sort();
+    languages.dedup();
+    languages
+}
+
+fn dictionary_path(language: &str) -> Option<PathBuf> {
+    HUNSPELL_DICT_DIRS
+        .iter()
+        .map(|dir| PathBuf::from(dir).join(format!("{}.dic", language)))
+        .find(|path| path.exists())
+}
+
+/// A loaded Hunspell dictionary.
+pub struct SpellChecker {
+    dictionary: zspell::Dictionary,
+}
+
+impl SpellChecker {
+    /// Loads the system dictionary for `language`, e.g. `en_US`.
+    pub fn load(language: &str) -> Result<Self, String> {
+        let dic_path = dictionary_path(language)
+            .ok_or_else(|| format!("No dictionary installed for {}", language))?;
+        let dic = std::fs::read_to_string(&dic_path)
+            .map_err(|e| format!("Failed to read {}: {}", dic_path.display(), e))?;
+        let aff = std::fs::read_to_string(dic_path.with_extension("aff"))
+            .map_err(|e| format!("Failed to read affix file: {}", e))?;
+        let dictionary = zspell::builder()
+            .config_str(&aff)
+            .dict_str(&dic)
+            .build()
+            .map_err(|e| format!("Failed to load dictionary: {}", e))?;
+        Ok(Self { dictionary })
+    }
+
+    /// Byte ranges of the misspelled prose words in `text`.
+    pub fn misspelled(&self, text: &str) -> Vec<Range<usize>> {
+        prose_words(text)
+            .into_iter()
+            .filter(|range| !self.dictionary.check_word(&text[range.clone()]))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<&str> {
+        prose_words(text).into_iter().map(|r| &text[r]).collect()
+    }
+
+    #[test]
+    fn test_prose_words_skips_commands() {
+        assert_eq!(
+            words(r"Some \textit{emphasised} text\footnote{here}."),
+            vec!["Some", "emphasised", "text", "here"]
+        );
+        assert_eq!(words(r"It's 100\% done"), vec!["It's", "done"]);
+    }
+
+    #[test]
+    fn test_prose_words_skips_math_and_comments() {
+        assert_eq!(
+            words("Let $x + y$ and $$abc$$ or \\(qq\\) be \\[zz\\] fine % todo fixme\nnext"),
+            vec!["Let", "and", "or", "be", "fine", "next"]
+        );
+        assert_eq!(
+            words("before \\begin{align} foo &= bar \\end{align} after"),
+            vec!["before", "after"]
+        );
+    }
+
+    #[test]
+    fn test_enclosing_paragraphs() {
+        let text = "Intro line.\n\nFirst $a\nb$ line\nsecond line\n  \nLast.";
+        let second = text.find("second").unwrap();
+        let paragraph = enclosing_paragraphs(text, second..second + 3);
+        assert_eq!(&text[paragraph], "First $a\nb$ line\nsecond line");
+        let paragraph = enclosing_paragraphs(text, 0..text.len());
+        assert_eq!(paragraph, 0..text.len());
+        assert_eq!(enclosing_paragraphs("", 0..0), 0..0);
+        assert_eq!(&text[enclosing_paragraphs(text, 2..2)], "Intro line.");
+    }
+
+    #[test]
+    fn test_prose_words_skips_identifiers() {
+        assert_eq!(
+            words(r"\begin{itemize} See \cite[p.~3]{knuthTeX} and \ref{sec:intro} \end{itemize}"),
+            vec!["See", "and"]
+        );
+    }
+}
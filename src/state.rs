@@ -1,7 +1,15 @@
-use crate::api::AiProvider;
+use crate::api::{AiProvider, TokenUsage};
 use crate::config::AppConfig;
-use crate::queue::CompilationQueue;
+use crate::constants::BUILD_DIR_NAME;
+use crate::diagnostics::{self, DiagnosticsOutput};
+use crate::navigation::NavigationHistory;
+use crate::preview::CompileOptions;
+use crate::project::{MagicComments, ProjectSettings};
+use crate::queue::{CompilationQueue, JobId};
+use crate::spell::SpellChecker;
+use gtk4::glib;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 
@@ -9,6 +17,16 @@ use tokio::sync::mpsc;
 pub struct AppState {
     /// Currently open file path, if any.
     pub current_file: Option<PathBuf>,
+    /// Settings from the `.latexrs.toml` next to the current file.
+    pub project: ProjectSettings,
+    /// `% !TEX` directives of the open document, refreshed before each compile.
+    pub magic_comments: MagicComments,
+    /// Cursor positions to return to with Navigate Back/Forward.
+    pub navigation: NavigationHistory,
+    /// Encoding the current file was read in, used when saving it back.
+    pub current_encoding: &'static encoding_rs::Encoding,
+    /// Whether the current file began with a byte-order mark, kept on save.
+    pub current_bom: bool,
     /// Active AI Provider.
     pub ai_provider: Option<Arc<dyn AiProvider>>,
     /// AI Cancellation channel.
@@ -19,12 +37,178 @@ pub struct AppState {
     pub pending_suggestion: Option<String>,
     /// Original text that the suggestion would replace.
     pub original_text_selection: Option<String>,
+    /// Instruction of the last completed AI request, re-sent by Regenerate.
+    pub last_ai_instruction: Option<String>,
+    /// Tokens used by all AI requests since the app started.
+    pub session_token_usage: TokenUsage,
     /// Application configuration.
     pub config: AppConfig,
     /// Compilation queue.
     pub compilation_queue: Option<CompilationQueue>,
+    /// Latest preview compile submitted to the queue and not yet finished.
+    pub compile_job: Option<JobId>,
+    /// Page count of the last successful compilation.
+    pub last_page_count: Option<usize>,
+    /// PDF of the last successful compilation, reused by Copy Page as PNG.
+    pub last_pdf: Option<Vec<u8>>,
+    /// Pending debounced preview refresh, if the user is still typing.
+    pub preview_debounce: Option<glib::SourceId>,
+    /// Loaded spell-check dictionary; `None` when disabled or unavailable.
+    pub spell_checker: Option<Rc<SpellChecker>>,
     /// Current zoom level for the text editor.
     pub editor_zoom: f64,
     /// Current zoom level for the preview pane.
     pub preview_zoom: f64,
+    /// While set, the preview shows the selected formula instead of the document.
+    pub math_scratch: bool,
+}
+
+impl AppState {
+    /// Switches to `file` (`None` for an unsaved document) and loads its
+    /// project settings.
+    pub fn set_current_file(&mut self, file: Option<PathBuf>) {
+        self.project = file
+            .as_deref()
+            .map(ProjectSettings::load)
+            .unwrap_or_default();
+        self.current_file = file;
+        self.magic_comments = MagicComments::default();
+        self.navigation = NavigationHistory::default();
+        if self.project.shell_escape == Some(true) && !self.project_trusted() {
+            tracing::warn!("Ignoring shell_escape from an untrusted .latexrs.toml");
+        }
+    }
+
+    /// Whether the current file's folder may turn shell escape on.
+    fn project_trusted(&self) -> bool {
+        self.current_file
+            .as_ref()
+            .and_then(|f| f.parent())
+            .is_some_and(|dir| {
+                self.config
+                    .shell_escape_trusted_dirs
+                    .iter()
+                    .any(|d| d == dir)
+            })
+    }
+
+    /// Saves `project` next to the current file. Turning shell escape on here
+    /// also trusts the folder, since the user asked for it.
+    pub fn save_project(&mut self) -> anyhow::Result<()> {
+        let Some(file) = self.current_file.clone() else {
+            anyhow::bail!("Save the document first");
+        };
+        if self.project.shell_escape == Some(true) && !self.project_trusted() {
+            if let Some(dir) = file.parent() {
+                self.config
+                    .shell_escape_trusted_dirs
+                    .push(dir.to_path_buf());
+                self.config.save()?;
+            }
+        }
+        self.project.save(&file)
+    }
+
+    /// Re-reads the `% !TEX` directives from the open document's `text`.
+    pub fn read_magic_comments(&mut self, text: &str) {
+        self.magic_comments = MagicComments::parse(text);
+    }
+
+    /// The file the preview should compile instead of the open document: the
+    /// `% !TEX root`, else the project's main file.
+    pub fn main_file(&self) -> Option<PathBuf> {
+        let file = self.current_file.as_ref()?;
+        self.magic_comments
+            .root_for(file)
+            .or_else(|| self.project.main_file_for(file))
+    }
+
+    /// The folder Search in Project looks through: that of the main file, else
+    /// that of the open document.
+    pub fn project_root(&self) -> Option<PathBuf> {
+        self.main_file()
+            .or_else(|| self.current_file.clone())?
+            .parent()
+            .map(|dir| dir.to_path_buf())
+    }
+
+    /// What the preview compiles for the open document's `text`: the project's
+    /// main file as saved, if there is one, else `text` itself.
+    pub fn source_to_compile(&self, text: String) -> String {
+        let Some(main) = self.main_file() else {
+            return text;
+        };
+        match crate::utils::open_file(&main) {
+            Ok(decoded) => decoded.contents,
+            Err(e) => {
+                tracing::warn!("Could not read main file {}: {}", main.display(), e);
+                text
+            }
+        }
+    }
+
+    /// Whether `\write18` is allowed: the project's choice (turning it on only
+    /// counts in trusted folders), else the global setting.
+    fn shell_escape(&self) -> bool {
+        match self.project.shell_escape {
+            Some(enabled) => enabled && self.project_trusted(),
+            None => self.config.shell_escape,
+        }
+    }
+
+    /// Folder where build artifacts are kept for the current document.
+    ///
+    /// Returns `None` for unsaved documents, which have no directory to build next to.
+    pub fn build_dir(&self) -> Option<PathBuf> {
+        self.current_file
+            .as_ref()
+            .and_then(|f| f.parent())
+            .map(|dir| dir.join(BUILD_DIR_NAME))
+    }
+
+    /// Where compile diagnostics go, if enabled and the document has been saved.
+    fn diagnostics_output(&self) -> Option<DiagnosticsOutput> {
+        if !self.config.write_diagnostics {
+            return None;
+        }
+        let file = self.current_file.as_ref()?;
+        Some(DiagnosticsOutput {
+            path: diagnostics::path_for(file, BUILD_DIR_NAME)?,
+            source: file.to_string_lossy().into_owned(),
+        })
+    }
+
+    /// Compile options derived from the current configuration and document.
+    pub fn compile_options(&self) -> CompileOptions {
+        CompileOptions {
+            build_dir: if self.config.keep_build_files {
+                self.build_dir()
+            } else {
+                None
+            },
+            preview_scale: self.config.preview_scale,
+            preview_layout: self.config.preview_layout,
+            backend: self.config.preview_backend,
+            precompile_preamble: self.config.precompile_preamble,
+            preview_preamble: self.config.preview_preamble.clone(),
+            wrap_fragments: self.config.wrap_fragments,
+            diagnostics: self.diagnostics_output(),
+            page_numbers: self.config.preview_page_numbers,
+            engine: self
+                .magic_comments
+                .program
+                .or(self.project.engine)
+                .unwrap_or(self.config.latex_engine),
+            shell_escape: self.shell_escape(),
+            timeout_secs: self
+                .project
+                .timeout_secs
+                .unwrap_or(self.config.compile_timeout_secs),
+            input_dir: self
+                .main_file()
+                .and_then(|main| main.parent().map(|dir| dir.to_path_buf())),
+            lint_first: self.config.lint_before_compile,
+            ..CompileOptions::default()
+        }
+    }
 }
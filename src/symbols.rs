@@ -0,0 +1,235 @@
+//! Symbols offered by the symbol picker: the character, the LaTeX command that
+//! produces it, and a name to search by.
+
+use crate::utils::fuzzy_score;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolGroup {
+    Greek,
+    Operators,
+    Arrows,
+    Accents,
+}
+
+impl SymbolGroup {
+    pub const ALL: [SymbolGroup; 4] = [
+        SymbolGroup::Greek,
+        SymbolGroup::Operators,
+        SymbolGroup::Arrows,
+        SymbolGroup::Accents,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SymbolGroup::Greek => "Greek",
+            SymbolGroup::Operators => "Operators",
+            SymbolGroup::Arrows => "Arrows",
+            SymbolGroup::Accents => "Accents",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub group: SymbolGroup,
+    pub glyph: &'static str,
+    /// Inserted as-is; a trailing `{}` takes the selection as its argument.
+    pub command: &'static str,
+    pub name: &'static str,
+}
+
+const fn sym(
+    group: SymbolGroup,
+    glyph: &'static str,
+    command: &'static str,
+    name: &'static str,
+) -> Symbol {
+    Symbol {
+        group,
+        glyph,
+        command,
+        name,
+    }
+}
+
+use SymbolGroup::{Accents, Arrows, Greek, Operators};
+
+/// Every symbol, grouped in [`SymbolGroup::ALL`] order.
+pub const SYMBOLS: &[Symbol] = &[
+    sym(Greek, "α", "\\alpha", "alpha"),
+    sym(Greek, "β", "\\beta", "beta"),
+    sym(Greek, "γ", "\\gamma", "gamma"),
+    sym(Greek, "δ", "\\delta", "delta"),
+    sym(Greek, "ε", "\\epsilon", "epsilon"),
+    sym(Greek, "ϵ", "\\varepsilon", "epsilon (variant)"),
+    sym(Greek, "ζ", "\\zeta", "zeta"),
+    sym(Greek, "η", "\\eta", "eta"),
+    sym(Greek, "θ", "\\theta", "theta"),
+    sym(Greek, "ϑ", "\\vartheta", "theta (variant)"),
+    sym(Greek, "ι", "\\iota", "iota"),
+    sym(Greek, "κ", "\\kappa", "kappa"),
+    sym(Greek, "λ", "\\lambda", "lambda"),
+    sym(Greek, "μ", "\\mu", "mu"),
+    sym(Greek, "ν", "\\nu", "nu"),
+    sym(Greek, "ξ", "\\xi", "xi"),
+    sym(Greek, "π", "\\pi", "pi"),
+    sym(Greek, "ρ", "\\rho", "rho"),
+    sym(Greek, "σ", "\\sigma", "sigma"),
+    sym(Greek, "τ", "\\tau", "tau"),
+    sym(Greek, "υ", "\\upsilon", "upsilon"),
+    sym(Greek, "φ", "\\phi", "phi"),
+    sym(Greek, "ϕ", "\\varphi", "phi (variant)"),
+    sym(Greek, "χ", "\\chi", "chi"),
+    sym(Greek, "ψ", "\\psi", "psi"),
+    sym(Greek, "ω", "\\omega", "omega"),
+    sym(Greek, "Γ", "\\Gamma", "Gamma (capital)"),
+    sym(Greek, "Δ", "\\Delta", "Delta (capital)"),
+    sym(Greek, "Θ", "\\Theta", "Theta (capital)"),
+    sym(Greek, "Λ", "\\Lambda", "Lambda (capital)"),
+    sym(Greek, "Ξ", "\\Xi", "Xi (capital)"),
+    sym(Greek, "Π", "\\Pi", "Pi (capital)"),
+    sym(Greek, "Σ", "\\Sigma", "Sigma (capital)"),
+    sym(Greek, "Φ", "\\Phi", "Phi (capital)"),
+    sym(Greek, "Ψ", "\\Psi", "Psi (capital)"),
+    sym(Greek, "Ω", "\\Omega", "Omega (capital)"),
+    sym(Operators, "±", "\\pm", "plus-minus"),
+    sym(Operators, "∓", "\\mp", "minus-plus"),
+    sym(Operators, "×", "\\times", "times"),
+    sym(Operators, "÷", "\\div", "divide"),
+    sym(Operators, "·", "\\cdot", "dot product"),
+    sym(Operators, "∘", "\\circ", "compose"),
+    sym(Operators, "≤", "\\leq", "less or equal"),
+    sym(Operators, "≥", "\\geq", "greater or equal"),
+    sym(Operators, "≠", "\\neq", "not equal"),
+    sym(Operators, "≈", "\\approx", "approximately"),
+    sym(Operators, "≡", "\\equiv", "equivalent"),
+    sym(Operators, "∼", "\\sim", "similar"),
+    sym(Operators, "∝", "\\propto", "proportional to"),
+    sym(Operators, "∞", "\\infty", "infinity"),
+    sym(Operators, "∂", "\\partial", "partial derivative"),
+    sym(Operators, "∇", "\\nabla", "nabla"),
+    sym(Operators, "∑", "\\sum", "sum"),
+    sym(Operators, "∏", "\\prod", "product"),
+    sym(Operators, "∫", "\\int", "integral"),
+    sym(Operators, "∮", "\\oint", "contour integral"),
+    sym(Operators, "√", "\\sqrt{}", "square root"),
+    sym(Operators, "∈", "\\in", "element of"),
+    sym(Operators, "∉", "\\notin", "not element of"),
+    sym(Operators, "⊂", "\\subset", "subset"),
+    sym(Operators, "⊆", "\\subseteq", "subset or equal"),
+    sym(Operators, "∪", "\\cup", "union"),
+    sym(Operators, "∩", "\\cap", "intersection"),
+    sym(Operators, "∅", "\\emptyset", "empty set"),
+    sym(Operators, "∀", "\\forall", "for all"),
+    sym(Operators, "∃", "\\exists", "exists"),
+    sym(Operators, "¬", "\\neg", "not"),
+    sym(Operators, "∧", "\\land", "and"),
+    sym(Operators, "∨", "\\lor", "or"),
+    sym(Operators, "⊕", "\\oplus", "direct sum"),
+    sym(Operators, "⊗", "\\otimes", "tensor product"),
+    sym(Arrows, "→", "\\rightarrow", "right arrow"),
+    sym(Arrows, "←", "\\leftarrow", "left arrow"),
+    sym(Arrows, "↔", "\\leftrightarrow", "left-right arrow"),
+    sym(Arrows, "↑", "\\uparrow", "up arrow"),
+    sym(Arrows, "↓", "\\downarrow", "down arrow"),
+    sym(Arrows, "⇒", "\\Rightarrow", "implies"),
+    sym(Arrows, "⇐", "\\Leftarrow", "implied by"),
+    sym(Arrows, "⇔", "\\Leftrightarrow", "if and only if"),
+    sym(Arrows, "↦", "\\mapsto", "maps to"),
+    sym(Arrows, "⟶", "\\longrightarrow", "long right arrow"),
+    sym(Arrows, "⟹", "\\Longrightarrow", "long implies"),
+    sym(Arrows, "↪", "\\hookrightarrow", "hook arrow (injection)"),
+    sym(Arrows, "⇀", "\\rightharpoonup", "right harpoon"),
+    sym(Accents, "á", "\\'{}", "acute (text)"),
+    sym(Accents, "à", "\\`{}", "grave (text)"),
+    sym(Accents, "â", "\\^{}", "circumflex (text)"),
+    sym(Accents, "ä", "\\\"{}", "umlaut (text)"),
+    sym(Accents, "ã", "\\~{}", "tilde (text)"),
+    sym(Accents, "ç", "\\c{}", "cedilla (text)"),
+    sym(Accents, "ǎ", "\\v{}", "caron (text)"),
+    sym(Accents, "ā", "\\={}", "macron (text)"),
+    sym(Accents, "å", "\\r{}", "ring (text)"),
+    sym(Accents, "ß", "\\ss", "sharp s"),
+    sym(Accents, "â", "\\hat{}", "hat (math)"),
+    sym(Accents, "ā", "\\bar{}", "bar (math)"),
+    sym(Accents, "a⃗", "\\vec{}", "vector (math)"),
+    sym(Accents, "ȧ", "\\dot{}", "dot (math)"),
+    sym(Accents, "ä", "\\ddot{}", "double dot (math)"),
+    sym(Accents, "ã", "\\tilde{}", "tilde (math)"),
+];
+
+/// Symbols in `group` (or all of them) matching `query` by name or command,
+/// best match first.
+pub fn search(query: &str, group: Option<SymbolGroup>) -> Vec<&'static Symbol> {
+    let mut matches: Vec<(i32, &Symbol)> = SYMBOLS
+        .iter()
+        .filter(|symbol| group.is_none_or(|g| symbol.group == g))
+        .filter_map(|symbol| {
+            [symbol.name, symbol.command]
+                .iter()
+                .filter_map(|text| fuzzy_score(query, text))
+                .max()
+                .map(|score| (score, symbol))
+        })
+        .collect();
+    // Stable, so an empty query keeps the table order
+    matches.sort_by(|a, b| b.0.cmp(&a.0));
+    matches.into_iter().map(|(_, symbol)| symbol).collect()
+}
+
+/// The text to insert for `command` around `selection`, and how many characters
+/// before its end the cursor should go. Commands ending in `{}` take the
+/// selection as their argument, or leave the cursor between the braces; a
+/// space keeps a control word from running into a letter right after it.
+pub fn insertion(command: &str, selection: &str, next: Option<char>) -> (String, usize) {
+    if let Some(head) = command.strip_suffix("{}") {
+        let text = format!("{}{{{}}}", head, selection);
+        let back = if selection.is_empty() { 1 } else { 0 };
+        return (text, back);
+    }
+    let ends_in_letter = command.ends_with(|c: char| c.is_ascii_alphabetic());
+    if ends_in_letter && next.is_some_and(|c| c.is_ascii_alphabetic()) {
+        (format!("{} ", command), 0)
+    } else {
+        (command.to_string(), 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_is_grouped_in_order() {
+        let mut last = 0;
+        for symbol in SYMBOLS {
+            let index = SymbolGroup::ALL
+                .iter()
+                .position(|g| *g == symbol.group)
+                .unwrap();
+            assert!(index >= last, "{} is out of order", symbol.command);
+            last = index;
+            assert!(symbol.command.starts_with('\\'));
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        assert_eq!(search("nabla", None)[0].command, "\\nabla");
+        assert_eq!(search("\\alp", None)[0].command, "\\alpha");
+        assert_eq!(search("implies", None)[0].command, "\\Rightarrow");
+
+        let greek = search("", Some(SymbolGroup::Greek));
+        assert_eq!(greek[0].command, "\\alpha");
+        assert!(greek.iter().all(|s| s.group == SymbolGroup::Greek));
+        assert!(search("zzz", None).is_empty());
+    }
+
+    #[test]
+    fn test_insertion() {
+        assert_eq!(insertion("\\alpha", "", Some('$')), ("\\alpha".into(), 0));
+        assert_eq!(insertion("\\alpha", "", Some('x')), ("\\alpha ".into(), 0));
+        assert_eq!(insertion("\\'{}", "e", None), ("\\'{e}".into(), 0));
+        assert_eq!(insertion("\\hat{}", "", None), ("\\hat{}".into(), 1));
+    }
+}
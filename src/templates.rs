@@ -0,0 +1,110 @@
+//! Document templates offered when creating a new document.
+//!
+//! Built-in templates live in `templates/` and are compiled into the binary;
+//! users can add their own `.tex` files to `<config_dir>/templates/`.
+
+use crate::config::AppConfig;
+use crate::constants::USER_TEMPLATES_DIR;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub name: String,
+    pub description: String,
+    pub content: String,
+}
+
+/// (name, description, content) of the bundled templates, in display order.
+const BUILTIN_TEMPLATES: &[(&str, &str, &str)] = &[
+    ("Blank", "Empty document", ""),
+    (
+        "Article",
+        "Standard article with title, abstract and sections",
+        include_str!("../templates/article.tex"),
+    ),
+    (
+        "Beamer Presentation",
+        "Slides with a title frame and outline",
+        include_str!("../templates/beamer.tex"),
+    ),
+    (
+        "Letter",
+        "Formal letter with addresses and signature",
+        include_str!("../templates/letter.tex"),
+    ),
+    (
+        "IEEE Conference Paper",
+        "IEEEtran conference layout",
+        include_str!("../templates/ieee.tex"),
+    ),
+    (
+        "ACM Paper",
+        "acmart sigconf layout",
+        include_str!("../templates/acm.tex"),
+    ),
+];
+
+/// `.tex` files in `dir`, sorted by name. A missing directory yields no templates.
+fn templates_in(dir: &Path) -> Vec<Template> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut templates: Vec<Template> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "tex" {
+                return None;
+            }
+            let content = std::fs::read_to_string(&path).ok()?;
+            Some(Template {
+                name: path.file_stem()?.to_string_lossy().into_owned(),
+                description: path.to_string_lossy().into_owned(),
+                content,
+            })
+        })
+        .collect();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+/// Bundled templates followed by the user's own.
+pub fn all_templates() -> Vec<Template> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .map(|(name, description, content)| Template {
+            name: name.to_string(),
+            description: description.to_string(),
+            content: content.to_string(),
+        })
+        .chain(templates_in(
+            &AppConfig::config_dir().join(USER_TEMPLATES_DIR),
+        ))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates_are_documents() {
+        for (name, _, content) in &BUILTIN_TEMPLATES[1..] {
+            assert!(content.contains("\\begin{document}"), "{}", name);
+            assert!(content.contains("\\end{document}"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_templates_in_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("thesis.tex"), "\\documentclass{report}").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not a template").unwrap();
+        std::fs::write(dir.path().join("cv.tex"), "\\documentclass{moderncv}").unwrap();
+
+        let templates = templates_in(dir.path());
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["cv", "thesis"]);
+        assert_eq!(templates[1].content, "\\documentclass{report}");
+        assert!(templates_in(&dir.path().join("missing")).is_empty());
+    }
+}
@@ -1,22 +1,71 @@
+use crate::api::presets::PromptPreset;
+use crate::config::AiContextMode;
 use gtk4::prelude::*;
 use gtk4::{
-    Box, Button, Label, Orientation, PolicyType, Revealer, RevealerTransitionType, ScrolledWindow,
-    Spinner, TextView,
+    Box, Button, DropDown, Expander, Label, Orientation, PolicyType, Revealer,
+    RevealerTransitionType, ScrolledWindow, Spinner, StringList, TextView,
 };
+use sourceview5::Buffer;
+
+/// Undoes a pending suggestion and selects the text it replaced, so the same
+/// request can be sent again. Returns `false`, leaving the buffer untouched,
+/// if no highlighted suggestion is found.
+pub fn restore_original_selection(buffer: &Buffer, original: &str) -> bool {
+    let Some(tag) = buffer.tag_table().lookup("ai-suggestion") else {
+        return false;
+    };
+    let mut start = buffer.start_iter();
+    if !start.starts_tag(Some(&tag)) && !start.forward_to_tag_toggle(Some(&tag)) {
+        return false;
+    }
+    let offset = start.offset();
+    buffer.undo();
+
+    let start = buffer.iter_at_offset(offset);
+    let mut end = start.clone();
+    end.forward_chars(original.chars().count() as i32);
+    buffer.select_range(&start, &end);
+    true
+}
+
+/// Lists `presets` in `dropdown`, selecting the one called `selected` or else
+/// the first.
+pub fn set_presets(dropdown: &DropDown, presets: &[PromptPreset], selected: Option<&str>) {
+    let names: Vec<&str> = presets.iter().map(|p| p.name.as_str()).collect();
+    dropdown.set_model(Some(&StringList::new(&names)));
+    let position = selected
+        .and_then(|name| names.iter().position(|n| *n == name))
+        .unwrap_or(0);
+    dropdown.set_selected(position as u32);
+}
 
 /// Creates the AI assistant panel consisting of a `Revealer` containing
-/// a text entry, a loading spinner, a run button, and a reasoning box.
+/// a text entry, a context selector, a system-prompt preset selector, a loading
+/// spinner, a run button, a regenerate button, the accept/review/reject
+/// suggestion buttons, a reasoning box, and a label reporting the token usage
+/// of the last response. The reasoning box's title label and expander come
+/// last so callers can name the model and collapse it.
+///
+/// The context selector's items follow the order of [`AiContextMode::ALL`]; the
+/// preset selector starts empty and is filled with [`set_presets`].
 pub fn create_ai_panel() -> (
     Revealer,
     TextView,
     Spinner,
     Button,
+    Button,
     Revealer,
     TextView,
     Revealer,
     Button,
     Button,
     Button,
+    Button,
+    Label,
+    DropDown,
+    DropDown,
+    Label,
+    Expander,
 ) {
     let container = Box::new(Orientation::Vertical, 0);
 
@@ -74,10 +123,40 @@ pub fn create_ai_panel() -> (
         .build();
     ai_run_btn.add_css_class("suggested-action");
 
+    // Enabled once a generation has completed
+    let regenerate_btn = Button::builder()
+        .icon_name("view-refresh-symbolic")
+        .tooltip_text("Regenerate: ask again with the last instruction")
+        .valign(gtk4::Align::Start)
+        .sensitive(false)
+        .build();
+
+    let context_modes = StringList::new(
+        AiContextMode::ALL
+            .iter()
+            .map(|m| m.label())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let context_dropdown = DropDown::builder()
+        .model(&context_modes)
+        .tooltip_text("What the AI sees")
+        .valign(gtk4::Align::Start)
+        .build();
+
     ai_entry_box.append(&ai_scroll);
     ai_entry_box.append(&clear_btn);
+    let preset_dropdown = DropDown::builder()
+        .model(&StringList::new(&[]))
+        .tooltip_text("System prompt preset")
+        .valign(gtk4::Align::Start)
+        .build();
+
+    ai_entry_box.append(&context_dropdown);
+    ai_entry_box.append(&preset_dropdown);
     ai_entry_box.append(&ai_spinner);
     ai_entry_box.append(&ai_run_btn);
+    ai_entry_box.append(&regenerate_btn);
     container.append(&ai_entry_box);
 
     // Suggestion Actions (Accept/Reject)
@@ -104,7 +183,14 @@ pub fn create_ai_panel() -> (
         .build();
     reject_btn.add_css_class("destructive-action");
 
+    let review_btn = Button::builder()
+        .label("Review Changes…")
+        .icon_name("view-dual-symbolic")
+        .tooltip_text("Show the changes as a diff and choose which hunks to apply")
+        .build();
+
     suggestion_box.append(&accept_btn);
+    suggestion_box.append(&review_btn);
     suggestion_box.append(&reject_btn);
     suggestion_revealer.set_child(Some(&suggestion_box));
     container.append(&suggestion_revealer);
@@ -148,16 +234,24 @@ pub fn create_ai_panel() -> (
 
     let header_box = Box::new(Orientation::Horizontal, 6);
     let reasoning_label_title = Label::builder()
-        .label("<b>Reasoning (DeepSeek R1)</b>")
+        .label("<b>Reasoning</b>")
         .use_markup(true)
         .xalign(0.0)
-        .hexpand(true)
         .build();
     reasoning_label_title.add_css_class("dim-label");
 
+    // Collapsing keeps the header visible so the reasoning can be opened on demand
+    let reasoning_expander = Expander::builder()
+        .label_widget(&reasoning_label_title)
+        .child(&scroll)
+        .expanded(true)
+        .hexpand(true)
+        .build();
+
     let close_btn = Button::builder()
         .icon_name("window-close-symbolic")
         .has_frame(false)
+        .valign(gtk4::Align::Start)
         .build();
     close_btn.connect_clicked(glib::clone!(
         #[weak]
@@ -167,24 +261,40 @@ pub fn create_ai_panel() -> (
         }
     ));
 
-    header_box.append(&reasoning_label_title);
+    header_box.append(&reasoning_expander);
     header_box.append(&close_btn);
 
     reasoning_box.append(&header_box);
-    reasoning_box.append(&scroll);
     reasoning_revealer.set_child(Some(&reasoning_box));
     container.append(&reasoning_revealer);
 
+    let usage_label = Label::builder()
+        .xalign(0.0)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_bottom(6)
+        .visible(false)
+        .build();
+    usage_label.add_css_class("dim-label");
+    container.append(&usage_label);
+
     (
         ai_revealer,
         ai_entry,
         ai_spinner,
         ai_run_btn,
+        regenerate_btn,
         reasoning_revealer,
         reasoning_view,
         suggestion_revealer,
         accept_btn,
+        review_btn,
         reject_btn,
         clear_btn,
+        usage_label,
+        context_dropdown,
+        preset_dropdown,
+        reasoning_label_title,
+        reasoning_expander,
     )
 }
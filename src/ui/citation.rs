@@ -0,0 +1,299 @@
+use crate::bib::{find_bib_files, parse_bib, search, BibEntry};
+use crate::state::AppState;
+use adw::prelude::*;
+use gtk4::{
+    gdk, gio, glib, Box, Button, ListBox, Orientation, Popover, ScrolledWindow, SearchEntry,
+};
+use regex::Regex;
+use sourceview5::{Buffer, View};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// Most entries listed at once; typing narrows the rest.
+const MAX_CITATION_ROWS: usize = 50;
+
+/// Parsed `.bib` files keyed by path, re-read when the modification time changes.
+type BibCache = HashMap<PathBuf, (SystemTime, Vec<BibEntry>)>;
+
+fn open_cite_regex() -> &'static Regex {
+    static OPEN_CITE_REGEX: OnceLock<Regex> = OnceLock::new();
+    OPEN_CITE_REGEX.get_or_init(|| Regex::new(r"\\[a-zA-Z]*cite[a-zA-Z]*\{$").unwrap())
+}
+
+/// Whether the text just before the cursor is an unfinished `\cite{`-style command.
+fn after_open_cite(buffer: &Buffer) -> bool {
+    let end = buffer.iter_at_mark(&buffer.get_insert());
+    let mut start = end.clone();
+    start.set_line_offset(0);
+    open_cite_regex().is_match(&buffer.text(&start, &end, false))
+}
+
+/// Loads the entries of every `.bib` the document uses (or the one the user
+/// picked), re-parsing files that changed on disk since the last lookup.
+fn load_entries(
+    buffer: &Buffer,
+    state: &Rc<RefCell<AppState>>,
+    chosen_bib: &Rc<RefCell<Option<PathBuf>>>,
+    cache: &Rc<RefCell<BibCache>>,
+) -> Vec<BibEntry> {
+    let files = match chosen_bib.borrow().clone() {
+        Some(path) => vec![path],
+        None => {
+            let Some(dir) = state
+                .borrow()
+                .current_file
+                .as_ref()
+                .and_then(|f| f.parent().map(|p| p.to_path_buf()))
+            else {
+                return Vec::new();
+            };
+            find_bib_files(&crate::utils::buffer_to_string(buffer.upcast_ref()), &dir)
+        }
+    };
+
+    let mut cache = cache.borrow_mut();
+    let mut entries = Vec::new();
+    for path in files {
+        let Ok(modified) = std::fs::metadata(&path).and_then(|m| m.modified()) else {
+            continue;
+        };
+        let stale = cache.get(&path).is_none_or(|(time, _)| *time != modified);
+        if stale {
+            let parsed = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|src| parse_bib(&src));
+            match parsed {
+                Ok(parsed) => {
+                    cache.insert(path.clone(), (modified, parsed));
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to parse {}: {}", path.display(), e);
+                    continue;
+                }
+            }
+        }
+        if let Some((_, parsed)) = cache.get(&path) {
+            entries.extend(parsed.iter().cloned());
+        }
+    }
+    entries
+}
+
+/// Inserts `key` at the cursor, completing an open `\cite{` or wrapping it in one.
+fn insert_citation(buffer: &Buffer, key: &str) {
+    let text = if after_open_cite(buffer) {
+        let cursor = buffer.iter_at_mark(&buffer.get_insert());
+        if cursor.char() == '}' {
+            key.to_string()
+        } else {
+            format!("{}}}", key)
+        }
+    } else {
+        format!("\\cite{{{}}}", key)
+    };
+    buffer.insert_at_cursor(&text);
+}
+
+fn populate(list: &ListBox, entries: &[BibEntry], query: &str) {
+    list.remove_all();
+    for entry in search(entries, query).into_iter().take(MAX_CITATION_ROWS) {
+        let subtitle = format!("{} ({}) — {}", entry.authors, entry.year, entry.title);
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(&entry.key).as_str())
+            .subtitle(glib::markup_escape_text(&subtitle).as_str())
+            .subtitle_lines(2)
+            .activatable(true)
+            .build();
+        row.set_widget_name(&entry.key);
+        list.append(&row);
+    }
+    if let Some(first) = list.row_at_index(0) {
+        list.select_row(Some(&first));
+    }
+}
+
+/// Creates the citation picker and registers `win.insert-citation`.
+///
+/// The picker lists entries from the `.bib` files named by `\bibliography` or
+/// `\addbibresource` (or one chosen by hand) and opens automatically after typing
+/// `\cite{`. Choosing an entry inserts its key at the cursor.
+pub fn connect_citation_picker(
+    window: &adw::ApplicationWindow,
+    buffer: &Buffer,
+    editor_view: &View,
+    state: Rc<RefCell<AppState>>,
+) {
+    let cache: Rc<RefCell<BibCache>> = Rc::new(RefCell::new(HashMap::new()));
+    let chosen_bib: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    let entries: Rc<RefCell<Vec<BibEntry>>> = Rc::new(RefCell::new(Vec::new()));
+
+    let popover = Popover::builder().autohide(true).build();
+    popover.set_parent(editor_view);
+
+    let container = Box::new(Orientation::Vertical, 6);
+    container.set_width_request(420);
+    let search_entry = SearchEntry::builder()
+        .placeholder_text("Search author, title, year or key…")
+        .build();
+    let list = ListBox::new();
+    list.add_css_class("boxed-list");
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(360)
+        .child(&list)
+        .build();
+    let choose_btn = Button::builder()
+        .label("Choose .bib File…")
+        .has_frame(false)
+        .build();
+    container.append(&search_entry);
+    container.append(&scroll);
+    container.append(&choose_btn);
+    popover.set_child(Some(&container));
+
+    let show = glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        editor_view,
+        #[weak]
+        popover,
+        #[weak]
+        search_entry,
+        #[weak]
+        list,
+        #[strong]
+        state,
+        #[strong]
+        chosen_bib,
+        #[strong]
+        cache,
+        #[strong]
+        entries,
+        move || {
+            *entries.borrow_mut() = load_entries(&buffer, &state, &chosen_bib, &cache);
+            search_entry.set_text("");
+            populate(&list, &entries.borrow(), "");
+
+            // Point at the cursor
+            let cursor = buffer.iter_at_mark(&buffer.get_insert());
+            let rect = editor_view.iter_location(&cursor);
+            let (x, y) = editor_view.buffer_to_window_coords(
+                gtk4::TextWindowType::Widget,
+                rect.x(),
+                rect.y(),
+            );
+            popover.set_pointing_to(Some(&gdk::Rectangle::new(x, y, 1, rect.height())));
+            popover.popup();
+            search_entry.grab_focus();
+        }
+    );
+
+    search_entry.connect_search_changed(glib::clone!(
+        #[weak]
+        list,
+        #[strong]
+        entries,
+        move |entry| {
+            populate(&list, &entries.borrow(), &entry.text());
+        }
+    ));
+    search_entry.connect_activate(glib::clone!(
+        #[weak]
+        list,
+        move |_| {
+            if let Some(row) = list.selected_row() {
+                row.emit_activate();
+            }
+        }
+    ));
+    search_entry.connect_stop_search(glib::clone!(
+        #[weak]
+        popover,
+        move |_| {
+            popover.popdown();
+        }
+    ));
+
+    list.connect_row_activated(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        popover,
+        #[weak]
+        editor_view,
+        move |_, row| {
+            popover.popdown();
+            insert_citation(&buffer, &row.widget_name());
+            editor_view.grab_focus();
+        }
+    ));
+
+    choose_btn.connect_clicked(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        popover,
+        #[strong]
+        chosen_bib,
+        #[strong]
+        show,
+        move |_| {
+            popover.popdown();
+            let filter = gtk4::FileFilter::new();
+            filter.set_name(Some("BibTeX"));
+            filter.add_pattern("*.bib");
+            let filters = gio::ListStore::new::<gtk4::FileFilter>();
+            filters.append(&filter);
+            let dialog = gtk4::FileDialog::builder()
+                .title("Choose Bibliography")
+                .filters(&filters)
+                .build();
+            dialog.open(
+                Some(&window),
+                None::<&gio::Cancellable>,
+                glib::clone!(
+                    #[strong]
+                    chosen_bib,
+                    #[strong]
+                    show,
+                    move |res| {
+                        if let Some(path) = res.ok().and_then(|f| f.path()) {
+                            *chosen_bib.borrow_mut() = Some(path);
+                            show();
+                        }
+                    }
+                ),
+            );
+        }
+    ));
+
+    // Open automatically when `{` completes a `\cite{`
+    buffer.connect_insert_text(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        show,
+        move |buf, _, text| {
+            if text != "{" || state.borrow().is_ai_generating {
+                return;
+            }
+            let buf = buf.clone();
+            let show = show.clone();
+            glib::idle_add_local_once(move || {
+                if after_open_cite(&buf) {
+                    show();
+                }
+            });
+        }
+    ));
+
+    let action = gio::SimpleAction::new("insert-citation", None);
+    action.connect_activate(move |_, _| show());
+    window.add_action(&action);
+}
@@ -0,0 +1,141 @@
+use crate::diff::{apply_hunks, diff_hunks, DiffLine};
+use crate::state::AppState;
+use adw::prelude::*;
+use adw::{AlertDialog, ApplicationWindow};
+use gtk4::{glib, Box, Button, CheckButton, Label, Orientation, PolicyType, ScrolledWindow};
+use sourceview5::Buffer;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The buffer range currently highlighted as the AI suggestion.
+fn suggestion_range(buffer: &Buffer) -> Option<(gtk4::TextIter, gtk4::TextIter)> {
+    let tag = buffer.tag_table().lookup("ai-suggestion")?;
+    let mut start = buffer.start_iter();
+    if !start.has_tag(&tag) && !start.forward_to_tag_toggle(Some(&tag)) {
+        return None;
+    }
+    let mut end = start.clone();
+    end.forward_to_tag_toggle(Some(&tag));
+    Some((start, end))
+}
+
+fn diff_line_label(line: &DiffLine) -> Label {
+    let (prefix, text, class) = match line {
+        DiffLine::Context(text) => (' ', text, None),
+        DiffLine::Removed(text) => ('-', text, Some("error")),
+        DiffLine::Added(text) => ('+', text, Some("success")),
+    };
+    let label = Label::builder()
+        .label(format!("{}{}", prefix, text.trim_end_matches('\n')))
+        .xalign(0.0)
+        .selectable(true)
+        .build();
+    label.add_css_class("monospace");
+    if let Some(class) = class {
+        label.add_css_class(class);
+    }
+    label
+}
+
+/// Shows the pending AI suggestion as a unified diff against the original
+/// text, with a checkbox per hunk.
+///
+/// "Apply Selected" replaces the suggestion with the original plus the checked
+/// hunks and then finishes like `accept_btn`; "Reject All" defers to `reject_btn`.
+pub fn show_diff_review(
+    window: &ApplicationWindow,
+    buffer: &Buffer,
+    state: &Rc<RefCell<AppState>>,
+    accept_btn: &Button,
+    reject_btn: &Button,
+) {
+    let (original, suggestion) = {
+        let s = state.borrow();
+        match (&s.original_text_selection, &s.pending_suggestion) {
+            (Some(original), Some(suggestion)) => (original.clone(), suggestion.clone()),
+            _ => return,
+        }
+    };
+    let hunks = diff_hunks(&original, &suggestion);
+
+    let list = Box::new(Orientation::Vertical, 12);
+    let mut checks = Vec::with_capacity(hunks.len());
+    for hunk in &hunks {
+        let hunk_box = Box::new(Orientation::Vertical, 0);
+        let check = CheckButton::builder()
+            .label(&hunk.header)
+            .active(true)
+            .build();
+        hunk_box.append(&check);
+        for line in &hunk.lines {
+            hunk_box.append(&diff_line_label(line));
+        }
+        list.append(&hunk_box);
+        checks.push(check);
+    }
+    if hunks.is_empty() {
+        let label = Label::new(Some("The suggestion makes no changes."));
+        label.add_css_class("dim-label");
+        list.append(&label);
+    }
+
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(PolicyType::Automatic)
+        .vscrollbar_policy(PolicyType::Automatic)
+        .min_content_height(300)
+        .min_content_width(520)
+        .child(&list)
+        .build();
+
+    let dialog = AlertDialog::builder()
+        .heading("Review AI Changes")
+        .body("Uncheck hunks to leave that part of the original text unchanged.")
+        .extra_child(&scroll)
+        .build();
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("reject", "Reject All");
+    dialog.add_response("apply", "Apply Selected");
+    dialog.set_response_appearance("reject", adw::ResponseAppearance::Destructive);
+    dialog.set_response_appearance("apply", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("apply"));
+    dialog.set_close_response("cancel");
+
+    dialog.connect_response(
+        Some("reject"),
+        glib::clone!(
+            #[weak]
+            reject_btn,
+            move |_, _| {
+                reject_btn.emit_clicked();
+            }
+        ),
+    );
+
+    dialog.connect_response(
+        Some("apply"),
+        glib::clone!(
+            #[weak]
+            buffer,
+            #[weak]
+            accept_btn,
+            move |_, _| {
+                let accepted: Vec<bool> = checks.iter().map(|c| c.is_active()).collect();
+                if accepted.iter().any(|a| !a) {
+                    let Some((mut start, mut end)) = suggestion_range(&buffer) else {
+                        tracing::warn!("AI suggestion is no longer highlighted; applying it whole");
+                        accept_btn.emit_clicked();
+                        return;
+                    };
+                    let merged = apply_hunks(&original, &hunks, &accepted);
+                    buffer.begin_user_action();
+                    buffer.delete(&mut start, &mut end);
+                    buffer.insert(&mut start, &merged);
+                    buffer.end_user_action();
+                }
+                accept_btn.emit_clicked();
+            }
+        ),
+    );
+
+    dialog.present(Some(window));
+}
@@ -0,0 +1,168 @@
+use crate::config::LatexEngine;
+use crate::constants::PROJECT_FILE_NAME;
+use crate::project::ProjectSettings;
+use crate::state::AppState;
+use adw::prelude::*;
+use adw::{AlertDialog, ApplicationWindow, ComboRow, EntryRow, PreferencesGroup, SpinRow};
+use gtk4::{gio, glib, StringList};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// Choices of the shell escape row, in order; `None` keeps the global setting.
+const SHELL_ESCAPE_CHOICES: [Option<bool>; 3] = [None, Some(false), Some(true)];
+
+fn show_document_settings(
+    window: &ApplicationWindow,
+    state: &Rc<RefCell<AppState>>,
+    toast_overlay: &adw::ToastOverlay,
+) {
+    let (project, global_engine) = {
+        let s = state.borrow();
+        if s.current_file.is_none() {
+            toast_overlay.add_toast(adw::Toast::new(
+                "Save the document first to give it project settings",
+            ));
+            return;
+        }
+        (s.project.clone(), s.config.latex_engine)
+    };
+
+    let form = PreferencesGroup::new();
+
+    let engine_labels: Vec<String> =
+        std::iter::once(format!("Default ({})", global_engine.label()))
+            .chain(LatexEngine::ALL.iter().map(|e| e.label().to_string()))
+            .collect();
+    let engine_row = ComboRow::builder()
+        .title("Engine")
+        .model(&StringList::new(
+            &engine_labels.iter().map(String::as_str).collect::<Vec<_>>(),
+        ))
+        .selected(
+            project
+                .engine
+                .and_then(|engine| LatexEngine::ALL.iter().position(|e| *e == engine))
+                .map_or(0, |i| i + 1) as u32,
+        )
+        .build();
+
+    let shell_escape_row = ComboRow::builder()
+        .title("Shell Escape")
+        .subtitle("Lets the document run commands, e.g. for minted")
+        .model(&StringList::new(&["Default", "Off", "On"]))
+        .selected(
+            SHELL_ESCAPE_CHOICES
+                .iter()
+                .position(|c| *c == project.shell_escape)
+                .unwrap_or(0) as u32,
+        )
+        .build();
+
+    let timeout_row = SpinRow::with_range(0.0, 3600.0, 10.0);
+    timeout_row.set_title("Timeout (seconds)");
+    timeout_row.set_subtitle("0 uses the global limit");
+    timeout_row.set_value(project.timeout_secs.unwrap_or(0) as f64);
+
+    let main_file_row = EntryRow::builder()
+        .title("Main File (relative to this folder)")
+        .text(
+            project
+                .main_file
+                .as_ref()
+                .map(|p| p.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        )
+        .build();
+
+    form.add(&engine_row);
+    form.add(&shell_escape_row);
+    form.add(&timeout_row);
+    form.add(&main_file_row);
+
+    let dialog = AlertDialog::builder()
+        .heading("Document Settings")
+        .body(format!(
+            "Stored in {} next to the document and shared by every file in its folder.",
+            PROJECT_FILE_NAME
+        ))
+        .extra_child(&form)
+        .build();
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("save", "Save");
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+
+    dialog.connect_response(
+        Some("save"),
+        glib::clone!(
+            #[strong]
+            state,
+            #[weak]
+            window,
+            #[weak]
+            toast_overlay,
+            #[weak]
+            engine_row,
+            #[weak]
+            shell_escape_row,
+            #[weak]
+            timeout_row,
+            #[weak]
+            main_file_row,
+            move |_, _| {
+                let main_file = main_file_row.text().trim().to_string();
+                let settings = ProjectSettings {
+                    engine: (engine_row.selected() as usize)
+                        .checked_sub(1)
+                        .and_then(|i| LatexEngine::ALL.get(i).copied()),
+                    shell_escape: SHELL_ESCAPE_CHOICES
+                        .get(shell_escape_row.selected() as usize)
+                        .copied()
+                        .flatten(),
+                    timeout_secs: Some(timeout_row.value() as u64).filter(|secs| *secs > 0),
+                    main_file: (!main_file.is_empty()).then(|| PathBuf::from(main_file)),
+                };
+                let result = {
+                    let mut s = state.borrow_mut();
+                    s.project = settings;
+                    s.save_project()
+                };
+                match result {
+                    Ok(()) => {
+                        let _ = WidgetExt::activate_action(&window, "win.recompile", None);
+                    }
+                    Err(e) => toast_overlay.add_toast(adw::Toast::new(&format!(
+                        "Failed to save document settings: {}",
+                        e
+                    ))),
+                }
+            }
+        ),
+    );
+
+    dialog.present(Some(window));
+}
+
+/// Registers `win.document-settings`, which edits the engine, shell escape,
+/// timeout and main file stored in the document's `.latexrs.toml`.
+pub fn connect_document_settings_action(
+    window: &ApplicationWindow,
+    state: Rc<RefCell<AppState>>,
+    toast_overlay: &adw::ToastOverlay,
+) {
+    let action = gio::SimpleAction::new("document-settings", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        toast_overlay,
+        #[strong]
+        state,
+        move |_, _| {
+            show_document_settings(&window, &state, &toast_overlay);
+        }
+    ));
+    window.add_action(&action);
+}
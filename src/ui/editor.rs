@@ -1,22 +1,58 @@
+use crate::config::AppConfig;
 use crate::constants::{
-    DEFAULT_EDITOR_FONT, DEFAULT_EDITOR_FONT_SIZE, DEFAULT_ZOOM_LEVEL, MAX_ZOOM_LEVEL,
-    MIN_ZOOM_LEVEL, ZOOM_STEP,
+    DEFAULT_TAB_WIDTH, DEFAULT_ZOOM_LEVEL, ENVIRONMENT_MATCH_FLASH_MS, LINT_DELAY_MS,
+    LINT_MARK_CATEGORY, MAX_ZOOM_LEVEL, MIN_ZOOM_LEVEL, SPELL_CHECK_DELAY_MS, TODO_SCAN_DELAY_MS,
+    ZOOM_STEP,
+};
+use crate::format::{format_latex, FormatOptions};
+use crate::navigation::Position;
+use crate::spell::SpellChecker;
+use crate::utils::{
+    find_todos, fold_regions, lint_latex, matching_environment, toggle_comment, FoldKind,
+    FoldRegion, LintIssue, TodoItem,
 };
 use crate::AppState;
 use adw::StyleManager;
 use glib;
-use gtk4::gdk;
 use gtk4::prelude::*;
+use gtk4::{gdk, gio};
 use gtk4::{Box, Orientation, Revealer, RevealerTransitionType, ScrolledWindow, SearchEntry};
 use sourceview5::prelude::*;
 use sourceview5::{Buffer, LanguageManager, StyleSchemeManager, View};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use webkit6::prelude::*;
 
+/// Applies the style scheme `scheme_id`, or Adwaita/Adwaita-dark following
+/// `is_dark` when it is `None` or not installed.
+fn update_editor_theme(buffer: &Buffer, scheme_id: Option<&str>, is_dark: bool) {
+    let scheme_manager = StyleSchemeManager::default();
+    let fallback = if is_dark { "classic-dark" } else { "classic" };
+    let default_id = if is_dark { "Adwaita-dark" } else { "Adwaita" };
+    let scheme = scheme_id
+        .and_then(|id| scheme_manager.scheme(id))
+        .or_else(|| scheme_manager.scheme(default_id))
+        .or_else(|| scheme_manager.scheme(fallback));
+    let Some(scheme) = scheme else {
+        return;
+    };
+    // Only colors change: the cursor, selection and scroll offset are left
+    // alone, and re-applying the current scheme is skipped to avoid a re-highlight
+    if buffer
+        .style_scheme()
+        .is_some_and(|current| current.id() == scheme.id())
+    {
+        return;
+    }
+    buffer.set_style_scheme(Some(&scheme));
+}
+
 /// Creates the text editor component with LaTeX syntax highlighting, undo support,
 /// and theme synchronization.
-pub fn create_editor(style_manager: &StyleManager) -> (Buffer, View, ScrolledWindow) {
+pub fn create_editor(
+    style_manager: &StyleManager,
+    state: Rc<RefCell<AppState>>,
+) -> (Buffer, View, ScrolledWindow) {
     let lang_manager = LanguageManager::default();
     let lang = lang_manager.language("latex");
     let buffer = Buffer::new(None);
@@ -40,33 +76,25 @@ pub fn create_editor(style_manager: &StyleManager) -> (Buffer, View, ScrolledWin
     editor_view.set_highlight_current_line(true);
     editor_view.set_auto_indent(true);
     editor_view.set_insert_spaces_instead_of_tabs(true);
-    editor_view.set_indent_width(4);
+    editor_view.set_indent_width(DEFAULT_TAB_WIDTH as i32);
     buffer.set_highlight_matching_brackets(true);
     editor_view.set_smart_backspace(true);
 
-    // Helper function to update theme
-    fn update_editor_theme(buffer: &Buffer, is_dark: bool) {
-        let scheme_manager = StyleSchemeManager::default();
-        let scheme_id = if is_dark { "Adwaita-dark" } else { "Adwaita" };
-        if let Some(scheme) = scheme_manager.scheme(scheme_id) {
-            buffer.set_style_scheme(Some(&scheme));
-        } else {
-            let fallback = if is_dark { "classic-dark" } else { "classic" };
-            if let Some(scheme) = scheme_manager.scheme(fallback) {
-                buffer.set_style_scheme(Some(&scheme));
-            }
-        }
-    }
-
     // Initial theme
-    update_editor_theme(&buffer, style_manager.is_dark());
+    update_editor_theme(
+        &buffer,
+        state.borrow().config.editor_color_scheme.as_deref(),
+        style_manager.is_dark(),
+    );
 
-    // Listen for system theme changes
+    // Listen for system theme changes; a chosen scheme stays as it is
     style_manager.connect_dark_notify(glib::clone!(
         #[weak]
         buffer,
         move |sm| {
-            update_editor_theme(&buffer, sm.is_dark());
+            if state.borrow().config.editor_color_scheme.is_none() {
+                update_editor_theme(&buffer, None, sm.is_dark());
+            }
         }
     ));
 
@@ -103,8 +131,61 @@ pub fn create_search_bar() -> (Revealer, SearchEntry, gtk4::Label) {
     search_revealer.set_child(Some(&search_box));
     (search_revealer, search_entry, count_label)
 }
+/// Applies the tab, line-number, wrapping and color scheme preferences to the editor.
+///
+/// A document with a very long line is always wrapped by character and shown
+/// without syntax or bracket highlighting, which would make typing in it crawl.
+fn apply_view_settings(editor_view: &View, config: &AppConfig) {
+    let mut long_lines = false;
+    if let Ok(buffer) = editor_view.buffer().downcast::<Buffer>() {
+        update_editor_theme(
+            &buffer,
+            config.editor_color_scheme.as_deref(),
+            StyleManager::default().is_dark(),
+        );
+        long_lines =
+            crate::utils::has_long_line(&crate::utils::buffer_to_string(buffer.upcast_ref()));
+        buffer.set_highlight_syntax(!long_lines);
+        buffer.set_highlight_matching_brackets(!long_lines);
+    }
+    editor_view.set_tab_width(config.tab_width);
+    editor_view.set_indent_width(config.tab_width as i32);
+    editor_view.set_insert_spaces_instead_of_tabs(config.insert_spaces);
+    editor_view.set_show_line_numbers(config.show_line_numbers);
+    editor_view.set_wrap_mode(if long_lines {
+        gtk4::WrapMode::Char
+    } else if config.wrap_lines {
+        gtk4::WrapMode::WordChar
+    } else {
+        gtk4::WrapMode::None
+    });
+}
+
+/// Re-applies the view settings whenever an edit adds the document's first very
+/// long line or removes its last one, see [`apply_view_settings`].
+pub fn connect_long_line_guard(buffer: &Buffer, editor_view: &View, state: Rc<RefCell<AppState>>) {
+    let long_lines = Rc::new(Cell::new(false));
+    buffer.connect_changed(glib::clone!(
+        #[weak]
+        editor_view,
+        move |buf| {
+            let text = crate::utils::buffer_to_string(buf.upcast_ref());
+            let has_long_line = crate::utils::has_long_line(&text);
+            if long_lines.replace(has_long_line) != has_long_line {
+                if has_long_line {
+                    tracing::info!("Document has very long lines; wrapping by character");
+                }
+                apply_view_settings(&editor_view, &state.borrow().config);
+            }
+        }
+    ));
+}
+
 /// Connects zoom handlers for keyboard shortcuts (Ctrl+Plus/Minus/0) and mouse scroll.
 /// Also handles document search shortcuts (Ctrl+F, Escape).
+///
+/// Returns a callback that re-applies the editor font and view preferences
+/// from `AppConfig`, for use after settings change.
 pub fn connect_zoom_handlers(
     window: &adw::ApplicationWindow,
     state: Rc<RefCell<AppState>>,
@@ -113,7 +194,7 @@ pub fn connect_zoom_handlers(
     search_revealer: &gtk4::Revealer,
     search_entry: &gtk4::SearchEntry,
     web_view: &webkit6::WebView,
-) {
+) -> Rc<dyn Fn()> {
     let zoom_provider = gtk4::CssProvider::new();
     #[allow(deprecated)]
     editor_view
@@ -122,11 +203,12 @@ pub fn connect_zoom_handlers(
 
     let apply_editor_zoom = {
         let zoom_provider = zoom_provider.clone();
-        move |zoom: f64| {
-            let size = (DEFAULT_EDITOR_FONT_SIZE as f64 * zoom) as i32;
+        move |s: &AppState| {
+            let size = (s.config.editor_font_size as f64 * s.editor_zoom) as i32;
             let css = format!(
                 "textview {{ font-family: '{}'; font-size: {}pt; }}",
-                DEFAULT_EDITOR_FONT, size
+                s.config.editor_font.replace('\'', ""),
+                size
             );
             zoom_provider.load_from_string(&css);
         }
@@ -139,8 +221,22 @@ pub fn connect_zoom_handlers(
         }
     };
 
+    let refresh_editor: Rc<dyn Fn()> = Rc::new(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        editor_view,
+        #[strong]
+        apply_editor_zoom,
+        move || {
+            let s = state.borrow();
+            apply_editor_zoom(&s);
+            apply_view_settings(&editor_view, &s.config);
+        }
+    ));
+
     // Initial application
-    apply_editor_zoom(DEFAULT_ZOOM_LEVEL);
+    refresh_editor();
     apply_preview_zoom(DEFAULT_ZOOM_LEVEL);
 
     // Keyboard zoom & search shortcuts
@@ -211,7 +307,7 @@ pub fn connect_zoom_handlers(
                     gdk::Key::plus | gdk::Key::equal | gdk::Key::KP_Add => {
                         if is_editor {
                             s.editor_zoom = (s.editor_zoom + ZOOM_STEP).min(MAX_ZOOM_LEVEL);
-                            apply_editor_zoom(s.editor_zoom);
+                            apply_editor_zoom(&s);
                         } else {
                             s.preview_zoom = (s.preview_zoom + ZOOM_STEP).min(MAX_ZOOM_LEVEL);
                             apply_preview_zoom(s.preview_zoom);
@@ -221,7 +317,7 @@ pub fn connect_zoom_handlers(
                     gdk::Key::minus | gdk::Key::underscore | gdk::Key::KP_Subtract => {
                         if is_editor {
                             s.editor_zoom = (s.editor_zoom - ZOOM_STEP).max(MIN_ZOOM_LEVEL);
-                            apply_editor_zoom(s.editor_zoom);
+                            apply_editor_zoom(&s);
                         } else {
                             s.preview_zoom = (s.preview_zoom - ZOOM_STEP).max(MIN_ZOOM_LEVEL);
                             apply_preview_zoom(s.preview_zoom);
@@ -231,7 +327,7 @@ pub fn connect_zoom_handlers(
                     gdk::Key::_0 | gdk::Key::KP_0 => {
                         if is_editor {
                             s.editor_zoom = DEFAULT_ZOOM_LEVEL;
-                            apply_editor_zoom(s.editor_zoom);
+                            apply_editor_zoom(&s);
                         } else {
                             s.preview_zoom = DEFAULT_ZOOM_LEVEL;
                             apply_preview_zoom(s.preview_zoom);
@@ -266,7 +362,7 @@ pub fn connect_zoom_handlers(
                     } else {
                         s.editor_zoom = (s.editor_zoom - ZOOM_STEP).max(MIN_ZOOM_LEVEL);
                     }
-                    apply_editor_zoom(s.editor_zoom);
+                    apply_editor_zoom(&s);
                     return glib::Propagation::Stop;
                 }
             }
@@ -301,32 +397,833 @@ pub fn connect_zoom_handlers(
             glib::Propagation::Proceed
         }
     });
+
+    refresh_editor
+}
+
+/// Adds code folding to the editor: a gutter column with fold toggles for
+/// environments and sections, plus `win.fold-all-environments` and
+/// `win.unfold-all` actions. Folds are transient and hide text with an
+/// invisible tag.
+pub fn connect_folding(window: &adw::ApplicationWindow, buffer: &Buffer, editor_view: &View) {
+    let fold_tag = gtk4::TextTag::builder()
+        .name("folded")
+        .invisible(true)
+        .build();
+    buffer.tag_table().add(&fold_tag);
+
+    let regions: Rc<RefCell<Vec<FoldRegion>>> = Rc::new(RefCell::new(Vec::new()));
+    buffer.connect_changed(glib::clone!(
+        #[strong]
+        regions,
+        move |buf| {
+            *regions.borrow_mut() = fold_regions(&crate::utils::buffer_to_string(buf.upcast_ref()));
+        }
+    ));
+
+    // Hidden range: from the end of the start line through the end of the last line
+    let fold_range = |buf: &Buffer, region: &FoldRegion| {
+        let mut start = buf.iter_at_line(region.start_line)?;
+        let mut end = buf.iter_at_line(region.end_line)?;
+        // forward_to_line_end() on an empty line would skip to the next one
+        for iter in [&mut start, &mut end] {
+            if !iter.ends_line() {
+                iter.forward_to_line_end();
+            }
+        }
+        Some((start, end))
+    };
+    let is_folded = move |buf: &Buffer, tag: &gtk4::TextTag, region: &FoldRegion| {
+        fold_range(buf, region).is_some_and(|(start, _)| start.has_tag(tag))
+    };
+    let set_folded = move |buf: &Buffer, region: &FoldRegion, folded: bool| {
+        if let Some((start, end)) = fold_range(buf, region) {
+            if folded {
+                buf.apply_tag_by_name("folded", &start, &end);
+            } else {
+                buf.remove_tag_by_name("folded", &start, &end);
+            }
+        }
+    };
+
+    let renderer = sourceview5::GutterRendererText::new();
+    renderer.set_xpad(4);
+    renderer.connect_query_data(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        fold_tag,
+        #[strong]
+        regions,
+        move |renderer, _, line| {
+            let regions = regions.borrow();
+            let text = match regions.iter().find(|r| r.start_line == line as i32) {
+                Some(region) if is_folded(&buffer, &fold_tag, region) => "▸",
+                Some(_) => "▾",
+                None => "",
+            };
+            renderer.set_text(text, -1);
+        }
+    ));
+    renderer.connect_query_activatable(glib::clone!(
+        #[strong]
+        regions,
+        move |_, iter, _| regions.borrow().iter().any(|r| r.start_line == iter.line())
+    ));
+    renderer.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        fold_tag,
+        #[strong]
+        regions,
+        move |renderer, iter, _, _, _, _| {
+            let region = regions
+                .borrow()
+                .iter()
+                .find(|r| r.start_line == iter.line())
+                .copied();
+            if let Some(region) = region {
+                set_folded(&buffer, &region, !is_folded(&buffer, &fold_tag, &region));
+                renderer.queue_draw();
+            }
+        }
+    ));
+    editor_view
+        .gutter(gtk4::TextWindowType::Left)
+        .insert(&renderer, 1);
+
+    let fold_all = gio::SimpleAction::new("fold-all-environments", None);
+    fold_all.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        renderer,
+        #[strong]
+        regions,
+        move |_, _| {
+            for region in regions
+                .borrow()
+                .iter()
+                .filter(|r| r.kind == FoldKind::Environment)
+            {
+                set_folded(&buffer, region, true);
+            }
+            renderer.queue_draw();
+        }
+    ));
+    window.add_action(&fold_all);
+
+    let unfold_all = gio::SimpleAction::new("unfold-all", None);
+    unfold_all.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        renderer,
+        move |_, _| {
+            let (start, end) = buffer.bounds();
+            buffer.remove_tag_by_name("folded", &start, &end);
+            renderer.queue_draw();
+        }
+    ));
+    window.add_action(&unfold_all);
+}
+
+/// Registers `win.jump-to-matching-environment`, which moves the cursor from a
+/// `\begin{env}` to its `\end{env}` (or back) and briefly highlights the target.
+pub fn connect_environment_jump(
+    window: &adw::ApplicationWindow,
+    buffer: &Buffer,
+    editor_view: &View,
+    state: Rc<RefCell<AppState>>,
+) {
+    let match_tag = gtk4::TextTag::builder()
+        .name("environment-match")
+        .background("rgba(53, 132, 228, 0.3)")
+        .build();
+    buffer.tag_table().add(&match_tag);
+
+    let action = gio::SimpleAction::new("jump-to-matching-environment", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        editor_view,
+        move |_, _| {
+            let cursor = buffer.iter_at_mark(&buffer.get_insert());
+            let text = crate::utils::buffer_to_string(buffer.upcast_ref());
+            let Some((line, start_col, end_col)) =
+                matching_environment(&text, cursor.line(), cursor.line_offset())
+            else {
+                return;
+            };
+            let (Some(mut start), Some(end)) = (
+                buffer.iter_at_line_offset(line, start_col),
+                buffer.iter_at_line_offset(line, end_col),
+            ) else {
+                return;
+            };
+
+            record_jump(&buffer, &state);
+            buffer.place_cursor(&start);
+            editor_view.scroll_to_iter(&mut start, 0.1, false, 0.0, 0.0);
+            buffer.apply_tag_by_name("environment-match", &start, &end);
+
+            let start_mark = buffer.create_mark(None, &start, true);
+            let end_mark = buffer.create_mark(None, &end, false);
+            glib::timeout_add_local_once(
+                std::time::Duration::from_millis(ENVIRONMENT_MATCH_FLASH_MS),
+                glib::clone!(
+                    #[weak]
+                    buffer,
+                    move || {
+                        let start = buffer.iter_at_mark(&start_mark);
+                        let end = buffer.iter_at_mark(&end_mark);
+                        buffer.remove_tag_by_name("environment-match", &start, &end);
+                        buffer.delete_mark(&start_mark);
+                        buffer.delete_mark(&end_mark);
+                    }
+                ),
+            );
+        }
+    ));
+    window.add_action(&action);
 }
 
-/// Connects the sidebar row activation to scroll the editor to the selected section.
+fn cursor_position(buffer: &Buffer) -> Position {
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    Position {
+        line: cursor.line(),
+        offset: cursor.line_offset(),
+    }
+}
+
+/// Remembers the cursor position before a programmatic jump (outline, TODO
+/// list, search, matching environment) so Navigate Back returns to it.
+pub fn record_jump(buffer: &Buffer, state: &Rc<RefCell<AppState>>) {
+    state.borrow_mut().navigation.push(cursor_position(buffer));
+}
+
+/// Registers `win.navigate-back` and `win.navigate-forward`, which move the
+/// cursor through the positions recorded by [`record_jump`].
+pub fn connect_navigation_history(
+    window: &adw::ApplicationWindow,
+    buffer: &Buffer,
+    editor_view: &View,
+    state: Rc<RefCell<AppState>>,
+) {
+    for (name, back) in [("navigate-back", true), ("navigate-forward", false)] {
+        let action = gio::SimpleAction::new(name, None);
+        action.connect_activate(glib::clone!(
+            #[weak]
+            buffer,
+            #[weak]
+            editor_view,
+            #[strong]
+            state,
+            move |_, _| {
+                let current = cursor_position(&buffer);
+                let target = {
+                    let navigation = &mut state.borrow_mut().navigation;
+                    if back {
+                        navigation.back(current)
+                    } else {
+                        navigation.forward(current)
+                    }
+                };
+                let Some(target) = target else {
+                    return;
+                };
+                // Edits since the jump may have shortened the line or the buffer
+                let mut iter = buffer
+                    .iter_at_line_offset(target.line, target.offset)
+                    .or_else(|| buffer.iter_at_line(target.line))
+                    .unwrap_or_else(|| buffer.end_iter());
+                buffer.place_cursor(&iter);
+                editor_view.scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
+                editor_view.grab_focus();
+            }
+        ));
+        window.add_action(&action);
+    }
+}
+
+/// Registers `win.format-document`, which re-indents, wraps and aligns the
+/// whole buffer using the formatting settings. The change is one undo step.
+pub fn connect_format_action(
+    window: &adw::ApplicationWindow,
+    buffer: &Buffer,
+    editor_view: &View,
+    state: Rc<RefCell<AppState>>,
+) {
+    let action = gio::SimpleAction::new("format-document", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        editor_view,
+        #[strong]
+        state,
+        move |_, _| {
+            let options = {
+                let config = &state.borrow().config;
+                FormatOptions {
+                    indent: if config.insert_spaces {
+                        " ".repeat(config.tab_width as usize)
+                    } else {
+                        "\t".to_string()
+                    },
+                    wrap_column: config.format_wrap_column,
+                    align_tables: config.format_align_tables,
+                }
+            };
+            let text = crate::utils::buffer_to_string(buffer.upcast_ref());
+            let formatted = format_latex(&text, &options);
+            if formatted == text {
+                return;
+            }
+
+            let cursor_line = buffer.iter_at_mark(&buffer.get_insert()).line();
+            let (mut start, mut end) = buffer.bounds();
+            buffer.begin_user_action();
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &formatted);
+            buffer.end_user_action();
+
+            let mut cursor = buffer
+                .iter_at_line(cursor_line)
+                .unwrap_or_else(|| buffer.end_iter());
+            buffer.place_cursor(&cursor);
+            editor_view.scroll_to_iter(&mut cursor, 0.1, false, 0.0, 0.0);
+        }
+    ));
+    window.add_action(&action);
+}
+
+/// Registers `win.toggle-comment`, which comments out the lines touched by the
+/// selection (or the cursor's line) with [`toggle_comment`], or uncomments
+/// them when they all are comments already. The change is one undo step.
+pub fn connect_comment_action(window: &adw::ApplicationWindow, buffer: &Buffer) {
+    let action = gio::SimpleAction::new("toggle-comment", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        move |_, _| {
+            let cursor = buffer.iter_at_mark(&buffer.get_insert());
+            let cursor_offset = cursor.line_offset();
+            let selection = buffer.selection_bounds();
+            let has_selection = selection.is_some();
+            let (sel_start, sel_end) =
+                selection.unwrap_or_else(|| (cursor.clone(), cursor.clone()));
+            let first = sel_start.line();
+            let mut last = sel_end.line();
+            // A selection ending at the start of a line doesn't include that line
+            if last > first && sel_end.starts_line() {
+                last -= 1;
+            }
+            let (Some(mut start), Some(mut end)) =
+                (buffer.iter_at_line(first), buffer.iter_at_line(last))
+            else {
+                return;
+            };
+            if !end.ends_line() {
+                end.forward_to_line_end();
+            }
+            let text = buffer.text(&start, &end, true).to_string();
+            let toggled = toggle_comment(&text);
+            if toggled == text {
+                return;
+            }
+
+            let old_len = line_len(&buffer, first);
+            buffer.begin_user_action();
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &toggled);
+            buffer.end_user_action();
+
+            if has_selection {
+                let (Some(start), Some(mut end)) =
+                    (buffer.iter_at_line(first), buffer.iter_at_line(last))
+                else {
+                    return;
+                };
+                if !end.ends_line() {
+                    end.forward_to_line_end();
+                }
+                buffer.select_range(&start, &end);
+            } else {
+                // Keep the cursor on the same text as the line grows or shrinks
+                let new_len = line_len(&buffer, first);
+                let offset = (cursor_offset + new_len - old_len).clamp(0, new_len);
+                if let Some(iter) = buffer.iter_at_line_offset(first, offset) {
+                    buffer.place_cursor(&iter);
+                }
+            }
+        }
+    ));
+    window.add_action(&action);
+}
+
+/// Characters on `line`, not counting the line break.
+fn line_len(buffer: &Buffer, line: i32) -> i32 {
+    buffer.iter_at_line(line).map_or(0, |mut iter| {
+        if !iter.ends_line() {
+            iter.forward_to_line_end();
+        }
+        iter.line_offset()
+    })
+}
+
+/// Widens the text still to be spell checked, between the `pending` marks, to
+/// cover `start`..`end`.
+fn extend_spell_range(
+    buffer: &Buffer,
+    pending: &(gtk4::TextMark, gtk4::TextMark),
+    dirty: &Cell<bool>,
+    start: &gtk4::TextIter,
+    end: &gtk4::TextIter,
+) {
+    let (first, last) = pending;
+    if !dirty.replace(true) {
+        buffer.move_mark(first, start);
+        buffer.move_mark(last, end);
+        return;
+    }
+    if start.offset() < buffer.iter_at_mark(first).offset() {
+        buffer.move_mark(first, start);
+    }
+    if end.offset() > buffer.iter_at_mark(last).offset() {
+        buffer.move_mark(last, end);
+    }
+}
+
+/// Underlines misspelled prose words using the dictionary from
+/// `AppConfig::spell_language`. Checking runs shortly after typing pauses and
+/// covers only the paragraphs edited since the last check.
+///
+/// Returns a callback that reloads the dictionary from the current config and
+/// rechecks the buffer, for use after the spelling settings change. The
+/// dictionary is built off the main thread.
+pub fn connect_spell_check(buffer: &Buffer, state: Rc<RefCell<AppState>>) -> Rc<dyn Fn()> {
+    let spell_tag = gtk4::TextTag::builder()
+        .name("misspelled")
+        .underline(gtk4::pango::Underline::Error)
+        .build();
+    buffer.tag_table().add(&spell_tag);
+
+    // The end mark has right gravity so text typed at it stays inside
+    let pending = Rc::new((
+        buffer.create_mark(None, &buffer.start_iter(), true),
+        buffer.create_mark(None, &buffer.start_iter(), false),
+    ));
+    let dirty = Rc::new(Cell::new(false));
+
+    let check = glib::clone!(
+        #[weak]
+        buffer,
+        #[strong]
+        state,
+        #[strong]
+        pending,
+        #[strong]
+        dirty,
+        move || {
+            if !dirty.replace(false) {
+                return;
+            }
+            let Some(checker) = state.borrow().spell_checker.clone() else {
+                return;
+            };
+
+            let text = crate::utils::buffer_to_string(buffer.upcast_ref());
+            let byte_at = |offset: i32| {
+                text.char_indices()
+                    .nth(offset as usize)
+                    .map_or(text.len(), |(i, _)| i)
+            };
+            let changed = byte_at(buffer.iter_at_mark(&pending.0).offset())
+                ..byte_at(buffer.iter_at_mark(&pending.1).offset());
+            let paragraphs = crate::spell::enclosing_paragraphs(&text, changed);
+            let paragraphs_text = &text[paragraphs.clone()];
+            let first_char = text[..paragraphs.start].chars().count() as i32;
+            let start = buffer.iter_at_offset(first_char);
+            let end = buffer.iter_at_offset(first_char + paragraphs_text.chars().count() as i32);
+            buffer.remove_tag_by_name("misspelled", &start, &end);
+
+            // Tagging words by offset inside one huge line is quadratic in GTK
+            if crate::utils::has_long_line(paragraphs_text) {
+                return;
+            }
+            // Convert byte ranges to character offsets incrementally
+            let (mut byte_pos, mut char_pos) = (0, first_char);
+            let mut to_chars = |byte: usize| {
+                char_pos += paragraphs_text[byte_pos..byte].chars().count() as i32;
+                byte_pos = byte;
+                char_pos
+            };
+            for range in checker.misspelled(paragraphs_text) {
+                let start = buffer.iter_at_offset(to_chars(range.start));
+                let end = buffer.iter_at_offset(to_chars(range.end));
+                buffer.apply_tag_by_name("misspelled", &start, &end);
+            }
+        }
+    );
+
+    buffer.connect_insert_text(glib::clone!(
+        #[strong]
+        pending,
+        #[strong]
+        dirty,
+        move |buffer, location, _| {
+            extend_spell_range(buffer, &pending, &dirty, location, location);
+        }
+    ));
+    buffer.connect_delete_range(glib::clone!(
+        #[strong]
+        pending,
+        #[strong]
+        dirty,
+        move |buffer, start, end| {
+            extend_spell_range(buffer, &pending, &dirty, start, end);
+        }
+    ));
+
+    let scheduled: Rc<RefCell<Option<glib::SourceId>>> = Rc::new(RefCell::new(None));
+    buffer.connect_changed(glib::clone!(
+        #[strong]
+        check,
+        #[strong]
+        scheduled,
+        move |_| {
+            if let Some(source_id) = scheduled.borrow_mut().take() {
+                source_id.remove();
+            }
+            let source_id = glib::timeout_add_local_once(
+                std::time::Duration::from_millis(SPELL_CHECK_DELAY_MS),
+                glib::clone!(
+                    #[strong]
+                    check,
+                    #[strong]
+                    scheduled,
+                    move || {
+                        scheduled.borrow_mut().take();
+                        check();
+                    }
+                ),
+            );
+            *scheduled.borrow_mut() = Some(source_id);
+        }
+    ));
+
+    // Bumped by every reload so a slow load of an old language is dropped
+    let generation = Rc::new(Cell::new(0u64));
+    let reload = glib::clone!(
+        #[weak]
+        buffer,
+        move || {
+            generation.set(generation.get() + 1);
+            let current = generation.get();
+            let (start, end) = buffer.bounds();
+            buffer.remove_tag_by_name("misspelled", &start, &end);
+            let language = {
+                let mut state = state.borrow_mut();
+                state.spell_checker = None;
+                if !state.config.spell_check {
+                    return;
+                }
+                state.config.spell_language.clone()
+            };
+
+            glib::MainContext::default().spawn_local(glib::clone!(
+                #[weak]
+                buffer,
+                #[strong]
+                state,
+                #[strong]
+                generation,
+                #[strong]
+                pending,
+                #[strong]
+                dirty,
+                #[strong]
+                check,
+                async move {
+                    let loaded = tokio::task::spawn_blocking(move || SpellChecker::load(&language))
+                        .await
+                        .unwrap_or_else(|e| Err(e.to_string()));
+                    if generation.get() != current {
+                        return;
+                    }
+                    match loaded {
+                        Ok(checker) => {
+                            state.borrow_mut().spell_checker = Some(Rc::new(checker));
+                            let (start, end) = buffer.bounds();
+                            extend_spell_range(&buffer, &pending, &dirty, &start, &end);
+                            check();
+                        }
+                        Err(e) => tracing::warn!("Spell check unavailable: {}", e),
+                    }
+                }
+            ));
+        }
+    );
+    reload();
+    Rc::new(reload)
+}
+
+/// Flags unbalanced braces and `\begin`/`\end` pairs found by [`lint_latex`]
+/// with an icon in the gutter, whose tooltip names the problem, and an
+/// underline. Checking runs shortly after typing pauses.
+pub fn connect_lint(buffer: &Buffer, editor_view: &View) {
+    let lint_tag = gtk4::TextTag::builder()
+        .name("lint-issue")
+        .underline(gtk4::pango::Underline::Error)
+        // Orange, to tell it from a misspelling
+        .underline_rgba(&gdk::RGBA::new(0.9, 0.55, 0.0, 1.0))
+        .build();
+    buffer.tag_table().add(&lint_tag);
+
+    let issues: Rc<RefCell<Vec<LintIssue>>> = Rc::new(RefCell::new(Vec::new()));
+    let attributes = sourceview5::MarkAttributes::new();
+    attributes.set_icon_name("dialog-warning-symbolic");
+    attributes.connect_query_tooltip_text(glib::clone!(
+        #[strong]
+        issues,
+        move |_, mark| {
+            let Some(buffer) = mark.buffer() else {
+                return String::new().into();
+            };
+            let line = buffer.iter_at_mark(mark).line();
+            issues
+                .borrow()
+                .iter()
+                .filter(|issue| issue.line == line)
+                .map(|issue| issue.message.as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+                .into()
+        }
+    ));
+    editor_view.set_mark_attributes(LINT_MARK_CATEGORY, &attributes, 0);
+    editor_view.set_show_line_marks(true);
+
+    let lint = glib::clone!(
+        #[weak]
+        buffer,
+        #[strong]
+        issues,
+        move || {
+            let (start, end) = buffer.bounds();
+            buffer.remove_tag_by_name("lint-issue", &start, &end);
+            buffer.remove_source_marks(&start, &end, Some(LINT_MARK_CATEGORY));
+
+            let text = crate::utils::buffer_to_string(buffer.upcast_ref());
+            let found = if crate::utils::has_long_line(&text) {
+                Vec::new()
+            } else {
+                lint_latex(&text)
+            };
+            let mut marked_line = None;
+            for issue in &found {
+                let Some(start) = buffer.iter_at_line_offset(issue.line, issue.column) else {
+                    continue;
+                };
+                // Underline a whole `\begin`/`\end`, or the single brace
+                let mut end = start.clone();
+                end.forward_char();
+                if start.char() == '\\' {
+                    end.forward_word_end();
+                }
+                buffer.apply_tag_by_name("lint-issue", &start, &end);
+                if marked_line.replace(issue.line) != Some(issue.line) {
+                    buffer.create_source_mark(None, LINT_MARK_CATEGORY, &start);
+                }
+            }
+            *issues.borrow_mut() = found;
+        }
+    );
+    lint();
+
+    let pending: Rc<RefCell<Option<glib::SourceId>>> = Rc::new(RefCell::new(None));
+    buffer.connect_changed(move |_| {
+        if let Some(source_id) = pending.borrow_mut().take() {
+            source_id.remove();
+        }
+        let source_id = glib::timeout_add_local_once(
+            std::time::Duration::from_millis(LINT_DELAY_MS),
+            glib::clone!(
+                #[strong]
+                lint,
+                #[strong]
+                pending,
+                move || {
+                    pending.borrow_mut().take();
+                    lint();
+                }
+            ),
+        );
+        *pending.borrow_mut() = Some(source_id);
+    });
+}
+
+/// Connects the outline activation to scroll the editor to the selected section's line.
+/// The preview also scrolls to the section's SyncTeX anchor, when it has one.
 pub fn connect_sidebar_activation(
-    sidebar_list: &gtk4::ListBox,
+    sidebar_list: &gtk4::ListView,
     buffer: &sourceview5::Buffer,
     editor_view: &sourceview5::View,
+    web_view: &webkit6::WebView,
+    state: Rc<RefCell<AppState>>,
 ) {
-    sidebar_list.connect_row_activated(glib::clone!(
+    sidebar_list.connect_activate(glib::clone!(
         #[weak]
         buffer,
         #[weak]
         editor_view,
-        move |_, row| {
-            let index = row.index();
+        #[weak]
+        web_view,
+        move |outline, position| {
+            let Some((index, line)) = crate::ui::sidebar::outline::section_at(outline, position)
+            else {
+                return;
+            };
+            let script = format!(
+                "document.getElementById('section-{}')?.scrollIntoView({{ behavior: 'smooth' }});",
+                index
+            );
+            web_view.evaluate_javascript(&script, None, None, None::<&gio::Cancellable>, |_| {});
+
+            let buf = buffer.upcast_ref::<gtk4::TextBuffer>();
+            if let Some(mut iter) = buf.iter_at_line(line) {
+                record_jump(&buffer, &state);
+                buf.place_cursor(&iter);
+                editor_view.scroll_to_iter(&mut iter, 0.0, false, 0.5, 0.5);
+                editor_view.grab_focus();
+            }
+        }
+    ));
+}
+
+/// Highlights `% TODO`-style comments and lists them in `todo_list`; activating
+/// a row moves the cursor to its line. The keywords come from
+/// `AppConfig::todo_keywords` and the list follows edits after a short pause.
+///
+/// Returns a callback that rescans the buffer, for use after the keywords change.
+pub fn connect_todo_list(
+    todo_list: &gtk4::ListBox,
+    buffer: &Buffer,
+    editor_view: &View,
+    state: Rc<RefCell<AppState>>,
+) -> Rc<dyn Fn()> {
+    let todo_tag = gtk4::TextTag::builder()
+        .name("todo-comment")
+        .background("rgba(245, 194, 17, 0.25)")
+        .build();
+    buffer.tag_table().add(&todo_tag);
+
+    let todos: Rc<RefCell<Vec<TodoItem>>> = Rc::new(RefCell::new(Vec::new()));
+
+    let refresh = glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        todo_list,
+        #[strong]
+        todos,
+        move || {
             let text = crate::utils::buffer_to_string(buffer.upcast_ref());
-            let sections = crate::utils::extract_sections(&text);
-
-            if let Some((_, line)) = sections.get(index as usize) {
-                let buf = buffer.upcast_ref::<gtk4::TextBuffer>();
-                if let Some(mut iter) = buf.iter_at_line(*line) {
-                    buf.place_cursor(&iter);
-                    editor_view.scroll_to_iter(&mut iter, 0.0, false, 0.5, 0.5);
-                    editor_view.grab_focus();
+            let found = find_todos(&text, &state.borrow().config.todo_keywords);
+
+            let (start, end) = buffer.bounds();
+            buffer.remove_tag_by_name("todo-comment", &start, &end);
+            for todo in &found {
+                if let Some(start) = buffer.iter_at_line_offset(todo.line, todo.column) {
+                    let mut end = start.clone();
+                    if !end.ends_line() {
+                        end.forward_to_line_end();
+                    }
+                    buffer.apply_tag_by_name("todo-comment", &start, &end);
                 }
             }
+
+            if *todos.borrow() == found {
+                return;
+            }
+            todo_list.remove_all();
+            for todo in &found {
+                let label = gtk4::Label::new(None);
+                label.set_markup(&format!(
+                    "<b>{}</b> {}",
+                    glib::markup_escape_text(&todo.keyword),
+                    glib::markup_escape_text(&todo.text)
+                ));
+                label.set_xalign(0.0);
+                label.set_hexpand(true);
+                label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+                let line_label = gtk4::Label::new(Some(&format!("{}", todo.line + 1)));
+                line_label.add_css_class("dim-label");
+                line_label.add_css_class("numeric");
+
+                let row_box = Box::new(Orientation::Horizontal, 6);
+                row_box.append(&label);
+                row_box.append(&line_label);
+                let row = gtk4::ListBoxRow::new();
+                row.set_tooltip_text(Some(&todo.text));
+                row.set_child(Some(&row_box));
+                todo_list.append(&row);
+            }
+            *todos.borrow_mut() = found;
+        }
+    );
+
+    todo_list.connect_row_activated(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        editor_view,
+        #[strong]
+        todos,
+        move |_, row| {
+            let Some(todo) = todos.borrow().get(row.index() as usize).cloned() else {
+                return;
+            };
+            if let Some(mut iter) = buffer.iter_at_line(todo.line) {
+                record_jump(&buffer, &state);
+                buffer.place_cursor(&iter);
+                editor_view.scroll_to_iter(&mut iter, 0.0, false, 0.5, 0.5);
+                editor_view.grab_focus();
+            }
         }
     ));
+
+    let refresh: Rc<dyn Fn()> = Rc::new(refresh);
+    let pending: Rc<RefCell<Option<glib::SourceId>>> = Rc::new(RefCell::new(None));
+    buffer.connect_changed(glib::clone!(
+        #[strong]
+        refresh,
+        #[strong]
+        pending,
+        move |_| {
+            if let Some(source_id) = pending.borrow_mut().take() {
+                source_id.remove();
+            }
+            let source_id = glib::timeout_add_local_once(
+                std::time::Duration::from_millis(TODO_SCAN_DELAY_MS),
+                glib::clone!(
+                    #[strong]
+                    refresh,
+                    #[strong]
+                    pending,
+                    move || {
+                        pending.borrow_mut().take();
+                        refresh();
+                    }
+                ),
+            );
+            *pending.borrow_mut() = Some(source_id);
+        }
+    ));
+
+    refresh();
+    refresh
 }
@@ -1,27 +1,64 @@
-use crate::constants::MAX_LATEX_SIZE_BYTES;
+use crate::constants::{
+    BUILD_DIR_NAME, DROP_DOCUMENT_EXTENSIONS, DROP_IMAGE_EXTENSIONS, MAX_LATEX_SIZE_BYTES,
+};
+use crate::preview::{CompileError, Preview};
 use crate::utils::{open_file, save_file};
 use crate::AppState;
+use adw::prelude::{AdwDialogExt, AlertDialogExt};
 use adw::{ApplicationWindow, ToastOverlay};
+use encoding_rs::{Encoding, UTF_8};
 use glib;
-use gtk4::gio::prelude::FileExt;
-use gtk4::prelude::{ButtonExt, Cast, TextBufferExt};
-use gtk4::Button;
+use gtk4::gio::prelude::{ActionMapExt, FileExt};
+use gtk4::prelude::{ButtonExt, Cast, StaticType, TextBufferExt, WidgetExt};
+use gtk4::{gdk, Button};
 use sourceview5::Buffer;
 use std::cell::RefCell;
-use std::process::Command;
+use std::path::Path;
 use std::rc::Rc;
+use webkit6::prelude::WebViewExt;
+use webkit6::WebView;
+
+/// Evaluates to the 1-based number of the preview page crossing the middle of the view.
+const VISIBLE_PAGE_SCRIPT: &str = "(() => {
+    const middle = window.innerHeight / 2;
+    const pages = Array.from(document.querySelectorAll('.page'));
+    const index = pages.findIndex(page => page.getBoundingClientRect().bottom >= middle);
+    return index < 0 ? 1 : index + 1;
+})()";
+
+/// One-line toast text for a failed export.
+fn export_error_message(error: &CompileError) -> String {
+    match error {
+        CompileError::BinaryNotFound(program) => {
+            format!("PDF export needs {}, which is not installed", program)
+        }
+        CompileError::Timeout(secs) => format!("PDF export timed out after {} seconds", secs),
+        CompileError::TooLarge { .. } => "Document too large for PDF export (max 10 MB).".into(),
+        CompileError::LatexFailed { .. } => {
+            "PDF compilation failed; see the preview for the LaTeX errors".into()
+        }
+        CompileError::ConversionFailed { .. }
+        | CompileError::Unbalanced { .. }
+        | CompileError::Io(_)
+        | CompileError::Cancelled => {
+            format!("PDF export failed: {}", error)
+        }
+    }
+}
 
 /// Connects the export button to the PDF generation logic using `pdflatex`.
 pub fn connect_export_pdf(
     export_btn: &Button,
     window: &ApplicationWindow,
     buffer: &Buffer,
-    _state: Rc<RefCell<AppState>>,
+    state: Rc<RefCell<AppState>>,
     toast_overlay: &ToastOverlay,
 ) {
     export_btn.connect_clicked(glib::clone!(
         #[weak]
         window,
+        #[strong]
+        state,
         #[weak]
         buffer,
         #[weak]
@@ -49,6 +86,8 @@ pub fn connect_export_pdf(
                     buffer,
                     #[weak]
                     toast_overlay,
+                    #[strong]
+                    state,
                     move |result| {
                         match result {
                             Ok(gfile) => {
@@ -61,83 +100,501 @@ pub fn connect_export_pdf(
                                     path_buf.set_extension("pdf");
                                 }
 
-                                // Save temporary .tex file
-                                let temp_dir = std::env::temp_dir();
-                                let temp_tex = temp_dir.join("export_temp.tex");
-                                if let Err(e) = std::fs::write(
-                                    &temp_tex,
-                                    crate::utils::buffer_to_string(buffer.upcast_ref()),
-                                ) {
-                                    toast_overlay.add_toast(adw::Toast::new(&format!(
-                                        "Failed to create temp file: {}",
-                                        e
-                                    )));
-                                    return;
+                                let latex = crate::utils::buffer_to_string(buffer.upcast_ref());
+                                let options = {
+                                    let mut s = state.borrow_mut();
+                                    s.read_magic_comments(&latex);
+                                    s.compile_options()
+                                };
+                                let message =
+                                    match Preview::new().export_pdf(&latex, &path_buf, &options) {
+                                        Ok(()) => format!("PDF exported to {}", path_buf.display()),
+                                        Err(e) => export_error_message(&e),
+                                    };
+                                toast_overlay.add_toast(adw::Toast::new(&message));
+                            }
+                            Err(e) => {
+                                tracing::warn!("File dialog cancelled: {}", e);
+                            }
+                        }
+                    }
+                ),
+            );
+        }
+    ));
+}
+/// Registers `win.copy-page-png`, which rasterizes the preview page in view from
+/// the last compiled PDF at `AppConfig::png_copy_dpi` and puts it on the clipboard.
+pub fn connect_copy_page_action(
+    window: &ApplicationWindow,
+    web_view: &WebView,
+    state: Rc<RefCell<AppState>>,
+    toast_overlay: &ToastOverlay,
+) {
+    let action = gio::SimpleAction::new("copy-page-png", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        web_view,
+        #[strong]
+        state,
+        #[weak]
+        toast_overlay,
+        move |_, _| {
+            let Some(pdf) = state.borrow().last_pdf.clone() else {
+                toast_overlay.add_toast(adw::Toast::new(
+                    "Nothing to copy until the document compiles",
+                ));
+                return;
+            };
+            let dpi = state.borrow().config.png_copy_dpi;
+            web_view.evaluate_javascript(
+                VISIBLE_PAGE_SCRIPT,
+                None,
+                None,
+                None::<&gio::Cancellable>,
+                glib::clone!(
+                    #[weak]
+                    window,
+                    #[weak]
+                    toast_overlay,
+                    move |result| {
+                        // The embedded PDF preview has no page elements, so it copies page 1
+                        let page = result
+                            .ok()
+                            .map(|value| value.to_int32())
+                            .filter(|page| *page > 0)
+                            .unwrap_or(1) as usize;
+                        // Rendering at a high DPI takes a while; keep the window responsive
+                        glib::MainContext::default().spawn_local(async move {
+                            let rendered = tokio::task::spawn_blocking(move || {
+                                Preview::new().render_page_png(&pdf, page, dpi)
+                            })
+                            .await;
+                            let message = match rendered {
+                                Ok(Ok(png)) => {
+                                    match gdk::Texture::from_bytes(&glib::Bytes::from_owned(png)) {
+                                        Ok(texture) => {
+                                            window.clipboard().set_texture(&texture);
+                                            format!("Page {} copied as PNG", page)
+                                        }
+                                        Err(e) => {
+                                            format!("Could not load the rendered page: {}", e)
+                                        }
+                                    }
                                 }
+                                Ok(Err(CompileError::BinaryNotFound(program))) => {
+                                    format!("Copying pages needs {} from poppler-utils", program)
+                                }
+                                Ok(Err(e)) => {
+                                    format!("Could not copy page {}: {}", page, e.title())
+                                }
+                                Err(e) => format!("Could not copy page {}: {}", page, e),
+                            };
+                            toast_overlay.add_toast(adw::Toast::new(&message));
+                        });
+                    }
+                ),
+            );
+        }
+    ));
+    window.add_action(&action);
+}
 
-                                // Run pdflatex
-                                let output = Command::new("pdflatex")
-                                    .arg("-interaction=nonstopmode")
-                                    .arg("-output-directory")
-                                    .arg(&temp_dir)
-                                    .arg(&temp_tex)
-                                    .output();
+/// Registers the `win.open-build-folder` action, which opens the kept build
+/// directory of the current document in the system file manager.
+pub fn connect_build_folder_action(
+    window: &ApplicationWindow,
+    state: Rc<RefCell<AppState>>,
+    toast_overlay: &ToastOverlay,
+) {
+    let action = gio::SimpleAction::new("open-build-folder", None);
+    action.connect_activate(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        toast_overlay,
+        move |_, _| {
+            let build_dir = state.borrow().build_dir();
+            match build_dir {
+                Some(dir) if dir.exists() => {
+                    let uri = gio::File::for_path(&dir).uri();
+                    if let Err(e) =
+                        gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>)
+                    {
+                        toast_overlay.add_toast(adw::Toast::new(&format!(
+                            "Failed to open build folder: {}",
+                            e
+                        )));
+                    }
+                }
+                Some(_) => {
+                    toast_overlay.add_toast(adw::Toast::new(
+                        "No build folder yet. Enable \"Keep Build Files\" in Settings.",
+                    ));
+                }
+                None => {
+                    toast_overlay
+                        .add_toast(adw::Toast::new("Save the document to get a build folder."));
+                }
+            }
+        }
+    ));
+    window.add_action(&action);
+}
 
-                                match output {
-                                    Ok(output) if output.status.success() => {
-                                        let pdf_path = temp_dir.join("export_temp.pdf");
-                                        if pdf_path.exists() {
-                                            if let Err(e) = std::fs::copy(&pdf_path, &path_buf) {
-                                                toast_overlay.add_toast(adw::Toast::new(&format!(
-                                                    "Failed to copy PDF: {}",
-                                                    e
-                                                )));
-                                            } else {
-                                                toast_overlay.add_toast(adw::Toast::new(&format!(
-                                                    "PDF exported to {}",
-                                                    path_buf.display()
-                                                )));
-                                            }
-                                            // Cleanup
-                                            let _ = std::fs::remove_file(&temp_tex);
-                                            let _ = std::fs::remove_file(&pdf_path);
-                                            let _ = std::fs::remove_file(
-                                                temp_dir.join("export_temp.aux"),
-                                            );
-                                            let _ = std::fs::remove_file(
-                                                temp_dir.join("export_temp.log"),
-                                            );
-                                        } else {
-                                            toast_overlay.add_toast(adw::Toast::new(
-                                                "PDF generation failed (no output file).",
-                                            ));
-                                        }
-                                    }
-                                    Ok(output) => {
-                                        let stderr = String::from_utf8_lossy(&output.stderr);
-                                        toast_overlay.add_toast(adw::Toast::new(&format!(
-                                            "PDF compilation failed: {}",
-                                            stderr.lines().next().unwrap_or("Unknown error")
-                                        )));
-                                    }
-                                    Err(e) => {
-                                        toast_overlay.add_toast(adw::Toast::new(&format!(
-                                            "Failed to run pdflatex: {}",
-                                            e
-                                        )));
-                                    }
+/// Registers the `win.clean-aux-files` action, which deletes the `.aux`, `.log`
+/// and other auxiliary files (per `AppConfig::aux_extensions`) from the project
+/// folder and its build folder after listing them for confirmation.
+pub fn connect_clean_action(
+    window: &ApplicationWindow,
+    state: Rc<RefCell<AppState>>,
+    toast_overlay: &ToastOverlay,
+) {
+    let action = gio::SimpleAction::new("clean-aux-files", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[strong]
+        state,
+        #[weak]
+        toast_overlay,
+        move |_, _| {
+            let (root, extensions) = {
+                let s = state.borrow();
+                (s.project_root(), s.config.aux_extensions.clone())
+            };
+            let Some(root) = root else {
+                toast_overlay.add_toast(adw::Toast::new("Save the document to clean its folder."));
+                return;
+            };
+            let dirs = [root.clone(), root.join(BUILD_DIR_NAME)];
+            let files = crate::project::auxiliary_files(&dirs, &extensions);
+            if files.is_empty() {
+                toast_overlay.add_toast(adw::Toast::new("No auxiliary files to remove."));
+                return;
+            }
+
+            let names: Vec<String> = files
+                .iter()
+                .map(|f| f.strip_prefix(&root).unwrap_or(f).display().to_string())
+                .collect();
+            let list = gtk4::Label::builder()
+                .label(names.join("\n"))
+                .xalign(0.0)
+                .selectable(true)
+                .build();
+            let scroll = gtk4::ScrolledWindow::builder()
+                .hscrollbar_policy(gtk4::PolicyType::Never)
+                .propagate_natural_height(true)
+                .max_content_height(240)
+                .child(&list)
+                .build();
+            let dialog = adw::AlertDialog::builder()
+                .heading("Clean Auxiliary Files")
+                .body(format!(
+                    "Delete {} file(s) from {}?",
+                    files.len(),
+                    root.display()
+                ))
+                .extra_child(&scroll)
+                .build();
+            dialog.add_response("cancel", "Cancel");
+            dialog.add_response("clean", "Delete");
+            dialog.set_response_appearance("clean", adw::ResponseAppearance::Destructive);
+            dialog.set_default_response(Some("cancel"));
+            dialog.set_close_response("cancel");
+
+            dialog.connect_response(
+                Some("clean"),
+                glib::clone!(
+                    #[weak]
+                    toast_overlay,
+                    move |_, _| {
+                        let mut removed = 0;
+                        for file in &files {
+                            match std::fs::remove_file(file) {
+                                Ok(()) => removed += 1,
+                                Err(e) => {
+                                    tracing::warn!("Could not remove {}: {}", file.display(), e)
                                 }
                             }
-                            Err(e) => {
-                                tracing::warn!("File dialog cancelled: {}", e);
-                            }
                         }
+                        let message = if removed == files.len() {
+                            format!("Removed {} auxiliary file(s)", removed)
+                        } else {
+                            format!(
+                                "Removed {} of {} auxiliary files; see the log for the rest",
+                                removed,
+                                files.len()
+                            )
+                        };
+                        toast_overlay.add_toast(adw::Toast::new(&message));
                     }
                 ),
             );
+            dialog.present(Some(&window));
         }
     ));
+    window.add_action(&action);
+}
+
+/// Shows the document's encoding in the status bar, flagging files whose
+/// invalid byte sequences were replaced on load.
+fn show_encoding(label: &gtk4::Label, encoding: &'static Encoding, had_errors: bool) {
+    if had_errors {
+        label.set_text(&format!("{} (invalid bytes replaced)", encoding.name()));
+        label.set_tooltip_text(Some(
+            "Some bytes could not be decoded and were replaced with \u{FFFD}. \
+             Saving will write the replacement characters.",
+        ));
+        label.add_css_class("warning");
+    } else {
+        label.set_text(encoding.name());
+        label.set_tooltip_text(None);
+        label.remove_css_class("warning");
+    }
+}
+
+/// Loads `path` into the editor and makes it the current document.
+/// Shared by the Open dialog, drag-and-drop and Search in Project.
+pub(crate) fn load_document(
+    path: &Path,
+    buffer: &Buffer,
+    state: &Rc<RefCell<AppState>>,
+    view_title: &adw::WindowTitle,
+    encoding_label: &gtk4::Label,
+) -> bool {
+    match open_file(path) {
+        Ok(decoded) => {
+            if decoded.had_errors {
+                tracing::warn!(
+                    "{} contains invalid {} sequences; replaced with U+FFFD",
+                    path.display(),
+                    decoded.encoding.name()
+                );
+            }
+            crate::ui::webview::cancel_compilation(state);
+            buffer.set_text(&decoded.contents);
+            buffer.set_modified(false);
+            {
+                let mut s = state.borrow_mut();
+                s.set_current_file(Some(path.to_path_buf()));
+                s.current_encoding = decoded.encoding;
+                s.current_bom = decoded.has_bom;
+            }
+            view_title.set_subtitle(&path.to_string_lossy());
+            show_encoding(encoding_label, decoded.encoding, decoded.had_errors);
+            true
+        }
+        Err(e) => {
+            tracing::error!("Failed to open {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// Runs `discard` right away when the editor has no unsaved changes, and
+/// otherwise only once the user agrees to lose them. Used before another
+/// document replaces the current one.
+pub(crate) fn confirm_discard_changes(
+    window: &ApplicationWindow,
+    buffer: &Buffer,
+    discard: impl Fn() + 'static,
+) {
+    if !buffer.is_modified() || buffer.char_count() == 0 {
+        discard();
+        return;
+    }
+    let dialog = adw::AlertDialog::builder()
+        .heading("Discard Unsaved Changes?")
+        .body("The current document has changes that have not been saved.")
+        .build();
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("discard", "Discard");
+    dialog.set_response_appearance("discard", adw::ResponseAppearance::Destructive);
+    dialog.set_default_response(Some("cancel"));
+    dialog.set_close_response("cancel");
+    dialog.connect_response(Some("discard"), move |_, _| discard());
+    dialog.present(Some(window));
+}
+
+/// Opens a file passed in from outside, on the command line or by a file
+/// manager, telling the user with a toast when it is missing, not a local file
+/// or cannot be read.
+pub fn open_external_file(
+    file: &gio::File,
+    buffer: &Buffer,
+    state: &Rc<RefCell<AppState>>,
+    view_title: &adw::WindowTitle,
+    encoding_label: &gtk4::Label,
+    toast_overlay: &ToastOverlay,
+) -> bool {
+    let Some(path) = file.path() else {
+        toast_overlay.add_toast(adw::Toast::new(&format!(
+            "Only local files can be opened, not {}",
+            file.uri()
+        )));
+        return false;
+    };
+    if !path.exists() {
+        toast_overlay.add_toast(adw::Toast::new(&format!(
+            "{} does not exist",
+            path.display()
+        )));
+        return false;
+    }
+    if !load_document(&path, buffer, state, view_title, encoding_label) {
+        toast_overlay.add_toast(adw::Toast::new(&format!(
+            "Could not open {}",
+            path.display()
+        )));
+        return false;
+    }
+    true
 }
+
+/// Saves the buffer to `path` in the document's encoding and records the
+/// encoding actually written.
+fn save_document(
+    path: &Path,
+    buffer: &Buffer,
+    state: &Rc<RefCell<AppState>>,
+    encoding_label: &gtk4::Label,
+) -> bool {
+    let (encoding, bom) = {
+        let s = state.borrow();
+        (s.current_encoding, s.current_bom)
+    };
+    match save_file(path, buffer.upcast_ref(), encoding, bom) {
+        Ok(used) => {
+            if used != encoding {
+                tracing::warn!(
+                    "Document no longer fits {}; saved as {}",
+                    encoding.name(),
+                    used.name()
+                );
+            }
+            state.borrow_mut().current_encoding = used;
+            show_encoding(encoding_label, used, false);
+            buffer.set_modified(false);
+            true
+        }
+        Err(e) => {
+            tracing::error!("Failed to save: {}", e);
+            false
+        }
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Handles files dropped on the window or editor. Documents are opened like the
+/// Open button, asking first if that would discard unsaved changes; images are
+/// inserted at the cursor when `insert_images` is set.
+#[allow(clippy::too_many_arguments)]
+fn handle_drop(
+    value: &glib::Value,
+    insert_images: bool,
+    window: &ApplicationWindow,
+    buffer: &Buffer,
+    state: &Rc<RefCell<AppState>>,
+    view_title: &adw::WindowTitle,
+    encoding_label: &gtk4::Label,
+    on_opened: &Rc<dyn Fn()>,
+) -> bool {
+    let Ok(files) = value.get::<gdk::FileList>() else {
+        return false;
+    };
+    let mut handled = false;
+    for path in files.files().iter().filter_map(|f| f.path()) {
+        if has_extension(&path, DROP_DOCUMENT_EXTENSIONS) {
+            // Only one document can be open; take the first
+            confirm_discard_changes(
+                window,
+                buffer,
+                glib::clone!(
+                    #[weak]
+                    buffer,
+                    #[strong]
+                    state,
+                    #[weak]
+                    view_title,
+                    #[weak]
+                    encoding_label,
+                    #[strong]
+                    on_opened,
+                    move || {
+                        if load_document(&path, &buffer, &state, &view_title, &encoding_label) {
+                            on_opened();
+                        }
+                    }
+                ),
+            );
+            return true;
+        } else if insert_images && has_extension(&path, DROP_IMAGE_EXTENSIONS) {
+            let current = state.borrow().current_file.clone();
+            let command = crate::utils::includegraphics_for(&path, current.as_deref());
+            buffer.insert_at_cursor(&format!("{}\n", command));
+            handled = true;
+        }
+    }
+    handled
+}
+
+/// Accepts `.tex`/`.txt` files dropped anywhere on the window, and images dropped
+/// onto the editor, which become `\includegraphics` lines at the cursor.
+pub fn connect_file_drop(
+    window: &ApplicationWindow,
+    editor_view: &sourceview5::View,
+    buffer: &Buffer,
+    state: Rc<RefCell<AppState>>,
+    view_title: &adw::WindowTitle,
+    encoding_label: &gtk4::Label,
+    on_opened: Rc<dyn Fn()>,
+) {
+    for (widget, insert_images) in [
+        (window.upcast_ref::<gtk4::Widget>(), false),
+        (editor_view.upcast_ref::<gtk4::Widget>(), true),
+    ] {
+        let target = gtk4::DropTarget::new(gdk::FileList::static_type(), gdk::DragAction::COPY);
+        target.connect_drop(glib::clone!(
+            #[weak]
+            window,
+            #[weak]
+            buffer,
+            #[strong]
+            state,
+            #[weak]
+            view_title,
+            #[weak]
+            encoding_label,
+            #[strong]
+            on_opened,
+            #[upgrade_or]
+            false,
+            move |_, value, _, _| {
+                handle_drop(
+                    value,
+                    insert_images,
+                    &window,
+                    &buffer,
+                    &state,
+                    &view_title,
+                    &encoding_label,
+                    &on_opened,
+                )
+            }
+        ));
+        widget.add_controller(target);
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 /// Connects standard file operations (New, Open, Save) and status bar updates
 /// (cursor position, word count).
@@ -151,19 +608,50 @@ pub fn connect_file_operations(
     view_title: &adw::WindowTitle,
     pos_label: &gtk4::Label,
     word_count_label: &gtk4::Label,
+    encoding_label: &gtk4::Label,
 ) {
-    // New button
+    // New button: start from a template
     new_btn.connect_clicked(glib::clone!(
+        #[weak]
+        window,
         #[weak]
         buffer,
         #[strong]
         state,
         #[weak]
         view_title,
+        #[weak]
+        encoding_label,
         move |_| {
-            buffer.set_text("");
-            state.borrow_mut().current_file = None;
-            view_title.set_subtitle("");
+            let has_unsaved_changes = buffer.is_modified() && buffer.char_count() > 0;
+            crate::ui::templates::show_template_picker(
+                &window,
+                has_unsaved_changes,
+                glib::clone!(
+                    #[weak]
+                    buffer,
+                    #[strong]
+                    state,
+                    #[weak]
+                    view_title,
+                    #[weak]
+                    encoding_label,
+                    move |template| {
+                        crate::ui::webview::cancel_compilation(&state);
+                        buffer.set_text(&template.content);
+                        buffer.set_modified(false);
+                        buffer.place_cursor(&buffer.start_iter());
+                        {
+                            let mut s = state.borrow_mut();
+                            s.set_current_file(None);
+                            s.current_encoding = UTF_8;
+                            s.current_bom = false;
+                        }
+                        view_title.set_subtitle("");
+                        show_encoding(&encoding_label, UTF_8, false);
+                    }
+                ),
+            );
         }
     ));
 
@@ -204,6 +692,8 @@ pub fn connect_file_operations(
         state,
         #[weak]
         view_title,
+        #[weak]
+        encoding_label,
         move |_| {
             let dialog = gtk4::FileDialog::builder().title("Open File").build();
 
@@ -211,22 +701,43 @@ pub fn connect_file_operations(
                 Some(&window),
                 None::<&gio::Cancellable>,
                 glib::clone!(
+                    #[weak]
+                    window,
                     #[strong]
                     state,
                     #[weak]
                     buffer,
                     #[weak]
                     view_title,
+                    #[weak]
+                    encoding_label,
                     move |res| {
-                        if let Ok(file) = res {
-                            if let Some(path) = file.path() {
-                                if let Ok(content) = open_file(&path) {
-                                    buffer.set_text(&content);
-                                    state.borrow_mut().current_file = Some(path.to_path_buf());
-                                    view_title.set_subtitle(&path.to_string_lossy());
+                        let Some(path) = res.ok().and_then(|file| file.path()) else {
+                            return;
+                        };
+                        confirm_discard_changes(
+                            &window,
+                            &buffer,
+                            glib::clone!(
+                                #[strong]
+                                state,
+                                #[weak]
+                                buffer,
+                                #[weak]
+                                view_title,
+                                #[weak]
+                                encoding_label,
+                                move || {
+                                    load_document(
+                                        &path,
+                                        &buffer,
+                                        &state,
+                                        &view_title,
+                                        &encoding_label,
+                                    );
                                 }
-                            }
-                        }
+                            ),
+                        );
                     }
                 ),
             );
@@ -243,12 +754,12 @@ pub fn connect_file_operations(
         state,
         #[weak]
         view_title,
+        #[weak]
+        encoding_label,
         move |_| {
             let path_opt = state.borrow().current_file.clone();
             if let Some(path) = path_opt {
-                if let Err(e) = save_file(&path, buffer.upcast_ref()) {
-                    tracing::error!("Failed to save: {}", e);
-                }
+                save_document(&path, &buffer, &state, &encoding_label);
             } else {
                 let dialog = gtk4::FileDialog::builder().title("Save File").build();
 
@@ -262,11 +773,15 @@ pub fn connect_file_operations(
                         buffer,
                         #[weak]
                         view_title,
+                        #[weak]
+                        encoding_label,
                         move |res| {
                             if let Ok(file) = res {
                                 if let Some(path) = file.path() {
-                                    if save_file(&path, buffer.upcast_ref()).is_ok() {
-                                        state.borrow_mut().current_file = Some(path.to_path_buf());
+                                    if save_document(&path, &buffer, &state, &encoding_label) {
+                                        state
+                                            .borrow_mut()
+                                            .set_current_file(Some(path.to_path_buf()));
                                         view_title.set_subtitle(&path.to_string_lossy());
                                     }
                                 }
@@ -1,6 +1,6 @@
 use adw::{HeaderBar, WindowTitle};
 use gtk4::prelude::{BoxExt, WidgetExt};
-use gtk4::{Box, Button, Orientation, ToggleButton};
+use gtk4::{gio, Box, Button, MenuButton, Orientation, ToggleButton};
 
 /// Creates the application header bar containing file operations and AI/Sidebar toggles.
 pub fn create_header_bar(
@@ -74,6 +74,37 @@ pub fn create_header_bar(
         .active(true)
         .build();
 
+    // Primary menu for less frequent actions; entries target `win.*` actions.
+    let menu = gio::Menu::new();
+    let palette_section = gio::Menu::new();
+    palette_section.append(Some("Command Palette"), Some("win.command-palette"));
+    menu.append_section(None, &palette_section);
+    let insert_section = gio::Menu::new();
+    insert_section.append(Some("Insert Figure…"), Some("win.insert-figure"));
+    insert_section.append(Some("Insert Table…"), Some("win.insert-table"));
+    insert_section.append(Some("Insert Citation…"), Some("win.insert-citation"));
+    insert_section.append(Some("Insert Symbol…"), Some("win.insert-symbol"));
+    menu.append_section(None, &insert_section);
+    let build_section = gio::Menu::new();
+    build_section.append(Some("Recompile"), Some("win.recompile"));
+    build_section.append(Some("Explain Compilation"), Some("win.explain-compile"));
+    build_section.append(Some("Open Build Folder"), Some("win.open-build-folder"));
+    build_section.append(Some("Clean Auxiliary Files…"), Some("win.clean-aux-files"));
+    build_section.append(Some("Copy Page as PNG"), Some("win.copy-page-png"));
+    build_section.append(Some("Format Document"), Some("win.format-document"));
+    build_section.append(Some("Toggle Comment"), Some("win.toggle-comment"));
+    build_section.append(Some("Math Scratch"), Some("win.math-scratch"));
+    build_section.append(Some("Document Statistics"), Some("win.document-statistics"));
+    build_section.append(Some("Document Settings…"), Some("win.document-settings"));
+    menu.append_section(None, &build_section);
+
+    let menu_btn = MenuButton::builder()
+        .icon_name("open-menu-symbolic")
+        .tooltip_text("Main Menu")
+        .menu_model(&menu)
+        .build();
+
+    header_bar.pack_end(&menu_btn);
     header_bar.pack_end(&sidebar_toggle);
     header_bar.pack_end(&settings_btn);
     header_bar.pack_end(&ai_btn);
@@ -0,0 +1,249 @@
+use crate::constants::DROP_IMAGE_EXTENSIONS;
+use crate::state::AppState;
+use crate::utils::{figure_snippet, table_snippet};
+use adw::prelude::*;
+use adw::{AlertDialog, ApplicationWindow, EntryRow, PreferencesGroup, SpinRow, SwitchRow};
+use gtk4::{gio, glib, Button};
+use sourceview5::Buffer;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Inserts `snippet` on its own line at the cursor as a single undo step.
+fn insert_block(buffer: &Buffer, snippet: &str) {
+    let cursor = buffer.iter_at_mark(&buffer.get_insert());
+    buffer.begin_user_action();
+    if !cursor.starts_line() {
+        buffer.insert_at_cursor("\n");
+    }
+    buffer.insert_at_cursor(snippet);
+    buffer.end_user_action();
+}
+
+/// Copies `image` into `dir`, picking `name-1.ext`, `name-2.ext`, … if the name is taken.
+fn copy_into(image: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    let stem = image
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "image".to_string());
+    let extension = image
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let mut target = dir.join(format!("{}{}", stem, extension));
+    let mut n = 1;
+    while target.exists() {
+        target = dir.join(format!("{}-{}{}", stem, n, extension));
+        n += 1;
+    }
+    std::fs::copy(image, &target)?;
+    Ok(target)
+}
+
+fn alert_dialog(heading: &str, form: &PreferencesGroup) -> AlertDialog {
+    let dialog = AlertDialog::builder()
+        .heading(heading)
+        .extra_child(form)
+        .build();
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("insert", "Insert");
+    dialog.set_response_appearance("insert", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("insert"));
+    dialog.set_close_response("cancel");
+    dialog
+}
+
+fn show_figure_dialog(
+    window: &ApplicationWindow,
+    buffer: &Buffer,
+    state: &Rc<RefCell<AppState>>,
+    toast_overlay: &adw::ToastOverlay,
+) {
+    let document = state.borrow().current_file.clone();
+    let document_dir = document
+        .as_deref()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf);
+
+    let form = PreferencesGroup::new();
+    let image_row = EntryRow::builder().title("Image").build();
+    let browse_btn = Button::builder()
+        .icon_name("document-open-symbolic")
+        .valign(gtk4::Align::Center)
+        .has_frame(false)
+        .tooltip_text("Choose Image")
+        .build();
+    image_row.add_suffix(&browse_btn);
+    let caption_row = EntryRow::builder().title("Caption").build();
+    let copy_row = SwitchRow::builder()
+        .title("Copy Next to Document")
+        .active(document_dir.is_some())
+        .sensitive(document_dir.is_some())
+        .build();
+    if document_dir.is_none() {
+        copy_row.set_subtitle("Save the document first");
+    }
+    form.add(&image_row);
+    form.add(&caption_row);
+    form.add(&copy_row);
+
+    let dialog = alert_dialog("Insert Figure", &form);
+    dialog.set_response_enabled("insert", false);
+
+    image_row.connect_changed(glib::clone!(
+        #[weak]
+        dialog,
+        move |row| {
+            dialog.set_response_enabled("insert", !row.text().trim().is_empty());
+        }
+    ));
+
+    browse_btn.connect_clicked(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        image_row,
+        move |_| {
+            let filter = gtk4::FileFilter::new();
+            filter.set_name(Some("Images"));
+            for extension in DROP_IMAGE_EXTENSIONS {
+                filter.add_suffix(extension);
+            }
+            let filters = gio::ListStore::new::<gtk4::FileFilter>();
+            filters.append(&filter);
+            let file_dialog = gtk4::FileDialog::builder()
+                .title("Choose Image")
+                .filters(&filters)
+                .build();
+            file_dialog.open(
+                Some(&window),
+                None::<&gio::Cancellable>,
+                glib::clone!(
+                    #[weak]
+                    image_row,
+                    move |res| {
+                        if let Some(path) = res.ok().and_then(|f| f.path()) {
+                            image_row.set_text(&path.to_string_lossy());
+                        }
+                    }
+                ),
+            );
+        }
+    ));
+
+    dialog.connect_response(
+        Some("insert"),
+        glib::clone!(
+            #[weak]
+            buffer,
+            #[weak]
+            toast_overlay,
+            #[weak]
+            image_row,
+            #[weak]
+            caption_row,
+            #[weak]
+            copy_row,
+            move |_, _| {
+                let mut image = PathBuf::from(image_row.text().trim());
+                if let Some(dir) = document_dir.as_deref() {
+                    if image.is_relative() {
+                        image = dir.join(image);
+                    } else if copy_row.is_active() && !image.starts_with(dir) {
+                        match copy_into(&image, dir) {
+                            Ok(copied) => image = copied,
+                            Err(e) => toast_overlay.add_toast(adw::Toast::new(&format!(
+                                "Failed to copy image: {}",
+                                e
+                            ))),
+                        }
+                    }
+                }
+                insert_block(
+                    &buffer,
+                    &figure_snippet(&image, document.as_deref(), &caption_row.text()),
+                );
+            }
+        ),
+    );
+
+    dialog.present(Some(window));
+}
+
+fn show_table_dialog(window: &ApplicationWindow, buffer: &Buffer) {
+    let form = PreferencesGroup::new();
+    let rows_row = SpinRow::with_range(1.0, 100.0, 1.0);
+    rows_row.set_title("Rows");
+    rows_row.set_value(3.0);
+    let cols_row = SpinRow::with_range(1.0, 20.0, 1.0);
+    cols_row.set_title("Columns");
+    cols_row.set_value(3.0);
+    let caption_row = EntryRow::builder().title("Caption").build();
+    form.add(&rows_row);
+    form.add(&cols_row);
+    form.add(&caption_row);
+
+    let dialog = alert_dialog("Insert Table", &form);
+    dialog.connect_response(
+        Some("insert"),
+        glib::clone!(
+            #[weak]
+            buffer,
+            #[weak]
+            rows_row,
+            #[weak]
+            cols_row,
+            #[weak]
+            caption_row,
+            move |_, _| {
+                insert_block(
+                    &buffer,
+                    &table_snippet(
+                        rows_row.value() as usize,
+                        cols_row.value() as usize,
+                        &caption_row.text(),
+                    ),
+                );
+            }
+        ),
+    );
+
+    dialog.present(Some(window));
+}
+
+/// Registers `win.insert-figure` and `win.insert-table`, which open small forms
+/// and splice a `figure` or `table` environment at the cursor.
+pub fn connect_insert_dialogs(
+    window: &ApplicationWindow,
+    buffer: &Buffer,
+    state: Rc<RefCell<AppState>>,
+    toast_overlay: &adw::ToastOverlay,
+) {
+    let figure_action = gio::SimpleAction::new("insert-figure", None);
+    figure_action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        buffer,
+        #[weak]
+        toast_overlay,
+        #[strong]
+        state,
+        move |_, _| {
+            show_figure_dialog(&window, &buffer, &state, &toast_overlay);
+        }
+    ));
+    window.add_action(&figure_action);
+
+    let table_action = gio::SimpleAction::new("insert-table", None);
+    table_action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        buffer,
+        move |_, _| {
+            show_table_dialog(&window, &buffer);
+        }
+    ));
+    window.add_action(&table_action);
+}
@@ -1,6 +1,12 @@
-use crate::ui::sidebar;
-use gtk4::prelude::{BoxExt, WidgetExt};
-use gtk4::{Box, Label, ListBox, Orientation, Paned, SearchEntry};
+use crate::ui::webview::CompileStatus;
+use crate::ui::{log_view, sidebar};
+use adw::prelude::*;
+use gtk4::{
+    gio, glib, Box, Label, ListBox, ListView, Orientation, Paned, Revealer, SearchEntry,
+    ToggleButton,
+};
+use std::cell::Cell;
+use std::rc::Rc;
 
 /// Creates the main layout structure including the sidebar hub,
 /// the editor/preview split view, and the status bar.
@@ -9,15 +15,21 @@ pub fn create_main_layout(
 ) -> (
     Paned,
     Paned,
-    ListBox,        // Outline list
+    ListView,       // Outline tree
     adw::ViewStack, // Sidebar hub
     gtk4::Box,      // Sidebar container
     gtk4::Box,      // Status bar
+    Revealer,       // Compilation log pane
     Label,
     Label,
+    Label, // File encoding
     Label,
+    CompileStatus,
     SearchEntry, // Arxiv search
     ListBox,     // Arxiv results
+    ListBox,     // TODO comments
+    SearchEntry, // Project search
+    ListBox,     // Project search hits
 ) {
     let paned = Paned::new(Orientation::Horizontal);
     paned.set_hexpand(true);
@@ -34,7 +46,15 @@ pub fn create_main_layout(
     // We'll let main.rs decide where to append outer_paned
 
     // Sidebar Hub
-    let (sidebar_hub, outline_list, arxiv_search, arxiv_list) = sidebar::create_sidebar_hub();
+    let (
+        sidebar_hub,
+        outline_list,
+        arxiv_search,
+        arxiv_list,
+        todo_list,
+        project_search,
+        project_hits,
+    ) = sidebar::create_sidebar_hub();
     let sidebar_container = Box::new(Orientation::Vertical, 0);
     sidebar_container.add_css_class("sidebar");
     sidebar_container.set_width_request(250);
@@ -54,13 +74,21 @@ pub fn create_main_layout(
 
     let pos_label = Label::new(Some("Line: 1, Col: 1"));
     let word_count_label = Label::new(Some("Words: 0"));
+    let encoding_label = Label::new(Some("UTF-8"));
     let ai_status_label = Label::new(Some("AI: Checking..."));
     ai_status_label.set_hexpand(true);
     ai_status_label.set_halign(gtk4::Align::End);
 
+    let compile_status = CompileStatus::new();
+    let (log_revealer, log_toggle) = log_view::create_log_pane(&compile_status.log_buffer);
+
     status_bar.append(&pos_label);
     status_bar.append(&word_count_label);
+    status_bar.append(&encoding_label);
     status_bar.append(&ai_status_label);
+    status_bar.append(&compile_status.spinner);
+    status_bar.append(&compile_status.label);
+    status_bar.append(&log_toggle);
     // main_vbox.append(&status_bar); // Let main.rs handle this
 
     (
@@ -70,10 +98,87 @@ pub fn create_main_layout(
         sidebar_hub,
         sidebar_container,
         status_bar,
+        log_revealer,
         pos_label,
         word_count_label,
+        encoding_label,
         ai_status_label,
+        compile_status,
         arxiv_search,
         arxiv_list,
+        todo_list,
+        project_search,
+        project_hits,
     )
 }
+
+/// Layout captured on entering focus mode so leaving it restores the exact split.
+#[derive(Clone, Copy)]
+struct SavedLayout {
+    outer_position: i32,
+    inner_position: i32,
+    sidebar_visible: bool,
+}
+
+/// Registers `win.focus-mode`, a distraction-free writing mode.
+///
+/// Entering it fullscreens the window and hides the header bar, sidebar and
+/// preview; leaving it (via the action or the window manager) restores the
+/// previous pane positions and sidebar visibility.
+pub fn connect_focus_mode(
+    window: &adw::ApplicationWindow,
+    header_bar: &adw::HeaderBar,
+    sidebar_toggle: &ToggleButton,
+    outer_paned: &Paned,
+    paned: &Paned,
+) {
+    let saved: Rc<Cell<Option<SavedLayout>>> = Rc::new(Cell::new(None));
+
+    let action = gio::SimpleAction::new("focus-mode", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        move |_, _| {
+            if window.is_fullscreen() {
+                window.unfullscreen();
+            } else {
+                window.fullscreen();
+            }
+        }
+    ));
+    window.add_action(&action);
+
+    window.connect_fullscreened_notify(glib::clone!(
+        #[weak]
+        header_bar,
+        #[weak]
+        sidebar_toggle,
+        #[weak]
+        outer_paned,
+        #[weak]
+        paned,
+        move |window| {
+            let preview = paned.end_child();
+            if window.is_fullscreen() {
+                saved.set(Some(SavedLayout {
+                    outer_position: outer_paned.position(),
+                    inner_position: paned.position(),
+                    sidebar_visible: sidebar_toggle.is_active(),
+                }));
+                header_bar.set_visible(false);
+                sidebar_toggle.set_active(false);
+                if let Some(preview) = preview {
+                    preview.set_visible(false);
+                }
+            } else if let Some(layout) = saved.take() {
+                header_bar.set_visible(true);
+                sidebar_toggle.set_active(layout.sidebar_visible);
+                if let Some(preview) = preview {
+                    preview.set_visible(true);
+                }
+                outer_paned.set_position(layout.outer_position);
+                paned.set_position(layout.inner_position);
+            }
+        }
+    ));
+}
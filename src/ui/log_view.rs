@@ -0,0 +1,124 @@
+use gtk4::prelude::*;
+use gtk4::{
+    gdk, Box, Button, Label, Orientation, PolicyType, Revealer, RevealerTransitionType,
+    ScrolledWindow, TextBuffer, TextView, ToggleButton,
+};
+
+/// Creates the text buffer backing the compilation log pane, with tags for
+/// highlighting error and warning lines.
+pub fn create_log_buffer() -> TextBuffer {
+    let buffer = TextBuffer::new(None);
+    let tag_table = buffer.tag_table();
+    tag_table.add(
+        &gtk4::TextTag::builder()
+            .name("log-error")
+            .foreground("#e01b24")
+            .weight(700)
+            .build(),
+    );
+    tag_table.add(
+        &gtk4::TextTag::builder()
+            .name("log-warning")
+            .foreground("#c64600")
+            .build(),
+    );
+    buffer
+}
+
+/// Replaces the log contents and highlights error (`!`-prefixed) and warning lines.
+pub fn set_log(buffer: &TextBuffer, log: &str) {
+    buffer.set_text(log);
+    for (i, line) in log.lines().enumerate() {
+        let tag = if line.starts_with('!') || line.contains("Error") {
+            "log-error"
+        } else if line.contains("Warning") {
+            "log-warning"
+        } else {
+            continue;
+        };
+        if let Some(start) = buffer.iter_at_line(i as i32) {
+            let mut end = start.clone();
+            end.forward_to_line_end();
+            buffer.apply_tag_by_name(tag, &start, &end);
+        }
+    }
+}
+
+/// Creates the bottom pane displaying the log from the last compile.
+///
+/// Returns the `Revealer` holding the pane and the status bar `ToggleButton`
+/// that shows/hides it.
+pub fn create_log_pane(buffer: &TextBuffer) -> (Revealer, ToggleButton) {
+    let revealer = Revealer::builder()
+        .transition_type(RevealerTransitionType::SlideUp)
+        .build();
+
+    let container = Box::new(Orientation::Vertical, 4);
+    container.set_margin_start(12);
+    container.set_margin_end(12);
+    container.set_margin_top(6);
+    container.add_css_class("sidebar"); // Re-use sidebar style for border
+
+    let header_box = Box::new(Orientation::Horizontal, 6);
+    let title = Label::builder()
+        .label("<b>Compilation Log</b>")
+        .use_markup(true)
+        .xalign(0.0)
+        .hexpand(true)
+        .build();
+    title.add_css_class("dim-label");
+
+    let copy_btn = Button::builder()
+        .icon_name("edit-copy-symbolic")
+        .has_frame(false)
+        .tooltip_text("Copy Log")
+        .build();
+    copy_btn.connect_clicked(glib::clone!(
+        #[weak]
+        buffer,
+        move |_| {
+            let (start, end) = buffer.bounds();
+            let text = buffer.text(&start, &end, false);
+            if let Some(display) = gdk::Display::default() {
+                display.clipboard().set_text(&text);
+            }
+        }
+    ));
+
+    header_box.append(&title);
+    header_box.append(&copy_btn);
+
+    let log_view = TextView::builder()
+        .buffer(buffer)
+        .editable(false)
+        .monospace(true)
+        .wrap_mode(gtk4::WrapMode::WordChar)
+        .build();
+
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(PolicyType::Never)
+        .vscrollbar_policy(PolicyType::Automatic)
+        .min_content_height(160)
+        .max_content_height(300)
+        .child(&log_view)
+        .build();
+
+    container.append(&header_box);
+    container.append(&scroll);
+    revealer.set_child(Some(&container));
+
+    let toggle = ToggleButton::builder()
+        .icon_name("utilities-terminal-symbolic")
+        .tooltip_text("Toggle Compilation Log")
+        .has_frame(false)
+        .build();
+    toggle.connect_active_notify(glib::clone!(
+        #[weak]
+        revealer,
+        move |btn| {
+            revealer.set_reveal_child(btn.is_active());
+        }
+    ));
+
+    (revealer, toggle)
+}
@@ -1,10 +1,19 @@
 //! UI module containing all user interface components and signal handlers.
 
 pub mod ai;
+pub mod citation;
+pub mod diff_review;
+pub mod document_settings;
 pub mod editor;
 pub mod file_ops;
 pub mod header;
+pub mod insert;
 pub mod layout;
+pub mod log_view;
+pub mod palette;
 pub mod settings;
 pub mod sidebar;
+pub mod statistics;
+pub mod symbol_picker;
+pub mod templates;
 pub mod webview;
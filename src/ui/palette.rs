@@ -0,0 +1,337 @@
+use crate::utils::fuzzy_score;
+use adw::prelude::*;
+use adw::{Application, ApplicationWindow};
+use gtk4::{
+    gio, glib, Box, Button, Label, ListBox, ListBoxRow, Orientation, Popover, ScrolledWindow,
+    SearchEntry,
+};
+
+/// A window action exposed in the command palette.
+pub struct Command {
+    /// Action name without the `win.` prefix.
+    pub name: &'static str,
+    pub title: &'static str,
+    /// Default keyboard shortcut, in `gtk4::accelerator_parse` syntax.
+    pub accel: Option<&'static str>,
+}
+
+/// Every action the palette can enumerate, in display order for an empty query.
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "new",
+        title: "New Document",
+        accel: Some("<Control>n"),
+    },
+    Command {
+        name: "open",
+        title: "Open File",
+        accel: Some("<Control>o"),
+    },
+    Command {
+        name: "save",
+        title: "Save File",
+        accel: Some("<Control>s"),
+    },
+    Command {
+        name: "export-pdf",
+        title: "Export PDF",
+        accel: Some("<Control>e"),
+    },
+    Command {
+        name: "settings",
+        title: "Settings",
+        accel: Some("<Control>comma"),
+    },
+    Command {
+        name: "recompile",
+        title: "Recompile Preview",
+        accel: Some("F5"),
+    },
+    Command {
+        name: "explain-compile",
+        title: "Explain Compilation",
+        accel: None,
+    },
+    Command {
+        name: "toggle-ai",
+        title: "Toggle AI Assistant",
+        accel: Some("<Control>space"),
+    },
+    Command {
+        name: "ai-regenerate",
+        title: "Regenerate AI Suggestion",
+        accel: None,
+    },
+    Command {
+        name: "toggle-sidebar",
+        title: "Toggle Sidebar",
+        accel: Some("F9"),
+    },
+    Command {
+        name: "focus-mode",
+        title: "Toggle Focus Mode",
+        accel: Some("F11"),
+    },
+    Command {
+        name: "jump-to-matching-environment",
+        title: "Jump to Matching \\begin/\\end",
+        accel: Some("<Control>m"),
+    },
+    Command {
+        name: "navigate-back",
+        title: "Navigate Back",
+        accel: Some("<Alt>Left"),
+    },
+    Command {
+        name: "navigate-forward",
+        title: "Navigate Forward",
+        accel: Some("<Alt>Right"),
+    },
+    Command {
+        name: "insert-citation",
+        title: "Insert Citation",
+        accel: Some("<Control><Shift>b"),
+    },
+    Command {
+        name: "insert-symbol",
+        title: "Insert Symbol",
+        accel: Some("<Control><Shift>m"),
+    },
+    Command {
+        name: "insert-figure",
+        title: "Insert Figure",
+        accel: None,
+    },
+    Command {
+        name: "insert-table",
+        title: "Insert Table",
+        accel: None,
+    },
+    Command {
+        name: "fold-all-environments",
+        title: "Fold All Environments",
+        accel: None,
+    },
+    Command {
+        name: "unfold-all",
+        title: "Unfold All",
+        accel: None,
+    },
+    Command {
+        name: "open-build-folder",
+        title: "Open Build Folder",
+        accel: None,
+    },
+    Command {
+        name: "clean-aux-files",
+        title: "Clean Auxiliary Files",
+        accel: None,
+    },
+    Command {
+        name: "copy-page-png",
+        title: "Copy Page as PNG",
+        accel: None,
+    },
+    Command {
+        name: "format-document",
+        title: "Format Document",
+        accel: Some("<Control><Shift>i"),
+    },
+    Command {
+        name: "toggle-comment",
+        title: "Toggle Comment",
+        accel: Some("<Control>slash"),
+    },
+    Command {
+        name: "math-scratch",
+        title: "Toggle Math Scratch",
+        accel: None,
+    },
+    Command {
+        name: "document-statistics",
+        title: "Document Statistics",
+        accel: None,
+    },
+    Command {
+        name: "document-settings",
+        title: "Document Settings",
+        accel: None,
+    },
+    Command {
+        name: "reload-config",
+        title: "Reload Settings From Disk",
+        accel: None,
+    },
+];
+
+const PALETTE_ACCEL: &str = "<Control><Shift>p";
+
+/// Registers `win.<name>` as an action that clicks `button`.
+///
+/// The action follows the button's sensitivity, so e.g. the AI toggle stays
+/// disabled in the palette until a provider is ready.
+pub fn add_button_action(window: &ApplicationWindow, name: &str, button: &impl IsA<Button>) {
+    let action = gio::SimpleAction::new(name, None);
+    button
+        .bind_property("sensitive", &action, "enabled")
+        .sync_create()
+        .build();
+    action.connect_activate(glib::clone!(
+        #[weak]
+        button,
+        move |_, _| {
+            button.emit_clicked();
+        }
+    ));
+    window.add_action(&action);
+}
+
+/// Installs the default shortcuts for palette commands and the palette itself.
+pub fn set_accels(app: &Application) {
+    for command in COMMANDS {
+        if let Some(accel) = command.accel {
+            app.set_accels_for_action(&format!("win.{}", command.name), &[accel]);
+        }
+    }
+    app.set_accels_for_action("win.command-palette", &[PALETTE_ACCEL]);
+}
+
+/// Human-readable label for the first shortcut bound to `win.<name>`.
+fn accel_label(app: Option<gtk4::Application>, name: &str) -> Option<String> {
+    let accels = app?.accels_for_action(&format!("win.{}", name));
+    let (key, mods) = gtk4::accelerator_parse(accels.first()?)?;
+    Some(gtk4::accelerator_get_label(key, mods).to_string())
+}
+
+/// Rebuilds the result list for `query`, best fuzzy match first.
+fn populate(list: &ListBox, window: &ApplicationWindow, query: &str) {
+    list.remove_all();
+
+    let mut matches: Vec<(i32, &Command)> = COMMANDS
+        .iter()
+        .filter(|c| {
+            window
+                .lookup_action(c.name)
+                .map(|a| a.is_enabled())
+                .unwrap_or(false)
+        })
+        .filter_map(|c| fuzzy_score(query, c.title).map(|score| (score, c)))
+        .collect();
+    // Stable sort keeps COMMANDS order among equal scores
+    matches.sort_by(|a, b| b.0.cmp(&a.0));
+
+    for (_, command) in matches {
+        let row_box = Box::new(Orientation::Horizontal, 12);
+        row_box.set_margin_start(6);
+        row_box.set_margin_end(6);
+        row_box.set_margin_top(6);
+        row_box.set_margin_bottom(6);
+
+        let title = Label::builder()
+            .label(command.title)
+            .xalign(0.0)
+            .hexpand(true)
+            .build();
+        row_box.append(&title);
+
+        if let Some(accel) = accel_label(window.application(), command.name) {
+            let shortcut = Label::new(Some(&accel));
+            shortcut.add_css_class("dim-label");
+            row_box.append(&shortcut);
+        }
+
+        let row = ListBoxRow::builder().child(&row_box).build();
+        row.set_widget_name(command.name);
+        list.append(&row);
+    }
+
+    if let Some(first) = list.row_at_index(0) {
+        list.select_row(Some(&first));
+    }
+}
+
+/// Creates the command palette popover and registers `win.command-palette`
+/// (Ctrl+Shift+P) to show it below `anchor`.
+pub fn create_command_palette(window: &ApplicationWindow, anchor: &impl IsA<gtk4::Widget>) {
+    let popover = Popover::builder().has_arrow(false).autohide(true).build();
+    popover.set_parent(anchor);
+
+    let container = Box::new(Orientation::Vertical, 6);
+    container.set_width_request(360);
+
+    let search = SearchEntry::builder()
+        .placeholder_text("Type a command…")
+        .build();
+    let list = ListBox::new();
+    list.add_css_class("boxed-list");
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(320)
+        .child(&list)
+        .build();
+
+    container.append(&search);
+    container.append(&scroll);
+    popover.set_child(Some(&container));
+
+    list.connect_row_activated(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        popover,
+        move |_, row| {
+            popover.popdown();
+            let _ =
+                WidgetExt::activate_action(&window, &format!("win.{}", row.widget_name()), None);
+        }
+    ));
+
+    search.connect_search_changed(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        list,
+        move |entry| {
+            populate(&list, &window, &entry.text());
+        }
+    ));
+
+    // Enter runs the best match
+    search.connect_activate(glib::clone!(
+        #[weak]
+        list,
+        move |_| {
+            if let Some(row) = list.selected_row() {
+                row.emit_activate();
+            }
+        }
+    ));
+
+    search.connect_stop_search(glib::clone!(
+        #[weak]
+        popover,
+        move |_| {
+            popover.popdown();
+        }
+    ));
+
+    let action = gio::SimpleAction::new("command-palette", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        popover,
+        #[weak]
+        search,
+        #[weak]
+        list,
+        move |_, _| {
+            search.set_text("");
+            populate(&list, &window, "");
+            popover.popup();
+            search.grab_focus();
+        }
+    ));
+    window.add_action(&action);
+}
@@ -1,15 +1,45 @@
+use crate::config::{
+    AppConfig, OllamaEndpoint, PreviewBackend, PreviewLayout, PreviewTheme, ProviderKind,
+    WebKitSandbox,
+};
+use crate::constants::{
+    MAX_COMPILE_QUEUE_DEPTH, MAX_EDITOR_FONT_SIZE, MAX_FORMAT_WRAP_COLUMN, MAX_PNG_COPY_DPI,
+    MAX_PREVIEW_DEBOUNCE_MS, MAX_PREVIEW_SCALE, MAX_TAB_WIDTH, MIN_EDITOR_FONT_SIZE,
+    MIN_PNG_COPY_DPI, MIN_PREVIEW_SCALE,
+};
 use crate::state::AppState;
 use adw::prelude::*;
 use adw::{ActionRow, PreferencesGroup, PreferencesPage, PreferencesWindow};
-use gtk4::{glib, DropDown, Entry, PasswordEntry, StringList};
+use gtk4::pango::FontDescription;
+use gtk4::{
+    glib, Button, DropDown, Entry, FontDialog, FontDialogButton, FontLevel, Label, PasswordEntry,
+    StringList,
+};
 use std::cell::RefCell;
 use std::rc::Rc;
 
+/// Shows the outcome of a connection test, styled with `class` (`success` or `error`).
+fn set_test_status(label: &Label, text: &str, class: Option<&str>) {
+    label.remove_css_class("success");
+    label.remove_css_class("error");
+    if let Some(class) = class {
+        label.add_css_class(class);
+    }
+    label.set_text(text);
+}
+
+/// Trimmed entry text, or `None` when it is blank.
+fn optional_text(entry: &Entry) -> Option<String> {
+    let text = entry.text().trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
 pub fn show_settings(
     parent: &gtk4::Window,
     state: Rc<RefCell<AppState>>,
     on_settings_closed: Option<Rc<dyn Fn()>>,
     on_config_changed: Option<Rc<dyn Fn()>>,
+    on_editor_changed: Option<Rc<dyn Fn()>>,
 ) {
     let window = PreferencesWindow::builder()
         .transient_for(parent)
@@ -27,18 +57,736 @@ pub fn show_settings(
     general_group.set_title("Appearance");
     page.add(&general_group);
 
-    let dark_mode_row = ActionRow::builder()
-        .title("Preview True Dark Mode")
-        .subtitle("Invert LaTeX preview colors in dark mode")
+    let theme_list = StringList::new(
+        PreviewTheme::ALL
+            .iter()
+            .map(|t| t.label())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let current_theme = state.borrow().config.preview_theme;
+    let theme_row = ActionRow::builder()
+        .title("Preview Theme")
+        .subtitle("Dark inverts the pages' colors; independent of the editor theme")
+        .build();
+    let theme_dropdown = DropDown::builder()
+        .model(&theme_list)
+        .valign(gtk4::Align::Center)
+        .selected(
+            PreviewTheme::ALL
+                .iter()
+                .position(|t| *t == current_theme)
+                .unwrap_or(0) as u32,
+        )
+        .build();
+    theme_row.add_suffix(&theme_dropdown);
+    general_group.add(&theme_row);
+
+    theme_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        on_config_changed,
+        move |dropdown| {
+            let Some(theme) = PreviewTheme::ALL.get(dropdown.selected() as usize) else {
+                return;
+            };
+            {
+                let mut s = state.borrow_mut();
+                s.config.preview_theme = *theme;
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_config_changed {
+                on_changed();
+            }
+        }
+    ));
+
+    let editor_group = PreferencesGroup::new();
+    editor_group.set_title("Editor");
+    page.add(&editor_group);
+
+    // Saves one editor preference and applies it to the open editor right away
+    let update_editor = {
+        let state = state.clone();
+        move |update: &dyn Fn(&mut AppConfig)| {
+            {
+                let mut s = state.borrow_mut();
+                update(&mut s.config);
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_editor_changed {
+                on_changed();
+            }
+        }
+    };
+
+    let font_row = ActionRow::builder()
+        .title("Font")
+        .subtitle("Font family used in the editor")
+        .build();
+    let font_button = FontDialogButton::builder()
+        .dialog(&FontDialog::new())
+        .level(FontLevel::Family)
+        .font_desc(&FontDescription::from_string(
+            &state.borrow().config.editor_font,
+        ))
+        .valign(gtk4::Align::Center)
+        .build();
+    font_row.add_suffix(&font_button);
+    editor_group.add(&font_row);
+
+    font_button.connect_font_desc_notify(glib::clone!(
+        #[strong]
+        update_editor,
+        move |button| {
+            if let Some(family) = button.font_desc().and_then(|d| d.family()) {
+                update_editor(&|c| c.editor_font = family.to_string());
+            }
+        }
+    ));
+
+    // Index 0 is "Follow System"; the rest are installed schemes
+    let scheme_manager = sourceview5::StyleSchemeManager::default();
+    let scheme_ids: Vec<String> = scheme_manager
+        .scheme_ids()
+        .iter()
+        .map(|id| id.to_string())
+        .collect();
+    let mut scheme_names = vec!["Follow System".to_string()];
+    scheme_names.extend(scheme_ids.iter().map(|id| {
+        scheme_manager
+            .scheme(id)
+            .map(|s| s.name().to_string())
+            .unwrap_or_else(|| id.clone())
+    }));
+    let scheme_list = StringList::new(
+        scheme_names
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let current_scheme = state.borrow().config.editor_color_scheme.clone();
+    let scheme_row = ActionRow::builder()
+        .title("Color Scheme")
+        .subtitle("Syntax highlighting colors")
+        .build();
+    let scheme_dropdown = DropDown::builder()
+        .model(&scheme_list)
+        .valign(gtk4::Align::Center)
+        .selected(
+            current_scheme
+                .and_then(|current| scheme_ids.iter().position(|id| *id == current))
+                .map_or(0, |i| i + 1) as u32,
+        )
+        .build();
+    scheme_row.add_suffix(&scheme_dropdown);
+    editor_group.add(&scheme_row);
+
+    scheme_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        update_editor,
+        move |dropdown| {
+            let scheme = (dropdown.selected() as usize)
+                .checked_sub(1)
+                .and_then(|i| scheme_ids.get(i).cloned());
+            update_editor(&|c| c.editor_color_scheme = scheme.clone());
+        }
+    ));
+
+    let font_size_row = adw::SpinRow::with_range(
+        MIN_EDITOR_FONT_SIZE as f64,
+        MAX_EDITOR_FONT_SIZE as f64,
+        1.0,
+    );
+    font_size_row.set_title("Font Size");
+    font_size_row.set_subtitle("Size in points at 100% zoom");
+    font_size_row.set_value(state.borrow().config.editor_font_size as f64);
+    editor_group.add(&font_size_row);
+
+    font_size_row.connect_value_notify(glib::clone!(
+        #[strong]
+        update_editor,
+        move |row| {
+            let size = row.value() as i32;
+            update_editor(&|c| c.editor_font_size = size);
+        }
+    ));
+
+    let tab_width_row = adw::SpinRow::with_range(1.0, MAX_TAB_WIDTH as f64, 1.0);
+    tab_width_row.set_title("Tab Width");
+    tab_width_row.set_subtitle("Columns per indentation level");
+    tab_width_row.set_value(state.borrow().config.tab_width as f64);
+    editor_group.add(&tab_width_row);
+
+    tab_width_row.connect_value_notify(glib::clone!(
+        #[strong]
+        update_editor,
+        move |row| {
+            let width = row.value() as u32;
+            update_editor(&|c| c.tab_width = width);
+        }
+    ));
+
+    let editor_switches: [(&str, &str, bool, fn(&mut AppConfig, bool)); 3] = [
+        (
+            "Insert Spaces Instead of Tabs",
+            "Indent with spaces when pressing Tab",
+            state.borrow().config.insert_spaces,
+            |c, on| c.insert_spaces = on,
+        ),
+        (
+            "Show Line Numbers",
+            "Number lines in the editor gutter",
+            state.borrow().config.show_line_numbers,
+            |c, on| c.show_line_numbers = on,
+        ),
+        (
+            "Wrap Lines",
+            "Wrap long lines at the window edge instead of scrolling",
+            state.borrow().config.wrap_lines,
+            |c, on| c.wrap_lines = on,
+        ),
+    ];
+    for (title, subtitle, active, set) in editor_switches {
+        let row = ActionRow::builder().title(title).subtitle(subtitle).build();
+        let switch = gtk4::Switch::builder()
+            .valign(gtk4::Align::Center)
+            .active(active)
+            .build();
+        row.add_suffix(&switch);
+        row.set_activatable_widget(Some(&switch));
+        editor_group.add(&row);
+
+        switch.connect_active_notify(glib::clone!(
+            #[strong]
+            update_editor,
+            move |sw| {
+                let on = sw.is_active();
+                update_editor(&|c| set(c, on));
+            }
+        ));
+    }
+
+    let todo_row = adw::EntryRow::builder()
+        .title("TODO Keywords (comma-separated)")
+        .text(state.borrow().config.todo_keywords.join(", "))
+        .show_apply_button(true)
+        .build();
+    editor_group.add(&todo_row);
+
+    todo_row.connect_apply(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        on_config_changed,
+        move |row| {
+            {
+                let mut s = state.borrow_mut();
+                s.config.todo_keywords = row
+                    .text()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|k| !k.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_config_changed {
+                on_changed();
+            }
+        }
+    ));
+
+    let format_group = PreferencesGroup::new();
+    format_group.set_title("Formatting");
+    format_group.set_description(Some("Used by Format Document"));
+    page.add(&format_group);
+
+    let wrap_column_row = adw::SpinRow::with_range(0.0, MAX_FORMAT_WRAP_COLUMN as f64, 1.0);
+    wrap_column_row.set_title("Wrap Column");
+    wrap_column_row.set_subtitle("Wrap prose lines longer than this (0 = never wrap)");
+    wrap_column_row.set_value(state.borrow().config.format_wrap_column as f64);
+    format_group.add(&wrap_column_row);
+
+    wrap_column_row.connect_value_notify(glib::clone!(
+        #[strong]
+        state,
+        move |row| {
+            let mut s = state.borrow_mut();
+            s.config.format_wrap_column = row.value() as usize;
+            let _ = s.config.save();
+        }
+    ));
+
+    let align_row = ActionRow::builder()
+        .title("Align Table Columns")
+        .subtitle("Line up & separators in tabular and align environments")
+        .build();
+    let align_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.format_align_tables)
+        .build();
+    align_row.add_suffix(&align_switch);
+    align_row.set_activatable_widget(Some(&align_switch));
+    format_group.add(&align_row);
+
+    align_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            let mut s = state.borrow_mut();
+            s.config.format_align_tables = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
+    let project_search_group = PreferencesGroup::new();
+    project_search_group.set_title("Search in Project");
+    project_search_group.set_description(Some(
+        "Globs such as *.tex or chapters/**/*.tex, matched against paths in the project folder",
+    ));
+    page.add(&project_search_group);
+
+    let glob_rows: [(&str, Vec<String>, fn(&mut AppConfig, Vec<String>)); 2] = [
+        (
+            "Include Files (comma-separated)",
+            state.borrow().config.project_search_include.clone(),
+            |c, globs| c.project_search_include = globs,
+        ),
+        (
+            "Exclude Paths (comma-separated)",
+            state.borrow().config.project_search_exclude.clone(),
+            |c, globs| c.project_search_exclude = globs,
+        ),
+    ];
+    for (title, globs, set) in glob_rows {
+        let row = adw::EntryRow::builder()
+            .title(title)
+            .text(globs.join(", "))
+            .show_apply_button(true)
+            .build();
+        project_search_group.add(&row);
+
+        row.connect_apply(glib::clone!(
+            #[strong]
+            state,
+            move |row| {
+                let globs = row
+                    .text()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|g| !g.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                let mut s = state.borrow_mut();
+                set(&mut s.config, globs);
+                let _ = s.config.save();
+            }
+        ));
+    }
+
+    let preview_group = PreferencesGroup::new();
+    preview_group.set_title("Preview");
+    page.add(&preview_group);
+
+    let debounce_row = adw::SpinRow::with_range(0.0, MAX_PREVIEW_DEBOUNCE_MS as f64, 50.0);
+    debounce_row.set_title("Compile Delay (ms)");
+    debounce_row.set_subtitle("Wait this long after typing stops before recompiling");
+    debounce_row.set_value(state.borrow().config.preview_debounce_ms as f64);
+    preview_group.add(&debounce_row);
+
+    debounce_row.connect_value_notify(glib::clone!(
+        #[strong]
+        state,
+        move |row| {
+            let mut s = state.borrow_mut();
+            s.config.preview_debounce_ms = row.value() as u64;
+            let _ = s.config.save();
+        }
+    ));
+
+    let queue_depth_row = adw::SpinRow::with_range(1.0, MAX_COMPILE_QUEUE_DEPTH as f64, 1.0);
+    queue_depth_row.set_title("Queued Compiles");
+    queue_depth_row.set_subtitle(
+        "1 always jumps to the latest edit; more also shows the edits in between (after restart)",
+    );
+    queue_depth_row.set_value(state.borrow().config.compile_queue_depth as f64);
+    preview_group.add(&queue_depth_row);
+
+    queue_depth_row.connect_value_notify(glib::clone!(
+        #[strong]
+        state,
+        move |row| {
+            let mut s = state.borrow_mut();
+            s.config.compile_queue_depth = row.value() as usize;
+            let _ = s.config.save();
+        }
+    ));
+
+    let backend_list = StringList::new(
+        PreviewBackend::ALL
+            .iter()
+            .map(|b| b.label())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let current_backend = state.borrow().config.preview_backend;
+    let backend_row = ActionRow::builder()
+        .title("Preview Mode")
+        .subtitle("Embedded PDF is faster for long documents and needs no poppler-utils")
+        .build();
+    let backend_dropdown = DropDown::builder()
+        .model(&backend_list)
+        .valign(gtk4::Align::Center)
+        .selected(
+            PreviewBackend::ALL
+                .iter()
+                .position(|b| *b == current_backend)
+                .unwrap_or(0) as u32,
+        )
+        .build();
+    backend_row.add_suffix(&backend_dropdown);
+    preview_group.add(&backend_row);
+
+    backend_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        on_config_changed,
+        move |dropdown| {
+            let Some(backend) = PreviewBackend::ALL.get(dropdown.selected() as usize) else {
+                return;
+            };
+            {
+                let mut s = state.borrow_mut();
+                s.config.preview_backend = *backend;
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_config_changed {
+                on_changed();
+            }
+        }
+    ));
+
+    let sandbox_list = StringList::new(
+        WebKitSandbox::ALL
+            .iter()
+            .map(|s| s.label())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let current_sandbox = state.borrow().config.webkit_sandbox;
+    let sandbox_row = ActionRow::builder()
+        .title("WebKit Sandbox Workaround")
+        .subtitle("Disable the sandbox if the preview stays blank, e.g. on WSL (after restart)")
+        .build();
+    let sandbox_dropdown = DropDown::builder()
+        .model(&sandbox_list)
+        .valign(gtk4::Align::Center)
+        .selected(
+            WebKitSandbox::ALL
+                .iter()
+                .position(|s| *s == current_sandbox)
+                .unwrap_or(0) as u32,
+        )
+        .build();
+    sandbox_row.add_suffix(&sandbox_dropdown);
+    preview_group.add(&sandbox_row);
+
+    sandbox_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        state,
+        move |dropdown| {
+            let Some(sandbox) = WebKitSandbox::ALL.get(dropdown.selected() as usize) else {
+                return;
+            };
+            let mut s = state.borrow_mut();
+            s.config.webkit_sandbox = *sandbox;
+            let _ = s.config.save();
+        }
+    ));
+
+    let scale_row = adw::SpinRow::with_range(MIN_PREVIEW_SCALE, MAX_PREVIEW_SCALE, 0.25);
+    scale_row.set_title("Preview Scale");
+    scale_row.set_subtitle("Render pages larger for fine detail (1.0 = natural size)");
+    scale_row.set_digits(2);
+    scale_row.set_value(state.borrow().config.preview_scale);
+    preview_group.add(&scale_row);
+
+    scale_row.connect_value_notify(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        on_config_changed,
+        move |row| {
+            {
+                let mut s = state.borrow_mut();
+                s.config.preview_scale = row.value();
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_config_changed {
+                on_changed();
+            }
+        }
+    ));
+
+    let layout_list = StringList::new(
+        PreviewLayout::ALL
+            .iter()
+            .map(|l| l.label())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let current_layout = state.borrow().config.preview_layout;
+    let layout_row = ActionRow::builder()
+        .title("Page Width")
+        .subtitle("Fixed uses the scale above; Fit Width fills the pane; Actual Size shows 100%")
+        .build();
+    let layout_dropdown = DropDown::builder()
+        .model(&layout_list)
+        .valign(gtk4::Align::Center)
+        .selected(
+            PreviewLayout::ALL
+                .iter()
+                .position(|l| *l == current_layout)
+                .unwrap_or(0) as u32,
+        )
+        .build();
+    layout_row.add_suffix(&layout_dropdown);
+    preview_group.add(&layout_row);
+
+    layout_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        on_config_changed,
+        move |dropdown| {
+            let Some(layout) = PreviewLayout::ALL.get(dropdown.selected() as usize) else {
+                return;
+            };
+            {
+                let mut s = state.borrow_mut();
+                s.config.preview_layout = *layout;
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_config_changed {
+                on_changed();
+            }
+        }
+    ));
+
+    let png_dpi_row =
+        adw::SpinRow::with_range(MIN_PNG_COPY_DPI as f64, MAX_PNG_COPY_DPI as f64, 25.0);
+    png_dpi_row.set_title("Copied Page Resolution (DPI)");
+    png_dpi_row.set_subtitle("Used by Copy Page as PNG");
+    png_dpi_row.set_value(state.borrow().config.png_copy_dpi as f64);
+    preview_group.add(&png_dpi_row);
+
+    png_dpi_row.connect_value_notify(glib::clone!(
+        #[strong]
+        state,
+        move |row| {
+            let mut s = state.borrow_mut();
+            s.config.png_copy_dpi = row.value() as u32;
+            let _ = s.config.save();
+        }
+    ));
+
+    let preamble_row = ActionRow::builder()
+        .title("Cache Preamble")
+        .subtitle("Precompile the preamble once so edits to the body recompile faster")
+        .build();
+    let preamble_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.precompile_preamble)
+        .build();
+    preamble_row.add_suffix(&preamble_switch);
+    preamble_row.set_activatable_widget(Some(&preamble_switch));
+    preview_group.add(&preamble_row);
+
+    preamble_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            let mut s = state.borrow_mut();
+            s.config.precompile_preamble = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
+    let lint_row = ActionRow::builder()
+        .title("Check Structure Before Compiling")
+        .subtitle("Don't run LaTeX while braces or environments are unbalanced")
+        .build();
+    let lint_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.lint_before_compile)
+        .build();
+    lint_row.add_suffix(&lint_switch);
+    lint_row.set_activatable_widget(Some(&lint_switch));
+    preview_group.add(&lint_row);
+
+    lint_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            let mut s = state.borrow_mut();
+            s.config.lint_before_compile = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
+    let wrap_row = ActionRow::builder()
+        .title("Wrap Fragments")
+        .subtitle("Preview snippets without \\documentclass inside a minimal document")
         .build();
-    let dark_mode_switch = gtk4::Switch::builder()
+    let wrap_switch = gtk4::Switch::builder()
         .valign(gtk4::Align::Center)
-        .active(state.borrow().config.preview_dark_mode)
+        .active(state.borrow().config.wrap_fragments)
         .build();
-    dark_mode_row.add_suffix(&dark_mode_switch);
-    general_group.add(&dark_mode_row);
+    wrap_row.add_suffix(&wrap_switch);
+    wrap_row.set_activatable_widget(Some(&wrap_switch));
+    preview_group.add(&wrap_row);
 
-    dark_mode_switch.connect_active_notify(glib::clone!(
+    wrap_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            let mut s = state.borrow_mut();
+            s.config.wrap_fragments = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
+    let page_numbers_row = ActionRow::builder()
+        .title("Page Numbers")
+        .subtitle("Show each page's number in its corner")
+        .build();
+    let page_numbers_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.preview_page_numbers)
+        .build();
+    page_numbers_row.add_suffix(&page_numbers_switch);
+    page_numbers_row.set_activatable_widget(Some(&page_numbers_switch));
+    preview_group.add(&page_numbers_row);
+
+    page_numbers_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            let mut s = state.borrow_mut();
+            s.config.preview_page_numbers = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
+    let preview_preamble_row = adw::ExpanderRow::builder()
+        .title("Preview Preamble")
+        .subtitle("Macros injected after \\documentclass in every preview compile")
+        .build();
+    let preview_preamble_view = gtk4::TextView::builder()
+        .monospace(true)
+        .wrap_mode(gtk4::WrapMode::WordChar)
+        .top_margin(6)
+        .bottom_margin(6)
+        .left_margin(6)
+        .right_margin(6)
+        .build();
+    preview_preamble_view
+        .buffer()
+        .set_text(&state.borrow().config.preview_preamble);
+    preview_preamble_row.add_row(
+        &gtk4::ScrolledWindow::builder()
+            .hscrollbar_policy(gtk4::PolicyType::Never)
+            .min_content_height(120)
+            .child(&preview_preamble_view)
+            .build(),
+    );
+    preview_group.add(&preview_preamble_row);
+
+    preview_preamble_view.buffer().connect_changed(glib::clone!(
+        #[strong]
+        state,
+        move |buffer| {
+            let mut s = state.borrow_mut();
+            s.config.preview_preamble = buffer
+                .text(&buffer.start_iter(), &buffer.end_iter(), false)
+                .to_string();
+            let _ = s.config.save();
+        }
+    ));
+
+    let tex_bin_row = adw::EntryRow::builder()
+        .title("TeX Programs Folder (empty uses PATH)")
+        .text(
+            state
+                .borrow()
+                .config
+                .tex_bin_dir
+                .as_ref()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        )
+        .show_apply_button(true)
+        .build();
+    preview_group.add(&tex_bin_row);
+
+    tex_bin_row.connect_apply(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        on_config_changed,
+        move |row| {
+            let text = row.text().trim().to_string();
+            let dir = (!text.is_empty()).then(|| std::path::PathBuf::from(text));
+            crate::preview::set_tex_bin_dir(dir.clone());
+            {
+                let mut s = state.borrow_mut();
+                s.config.tex_bin_dir = dir;
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_config_changed {
+                on_changed();
+            }
+        }
+    ));
+
+    let diagnostics_row = ActionRow::builder()
+        .title("Write Diagnostics")
+        .subtitle("Save errors and warnings as JSON in .latexrs-build/ for other editors")
+        .build();
+    let diagnostics_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.write_diagnostics)
+        .build();
+    diagnostics_row.add_suffix(&diagnostics_switch);
+    diagnostics_row.set_activatable_widget(Some(&diagnostics_switch));
+    preview_group.add(&diagnostics_row);
+
+    diagnostics_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            let mut s = state.borrow_mut();
+            s.config.write_diagnostics = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
+    let keep_build_row = ActionRow::builder()
+        .title("Keep Build Files")
+        .subtitle("Compile into .latexrs-build/ next to the document and keep .aux, .bbl, .log")
+        .build();
+    let keep_build_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.keep_build_files)
+        .build();
+    keep_build_row.add_suffix(&keep_build_switch);
+    preview_group.add(&keep_build_row);
+
+    keep_build_switch.connect_active_notify(glib::clone!(
         #[strong]
         state,
         #[strong]
@@ -46,7 +794,136 @@ pub fn show_settings(
         move |sw| {
             {
                 let mut s = state.borrow_mut();
-                s.config.preview_dark_mode = sw.is_active();
+                s.config.keep_build_files = sw.is_active();
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_config_changed {
+                on_changed();
+            }
+        }
+    ));
+
+    let aux_row = adw::EntryRow::builder()
+        .title("Auxiliary Extensions (comma-separated)")
+        .text(state.borrow().config.aux_extensions.join(", "))
+        .show_apply_button(true)
+        .build();
+    aux_row.set_tooltip_text(Some(
+        "Deleted by Clean Auxiliary Files; .tex, .bib and .pdf files are always kept",
+    ));
+    preview_group.add(&aux_row);
+
+    aux_row.connect_apply(glib::clone!(
+        #[strong]
+        state,
+        move |row| {
+            let mut s = state.borrow_mut();
+            s.config.aux_extensions = row
+                .text()
+                .split(',')
+                .map(|e| e.trim().trim_start_matches('.'))
+                .filter(|e| !e.is_empty())
+                .map(str::to_string)
+                .collect();
+            let _ = s.config.save();
+        }
+    ));
+
+    let autosave_row = ActionRow::builder()
+        .title("Save Before Compiling")
+        .subtitle("Write the open file to disk whenever the preview recompiles")
+        .build();
+    let autosave_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.autosave_before_compile)
+        .build();
+    autosave_row.add_suffix(&autosave_switch);
+    autosave_row.set_activatable_widget(Some(&autosave_switch));
+    preview_group.add(&autosave_row);
+
+    autosave_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            let mut s = state.borrow_mut();
+            s.config.autosave_before_compile = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
+    let spelling_group = PreferencesGroup::new();
+    spelling_group.set_title("Spelling");
+    page.add(&spelling_group);
+
+    let spell_row = ActionRow::builder()
+        .title("Check Spelling")
+        .subtitle("Underline misspelled words, ignoring commands and math")
+        .build();
+    let spell_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.spell_check)
+        .build();
+    spell_row.add_suffix(&spell_switch);
+    spelling_group.add(&spell_row);
+
+    let mut languages = crate::spell::available_languages();
+    let current_language = state.borrow().config.spell_language.clone();
+    if !languages.contains(&current_language) {
+        languages.insert(0, current_language.clone());
+    }
+    let language_list = StringList::new(
+        languages
+            .iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let language_row = ActionRow::builder()
+        .title("Language")
+        .subtitle("Hunspell dictionary used for checking")
+        .build();
+    let language_dropdown = DropDown::builder()
+        .model(&language_list)
+        .valign(gtk4::Align::Center)
+        .selected(
+            languages
+                .iter()
+                .position(|l| *l == current_language)
+                .unwrap_or(0) as u32,
+        )
+        .build();
+    language_row.add_suffix(&language_dropdown);
+    spelling_group.add(&language_row);
+
+    spell_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        on_config_changed,
+        move |sw| {
+            {
+                let mut s = state.borrow_mut();
+                s.config.spell_check = sw.is_active();
+                let _ = s.config.save();
+            }
+            if let Some(on_changed) = &on_config_changed {
+                on_changed();
+            }
+        }
+    ));
+
+    language_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        state,
+        #[strong]
+        on_config_changed,
+        move |dropdown| {
+            let Some(language) = languages.get(dropdown.selected() as usize) else {
+                return;
+            };
+            {
+                let mut s = state.borrow_mut();
+                s.config.spell_language = language.clone();
                 let _ = s.config.save();
             }
             if let Some(on_changed) = &on_config_changed {
@@ -55,6 +932,33 @@ pub fn show_settings(
         }
     ));
 
+    let network_group = PreferencesGroup::new();
+    network_group.set_title("Network");
+    page.add(&network_group);
+
+    let offline_row = ActionRow::builder()
+        .title("Offline Mode")
+        .subtitle("Make no network connections: turns off the AI assistant and arXiv search")
+        .build();
+    let offline_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.offline)
+        .build();
+    offline_row.add_suffix(&offline_switch);
+    offline_row.set_activatable_widget(Some(&offline_switch));
+    network_group.add(&offline_row);
+
+    offline_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            crate::api::set_offline(sw.is_active());
+            let mut s = state.borrow_mut();
+            s.config.offline = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
     let page_ai = PreferencesPage::new();
     page_ai.set_title("AI Configuration");
     page_ai.set_icon_name(Some("starred-symbolic"));
@@ -63,10 +967,78 @@ pub fn show_settings(
     let group = PreferencesGroup::new();
     group.set_title("Provider Settings");
     group.set_description(Some(
-        "Configure your AI backends (Ollama, OpenAI, DeepSeek)",
+        "Configure your AI backends: Ollama servers or any OpenAI-compatible API",
     ));
     page_ai.add(&group);
 
+    let assistant_group = PreferencesGroup::new();
+    assistant_group.set_title("Assistant");
+    page_ai.add(&assistant_group);
+
+    let collapse_row = ActionRow::builder()
+        .title("Collapse Reasoning")
+        .subtitle("Show only the title of a model's reasoning until it is expanded")
+        .build();
+    let collapse_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.collapse_reasoning)
+        .build();
+    collapse_row.add_suffix(&collapse_switch);
+    collapse_row.set_activatable_widget(Some(&collapse_switch));
+    assistant_group.add(&collapse_row);
+
+    collapse_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            let mut s = state.borrow_mut();
+            s.config.collapse_reasoning = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
+    let reasoning_limit_row = adw::SpinRow::with_range(0.0, 1_000_000.0, 1000.0);
+    reasoning_limit_row.set_title("Reasoning Limit (characters)");
+    reasoning_limit_row.set_subtitle("Longer reasoning is cut off; 0 shows all of it");
+    reasoning_limit_row.set_value(state.borrow().config.max_reasoning_chars as f64);
+    assistant_group.add(&reasoning_limit_row);
+
+    reasoning_limit_row.connect_value_notify(glib::clone!(
+        #[strong]
+        state,
+        move |row| {
+            let mut s = state.borrow_mut();
+            s.config.max_reasoning_chars = row.value() as usize;
+            let _ = s.config.save();
+        }
+    ));
+
+    let debug_log_row = ActionRow::builder()
+        .title("AI Debug Log")
+        .subtitle(format!(
+            "Record requests and raw responses in {} (API keys are redacted)",
+            glib::markup_escape_text(&crate::api::debug_log::log_dir().to_string_lossy())
+        ))
+        .build();
+    let debug_log_switch = gtk4::Switch::builder()
+        .valign(gtk4::Align::Center)
+        .active(state.borrow().config.ai_debug_log)
+        .build();
+    debug_log_row.add_suffix(&debug_log_switch);
+    debug_log_row.set_activatable_widget(Some(&debug_log_switch));
+    assistant_group.add(&debug_log_row);
+
+    debug_log_switch.connect_active_notify(glib::clone!(
+        #[strong]
+        state,
+        move |sw| {
+            crate::api::debug_log::set_enabled(sw.is_active());
+            let mut s = state.borrow_mut();
+            s.config.ai_debug_log = sw.is_active();
+            let _ = s.config.save();
+        }
+    ));
+
     let config = state.borrow().config.clone();
     let provider_names: Vec<String> = config.providers.iter().map(|p| p.name.clone()).collect();
     let model_names = StringList::new(
@@ -94,9 +1066,48 @@ pub fn show_settings(
         .unwrap_or(0);
     provider_dropdown.set_selected(current_index as u32);
 
+    let remove_provider_btn = Button::builder()
+        .icon_name("user-trash-symbolic")
+        .valign(gtk4::Align::Center)
+        .has_frame(false)
+        .tooltip_text("Remove Provider")
+        .sensitive(config.providers.len() > 1)
+        .build();
+
     provider_row.add_suffix(&provider_dropdown);
+    provider_row.add_suffix(&remove_provider_btn);
     group.add(&provider_row);
 
+    let add_provider_row = ActionRow::builder()
+        .title("Add Provider")
+        .subtitle("Another Ollama server or OpenAI-compatible endpoint")
+        .build();
+    let kind_list = StringList::new(
+        ProviderKind::ALL
+            .iter()
+            .map(|k| k.label())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let kind_dropdown = DropDown::builder()
+        .model(&kind_list)
+        .valign(gtk4::Align::Center)
+        .build();
+    let new_name_entry = Entry::builder()
+        .valign(gtk4::Align::Center)
+        .placeholder_text("Name")
+        .width_chars(12)
+        .build();
+    let add_provider_btn = Button::builder()
+        .icon_name("list-add-symbolic")
+        .valign(gtk4::Align::Center)
+        .tooltip_text("Add Provider")
+        .build();
+    add_provider_row.add_suffix(&kind_dropdown);
+    add_provider_row.add_suffix(&new_name_entry);
+    add_provider_row.add_suffix(&add_provider_btn);
+    group.add(&add_provider_row);
+
     // Dynamic fields based on selection
     let api_key_row = ActionRow::builder()
         .title("API Key")
@@ -120,6 +1131,28 @@ pub fn show_settings(
     url_row.add_suffix(&url_entry);
     group.add(&url_row);
 
+    let organization_row = ActionRow::builder()
+        .title("Organization ID")
+        .subtitle("Optional OpenAI-Organization header")
+        .build();
+    let organization_entry = Entry::builder()
+        .valign(gtk4::Align::Center)
+        .hexpand(true)
+        .build();
+    organization_row.add_suffix(&organization_entry);
+    group.add(&organization_row);
+
+    let project_row = ActionRow::builder()
+        .title("Project ID")
+        .subtitle("Optional OpenAI-Project header")
+        .build();
+    let project_entry = Entry::builder()
+        .valign(gtk4::Align::Center)
+        .hexpand(true)
+        .build();
+    project_row.add_suffix(&project_entry);
+    group.add(&project_row);
+
     let model_row = ActionRow::builder()
         .title("Model Name")
         .subtitle("Specific model ID (e.g. gpt-4o, deepseek-reasoner)")
@@ -143,6 +1176,41 @@ pub fn show_settings(
     prompt_row.add_suffix(&prompt_entry);
     group.add(&prompt_row);
 
+    let endpoint_list = StringList::new(
+        OllamaEndpoint::ALL
+            .iter()
+            .map(|e| e.label())
+            .collect::<Vec<_>>()
+            .as_slice(),
+    );
+    let endpoint_row = ActionRow::builder()
+        .title("Ollama Endpoint")
+        .subtitle("Automatic falls back to /api/generate for models without a chat template")
+        .build();
+    let endpoint_dropdown = DropDown::builder()
+        .model(&endpoint_list)
+        .valign(gtk4::Align::Center)
+        .build();
+    endpoint_row.add_suffix(&endpoint_dropdown);
+    group.add(&endpoint_row);
+
+    let test_row = ActionRow::builder()
+        .title("Connection")
+        .subtitle("Check the URL, key and model above")
+        .build();
+    let test_status = Label::builder()
+        .valign(gtk4::Align::Center)
+        .wrap(true)
+        .max_width_chars(40)
+        .build();
+    let test_btn = Button::builder()
+        .label("Test Connection")
+        .valign(gtk4::Align::Center)
+        .build();
+    test_row.add_suffix(&test_status);
+    test_row.add_suffix(&test_btn);
+    group.add(&test_row);
+
     // Helper to update fields
     let update_fields = {
         let provider_dropdown = provider_dropdown.downgrade();
@@ -150,6 +1218,13 @@ pub fn show_settings(
         let url_entry = url_entry.downgrade();
         let model_entry = model_entry.downgrade();
         let prompt_entry = prompt_entry.downgrade();
+        let endpoint_row = endpoint_row.downgrade();
+        let endpoint_dropdown = endpoint_dropdown.downgrade();
+        let organization_row = organization_row.downgrade();
+        let organization_entry = organization_entry.downgrade();
+        let project_row = project_row.downgrade();
+        let project_entry = project_entry.downgrade();
+        let test_status = test_status.downgrade();
         let state = state.clone();
         move || {
             let provider_dropdown = match provider_dropdown.upgrade() {
@@ -172,6 +1247,28 @@ pub fn show_settings(
                 Some(e) => e,
                 None => return,
             };
+            let (Some(endpoint_row), Some(endpoint_dropdown), Some(test_status)) = (
+                endpoint_row.upgrade(),
+                endpoint_dropdown.upgrade(),
+                test_status.upgrade(),
+            ) else {
+                return;
+            };
+            let (
+                Some(organization_row),
+                Some(organization_entry),
+                Some(project_row),
+                Some(project_entry),
+            ) = (
+                organization_row.upgrade(),
+                organization_entry.upgrade(),
+                project_row.upgrade(),
+                project_entry.upgrade(),
+            )
+            else {
+                return;
+            };
+            set_test_status(&test_status, "", None);
 
             let config = state.borrow().config.clone();
             let selected = provider_dropdown.selected();
@@ -180,12 +1277,78 @@ pub fn show_settings(
                 url_entry.set_text(&p.base_url);
                 model_entry.set_text(&p.active_model);
                 prompt_entry.set_text(p.system_prompt.as_deref().unwrap_or(""));
+                let is_ollama = p.kind() == ProviderKind::Ollama;
+                endpoint_row.set_visible(is_ollama);
+                organization_entry.set_text(p.organization.as_deref().unwrap_or(""));
+                project_entry.set_text(p.project.as_deref().unwrap_or(""));
+                organization_row.set_visible(!is_ollama);
+                project_row.set_visible(!is_ollama);
+                endpoint_dropdown.set_selected(
+                    OllamaEndpoint::ALL
+                        .iter()
+                        .position(|e| *e == p.ollama_endpoint)
+                        .unwrap_or(0) as u32,
+                );
             }
         }
     };
 
     update_fields();
 
+    test_btn.connect_clicked(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        provider_dropdown,
+        #[weak]
+        api_key_entry,
+        #[weak]
+        url_entry,
+        #[weak]
+        model_entry,
+        #[weak]
+        endpoint_dropdown,
+        #[weak]
+        organization_entry,
+        #[weak]
+        project_entry,
+        #[weak]
+        test_status,
+        move |btn| {
+            let selected = provider_dropdown.selected() as usize;
+            let Some(mut p_config) = state.borrow().config.providers.get(selected).cloned() else {
+                return;
+            };
+            // Test what is typed in, even if the window hasn't been closed to save it yet
+            let key = api_key_entry.text().to_string();
+            p_config.api_key = if key.is_empty() { None } else { Some(key) };
+            p_config.base_url = url_entry.text().to_string();
+            p_config.active_model = model_entry.text().to_string();
+            if let Some(endpoint) = OllamaEndpoint::ALL.get(endpoint_dropdown.selected() as usize) {
+                p_config.ollama_endpoint = *endpoint;
+            }
+            p_config.organization = optional_text(&organization_entry);
+            p_config.project = optional_text(&project_entry);
+
+            btn.set_sensitive(false);
+            set_test_status(&test_status, "Testing…", None);
+            glib::MainContext::default().spawn_local(glib::clone!(
+                #[weak]
+                btn,
+                #[weak]
+                test_status,
+                async move {
+                    let provider = crate::api::create_provider(&p_config);
+                    match provider.check_availability().await {
+                        Ok(()) => set_test_status(&test_status, "✓ Connected", Some("success")),
+                        Err(e) => set_test_status(&test_status, &e.to_string(), Some("error")),
+                    }
+                    btn.set_sensitive(true);
+                }
+            ));
+        }
+    ));
+
     provider_dropdown.connect_selected_notify(glib::clone!(
         #[strong]
         update_fields,
@@ -194,6 +1357,103 @@ pub fn show_settings(
         }
     ));
 
+    // Adding or removing a provider is saved straight away; selecting the
+    // resulting entry refreshes the fields below through `update_fields`.
+    add_provider_btn.connect_clicked(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        model_names,
+        #[weak]
+        provider_dropdown,
+        #[weak]
+        kind_dropdown,
+        #[weak]
+        new_name_entry,
+        #[weak]
+        remove_provider_btn,
+        move |_| {
+            let Some(kind) = ProviderKind::ALL.get(kind_dropdown.selected() as usize) else {
+                return;
+            };
+            let (index, name) = {
+                let mut s = state.borrow_mut();
+                let index = s.config.add_provider(&new_name_entry.text(), *kind);
+                let _ = s.config.save();
+                (index, s.config.providers[index].name.clone())
+            };
+            new_name_entry.set_text("");
+            model_names.append(&name);
+            provider_dropdown.set_selected(index as u32);
+            remove_provider_btn.set_sensitive(true);
+        }
+    ));
+
+    remove_provider_btn.connect_clicked(glib::clone!(
+        #[strong]
+        state,
+        #[weak]
+        window,
+        #[weak]
+        model_names,
+        #[weak]
+        provider_dropdown,
+        move |btn| {
+            let index = provider_dropdown.selected();
+            let Some(name) = model_names.string(index) else {
+                return;
+            };
+            let dialog = adw::AlertDialog::builder()
+                .heading("Remove Provider?")
+                .body(format!("“{}” and its settings will be removed.", name))
+                .build();
+            dialog.add_response("cancel", "Cancel");
+            dialog.add_response("remove", "Remove");
+            dialog.set_response_appearance("remove", adw::ResponseAppearance::Destructive);
+            dialog.set_default_response(Some("cancel"));
+            dialog.set_close_response("cancel");
+            dialog.connect_response(
+                None,
+                glib::clone!(
+                    #[strong]
+                    state,
+                    #[weak]
+                    model_names,
+                    #[weak]
+                    provider_dropdown,
+                    #[weak]
+                    btn,
+                    move |_, response| {
+                        if response != "remove" {
+                            return;
+                        }
+                        let (active, remaining) = {
+                            let mut s = state.borrow_mut();
+                            if s.config.remove_provider(index as usize).is_none() {
+                                return;
+                            }
+                            let _ = s.config.save();
+                            if let Some(p_config) = s.config.get_active_provider() {
+                                s.ai_provider = Some(crate::api::create_provider(p_config));
+                            }
+                            let active = s
+                                .config
+                                .providers
+                                .iter()
+                                .position(|p| p.name == s.config.active_provider)
+                                .unwrap_or(0);
+                            (active, s.config.providers.len())
+                        };
+                        model_names.remove(index);
+                        provider_dropdown.set_selected(active as u32);
+                        btn.set_sensitive(remaining > 1);
+                    }
+                ),
+            );
+            dialog.present(Some(&window));
+        }
+    ));
+
     window.connect_close_request(glib::clone!(
         #[strong]
         state,
@@ -208,14 +1468,16 @@ pub fn show_settings(
         #[strong]
         prompt_entry,
         #[strong]
-        dark_mode_switch,
+        endpoint_dropdown,
+        #[strong]
+        organization_entry,
+        #[strong]
+        project_entry,
         move |_| {
             {
                 let mut s = state.borrow_mut();
                 let selected = provider_dropdown.selected();
 
-                s.config.preview_dark_mode = dark_mode_switch.is_active();
-
                 let config_clone = s.config.clone();
                 if let Some(p_name) = config_clone
                     .providers
@@ -236,6 +1498,13 @@ pub fn show_settings(
                     } else {
                         Some(prompt)
                     };
+                    if let Some(endpoint) =
+                        OllamaEndpoint::ALL.get(endpoint_dropdown.selected() as usize)
+                    {
+                        p.ollama_endpoint = *endpoint;
+                    }
+                    p.organization = optional_text(&organization_entry);
+                    p.project = optional_text(&project_entry);
                 }
 
                 let _ = s.config.save();
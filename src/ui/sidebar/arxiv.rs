@@ -1,5 +1,11 @@
-use gtk4::prelude::*;
-use gtk4::{Box, Label, ListBox, Orientation, ScrolledWindow, SearchEntry};
+use crate::api::arxiv::{self, ArxivEntry};
+use adw::prelude::*;
+use adw::{ApplicationWindow, ToastOverlay};
+use gtk4::{
+    gdk, gio, glib, Box, Label, ListBox, ListBoxRow, Orientation, ScrolledWindow, SearchEntry,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub fn create_arxiv_pane() -> (Box, SearchEntry, ListBox) {
     let container = Box::new(Orientation::Vertical, 6);
@@ -32,3 +38,311 @@ pub fn create_arxiv_pane() -> (Box, SearchEntry, ListBox) {
 
     (container, search_entry, list_box)
 }
+
+/// The query whose results are listed, and how far through them we are.
+#[derive(Default)]
+struct SearchState {
+    query: String,
+    /// Offset of the next page to fetch.
+    next_start: usize,
+    /// Bumped by every new search so late pages of an old one are dropped.
+    generation: u64,
+}
+
+/// Runs a search when Enter is pressed in `search_entry`, listing results in
+/// `list`, with a "Load more" row while arXiv reports further matches.
+pub fn connect_arxiv_search(
+    search_entry: &SearchEntry,
+    list: &ListBox,
+    window: &ApplicationWindow,
+    toast_overlay: &ToastOverlay,
+) {
+    let search = Rc::new(RefCell::new(SearchState::default()));
+
+    search_entry.connect_activate(glib::clone!(
+        #[weak]
+        list,
+        #[weak]
+        window,
+        #[weak]
+        toast_overlay,
+        move |entry| {
+            let query = entry.text().to_string();
+            if query.is_empty() {
+                return;
+            }
+
+            // Clear previous results
+            while let Some(child) = list.first_child() {
+                list.remove(&child);
+            }
+            {
+                let mut s = search.borrow_mut();
+                s.query = query;
+                s.next_start = 0;
+                s.generation += 1;
+            }
+            load_page(&list, &window, &toast_overlay, search.clone());
+        }
+    ));
+}
+
+fn status_row(text: &str) -> ListBoxRow {
+    let label = Label::new(Some(text));
+    label.add_css_class("dim-label");
+    label.set_margin_top(12);
+    label.set_margin_bottom(12);
+    ListBoxRow::builder()
+        .child(&label)
+        .selectable(false)
+        .activatable(false)
+        .build()
+}
+
+/// Fetches the next page of the current query and appends it to `list`.
+fn load_page(
+    list: &ListBox,
+    window: &ApplicationWindow,
+    toast_overlay: &ToastOverlay,
+    search: Rc<RefCell<SearchState>>,
+) {
+    let (query, start, generation) = {
+        let s = search.borrow();
+        (s.query.clone(), s.next_start, s.generation)
+    };
+
+    let loading_row = status_row("Searching arXiv...");
+    list.append(&loading_row);
+
+    let list = list.clone();
+    let window = window.clone();
+    let toast_overlay = toast_overlay.clone();
+    glib::MainContext::default().spawn_local(async move {
+        let result = arxiv::search_arxiv(&query, start).await;
+        if search.borrow().generation != generation {
+            return;
+        }
+        if loading_row.parent().is_some() {
+            list.remove(&loading_row);
+        }
+
+        match result {
+            Ok(page) => {
+                if page.entries.is_empty() && start == 0 {
+                    list.append(&status_row("No results found."));
+                    return;
+                }
+                let next_start = start + page.entries.len();
+                for entry in page.entries {
+                    list.append(&result_row(&entry, &window, &toast_overlay));
+                }
+                search.borrow_mut().next_start = next_start;
+
+                if next_start < page.total && next_start > start {
+                    list.append(&load_more_row(
+                        &list,
+                        &window,
+                        &toast_overlay,
+                        search.clone(),
+                        page.total - next_start,
+                    ));
+                }
+            }
+            Err(e) => {
+                let error_label = Label::new(Some(&format!("Error: {}", e)));
+                error_label.add_css_class("error");
+                list.append(&error_label);
+            }
+        }
+    });
+}
+
+/// A row that replaces itself with the next page when clicked.
+fn load_more_row(
+    list: &ListBox,
+    window: &ApplicationWindow,
+    toast_overlay: &ToastOverlay,
+    search: Rc<RefCell<SearchState>>,
+    remaining: usize,
+) -> ListBoxRow {
+    let button = gtk4::Button::builder()
+        .label(format!("Load more ({} remaining)", remaining))
+        .has_frame(false)
+        .build();
+    let row = ListBoxRow::builder()
+        .child(&button)
+        .selectable(false)
+        .activatable(false)
+        .build();
+
+    button.connect_clicked(glib::clone!(
+        #[weak]
+        list,
+        #[weak]
+        window,
+        #[weak]
+        toast_overlay,
+        #[weak]
+        row,
+        move |_| {
+            list.remove(&row);
+            load_page(&list, &window, &toast_overlay, search.clone());
+        }
+    ));
+    row
+}
+
+fn result_row(
+    entry: &ArxivEntry,
+    window: &ApplicationWindow,
+    toast_overlay: &ToastOverlay,
+) -> adw::ActionRow {
+    let authors = entry
+        .authors
+        .iter()
+        .map(|a| a.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // Escape for pango markup just in case
+    let escaped_title = glib::markup_escape_text(&entry.title);
+    let mut subtitle = glib::markup_escape_text(&authors).to_string();
+    if !entry.categories.is_empty() {
+        let tags = glib::markup_escape_text(&entry.categories.join(" · "));
+        subtitle.push_str(&format!("\n<small><b>{}</b></small>", tags));
+    }
+
+    let row = adw::ActionRow::builder()
+        .title(escaped_title.as_str())
+        .subtitle(subtitle.as_str())
+        .title_lines(2)
+        .subtitle_lines(3)
+        .build();
+
+    let info_btn = gtk4::Button::builder()
+        .icon_name("info-symbolic")
+        .valign(gtk4::Align::Center)
+        .has_frame(false)
+        .tooltip_text("View Summary")
+        .build();
+
+    let mut summary = entry.summary.clone();
+    if let Some(comment) = &entry.comment {
+        summary.push_str(&format!("\n\nComment: {}", comment));
+    }
+    if let Some(doi) = &entry.doi {
+        summary.push_str(&format!("\nDOI: {}", doi));
+    }
+    if !entry.updated.is_empty() && entry.updated != entry.published {
+        summary.push_str(&format!("\nUpdated: {}", entry.updated));
+    }
+    info_btn.connect_clicked(glib::clone!(
+        #[weak]
+        window,
+        move |_| {
+            let dialog = adw::AlertDialog::builder()
+                .heading("Paper Summary")
+                .body(&summary)
+                .build();
+            dialog.add_response("close", "Close");
+            dialog.set_default_response(Some("close"));
+            dialog.present(Some(&window));
+        }
+    ));
+
+    let bib_btn = gtk4::Button::builder()
+        .icon_name("edit-copy-symbolic")
+        .valign(gtk4::Align::Center)
+        .has_frame(false)
+        .tooltip_text("Copy BibTeX")
+        .build();
+
+    let entry_id = arxiv::extract_id(&entry.id);
+    bib_btn.connect_clicked(glib::clone!(
+        #[weak]
+        toast_overlay,
+        move |_| {
+            let id = entry_id.clone();
+            glib::MainContext::default().spawn_local(glib::clone!(
+                #[weak]
+                toast_overlay,
+                async move {
+                    match arxiv::fetch_bibtex(&id).await {
+                        Ok(bib) => {
+                            let display =
+                                gdk::Display::default().expect("Could not connect to a display.");
+                            let clipboard = display.clipboard();
+                            clipboard.set_text(&bib);
+                            toast_overlay.add_toast(adw::Toast::new("BibTeX copied to clipboard"));
+                        }
+                        Err(e) => {
+                            toast_overlay.add_toast(adw::Toast::new(&format!(
+                                "Failed to fetch BibTeX: {}",
+                                e
+                            )));
+                        }
+                    }
+                }
+            ));
+        }
+    ));
+
+    let abstract_btn = gtk4::Button::builder()
+        .icon_name("web-browser-symbolic")
+        .valign(gtk4::Align::Center)
+        .has_frame(false)
+        .tooltip_text("Open Abstract Page")
+        .build();
+    let pdf_btn = gtk4::Button::builder()
+        .icon_name("document-open-symbolic")
+        .valign(gtk4::Align::Center)
+        .has_frame(false)
+        .tooltip_text("Open PDF")
+        .build();
+
+    for (btn, url, missing) in [
+        (
+            &abstract_btn,
+            entry.abstract_url(),
+            "No abstract page available",
+        ),
+        (&pdf_btn, entry.pdf_url(), "No PDF available"),
+    ] {
+        let Some(url) = url else {
+            btn.set_sensitive(false);
+            btn.set_tooltip_text(Some(missing));
+            continue;
+        };
+        btn.connect_clicked(glib::clone!(
+            #[weak]
+            window,
+            #[weak]
+            toast_overlay,
+            move |_| open_in_browser(&window, &url, &toast_overlay)
+        ));
+    }
+
+    row.add_suffix(&abstract_btn);
+    row.add_suffix(&pdf_btn);
+    row.add_suffix(&info_btn);
+    row.add_suffix(&bib_btn);
+    row
+}
+
+/// Opens `url` in the default browser, reporting failures as a toast.
+fn open_in_browser(window: &ApplicationWindow, url: &str, toast_overlay: &ToastOverlay) {
+    gtk4::UriLauncher::new(url).launch(
+        Some(window),
+        None::<&gio::Cancellable>,
+        glib::clone!(
+            #[weak]
+            toast_overlay,
+            move |result| {
+                if let Err(e) = result {
+                    toast_overlay
+                        .add_toast(adw::Toast::new(&format!("Failed to open link: {}", e)));
+                }
+            }
+        ),
+    );
+}
@@ -1,18 +1,25 @@
 pub mod arxiv;
 pub mod outline;
+pub mod search;
+pub mod todos;
 
-use gtk4::{ListBox, SearchEntry};
+use gtk4::{ListBox, ListView, SearchEntry};
 
 pub fn create_sidebar_hub() -> (
     adw::ViewStack,
-    ListBox,     // Outline list
+    ListView,    // Outline tree
     SearchEntry, // Arxiv search
     ListBox,     // Arxiv results
+    ListBox,     // TODO comments
+    SearchEntry, // Project search
+    ListBox,     // Project search hits
 ) {
     let stack = adw::ViewStack::new();
 
     let (outline_pane, outline_list) = outline::create_outline_pane();
     let (arxiv_pane, arxiv_search, arxiv_list) = arxiv::create_arxiv_pane();
+    let (todo_pane, todo_list) = todos::create_todo_pane();
+    let (search_pane, project_search, project_hits) = search::create_search_pane();
 
     let outline_page = stack.add_titled(&outline_pane, Some("outline"), "Outline");
     outline_page.set_icon_name(Some("view-list-bullet-symbolic"));
@@ -20,5 +27,26 @@ pub fn create_sidebar_hub() -> (
     let arxiv_page = stack.add_titled(&arxiv_pane, Some("arxiv"), "arXiv");
     arxiv_page.set_icon_name(Some("system-search-symbolic"));
 
-    (stack, outline_list, arxiv_search, arxiv_list)
+    let todo_page = stack.add_titled(&todo_pane, Some("todos"), "TODOs");
+    todo_page.set_icon_name(Some("object-select-symbolic"));
+
+    let search_page = stack.add_titled(&search_pane, Some("search"), "Search");
+    search_page.set_icon_name(Some("edit-find-symbolic"));
+
+    (
+        stack,
+        outline_list,
+        arxiv_search,
+        arxiv_list,
+        todo_list,
+        project_search,
+        project_hits,
+    )
+}
+
+/// Shows or hides the arXiv tab of the hub, e.g. for offline mode.
+pub fn set_arxiv_visible(stack: &adw::ViewStack, visible: bool) {
+    if let Some(pane) = stack.child_by_name("arxiv") {
+        stack.page(&pane).set_visible(visible);
+    }
 }
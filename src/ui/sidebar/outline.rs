@@ -1,11 +1,169 @@
-use gtk4::{ListBox, ScrolledWindow};
+use crate::utils::{outline_tree, OutlineNode, Section};
+use gtk4::prelude::*;
+use gtk4::{
+    gio, glib, Label, ListItem, ListView, ScrolledWindow, SignalListItemFactory, SingleSelection,
+    TreeExpander, TreeListModel, TreeListRow,
+};
 
-pub fn create_outline_pane() -> (ScrolledWindow, ListBox) {
-    let list_box = ListBox::new();
+/// Item of the outline model, wrapped in a `glib::BoxedAnyObject`.
+struct OutlineEntry {
+    section: Section,
+    /// Position among all headings, which numbers the preview's
+    /// `section-{index}` anchors.
+    index: usize,
+    /// Entries nested below this one; empty for leaves.
+    children: gio::ListStore,
+}
+
+/// Creates the outline: a tree of the document's headings in which
+/// subsections nest under their section and can be collapsed.
+pub fn create_outline_pane() -> (ScrolledWindow, ListView) {
+    let root = gio::ListStore::new::<glib::BoxedAnyObject>();
+    let tree = TreeListModel::new(root, false, true, |item| {
+        let entry = item.downcast_ref::<glib::BoxedAnyObject>()?;
+        let children = entry.borrow::<OutlineEntry>().children.clone();
+        (children.n_items() > 0).then(|| children.upcast())
+    });
+    let selection = SingleSelection::builder()
+        .model(&tree)
+        .autoselect(false)
+        .build();
+
+    let factory = SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+        let label = Label::new(None);
+        label.set_xalign(0.0);
+        label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+        let expander = TreeExpander::new();
+        expander.set_child(Some(&label));
+        if let Some(item) = item.downcast_ref::<ListItem>() {
+            item.set_child(Some(&expander));
+        }
+    });
+    factory.connect_bind(|_, item| {
+        let Some(item) = item.downcast_ref::<ListItem>() else {
+            return;
+        };
+        let (Some(row), Some(expander)) = (
+            item.item().and_downcast::<TreeListRow>(),
+            item.child().and_downcast::<TreeExpander>(),
+        ) else {
+            return;
+        };
+        let (Some(entry), Some(label)) = (
+            row.item().and_downcast::<glib::BoxedAnyObject>(),
+            expander.child().and_downcast::<Label>(),
+        ) else {
+            return;
+        };
+        let entry = entry.borrow::<OutlineEntry>();
+        label.set_label(&entry.section.title);
+        label.set_tooltip_text(Some(&entry.section.title));
+        // Sections are bold and subsubsections dimmed so the hierarchy reads
+        // at a glance; rows are recycled, so reset the classes first
+        label.remove_css_class("heading");
+        label.remove_css_class("dim-label");
+        match entry.section.level {
+            0 => label.add_css_class("heading"),
+            1 => {}
+            _ => label.add_css_class("dim-label"),
+        }
+        expander.set_list_row(Some(&row));
+    });
+    factory.connect_unbind(|_, item| {
+        if let Some(expander) = item
+            .downcast_ref::<ListItem>()
+            .and_then(|item| item.child().and_downcast::<TreeExpander>())
+        {
+            expander.set_list_row(None);
+        }
+    });
+
+    let list_view = ListView::builder()
+        .model(&selection)
+        .factory(&factory)
+        .single_click_activate(true)
+        .build();
     let scrolled_window = ScrolledWindow::builder()
-        .child(&list_box)
+        .child(&list_view)
         .vexpand(true)
         .build();
 
-    (scrolled_window, list_box)
+    (scrolled_window, list_view)
+}
+
+/// The store of top-level entries behind `outline`.
+fn root_store(outline: &ListView) -> Option<gio::ListStore> {
+    outline
+        .model()
+        .and_downcast::<SingleSelection>()?
+        .model()
+        .and_downcast::<TreeListModel>()?
+        .model()
+        .downcast::<gio::ListStore>()
+        .ok()
+}
+
+/// Entries of `store` and everything below them, in document order.
+fn flatten(store: &gio::ListStore, entries: &mut Vec<glib::BoxedAnyObject>) {
+    for entry in store.iter::<glib::BoxedAnyObject>().flatten() {
+        let children = entry.borrow::<OutlineEntry>().children.clone();
+        entries.push(entry);
+        flatten(&children, entries);
+    }
+}
+
+fn build_entries(nodes: &[OutlineNode], sections: &[Section]) -> Vec<glib::BoxedAnyObject> {
+    nodes
+        .iter()
+        .map(|node| {
+            let children = gio::ListStore::new::<glib::BoxedAnyObject>();
+            children.extend_from_slice(&build_entries(&node.children, sections));
+            glib::BoxedAnyObject::new(OutlineEntry {
+                section: sections[node.index].clone(),
+                index: node.index,
+                children,
+            })
+        })
+        .collect()
+}
+
+/// Shows `sections` in `outline`. When only their lines moved, as while typing
+/// in a paragraph, the entries are updated in place so collapsed sections stay
+/// collapsed; otherwise the tree is rebuilt.
+pub fn set_sections(outline: &ListView, sections: &[Section]) {
+    let Some(root) = root_store(outline) else {
+        return;
+    };
+    let mut entries = Vec::new();
+    flatten(&root, &mut entries);
+    let same_headings = entries.len() == sections.len()
+        && entries.iter().zip(sections).all(|(entry, section)| {
+            let entry = entry.borrow::<OutlineEntry>();
+            entry.section.title == section.title && entry.section.level == section.level
+        });
+    if same_headings {
+        for (entry, section) in entries.iter().zip(sections) {
+            entry.borrow_mut::<OutlineEntry>().section.line = section.line;
+        }
+        return;
+    }
+
+    root.splice(
+        0,
+        root.n_items(),
+        &build_entries(&outline_tree(sections), sections),
+    );
+}
+
+/// Heading index and 0-based line of the entry at `position` in `outline`.
+pub fn section_at(outline: &ListView, position: u32) -> Option<(usize, i32)> {
+    let entry = outline
+        .model()?
+        .item(position)
+        .and_downcast::<TreeListRow>()?
+        .item()
+        .and_downcast::<glib::BoxedAnyObject>()?;
+    let entry = entry.borrow::<OutlineEntry>();
+    Some((entry.index, entry.section.line))
 }
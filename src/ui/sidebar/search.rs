@@ -0,0 +1,224 @@
+use crate::project_search::{search_project, SearchHit};
+use crate::state::AppState;
+use crate::ui::editor::record_jump;
+use adw::prelude::*;
+use gtk4::{glib, Box, Label, ListBox, ListBoxRow, Orientation, ScrolledWindow, SearchEntry};
+use sourceview5::{Buffer, View};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+pub fn create_search_pane() -> (Box, SearchEntry, ListBox) {
+    let container = Box::new(Orientation::Vertical, 6);
+    container.set_margin_start(6);
+    container.set_margin_end(6);
+    container.set_margin_top(6);
+
+    let search_entry = SearchEntry::builder()
+        .placeholder_text("Search in project...")
+        .build();
+
+    let list_box = ListBox::new();
+    let placeholder = Label::new(Some("Search the files next to the main document"));
+    placeholder.add_css_class("dim-label");
+    placeholder.set_margin_top(12);
+    placeholder.set_wrap(true);
+    list_box.set_placeholder(Some(&placeholder));
+
+    let scrolled_window = ScrolledWindow::builder()
+        .child(&list_box)
+        .vexpand(true)
+        .build();
+
+    container.append(&search_entry);
+    container.append(&scrolled_window);
+
+    (container, search_entry, list_box)
+}
+
+/// The hits listed, and a counter bumped by every search so a slow walk of an
+/// old query cannot overwrite newer results.
+#[derive(Default)]
+struct SearchState {
+    hits: Vec<SearchHit>,
+    generation: u64,
+}
+
+fn status_row(text: &str) -> ListBoxRow {
+    let label = Label::new(Some(text));
+    label.add_css_class("dim-label");
+    label.set_margin_top(12);
+    label.set_margin_bottom(12);
+    label.set_wrap(true);
+    ListBoxRow::builder()
+        .child(&label)
+        .selectable(false)
+        .activatable(false)
+        .build()
+}
+
+fn hit_row(hit: &SearchHit, root: &Path) -> ListBoxRow {
+    let relative = hit.path.strip_prefix(root).unwrap_or(&hit.path);
+    let location = Label::new(Some(&format!("{}:{}", relative.display(), hit.line + 1)));
+    location.set_xalign(0.0);
+    location.set_ellipsize(gtk4::pango::EllipsizeMode::Start);
+    location.add_css_class("dim-label");
+    location.add_css_class("caption");
+    let text = Label::new(Some(&hit.text));
+    text.set_xalign(0.0);
+    text.set_ellipsize(gtk4::pango::EllipsizeMode::End);
+
+    let row_box = Box::new(Orientation::Vertical, 2);
+    row_box.set_margin_top(4);
+    row_box.set_margin_bottom(4);
+    row_box.append(&location);
+    row_box.append(&text);
+    let row = ListBoxRow::new();
+    row.set_tooltip_text(Some(&hit.text));
+    row.set_child(Some(&row_box));
+    row
+}
+
+/// Searches the project folder when Enter is pressed in `search_entry` and
+/// lists matching lines in `list`. Activating a hit opens its file, unless it
+/// is the open document, and moves the cursor to the match. Opening another
+/// file asks first if the editor has unsaved changes.
+///
+/// The folder is that of the main file (see [`AppState::project_root`]); which
+/// files are searched follows `AppConfig::project_search_include` and
+/// `project_search_exclude`. `on_opened` runs after another file is loaded.
+#[allow(clippy::too_many_arguments)]
+pub fn connect_project_search(
+    search_entry: &SearchEntry,
+    list: &ListBox,
+    buffer: &Buffer,
+    editor_view: &View,
+    state: Rc<RefCell<AppState>>,
+    view_title: &adw::WindowTitle,
+    encoding_label: &Label,
+    on_opened: Rc<dyn Fn()>,
+) {
+    let search = Rc::new(RefCell::new(SearchState::default()));
+
+    search_entry.connect_activate(glib::clone!(
+        #[weak]
+        list,
+        #[strong]
+        state,
+        #[strong]
+        search,
+        move |entry| {
+            let query = entry.text().to_string();
+            if query.is_empty() {
+                return;
+            }
+            list.remove_all();
+            let generation = {
+                let mut s = search.borrow_mut();
+                s.hits.clear();
+                s.generation += 1;
+                s.generation
+            };
+
+            let (root, include, exclude) = {
+                let s = state.borrow();
+                (
+                    s.project_root(),
+                    s.config.project_search_include.clone(),
+                    s.config.project_search_exclude.clone(),
+                )
+            };
+            let Some(root) = root else {
+                list.append(&status_row("Save the document to search its folder."));
+                return;
+            };
+            let searching_row = status_row("Searching...");
+            list.append(&searching_row);
+
+            let list = list.clone();
+            let search = search.clone();
+            glib::MainContext::default().spawn_local(async move {
+                let walk_root = root.clone();
+                let hits = tokio::task::spawn_blocking(move || {
+                    search_project(&walk_root, &query, &include, &exclude)
+                })
+                .await
+                .unwrap_or_default();
+                if search.borrow().generation != generation {
+                    return;
+                }
+                list.remove(&searching_row);
+                if hits.is_empty() {
+                    list.append(&status_row("No matches found."));
+                }
+                for hit in &hits {
+                    list.append(&hit_row(hit, &root));
+                }
+                search.borrow_mut().hits = hits;
+            });
+        }
+    ));
+
+    list.connect_row_activated(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        editor_view,
+        #[weak]
+        view_title,
+        #[weak]
+        encoding_label,
+        move |_, row| {
+            let Some(hit) = search.borrow().hits.get(row.index() as usize).cloned() else {
+                return;
+            };
+            let path = hit.path.clone();
+            let show_hit = move |buffer: &Buffer, editor_view: &View| {
+                let Some(mut iter) = buffer.iter_at_line_offset(hit.line, hit.column) else {
+                    return;
+                };
+                buffer.place_cursor(&iter);
+                editor_view.scroll_to_iter(&mut iter, 0.0, false, 0.5, 0.5);
+                editor_view.grab_focus();
+            };
+            if state.borrow().current_file.as_deref() == Some(path.as_path()) {
+                record_jump(&buffer, &state);
+                show_hit(&buffer, &editor_view);
+                return;
+            }
+            let Some(window) = editor_view.root().and_downcast::<adw::ApplicationWindow>() else {
+                return;
+            };
+            crate::ui::file_ops::confirm_discard_changes(
+                &window,
+                &buffer,
+                glib::clone!(
+                    #[weak]
+                    buffer,
+                    #[weak]
+                    editor_view,
+                    #[strong]
+                    state,
+                    #[weak]
+                    view_title,
+                    #[weak]
+                    encoding_label,
+                    #[strong]
+                    on_opened,
+                    move || {
+                        if crate::ui::file_ops::load_document(
+                            &path,
+                            &buffer,
+                            &state,
+                            &view_title,
+                            &encoding_label,
+                        ) {
+                            on_opened();
+                            show_hit(&buffer, &editor_view);
+                        }
+                    }
+                ),
+            );
+        }
+    ));
+}
@@ -0,0 +1,15 @@
+use gtk4::{ListBox, ScrolledWindow};
+
+pub fn create_todo_pane() -> (ScrolledWindow, ListBox) {
+    let list_box = ListBox::new();
+    let placeholder = gtk4::Label::new(Some("No TODO comments"));
+    placeholder.add_css_class("dim-label");
+    placeholder.set_margin_top(12);
+    list_box.set_placeholder(Some(&placeholder));
+    let scrolled_window = ScrolledWindow::builder()
+        .child(&list_box)
+        .vexpand(true)
+        .build();
+
+    (scrolled_window, list_box)
+}
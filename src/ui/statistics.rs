@@ -0,0 +1,78 @@
+use crate::state::AppState;
+use crate::utils::{buffer_to_string, document_stats};
+use adw::prelude::*;
+use adw::{ActionRow, AlertDialog, ApplicationWindow, PreferencesGroup};
+use gtk4::{gio, glib, Label};
+use sourceview5::Buffer;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn stat_row(group: &PreferencesGroup, title: &str, value: &str) {
+    let row = ActionRow::builder().title(title).build();
+    let label = Label::new(Some(value));
+    label.add_css_class("numeric");
+    label.set_selectable(true);
+    row.add_suffix(&label);
+    group.add(&row);
+}
+
+fn show_statistics(window: &ApplicationWindow, buffer: &Buffer, state: &Rc<RefCell<AppState>>) {
+    let stats = document_stats(&buffer_to_string(buffer.upcast_ref()));
+    let pages = state
+        .borrow()
+        .last_page_count
+        .map_or_else(|| "Not compiled".to_string(), |n| n.to_string());
+
+    let text_group = PreferencesGroup::new();
+    text_group.set_title("Text");
+    stat_row(&text_group, "Words", &stats.words.to_string());
+    stat_row(&text_group, "Characters", &stats.characters.to_string());
+    stat_row(&text_group, "Pages", &pages);
+
+    let structure_group = PreferencesGroup::new();
+    structure_group.set_title("Structure");
+    stat_row(&structure_group, "Sections", &stats.sections.to_string());
+    stat_row(&structure_group, "Figures", &stats.figures.to_string());
+    stat_row(&structure_group, "Tables", &stats.tables.to_string());
+    stat_row(&structure_group, "Equations", &stats.equations.to_string());
+    stat_row(
+        &structure_group,
+        "Citations",
+        &format!("{} ({} unique)", stats.citations, stats.unique_citations),
+    );
+
+    let content = gtk4::Box::new(gtk4::Orientation::Vertical, 12);
+    content.append(&text_group);
+    content.append(&structure_group);
+
+    let dialog = AlertDialog::builder()
+        .heading("Document Statistics")
+        .body("Words and characters count prose only, without commands, math or comments.")
+        .extra_child(&content)
+        .build();
+    dialog.add_response("close", "Close");
+    dialog.set_close_response("close");
+    dialog.present(Some(window));
+}
+
+/// Registers `win.document-statistics`, which shows word, structure and
+/// citation counts plus the page count of the last successful compile.
+pub fn connect_statistics_action(
+    window: &ApplicationWindow,
+    buffer: &Buffer,
+    state: Rc<RefCell<AppState>>,
+) {
+    let action = gio::SimpleAction::new("document-statistics", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        buffer,
+        #[strong]
+        state,
+        move |_, _| {
+            show_statistics(&window, &buffer, &state);
+        }
+    ));
+    window.add_action(&action);
+}
@@ -0,0 +1,221 @@
+use crate::symbols::{insertion, search, Symbol, SymbolGroup};
+use adw::prelude::*;
+use gtk4::{
+    gdk, gio, glib, Box, DropDown, Label, ListBox, Orientation, Popover, ScrolledWindow,
+    SearchEntry, StringList,
+};
+use sourceview5::{Buffer, View};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Inserts `symbol`'s command at the cursor, wrapping the selection if it takes
+/// an argument, as a single undo step.
+fn insert_symbol(buffer: &Buffer, symbol: &Symbol) {
+    let selection = buffer
+        .selection_bounds()
+        .map(|(start, end)| buffer.text(&start, &end, false).to_string())
+        .unwrap_or_default();
+    let next = buffer
+        .selection_bounds()
+        .map_or_else(|| buffer.iter_at_mark(&buffer.get_insert()), |(_, end)| end)
+        .char();
+    let (text, back) = insertion(symbol.command, &selection, Some(next));
+
+    buffer.begin_user_action();
+    buffer.delete_selection(true, true);
+    buffer.insert_at_cursor(&text);
+    if back > 0 {
+        let mut cursor = buffer.iter_at_mark(&buffer.get_insert());
+        cursor.backward_chars(back as i32);
+        buffer.place_cursor(&cursor);
+    }
+    buffer.end_user_action();
+}
+
+/// The group picked in the dropdown; index 0 is "All".
+fn selected_group(dropdown: &DropDown) -> Option<SymbolGroup> {
+    (dropdown.selected() as usize)
+        .checked_sub(1)
+        .and_then(|i| SymbolGroup::ALL.get(i).copied())
+}
+
+fn populate(
+    list: &ListBox,
+    shown: &RefCell<Vec<&'static Symbol>>,
+    query: &str,
+    group: Option<SymbolGroup>,
+) {
+    list.remove_all();
+    let symbols = search(query, group);
+    for symbol in &symbols {
+        let glyph = Label::builder().label(symbol.glyph).width_chars(2).build();
+        glyph.add_css_class("title-2");
+        let row = adw::ActionRow::builder()
+            .title(glib::markup_escape_text(symbol.command).as_str())
+            .subtitle(symbol.name)
+            .activatable(true)
+            .build();
+        row.add_prefix(&glyph);
+        list.append(&row);
+    }
+    // Label the first row of each group while the whole table is listed
+    list.unset_header_func();
+    if query.is_empty() && group.is_none() {
+        let groups: Vec<SymbolGroup> = symbols.iter().map(|s| s.group).collect();
+        list.set_header_func(move |row, before| {
+            let index = row.index() as usize;
+            let starts_group = before.is_none() || groups.get(index - 1) != groups.get(index);
+            row.set_header(
+                starts_group
+                    .then(|| {
+                        let header = Label::builder()
+                            .label(groups[index].label())
+                            .xalign(0.0)
+                            .margin_top(6)
+                            .margin_bottom(3)
+                            .margin_start(6)
+                            .build();
+                        header.add_css_class("heading");
+                        header
+                    })
+                    .as_ref(),
+            );
+        });
+    }
+    *shown.borrow_mut() = symbols;
+    if let Some(first) = list.row_at_index(0) {
+        list.select_row(Some(&first));
+    }
+}
+
+/// Creates the symbol picker and registers `win.insert-symbol`.
+///
+/// The picker lists Greek letters, operators, arrows and accents with their
+/// LaTeX commands, searchable by name or command, and inserts the chosen one
+/// at the cursor.
+pub fn connect_symbol_picker(window: &adw::ApplicationWindow, buffer: &Buffer, editor_view: &View) {
+    let shown: Rc<RefCell<Vec<&'static Symbol>>> = Rc::new(RefCell::new(Vec::new()));
+
+    let popover = Popover::builder().autohide(true).build();
+    popover.set_parent(editor_view);
+
+    let container = Box::new(Orientation::Vertical, 6);
+    container.set_width_request(360);
+    let filter_row = Box::new(Orientation::Horizontal, 6);
+    let search_entry = SearchEntry::builder()
+        .placeholder_text("Search symbols…")
+        .hexpand(true)
+        .build();
+    let group_labels: Vec<&str> = std::iter::once("All")
+        .chain(SymbolGroup::ALL.iter().map(|g| g.label()))
+        .collect();
+    let group_dropdown =
+        DropDown::new(Some(StringList::new(&group_labels)), gtk4::Expression::NONE);
+    filter_row.append(&search_entry);
+    filter_row.append(&group_dropdown);
+    let list = ListBox::new();
+    list.add_css_class("boxed-list");
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(gtk4::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(400)
+        .child(&list)
+        .build();
+    container.append(&filter_row);
+    container.append(&scroll);
+    popover.set_child(Some(&container));
+
+    let refresh = glib::clone!(
+        #[weak]
+        list,
+        #[weak]
+        search_entry,
+        #[weak]
+        group_dropdown,
+        #[strong]
+        shown,
+        move || {
+            populate(
+                &list,
+                &shown,
+                &search_entry.text(),
+                selected_group(&group_dropdown),
+            );
+        }
+    );
+
+    search_entry.connect_search_changed(glib::clone!(
+        #[strong]
+        refresh,
+        move |_| refresh()
+    ));
+    group_dropdown.connect_selected_notify(glib::clone!(
+        #[strong]
+        refresh,
+        move |_| refresh()
+    ));
+    search_entry.connect_activate(glib::clone!(
+        #[weak]
+        list,
+        move |_| {
+            if let Some(row) = list.selected_row() {
+                row.emit_activate();
+            }
+        }
+    ));
+    search_entry.connect_stop_search(glib::clone!(
+        #[weak]
+        popover,
+        move |_| {
+            popover.popdown();
+        }
+    ));
+
+    list.connect_row_activated(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        popover,
+        #[weak]
+        editor_view,
+        #[strong]
+        shown,
+        move |_, row| {
+            let Some(symbol) = shown.borrow().get(row.index() as usize).copied() else {
+                return;
+            };
+            popover.popdown();
+            insert_symbol(&buffer, symbol);
+            editor_view.grab_focus();
+        }
+    ));
+
+    let action = gio::SimpleAction::new("insert-symbol", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        editor_view,
+        #[weak]
+        popover,
+        #[weak]
+        search_entry,
+        move |_, _| {
+            search_entry.set_text("");
+            refresh();
+
+            // Point at the cursor
+            let cursor = buffer.iter_at_mark(&buffer.get_insert());
+            let rect = editor_view.iter_location(&cursor);
+            let (x, y) = editor_view.buffer_to_window_coords(
+                gtk4::TextWindowType::Widget,
+                rect.x(),
+                rect.y(),
+            );
+            popover.set_pointing_to(Some(&gdk::Rectangle::new(x, y, 1, rect.height())));
+            popover.popup();
+            search_entry.grab_focus();
+        }
+    ));
+    window.add_action(&action);
+}
@@ -0,0 +1,99 @@
+use crate::templates::{all_templates, Template};
+use adw::prelude::*;
+use adw::{ActionRow, AlertDialog, ApplicationWindow};
+use gtk4::{glib, ListBox, PolicyType, ScrolledWindow, SelectionMode};
+use std::rc::Rc;
+
+/// Asks which template a new document should start from and passes it to
+/// `on_chosen`. With `has_unsaved_changes` the dialog warns that the current
+/// text will be discarded.
+pub fn show_template_picker(
+    window: &ApplicationWindow,
+    has_unsaved_changes: bool,
+    on_chosen: impl Fn(&Template) + 'static,
+) {
+    let templates = Rc::new(all_templates());
+
+    let list = ListBox::builder()
+        .selection_mode(SelectionMode::Single)
+        .build();
+    list.add_css_class("boxed-list");
+    for template in templates.iter() {
+        let row = ActionRow::builder()
+            .title(glib::markup_escape_text(&template.name))
+            .subtitle(glib::markup_escape_text(&template.description))
+            .activatable(true)
+            .build();
+        list.append(&row);
+    }
+    list.select_row(list.row_at_index(0).as_ref());
+
+    let scroll = ScrolledWindow::builder()
+        .hscrollbar_policy(PolicyType::Never)
+        .min_content_height(320)
+        .child(&list)
+        .build();
+
+    let body = if has_unsaved_changes {
+        "Unsaved changes to the current document will be lost."
+    } else {
+        "Add your own .tex files to the templates folder in the settings directory."
+    };
+    let dialog = AlertDialog::builder()
+        .heading("New Document")
+        .body(body)
+        .extra_child(&scroll)
+        .build();
+    dialog.add_response("cancel", "Cancel");
+    dialog.add_response("create", "Create");
+    dialog.set_response_appearance(
+        "create",
+        if has_unsaved_changes {
+            adw::ResponseAppearance::Destructive
+        } else {
+            adw::ResponseAppearance::Suggested
+        },
+    );
+    dialog.set_default_response(Some("create"));
+    dialog.set_close_response("cancel");
+
+    let on_chosen = Rc::new(on_chosen);
+    let choose = glib::clone!(
+        #[strong]
+        templates,
+        #[strong]
+        on_chosen,
+        move |index: i32| {
+            if let Some(template) = usize::try_from(index).ok().and_then(|i| templates.get(i)) {
+                on_chosen(template);
+            }
+        }
+    );
+
+    // Activating a row (double-click or Enter) creates right away
+    list.connect_row_activated(glib::clone!(
+        #[weak]
+        dialog,
+        #[strong]
+        choose,
+        move |_, row| {
+            choose(row.index());
+            dialog.close();
+        }
+    ));
+
+    dialog.connect_response(
+        Some("create"),
+        glib::clone!(
+            #[weak]
+            list,
+            move |_, _| {
+                if let Some(row) = list.selected_row() {
+                    choose(row.index());
+                }
+            }
+        ),
+    );
+
+    dialog.present(Some(window));
+}
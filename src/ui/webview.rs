@@ -1,11 +1,15 @@
+use crate::config::{AppConfig, PreviewTheme};
+use crate::constants::CURSOR_PAGE_DELAY_MS;
+use crate::preview::{CompileProgress, Preview};
 use crate::state::AppState;
-use crate::utils::buffer_to_string;
+use crate::utils::{buffer_to_string, estimate_page};
 use adw::ToastOverlay;
 use glib;
 use gtk4::prelude::*;
-use gtk4::{ListBox, ScrolledWindow};
+use gtk4::{gdk, gio, Button, Label, ListView, Overlay, ScrolledWindow, Spinner};
 use sourceview5::Buffer;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::path::Path;
 use std::rc::Rc;
 use webkit6::prelude::*;
 use webkit6::WebView;
@@ -25,69 +29,332 @@ pub fn create_preview() -> (WebView, ScrolledWindow) {
     (web_view, preview_scroll)
 }
 
+/// Status bar widgets reporting the state of the latest LaTeX compilation,
+/// plus the buffer backing the compilation log pane.
+#[derive(Clone)]
+pub struct CompileStatus {
+    pub spinner: Spinner,
+    pub label: Label,
+    pub log_buffer: gtk4::TextBuffer,
+    /// Shown over the preview while it holds an error page; see [`overlay_copy_error_log`].
+    pub copy_error_btn: Button,
+}
+
+impl Default for CompileStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompileStatus {
+    pub fn new() -> Self {
+        let spinner = Spinner::new();
+        let label = Label::new(Some("Ready"));
+        let log_buffer = crate::ui::log_view::create_log_buffer();
+        let copy_error_btn = Button::builder()
+            .icon_name("edit-copy-symbolic")
+            .tooltip_text("Copy Full Error Log")
+            .halign(gtk4::Align::End)
+            .valign(gtk4::Align::Start)
+            .margin_top(12)
+            .margin_end(12)
+            .visible(false)
+            .build();
+        copy_error_btn.add_css_class("osd");
+        copy_error_btn.add_css_class("circular");
+        Self {
+            spinner,
+            label,
+            log_buffer,
+            copy_error_btn,
+        }
+    }
+
+    /// Shows the spinner while a job is waiting in or running through the queue.
+    pub fn set_compiling(&self) {
+        self.spinner.start();
+        self.label.set_text("Compiling…");
+    }
+
+    /// Shows the step a running compilation has reached. Ignored once the
+    /// compilation has been reported finished, as events can arrive late.
+    pub fn set_progress(&self, progress: &CompileProgress) {
+        if self.spinner.is_spinning() {
+            self.label.set_text(&progress.describe());
+        }
+    }
+
+    /// Reports a finished compilation and how long it took.
+    pub fn set_finished(
+        &self,
+        success: bool,
+        error_title: Option<&str>,
+        elapsed: std::time::Duration,
+    ) {
+        self.spinner.stop();
+        self.copy_error_btn.set_visible(!success);
+        if success {
+            self.label
+                .set_text(&format!("Compiled in {:.1}s", elapsed.as_secs_f64()));
+        } else {
+            self.label.set_text(error_title.unwrap_or("Error"));
+        }
+    }
+
+    /// Shows the log from the last compile in the log pane.
+    pub fn set_log(&self, log: &str) {
+        crate::ui::log_view::set_log(&self.log_buffer, log);
+    }
+
+    /// Resets the indicator when there is nothing to compile.
+    pub fn set_idle(&self) {
+        self.spinner.stop();
+        self.copy_error_btn.set_visible(false);
+        self.label.set_text("Ready");
+    }
+}
+
+/// Puts the preview under an overlay holding `compile_status.copy_error_btn`,
+/// which copies the log of a failed compile. The preview CSP blocks scripts, so
+/// the button can't live in the error page itself.
+pub fn overlay_copy_error_log(
+    preview_scroll: &ScrolledWindow,
+    compile_status: &CompileStatus,
+    toast_overlay: &ToastOverlay,
+) -> Overlay {
+    let overlay = Overlay::builder().child(preview_scroll).build();
+    overlay.add_overlay(&compile_status.copy_error_btn);
+
+    let log_buffer = compile_status.log_buffer.clone();
+    compile_status.copy_error_btn.connect_clicked(glib::clone!(
+        #[weak]
+        toast_overlay,
+        move |_| {
+            let (start, end) = log_buffer.bounds();
+            let log = log_buffer.text(&start, &end, false);
+            if let Some(display) = gdk::Display::default() {
+                display.clipboard().set_text(&log);
+                toast_overlay.add_toast(adw::Toast::new("Error log copied to clipboard"));
+            }
+        }
+    ));
+    overlay
+}
+
+/// Whether the preview is drawn dark: Settings → Preview Theme, which follows
+/// the desktop style rather than the editor's color scheme.
+fn preview_dark_mode(config: &AppConfig) -> bool {
+    config
+        .preview_theme
+        .is_dark(adw::StyleManager::default().is_dark())
+}
+
+/// Writes the buffer to `path`, the existing current file, in the document's
+/// encoding so a crash during the compile cannot lose what is being previewed.
+fn autosave(buffer: &Buffer, path: &Path, state: &Rc<RefCell<AppState>>) {
+    let (encoding, bom) = {
+        let s = state.borrow();
+        (s.current_encoding, s.current_bom)
+    };
+    match crate::utils::save_file(path, buffer.upcast_ref(), encoding, bom) {
+        Ok(used) => {
+            state.borrow_mut().current_encoding = used;
+            buffer.set_modified(false);
+        }
+        Err(e) => tracing::error!("Failed to save {} before compiling: {}", path.display(), e),
+    }
+}
+
+/// Keeps `compile_status` showing the current pass, helper tool or page
+/// conversion while the compilation queue works.
+pub fn connect_compile_progress(compile_status: &CompileStatus, state: &Rc<RefCell<AppState>>) {
+    let Some(mut progress) = state
+        .borrow()
+        .compilation_queue
+        .as_ref()
+        .map(|queue| queue.subscribe())
+    else {
+        return;
+    };
+    let compile_status = compile_status.clone();
+    glib::MainContext::default().spawn_local(async move {
+        loop {
+            match progress.recv().await {
+                Ok(event) => compile_status.set_progress(&event),
+                // Only the latest step matters, so skipped ones are fine
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
 /// Triggers a refresh of the LaTeX preview by enqueuing a compilation job.
+/// In math scratch mode the selected formula is rendered instead.
 pub fn trigger_refresh(
     buffer: &Buffer,
     web_view: &WebView,
-    sidebar_list: &ListBox,
+    sidebar_list: &ListView,
+    compile_status: &CompileStatus,
     state: Rc<RefCell<AppState>>,
 ) {
-    let state_borrow = state.borrow();
+    if state.borrow().math_scratch {
+        show_math_snippet(buffer, web_view, &state);
+        compile_status.set_idle();
+        return;
+    }
+
+    let text = buffer_to_string(buffer.upcast_ref());
+    state.borrow_mut().read_magic_comments(&text);
+    let mut state_borrow = state.borrow();
     let queue = match &state_borrow.compilation_queue {
         Some(q) => q.clone(),
         None => return,
     };
 
-    let text = buffer_to_string(buffer.upcast_ref());
     if text.trim().is_empty() {
         web_view.load_html("", None::<&str>);
+        compile_status.set_idle();
         return;
     }
 
+    compile_status.set_compiling();
+    if state_borrow.config.autosave_before_compile && buffer.is_modified() {
+        if let Some(path) = state_borrow.current_file.clone().filter(|p| p.exists()) {
+            drop(state_borrow);
+            autosave(buffer, &path, &state);
+            state_borrow = state.borrow();
+        }
+    }
+
     let web_view = web_view.clone();
     let sidebar_list = sidebar_list.clone();
+    let compile_status = compile_status.clone();
     let state = state.clone();
-    let text_for_enqueue = text.clone();
+    // A project's main file is compiled as saved; the outline stays with the open file
+    let text_for_enqueue = state_borrow.source_to_compile(text.clone());
     let text_for_sections = text.clone();
 
     glib::MainContext::default().spawn_local(async move {
-        let dark_mode = state.borrow().config.preview_dark_mode;
-        match queue.enqueue(text_for_enqueue, dark_mode).await {
-            Some(html) => {
-                web_view.load_html(&html, None::<&str>);
+        let dark_mode = preview_dark_mode(&state.borrow().config);
+        let options = state.borrow().compile_options();
+        let (job, outcome) = queue.enqueue_cancellable(text_for_enqueue, dark_mode, options);
+        state.borrow_mut().compile_job = Some(job);
+        let outcome = outcome.await;
+        {
+            let mut s = state.borrow_mut();
+            if s.compile_job == Some(job) {
+                s.compile_job = None;
+            }
+        }
+        match outcome {
+            Some(outcome) => {
+                compile_status.set_finished(outcome.success, outcome.error_title, outcome.elapsed);
+                compile_status.set_log(&outcome.log);
+                load_keeping_scroll(&web_view, outcome.html);
+                if outcome.success {
+                    let mut s = state.borrow_mut();
+                    s.last_page_count = outcome.page_count;
+                    s.last_pdf = outcome.pdf_bytes;
+                }
 
                 let sections = crate::utils::extract_sections(&text_for_sections);
-                sidebar_list.remove_all();
-                for (title_with_prefix, _line) in sections {
-                    let row = gtk4::ListBoxRow::new();
-                    let label = gtk4::Label::new(Some(&title_with_prefix));
-                    label.set_xalign(0.0);
-                    let prefix_spaces =
-                        title_with_prefix.len() - title_with_prefix.trim_start().len();
-                    let level = prefix_spaces / 2;
-                    label.set_margin_start((level * 12) as i32);
-                    row.set_child(Some(&label));
-                    sidebar_list.append(&row);
-                }
+                crate::ui::sidebar::outline::set_sections(&sidebar_list, &sections);
             }
             None => {
-                tracing::debug!("Compilation queue full, request dropped");
+                // The newer job that replaced this one will report the status.
+                tracing::debug!("Compilation request superseded or cancelled");
             }
         }
     });
 }
 
+/// Replaces the preview with `html` and scrolls back to where the reader was
+/// once it has loaded, so recompiles and theme switches keep the page in view.
+///
+/// The preview's CSP forbids page scripts, so the offset is read and restored
+/// through `evaluate_javascript`, which is not subject to it. A blank or error
+/// page reports 0 and restores nothing.
+fn load_keeping_scroll(web_view: &WebView, html: String) {
+    web_view.evaluate_javascript(
+        "window.scrollY",
+        None,
+        None,
+        None::<&gio::Cancellable>,
+        glib::clone!(
+            #[weak]
+            web_view,
+            move |result| {
+                let scroll_y = result
+                    .ok()
+                    .map(|value| value.to_double())
+                    .filter(|y| y.is_finite() && *y > 0.0);
+                if let Some(y) = scroll_y {
+                    let handler = Rc::new(RefCell::new(None));
+                    let id = web_view.connect_load_changed(glib::clone!(
+                        #[strong]
+                        handler,
+                        move |web_view, event| {
+                            if event != webkit6::LoadEvent::Finished {
+                                return;
+                            }
+                            web_view.evaluate_javascript(
+                                &format!("window.scrollTo(window.scrollX, {:.0});", y),
+                                None,
+                                None,
+                                None::<&gio::Cancellable>,
+                                |_| {},
+                            );
+                            if let Some(id) = handler.borrow_mut().take() {
+                                web_view.disconnect(id);
+                            }
+                        }
+                    ));
+                    *handler.borrow_mut() = Some(id);
+                }
+                web_view.load_html(&html, None::<&str>);
+            }
+        ),
+    );
+}
+
+/// Cancels a pending debounced preview refresh, if any.
+///
+/// Called when the document is swapped or cleared so a stale compile
+/// does not fire for content that is no longer in the buffer.
+pub fn cancel_pending_refresh(state: &Rc<RefCell<AppState>>) {
+    if let Some(source_id) = state.borrow_mut().preview_debounce.take() {
+        source_id.remove();
+    }
+}
+
+/// Cancels the pending refresh and abandons the compile in flight, killing
+/// its child process. Called when another document replaces the buffer, since
+/// the old document's preview would only be thrown away.
+pub fn cancel_compilation(state: &Rc<RefCell<AppState>>) {
+    cancel_pending_refresh(state);
+    let mut s = state.borrow_mut();
+    if let (Some(job), Some(queue)) = (s.compile_job.take(), s.compilation_queue.as_ref()) {
+        queue.cancel(job);
+    }
+}
+
 /// Connects the editor buffer change signal to the live preview compilation queue.
 /// Also updates the sidebar outline when the document structure changes.
+///
+/// Compilation is debounced: each change resets a timer of
+/// `AppConfig::preview_debounce_ms`, so the preview only recompiles once typing pauses.
 pub fn connect_live_preview(
     buffer: &Buffer,
     web_view: &WebView,
-    sidebar_list: &ListBox,
+    sidebar_list: &ListView,
+    compile_status: &CompileStatus,
     state: Rc<RefCell<AppState>>,
     _toast_overlay: &ToastOverlay,
 ) {
     let web_view = web_view.clone();
     let sidebar_list = sidebar_list.clone();
+    let compile_status = compile_status.clone();
     let state = state.clone();
 
     buffer.connect_changed(move |buf| {
@@ -96,6 +363,278 @@ pub fn connect_live_preview(
             return;
         }
 
-        trigger_refresh(buf, &web_view, &sidebar_list, state.clone());
+        cancel_pending_refresh(&state);
+
+        // A cleared buffer needs no compile; refresh immediately to blank the preview.
+        if buf.char_count() == 0 {
+            trigger_refresh(
+                buf,
+                &web_view,
+                &sidebar_list,
+                &compile_status,
+                state.clone(),
+            );
+            return;
+        }
+
+        let delay = state.borrow().config.preview_debounce_ms;
+        let source_id = glib::timeout_add_local_once(
+            std::time::Duration::from_millis(delay),
+            glib::clone!(
+                #[weak]
+                buf,
+                #[weak]
+                web_view,
+                #[weak]
+                sidebar_list,
+                #[strong]
+                compile_status,
+                #[strong]
+                state,
+                move || {
+                    // The source is finished once this runs; forget it so it isn't removed twice.
+                    state.borrow_mut().preview_debounce = None;
+                    trigger_refresh(
+                        &buf,
+                        &web_view,
+                        &sidebar_list,
+                        &compile_status,
+                        state.clone(),
+                    );
+                }
+            ),
+        );
+        state.borrow_mut().preview_debounce = Some(source_id);
+    });
+}
+
+/// Marks the preview page the cursor is probably on, scrolling to it when the
+/// cursor moves to another page. The page is estimated from the cursor's line
+/// with [`estimate_page`], once the cursor has rested for `CURSOR_PAGE_DELAY_MS`.
+pub fn connect_cursor_page(buffer: &Buffer, web_view: &WebView, state: Rc<RefCell<AppState>>) {
+    let pending: Rc<RefCell<Option<glib::SourceId>>> = Rc::new(RefCell::new(None));
+    let last_page = Rc::new(Cell::new(0));
+
+    let mark_page = glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        web_view,
+        move || {
+            let page_count = {
+                let s = state.borrow();
+                if s.math_scratch {
+                    return;
+                }
+                match s.last_page_count {
+                    Some(count) => count,
+                    None => return,
+                }
+            };
+            let line = buffer.iter_at_mark(&buffer.get_insert()).line() as usize;
+            let page = estimate_page(&buffer_to_string(buffer.upcast_ref()), line, page_count);
+            let scroll = last_page.replace(page) != page;
+            let script = format!(
+                "(() => {{
+                    const page = document.getElementById('page-{}');
+                    if (!page) return;
+                    document.querySelectorAll('.page.current').forEach(p => p.classList.remove('current'));
+                    page.classList.add('current');
+                    if ({}) page.scrollIntoView({{ block: 'nearest', behavior: 'smooth' }});
+                }})();",
+                page, scroll
+            );
+            web_view.evaluate_javascript(&script, None, None, None::<&gio::Cancellable>, |_| {});
+        }
+    );
+
+    buffer.connect_cursor_position_notify(move |_| {
+        if let Some(source_id) = pending.borrow_mut().take() {
+            source_id.remove();
+        }
+        let source_id = glib::timeout_add_local_once(
+            std::time::Duration::from_millis(CURSOR_PAGE_DELAY_MS),
+            glib::clone!(
+                #[strong]
+                pending,
+                #[strong]
+                mark_page,
+                move || {
+                    pending.borrow_mut().take();
+                    mark_page();
+                }
+            ),
+        );
+        *pending.borrow_mut() = Some(source_id);
     });
 }
+
+/// Selected text, or an empty string when nothing is selected.
+fn selected_text(buffer: &Buffer) -> String {
+    buffer
+        .selection_bounds()
+        .map(|(start, end)| buffer.text(&start, &end, false).to_string())
+        .unwrap_or_default()
+}
+
+/// Renders the selected formula into the preview with KaTeX.
+fn show_math_snippet(buffer: &Buffer, web_view: &WebView, state: &Rc<RefCell<AppState>>) {
+    let (preview, dark_mode) = {
+        let s = state.borrow();
+        (
+            Preview::from_config(&s.config),
+            preview_dark_mode(&s.config),
+        )
+    };
+    let html = preview.render_math_snippet(&selected_text(buffer), dark_mode);
+    web_view.load_html(&html, None::<&str>);
+}
+
+/// Registers `win.recompile`, which compiles the preview right away even though
+/// the buffer is unchanged, e.g. after an image or `.bib` file was edited.
+pub fn connect_recompile_action(
+    window: &adw::ApplicationWindow,
+    buffer: &Buffer,
+    web_view: &WebView,
+    sidebar_list: &ListView,
+    compile_status: &CompileStatus,
+    state: Rc<RefCell<AppState>>,
+) {
+    // A preview that follows the system style is redrawn when the style changes
+    adw::StyleManager::default().connect_dark_notify(glib::clone!(
+        #[weak]
+        window,
+        #[strong]
+        state,
+        move |_| {
+            if state.borrow().config.preview_theme == PreviewTheme::FollowSystem {
+                let _ = WidgetExt::activate_action(&window, "win.recompile", None);
+            }
+        }
+    ));
+
+    let action = gio::SimpleAction::new("recompile", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        web_view,
+        #[weak]
+        sidebar_list,
+        #[strong]
+        compile_status,
+        move |_, _| {
+            cancel_pending_refresh(&state);
+            trigger_refresh(
+                &buffer,
+                &web_view,
+                &sidebar_list,
+                &compile_status,
+                state.clone(),
+            );
+        }
+    ));
+    window.add_action(&action);
+}
+
+/// Registers `win.explain-compile`, which lists the tools a compile of the
+/// document would run (engine passes, biber/bibtex, makeindex, …) without
+/// running any of them.
+pub fn connect_explain_compile_action(
+    window: &adw::ApplicationWindow,
+    buffer: &Buffer,
+    state: Rc<RefCell<AppState>>,
+) {
+    let action = gio::SimpleAction::new("explain-compile", None);
+    action.connect_activate(glib::clone!(
+        #[weak]
+        window,
+        #[weak]
+        buffer,
+        move |_, _| {
+            let plan = {
+                let text = buffer_to_string(buffer.upcast_ref());
+                let mut s = state.borrow_mut();
+                s.read_magic_comments(&text);
+                let source = s.source_to_compile(text);
+                Preview::plan(&source, &s.compile_options())
+            };
+            let dialog = adw::AlertDialog::builder()
+                .heading("Compilation Plan")
+                .body(format!(
+                    "{}\n\nHelper tools are predicted from the source; the compile itself \
+                     skips those whose input LaTeX did not write.",
+                    plan.describe()
+                ))
+                .build();
+            dialog.add_response("close", "Close");
+            dialog.set_close_response("close");
+            dialog.present(Some(&window));
+        }
+    ));
+    window.add_action(&action);
+}
+
+/// Registers `win.math-scratch`, which toggles math scratch mode. While it is
+/// on, the preview shows the selected formula, rendered with the bundled KaTeX
+/// as the selection changes, instead of compiling the document with pdflatex.
+pub fn connect_math_scratch_action(
+    window: &adw::ApplicationWindow,
+    buffer: &Buffer,
+    web_view: &WebView,
+    sidebar_list: &ListView,
+    compile_status: &CompileStatus,
+    state: Rc<RefCell<AppState>>,
+) {
+    let action = gio::SimpleAction::new_stateful("math-scratch", None, &false.to_variant());
+    action.connect_activate(glib::clone!(
+        #[weak]
+        buffer,
+        #[weak]
+        web_view,
+        #[weak]
+        sidebar_list,
+        #[strong]
+        compile_status,
+        #[strong]
+        state,
+        move |action, _| {
+            let enabled = !action
+                .state()
+                .and_then(|s| s.get::<bool>())
+                .unwrap_or(false);
+            action.set_state(&enabled.to_variant());
+            state.borrow_mut().math_scratch = enabled;
+            cancel_pending_refresh(&state);
+            // Shows the snippet, or recompiles the document when leaving the mode
+            trigger_refresh(
+                &buffer,
+                &web_view,
+                &sidebar_list,
+                &compile_status,
+                state.clone(),
+            );
+        }
+    ));
+    window.add_action(&action);
+
+    // Follow the selection; typing also moves the cursor, so skip unchanged text
+    let last_snippet = RefCell::new(String::new());
+    buffer.connect_mark_set(glib::clone!(
+        #[weak]
+        web_view,
+        move |buf, _, mark| {
+            if !state.borrow().math_scratch
+                || !matches!(mark.name().as_deref(), Some("insert" | "selection_bound"))
+            {
+                return;
+            }
+            let snippet = selected_text(buf);
+            if *last_snippet.borrow() == snippet {
+                return;
+            }
+            last_snippet.replace(snippet);
+            show_math_snippet(buf, &web_view, &state);
+        }
+    ));
+}
@@ -1,4 +1,7 @@
+use crate::constants::{LONG_LINE_BYTES, MAX_LABEL_SLUG_LEN};
 use anyhow::{Context, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
 use gtk4::prelude::*;
 use regex::Regex;
 use std::fs::File;
@@ -16,7 +19,7 @@ pub fn check_dependencies() -> Vec<String> {
     let mut missing = Vec::new();
 
     // Check pdflatex
-    if std::process::Command::new("pdflatex")
+    if crate::preview::tool_command("pdflatex")
         .arg("--version")
         .output()
         .is_err()
@@ -24,17 +27,10 @@ pub fn check_dependencies() -> Vec<String> {
         missing.push("pdflatex (texlive-latex-base)".to_string());
     }
 
-    // Check pdftocairo
-    if std::process::Command::new("pdftocairo")
-        .arg("-v")
-        .output()
-        .is_err()
-    {
-        missing.push("pdftocairo (poppler-utils)".to_string());
-    }
+    // pdftocairo is optional: without it the preview embeds the PDF (see Preview::poppler_tools)
 
     // Check biber
-    if std::process::Command::new("biber")
+    if crate::preview::tool_command("biber")
         .arg("--version")
         .output()
         .is_err()
@@ -45,26 +41,98 @@ pub fn check_dependencies() -> Vec<String> {
     missing
 }
 
-pub fn open_file(filename: &Path) -> Result<String> {
+/// A text file decoded from its on-disk encoding.
+pub struct DecodedFile {
+    pub contents: String,
+    /// Detected encoding, used to write the file back unchanged.
+    pub encoding: &'static Encoding,
+    /// Whether invalid byte sequences were replaced with U+FFFD.
+    pub had_errors: bool,
+    /// Whether the file starts with a byte-order mark, written back on save.
+    pub has_bom: bool,
+}
+
+/// Detects the encoding of `bytes`: a byte-order mark wins, then valid UTF-8,
+/// then a statistical guess (covers Latin-1/Windows-1252 and similar).
+fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+pub fn open_file(filename: &Path) -> Result<DecodedFile> {
     let file =
         File::open(filename).with_context(|| format!("Failed to open file: {:?}", filename))?;
     let mut reader = BufReader::new(file);
-    let mut contents = String::new();
+    let mut bytes = Vec::new();
     reader
-        .read_to_string(&mut contents)
+        .read_to_end(&mut bytes)
         .with_context(|| "Failed to read file contents")?;
-    Ok(contents)
+
+    let (contents, encoding, had_errors) = detect_encoding(&bytes).decode(&bytes);
+    Ok(DecodedFile {
+        contents: contents.into_owned(),
+        encoding,
+        had_errors,
+        has_bom: Encoding::for_bom(&bytes).is_some(),
+    })
+}
+
+/// Encodes `contents` as `encoding`, starting with a UTF-8 byte-order mark if
+/// `bom` is set. encoding_rs cannot encode UTF-16, so that is done by hand
+/// (always with a BOM so the file is detected again on open).
+///
+/// Falls back to UTF-8 when the text has characters `encoding` cannot represent;
+/// returns the encoding actually used.
+fn encode_contents(
+    contents: &str,
+    encoding: &'static Encoding,
+    bom: bool,
+) -> (Vec<u8>, &'static Encoding) {
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let mut bytes = Vec::with_capacity(2 + contents.len() * 2);
+        for unit in std::iter::once(0xFEFF).chain(contents.encode_utf16()) {
+            if encoding == UTF_16LE {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&unit.to_be_bytes());
+            }
+        }
+        return (bytes, encoding);
+    }
+    match encoding.encode(contents) {
+        (bytes, used, false) if bom && used == UTF_8 => {
+            ([b"\xEF\xBB\xBF", &bytes[..]].concat(), UTF_8)
+        }
+        (bytes, used, false) => (bytes.into_owned(), used),
+        (_, _, true) => (contents.as_bytes().to_vec(), UTF_8),
+    }
 }
 
-pub fn save_file(filename: &Path, text_buffer: &gtk4::TextBuffer) -> Result<()> {
+/// Saves the buffer in `encoding`, after a byte-order mark if `bom` is set,
+/// returning the encoding actually written (UTF-8 if the text no longer fits
+/// the original encoding).
+pub fn save_file(
+    filename: &Path,
+    text_buffer: &gtk4::TextBuffer,
+    encoding: &'static Encoding,
+    bom: bool,
+) -> Result<&'static Encoding> {
     let contents = buffer_to_string(text_buffer);
+    let (bytes, used_encoding) = encode_contents(&contents, encoding, bom);
     let temp_filename = filename.with_extension("tmp");
 
     // Write contents to a temporary file first for atomic-like saving
     let mut file = File::create(&temp_filename)
         .with_context(|| format!("Failed to create temporary file: {:?}", temp_filename))?;
 
-    file.write_all(contents.as_bytes())
+    file.write_all(&bytes)
         .with_context(|| "Failed to write content to temporary file")?;
 
     file.sync_all()
@@ -74,7 +142,86 @@ pub fn save_file(filename: &Path, text_buffer: &gtk4::TextBuffer) -> Result<()>
     std::fs::rename(&temp_filename, filename)
         .with_context(|| format!("Failed to rename temporary file to {:?}", filename))?;
 
-    Ok(())
+    Ok(used_encoding)
+}
+
+/// Builds an `\includegraphics` command for `image`.
+///
+/// The path is made relative to the document's directory when the image lives
+/// below it, so the source stays portable; otherwise the absolute path is used.
+pub fn includegraphics_for(image: &Path, document: Option<&Path>) -> String {
+    format!("\\includegraphics{{{}}}", graphics_path(image, document))
+}
+
+/// The path `\includegraphics` should use for `image`; see [`includegraphics_for`].
+fn graphics_path(image: &Path, document: Option<&Path>) -> String {
+    let relative = document
+        .and_then(|doc| doc.parent())
+        .and_then(|dir| image.strip_prefix(dir).ok())
+        .unwrap_or(image);
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+/// Derives a `\label` such as `fig:growth-over-time` from a caption.
+pub fn label_for_caption(prefix: &str, caption: &str) -> String {
+    let mut slug = String::new();
+    for c in caption.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= MAX_LABEL_SLUG_LEN {
+            break;
+        }
+    }
+    format!("{}:{}", prefix, slug.trim_end_matches('-'))
+}
+
+/// A centred `figure` environment around `image`, with a caption and label when
+/// `caption` is not empty.
+pub fn figure_snippet(image: &Path, document: Option<&Path>, caption: &str) -> String {
+    let mut snippet = format!(
+        "\\begin{{figure}}[htbp]\n    \\centering\n    \\includegraphics[width=0.8\\linewidth]{{{}}}\n",
+        graphics_path(image, document)
+    );
+    if !caption.trim().is_empty() {
+        snippet.push_str(&format!(
+            "    \\caption{{{}}}\n    \\label{{{}}}\n",
+            caption.trim(),
+            label_for_caption("fig", caption)
+        ));
+    }
+    snippet.push_str("\\end{figure}\n");
+    snippet
+}
+
+/// A `table` environment holding an empty `rows` x `cols` `tabular`, with a
+/// caption and label when `caption` is not empty.
+pub fn table_snippet(rows: usize, cols: usize, caption: &str) -> String {
+    let rows = rows.max(1);
+    let cols = cols.max(1);
+    let mut snippet = String::from("\\begin{table}[htbp]\n    \\centering\n");
+    if !caption.trim().is_empty() {
+        snippet.push_str(&format!(
+            "    \\caption{{{}}}\n    \\label{{{}}}\n",
+            caption.trim(),
+            label_for_caption("tab", caption)
+        ));
+    }
+    snippet.push_str(&format!(
+        "    \\begin{{tabular}}{{{}}}\n        \\hline\n",
+        "c".repeat(cols)
+    ));
+    let row = format!("        {} \\\\\n", vec![""; cols].join(" & ").trim());
+    for i in 0..rows {
+        snippet.push_str(&row);
+        if i == 0 {
+            snippet.push_str("        \\hline\n");
+        }
+    }
+    snippet.push_str("        \\hline\n    \\end{tabular}\n\\end{table}\n");
+    snippet
 }
 
 fn section_regex() -> &'static Regex {
@@ -83,26 +230,529 @@ fn section_regex() -> &'static Regex {
         .get_or_init(|| Regex::new(r"\\(section|subsection|subsubsection)\*?\{([^}]+)\}").unwrap())
 }
 
-pub fn extract_sections(text: &str) -> Vec<(String, i32)> {
+/// A heading found by [`extract_sections`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub title: String,
+    /// Nesting depth: 0 for `\section`, 1 for `\subsection`, 2 for `\subsubsection`.
+    pub level: usize,
+    /// 0-based line of the heading.
+    pub line: i32,
+}
+
+/// Section headings in document order. Headings inside `%` comments are
+/// skipped, so indices match what TeX actually typesets.
+pub fn extract_sections(text: &str) -> Vec<Section> {
     let mut sections = Vec::new();
     let re = section_regex();
 
     for (i, line) in text.lines().enumerate() {
-        if let Some(caps) = re.captures(line) {
-            let level = &caps[1];
-            let title = &caps[2];
-            let prefix = match level {
-                "section" => "",
-                "subsection" => "  ",
-                "subsubsection" => "    ",
-                _ => "",
+        if let Some(caps) = re.captures(strip_line_comment(line)) {
+            let level = match &caps[1] {
+                "subsection" => 1,
+                "subsubsection" => 2,
+                _ => 0,
             };
-            sections.push((format!("{}{}", prefix, title), i as i32));
+            sections.push(Section {
+                title: caps[2].to_string(),
+                level,
+                line: i as i32,
+            });
         }
     }
     sections
 }
 
+/// A heading in the outline tree built by [`outline_tree`], with the headings
+/// nested below it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutlineNode {
+    /// Position of the heading in the slice given to [`outline_tree`].
+    pub index: usize,
+    pub children: Vec<OutlineNode>,
+}
+
+/// Nests `sections` (as returned by [`extract_sections`]) by level: each
+/// heading goes under the closest earlier heading of a lower level. Headings
+/// with no such parent, e.g. a `\subsection` before the first `\section`,
+/// stay at the top.
+pub fn outline_tree(sections: &[Section]) -> Vec<OutlineNode> {
+    fn children(sections: &[Section], next: &mut usize, parent: Option<usize>) -> Vec<OutlineNode> {
+        let mut nodes = Vec::new();
+        while let Some(section) = sections.get(*next) {
+            if parent.is_some_and(|level| section.level <= level) {
+                break;
+            }
+            let index = *next;
+            *next += 1;
+            nodes.push(OutlineNode {
+                index,
+                children: children(sections, next, Some(section.level)),
+            });
+        }
+        nodes
+    }
+    children(sections, &mut 0, None)
+}
+
+/// Whether any line of `text` is longer than [`LONG_LINE_BYTES`].
+pub fn has_long_line(text: &str) -> bool {
+    text.lines().any(|line| line.len() > LONG_LINE_BYTES)
+}
+
+/// Counts shown in the Document Statistics dialog.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentStats {
+    /// Prose words, excluding commands, math and comments.
+    pub words: usize,
+    /// Characters in those prose words.
+    pub characters: usize,
+    pub sections: usize,
+    pub figures: usize,
+    pub tables: usize,
+    /// Display equations: equation-like environments plus `\[ … \]`.
+    pub equations: usize,
+    /// Citation keys across all `\cite`-family commands, repeats included.
+    pub citations: usize,
+    pub unique_citations: usize,
+}
+
+const EQUATION_ENVIRONMENTS: &[&str] = &[
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "eqnarray*",
+    "displaymath",
+];
+
+fn cite_regex() -> &'static Regex {
+    static CITE_REGEX: OnceLock<Regex> = OnceLock::new();
+    CITE_REGEX.get_or_init(|| {
+        Regex::new(r"\\[a-zA-Z]*cite[a-zA-Z]*\*?(?:\[[^\]]*\])*\{([^}]*)\}").unwrap()
+    })
+}
+
+/// The part of `line` before an unescaped `%`; `\%` is a literal percent.
+fn strip_line_comment(line: &str) -> &str {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '%' if !escaped => return &line[..i],
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    line
+}
+
+/// Removes `%` comments, keeping escaped `\%`.
+pub fn strip_comments(text: &str) -> String {
+    text.lines()
+        .map(strip_line_comment)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Gathers word, structure and citation counts for `text`.
+pub fn document_stats(text: &str) -> DocumentStats {
+    let words = crate::spell::prose_words(text);
+    let code = strip_comments(text);
+
+    let mut stats = DocumentStats {
+        words: words.len(),
+        characters: words.iter().map(|r| text[r.clone()].chars().count()).sum(),
+        sections: extract_sections(&code).len(),
+        equations: code.matches("\\[").count(),
+        ..DocumentStats::default()
+    };
+
+    for caps in environment_regex().captures_iter(&code) {
+        if &caps[1] != "begin" {
+            continue;
+        }
+        match &caps[2] {
+            "figure" | "figure*" => stats.figures += 1,
+            "table" | "table*" => stats.tables += 1,
+            env if EQUATION_ENVIRONMENTS.contains(&env) => stats.equations += 1,
+            _ => {}
+        }
+    }
+
+    let mut keys: Vec<&str> = cite_regex()
+        .captures_iter(&code)
+        .flat_map(|caps| caps.get(1).map_or("", |m| m.as_str()).split(','))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .collect();
+    stats.citations = keys.len();
+    keys.sort_unstable();
+    keys.dedup();
+    stats.unique_citations = keys.len();
+
+    stats
+}
+
+/// Rough guess of the one-based page holding zero-based `line`, for when
+/// SyncTeX can't tell: the body between `\begin{document}` and
+/// `\end{document}` is assumed to spread evenly over `page_count` pages.
+pub fn estimate_page(text: &str, line: usize, page_count: usize) -> usize {
+    if page_count <= 1 {
+        return 1;
+    }
+    let lines: Vec<&str> = text.lines().map(strip_line_comment).collect();
+    let start = lines
+        .iter()
+        .position(|l| l.contains("\\begin{document}"))
+        .map_or(0, |i| i + 1);
+    let end = lines
+        .iter()
+        .rposition(|l| l.contains("\\end{document}"))
+        .unwrap_or(lines.len())
+        .max(start + 1);
+    let offset = line.clamp(start, end - 1) - start;
+    (offset * page_count / (end - start) + 1).min(page_count)
+}
+
+/// A `% TODO`-style note left in a comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TodoItem {
+    /// Zero-based line of the comment.
+    pub line: i32,
+    /// Character column of the `%` starting the comment.
+    pub column: i32,
+    pub keyword: String,
+    /// Note text after the keyword, without a leading `:`.
+    pub text: String,
+}
+
+/// Finds comments that start with one of `keywords`, e.g. `% TODO: cite this`
+/// or `%% FIXME wrong figure`. Keywords match case-sensitively as whole words.
+pub fn find_todos(text: &str, keywords: &[String]) -> Vec<TodoItem> {
+    let mut todos = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let mut escaped = false;
+        let Some(percent) = line.char_indices().find_map(|(pos, c)| match c {
+            '%' if !escaped => Some(pos),
+            '\\' => {
+                escaped = !escaped;
+                None
+            }
+            _ => {
+                escaped = false;
+                None
+            }
+        }) else {
+            continue;
+        };
+
+        let comment = line[percent..].trim_start_matches('%').trim_start();
+        let Some(keyword) = keywords.iter().find(|k| {
+            !k.is_empty()
+                && comment.starts_with(k.as_str())
+                && !comment[k.len()..].starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        }) else {
+            continue;
+        };
+        todos.push(TodoItem {
+            line: i as i32,
+            column: line[..percent].chars().count() as i32,
+            keyword: keyword.clone(),
+            text: comment[keyword.len()..]
+                .trim_start_matches(':')
+                .trim()
+                .to_string(),
+        });
+    }
+    todos
+}
+
+/// What a fold region spans in the editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldKind {
+    Environment,
+    Section,
+}
+
+/// A foldable block: `start_line` stays visible, the following lines through
+/// `end_line` are hidden when folded. Lines are zero-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoldRegion {
+    pub start_line: i32,
+    pub end_line: i32,
+    pub kind: FoldKind,
+}
+
+fn environment_regex() -> &'static Regex {
+    static ENVIRONMENT_REGEX: OnceLock<Regex> = OnceLock::new();
+    ENVIRONMENT_REGEX.get_or_init(|| Regex::new(r"\\(begin|end)\{([^}]+)\}").unwrap())
+}
+
+/// Computes fold regions for multi-line `\begin`/`\end` pairs and for section
+/// hierarchies. The `document` environment is skipped since folding it hides everything.
+pub fn fold_regions(text: &str) -> Vec<FoldRegion> {
+    let mut regions = Vec::new();
+    let mut open: Vec<(&str, i32)> = Vec::new();
+    let mut document_end = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let i = i as i32;
+        for caps in environment_regex().captures_iter(line) {
+            let name = caps.get(2).map_or("", |m| m.as_str());
+            if &caps[1] == "begin" {
+                open.push((name, i));
+            } else if let Some(pos) = open.iter().rposition(|(n, _)| *n == name) {
+                let (_, start_line) = open[pos];
+                open.truncate(pos);
+                if name == "document" {
+                    document_end = Some(i);
+                } else if i > start_line {
+                    regions.push(FoldRegion {
+                        start_line,
+                        end_line: i,
+                        kind: FoldKind::Environment,
+                    });
+                }
+            }
+        }
+    }
+
+    // A section runs until the next section of the same or higher level
+    let sections = extract_sections(text);
+    let last_line = document_end.unwrap_or(text.lines().count() as i32) - 1;
+    for (idx, section) in sections.iter().enumerate() {
+        let end_line = sections[idx + 1..]
+            .iter()
+            .find(|next| next.level <= section.level)
+            .map_or(last_line, |next| next.line - 1);
+        if end_line > section.line {
+            regions.push(FoldRegion {
+                start_line: section.line,
+                end_line,
+                kind: FoldKind::Section,
+            });
+        }
+    }
+
+    regions.sort_by_key(|r| r.start_line);
+    regions
+}
+
+/// Finds the `\begin`/`\end` partner of the environment delimiter under the cursor.
+///
+/// `line` and `column` are zero-based, with the column counted in characters. Returns
+/// the partner's `(line, start_column, end_column)`, skipping nested environments of
+/// the same name.
+pub fn matching_environment(text: &str, line: i32, column: i32) -> Option<(i32, i32, i32)> {
+    // (line, start column, end column, is_begin, name)
+    let mut delimiters = Vec::new();
+    for (i, text_line) in text.lines().enumerate() {
+        for caps in environment_regex().captures_iter(text_line) {
+            let m = caps.get(0)?;
+            let start = text_line[..m.start()].chars().count() as i32;
+            let end = start + m.as_str().chars().count() as i32;
+            delimiters.push((
+                i as i32,
+                start,
+                end,
+                &caps[1] == "begin",
+                caps[2].to_string(),
+            ));
+        }
+    }
+
+    let current = delimiters
+        .iter()
+        .position(|(l, start, end, _, _)| *l == line && (*start..=*end).contains(&column))?;
+    let (_, _, _, is_begin, ref name) = delimiters[current];
+
+    let mut depth = 0;
+    let candidates: Box<dyn Iterator<Item = &_>> = if is_begin {
+        Box::new(delimiters[current + 1..].iter())
+    } else {
+        Box::new(delimiters[..current].iter().rev())
+    };
+    for (l, start, end, begin, other) in candidates {
+        if other != name {
+            continue;
+        }
+        if *begin == is_begin {
+            depth += 1;
+        } else if depth == 0 {
+            return Some((*l, *start, *end));
+        } else {
+            depth -= 1;
+        }
+    }
+    None
+}
+
+/// A structural mistake found by [`lint_latex`]. `line` and `column` are
+/// zero-based, with the column counted in characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintIssue {
+    pub line: i32,
+    pub column: i32,
+    pub message: String,
+}
+
+/// Finds unbalanced `{`/`}` and mismatched `\begin`/`\end` pairs without
+/// running TeX. Escaped braces, comments, `\verb` and verbatim-like
+/// environments are skipped. Linear in the length of `text`, so it can run
+/// on every edit.
+pub fn lint_latex(text: &str) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut braces: Vec<(i32, i32)> = Vec::new();
+    let mut environments: Vec<(String, i32, i32)> = Vec::new();
+    // Name of the verbatim environment whose `\end` is awaited
+    let mut verbatim: Option<String> = None;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_index = line_index as i32;
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+
+        if let Some(name) = &verbatim {
+            let end = format!("\\end{{{}}}", name);
+            let Some(pos) = line.find(&end) else {
+                continue;
+            };
+            i = line[..pos].chars().count() + end.chars().count();
+            environments.pop();
+            verbatim = None;
+        }
+
+        while i < chars.len() {
+            let column = i as i32;
+            match chars[i] {
+                '%' => break,
+                '{' => braces.push((line_index, column)),
+                '}' => {
+                    if braces.pop().is_none() {
+                        issues.push(LintIssue {
+                            line: line_index,
+                            column,
+                            message: "Unmatched `}`".to_string(),
+                        });
+                    }
+                }
+                '\\' => {
+                    let word: String = chars[i + 1..]
+                        .iter()
+                        .take_while(|c| c.is_ascii_alphabetic())
+                        .collect();
+                    if word.is_empty() {
+                        // A control symbol such as `\{` or `\%`
+                        i += 2;
+                        continue;
+                    }
+                    i += 1 + word.len();
+                    match word.as_str() {
+                        "verb" => {
+                            if chars.get(i) == Some(&'*') {
+                                i += 1;
+                            }
+                            if let Some(&delimiter) = chars.get(i) {
+                                let close = chars[i + 1..].iter().position(|c| *c == delimiter);
+                                i = close.map_or(chars.len(), |pos| i + pos + 2);
+                            }
+                        }
+                        "begin" | "end" if chars.get(i) == Some(&'{') => {
+                            let Some(len) = chars[i + 1..].iter().position(|c| *c == '}') else {
+                                continue;
+                            };
+                            let name: String = chars[i + 1..i + 1 + len].iter().collect();
+                            i += len + 2;
+                            if word == "begin" {
+                                if crate::format::VERBATIM_ENVIRONMENTS.contains(&name.as_str()) {
+                                    verbatim = Some(name.clone());
+                                }
+                                environments.push((name, line_index, column));
+                                if verbatim.is_some() {
+                                    break;
+                                }
+                            } else if let Some(pos) =
+                                environments.iter().rposition(|(open, _, _)| *open == name)
+                            {
+                                // Anything opened since was never closed
+                                for (open, line, column) in environments.drain(pos + 1..) {
+                                    issues.push(LintIssue {
+                                        line,
+                                        column,
+                                        message: format!("`\\begin{{{}}}` is never closed", open),
+                                    });
+                                }
+                                environments.pop();
+                            } else {
+                                issues.push(LintIssue {
+                                    line: line_index,
+                                    column,
+                                    message: format!("`\\end{{{}}}` has no `\\begin`", name),
+                                });
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    for (line, column) in braces {
+        issues.push(LintIssue {
+            line,
+            column,
+            message: "Unclosed `{`".to_string(),
+        });
+    }
+    for (open, line, column) in environments {
+        issues.push(LintIssue {
+            line,
+            column,
+            message: format!("`\\begin{{{}}}` is never closed", open),
+        });
+    }
+    issues.sort_by_key(|issue| (issue.line, issue.column));
+    issues
+}
+
+/// Comments out each line of `text` by putting `% ` after its indentation, or,
+/// when every non-blank line already is a comment, removes the leading `%` and
+/// one space after it instead. Blank lines are left as they are.
+pub fn toggle_comment(text: &str) -> String {
+    let content: Vec<&str> = text
+        .split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    let uncomment = !content.is_empty()
+        && content
+            .iter()
+            .all(|line| line.trim_start().starts_with('%'));
+    text.split('\n')
+        .map(|line| {
+            if line.trim().is_empty() {
+                return line.to_string();
+            }
+            let (indent, rest) = line.split_at(line.len() - line.trim_start().len());
+            match rest.strip_prefix('%') {
+                Some(rest) if uncomment => {
+                    format!("{}{}", indent, rest.strip_prefix(' ').unwrap_or(rest))
+                }
+                _ => format!("{}% {}", indent, rest),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 pub fn extract_latex(response: &str) -> String {
     let raw = if let Some(start_idx) = response.find("```latex") {
         let after_start = &response[start_idx + 8..];
@@ -177,6 +827,41 @@ fn sanitize_latex(latex: &str) -> String {
     sanitized.trim().to_string()
 }
 
+/// Scores `candidate` against a fuzzy `query`.
+///
+/// Every query character must appear in order (case-insensitive); returns `None`
+/// otherwise. Consecutive matches and matches at word starts score higher, so
+/// "tsb" ranks "Toggle Sidebar" above candidates that merely contain the letters.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let mut query_chars = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut prev_matched = false;
+    let mut prev_char = ' ';
+
+    for c in candidate.chars() {
+        let Some(&q) = query_chars.peek() else { break };
+        let matched = c.to_lowercase().eq(std::iter::once(q));
+        if matched {
+            query_chars.next();
+            score += 1;
+            if prev_matched {
+                score += 2;
+            }
+            if !prev_char.is_alphanumeric() {
+                score += 3;
+            }
+        }
+        prev_matched = matched;
+        prev_char = c;
+    }
+
+    if query_chars.peek().is_some() {
+        None
+    } else {
+        Some(score)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -188,7 +873,8 @@ mod tests {
         fs::write(&path, "Hello LaTeX").unwrap();
 
         let content = open_file(&path).unwrap();
-        assert_eq!(content, "Hello LaTeX");
+        assert_eq!(content.contents, "Hello LaTeX");
+        assert_eq!(content.encoding, UTF_8);
 
         fs::remove_file(path).unwrap();
     }
@@ -274,11 +960,88 @@ More text
 End
 \section*{Starred}"#;
         let sections = extract_sections(text);
-        assert_eq!(sections.len(), 4);
-        assert_eq!(sections[0], ("Intro".to_string(), 0));
-        assert_eq!(sections[1], ("  Sub".to_string(), 2));
-        assert_eq!(sections[2], ("    SubSub".to_string(), 4));
-        assert_eq!(sections[3], ("Starred".to_string(), 6));
+        let outline: Vec<(&str, usize, i32)> = sections
+            .iter()
+            .map(|s| (s.title.as_str(), s.level, s.line))
+            .collect();
+        assert_eq!(
+            outline,
+            [
+                ("Intro", 0, 0),
+                ("Sub", 1, 2),
+                ("SubSub", 2, 4),
+                ("Starred", 0, 6)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outline_tree() {
+        let text =
+            "\\subsection{Preface}\n\\section{A}\n\\subsection{A.1}\n\\subsubsection{A.1.a}\n\
+                    \\subsection{A.2}\n\\section{B}\n\\subsubsection{B.0.a}";
+        fn shape(nodes: &[OutlineNode]) -> String {
+            nodes
+                .iter()
+                .map(|node| format!("{}({})", node.index, shape(&node.children)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+        let tree = outline_tree(&extract_sections(text));
+        assert_eq!(shape(&tree), "0() 1(2(3()) 4()) 5(6())");
+        assert!(outline_tree(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_single_megabyte_line_stays_fast() {
+        // A pasted minified document: 1 MB without a single newline
+        let mut text = String::from("\\documentclass{article}\\begin{document}");
+        while text.len() < 1_000_000 {
+            text.push_str("\\section{Part} word $x^2$ \\cite{key} 50\\% done % TODO: tidy ");
+        }
+        text.push_str("\\end{document}");
+        assert!(has_long_line(&text));
+        assert!(!has_long_line("short\nlines\n"));
+
+        let start = std::time::Instant::now();
+        let sections = extract_sections(&text);
+        let stats = document_stats(&text);
+        let words = text.split_whitespace().count();
+        let todos = find_todos(&text, &["TODO".to_string()]);
+        let page = estimate_page(&text, 0, 10);
+        let prose = crate::spell::prose_words(&text);
+        // Quadratic behaviour would take minutes on this input
+        assert!(start.elapsed() < std::time::Duration::from_secs(10));
+
+        // Everything after the first `%` on the line is a comment
+        assert_eq!(sections.len(), 1);
+        assert_eq!(stats.sections, 1);
+        assert!(words > 100_000);
+        assert_eq!(todos.len(), 1);
+        assert_eq!(page, 1);
+        assert!(!prose.is_empty());
+    }
+
+    #[test]
+    fn test_extract_sections_skips_comments() {
+        let text = r#"%\section{Draft}
+\section{Results} % \subsection{Old}
+  % \subsection{Indented}
+\subsection{Growth of 50\% per year}
+100\% done \section{Inline}
+\\% \section{After Linebreak}"#;
+        let outline: Vec<(String, i32)> = extract_sections(text)
+            .into_iter()
+            .map(|s| (s.title, s.line))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                ("Results".to_string(), 1),
+                (r"Growth of 50\% per year".to_string(), 3),
+                ("Inline".to_string(), 4),
+            ]
+        );
     }
 
     #[test]
@@ -305,4 +1068,268 @@ End
         assert!(result.contains("\\usepackage{amsmath}"));
         assert!(result.contains("\\usepackage{amssymb}"));
     }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("", "Save"), Some(0));
+        assert!(fuzzy_score("save", "Save File").is_some());
+        assert!(fuzzy_score("SF", "save file").is_some());
+        assert!(fuzzy_score("fs", "Save File").is_none());
+        assert!(fuzzy_score("xyz", "Export PDF").is_none());
+
+        // Word-start matches beat scattered ones
+        let sidebar = fuzzy_score("ts", "Toggle Sidebar").unwrap();
+        let settings = fuzzy_score("ts", "Settings").unwrap();
+        assert!(sidebar > settings);
+    }
+
+    #[test]
+    fn test_includegraphics_for() {
+        let doc = Path::new("/home/user/paper/main.tex");
+        assert_eq!(
+            includegraphics_for(Path::new("/home/user/paper/figs/plot.png"), Some(doc)),
+            "\\includegraphics{figs/plot.png}"
+        );
+        assert_eq!(
+            includegraphics_for(Path::new("/tmp/plot.png"), Some(doc)),
+            "\\includegraphics{/tmp/plot.png}"
+        );
+        assert_eq!(
+            includegraphics_for(Path::new("/tmp/plot.png"), None),
+            "\\includegraphics{/tmp/plot.png}"
+        );
+    }
+
+    #[test]
+    fn test_label_for_caption() {
+        assert_eq!(
+            label_for_caption("fig", "Growth over time (2020–2024)"),
+            "fig:growth-over-time-2020-2024"
+        );
+        assert_eq!(label_for_caption("tab", "  Results! "), "tab:results");
+    }
+
+    #[test]
+    fn test_figure_snippet() {
+        let doc = Path::new("/home/user/paper/main.tex");
+        let snippet = figure_snippet(Path::new("/home/user/paper/plot.png"), Some(doc), "A plot");
+        assert!(snippet.starts_with("\\begin{figure}[htbp]\n"));
+        assert!(snippet.contains("\\includegraphics[width=0.8\\linewidth]{plot.png}\n"));
+        assert!(snippet.contains("\\caption{A plot}\n    \\label{fig:a-plot}\n"));
+        assert!(snippet.ends_with("\\end{figure}\n"));
+
+        let bare = figure_snippet(Path::new("/tmp/plot.png"), None, "");
+        assert!(!bare.contains("\\caption"));
+        assert!(!bare.contains("\\label"));
+    }
+
+    #[test]
+    fn test_table_snippet() {
+        let snippet = table_snippet(2, 3, "Data");
+        assert!(snippet.contains("\\begin{tabular}{ccc}\n"));
+        assert_eq!(snippet.matches("&  & \\\\\n").count(), 2);
+        assert!(snippet.contains("\\label{tab:data}"));
+        assert!(snippet.ends_with("\\end{tabular}\n\\end{table}\n"));
+    }
+
+    #[test]
+    fn test_fold_regions() {
+        let text = "\\documentclass{article}\n\
+                    \\begin{document}\n\
+                    \\section{Intro}\n\
+                    \\begin{itemize}\n\
+                    \\item a \\begin{inline}x\\end{inline}\n\
+                    \\end{itemize}\n\
+                    \\subsection{Detail}\n\
+                    text\n\
+                    \\section{End}\n\
+                    more\n\
+                    \\end{document}\n";
+        let regions = fold_regions(text);
+        let env = |start_line, end_line| FoldRegion {
+            start_line,
+            end_line,
+            kind: FoldKind::Environment,
+        };
+        let section = |start_line, end_line| FoldRegion {
+            start_line,
+            end_line,
+            kind: FoldKind::Section,
+        };
+        assert_eq!(
+            regions,
+            vec![section(2, 7), env(3, 5), section(6, 7), section(8, 9)]
+        );
+    }
+
+    #[test]
+    fn test_matching_environment() {
+        let text = "\\begin{itemize}\n\
+                    \\item \\begin{itemize} \\item x \\end{itemize}\n\
+                    \\end{itemize}\n";
+        // Outer begin -> outer end, skipping the nested pair
+        assert_eq!(matching_environment(text, 0, 3), Some((2, 0, 13)));
+        assert_eq!(matching_environment(text, 2, 13), Some((0, 0, 15)));
+        // Inner end -> inner begin on the same line
+        assert_eq!(matching_environment(text, 1, 30), Some((1, 6, 21)));
+        // Not on a delimiter
+        assert_eq!(matching_environment(text, 1, 1), None);
+        // Unbalanced
+        assert_eq!(matching_environment("\\begin{a}\n", 0, 0), None);
+    }
+
+    #[test]
+    fn test_lint_latex() {
+        let clean = "\\begin{itemize}\n\
+                     \\item {\\bf x} \\{ 50\\% \\} % a } in a comment\n\
+                     \\item \\verb|{| and \\verb*+}+\n\
+                     \\end{itemize}\n\
+                     \\begin{verbatim}\n\
+                     { \\end{itemize}\n\
+                     \\end{verbatim}\n";
+        assert_eq!(lint_latex(clean), Vec::new());
+
+        let issues = lint_latex("\\textbf{x\n}}\n\\begin{a}\\begin{b}\n\\end{a}\n\\end{c}\n");
+        let found: Vec<(i32, i32, &str)> = issues
+            .iter()
+            .map(|i| (i.line, i.column, i.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, 1, "Unmatched `}`"),
+                (2, 9, "`\\begin{b}` is never closed"),
+                (4, 0, "`\\end{c}` has no `\\begin`"),
+            ]
+        );
+
+        let issues = lint_latex("\\begin{document}\n\\section{A\n");
+        assert_eq!(issues.len(), 2);
+        assert_eq!((issues[0].line, issues[0].column), (0, 0));
+        assert_eq!(issues[1].message, "Unclosed `{`");
+    }
+
+    #[test]
+    fn test_toggle_comment() {
+        let text = "\\begin{itemize}\n\n  \\item one\n";
+        let commented = toggle_comment(text);
+        assert_eq!(commented, "% \\begin{itemize}\n\n  % \\item one\n");
+        assert_eq!(toggle_comment(&commented), text);
+
+        // Mixed lines are all commented, keeping existing comments intact
+        assert_eq!(toggle_comment("%a\nb"), "% %a\n% b");
+        assert_eq!(toggle_comment("  %no space"), "  no space");
+        assert_eq!(toggle_comment(""), "");
+    }
+
+    #[test]
+    fn test_open_file_detects_encoding() {
+        let path = std::env::temp_dir().join("test_latex_rs_latin1.tex");
+        // "Café déjà vu" in ISO-8859-1 / Windows-1252
+        fs::write(&path, b"Caf\xe9 d\xe9j\xe0 vu, na\xefve r\xe9sum\xe9").unwrap();
+        let decoded = open_file(&path).unwrap();
+        assert_eq!(decoded.contents, "Café déjà vu, naïve résumé");
+        assert_eq!(decoded.encoding, encoding_rs::WINDOWS_1252);
+        assert!(!decoded.had_errors);
+
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend("Ω".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        fs::write(&path, &utf16).unwrap();
+        let decoded = open_file(&path).unwrap();
+        assert_eq!(decoded.contents, "Ω");
+        assert_eq!(decoded.encoding, UTF_16LE);
+        assert!(decoded.has_bom);
+
+        fs::write(&path, b"\xEF\xBB\xBF\\section{A}").unwrap();
+        let decoded = open_file(&path).unwrap();
+        assert_eq!(decoded.contents, "\\section{A}");
+        assert_eq!(decoded.encoding, UTF_8);
+        assert!(decoded.has_bom);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_contents_round_trip() {
+        let (bytes, used) = encode_contents("Café", encoding_rs::WINDOWS_1252, false);
+        assert_eq!(bytes, b"Caf\xe9");
+        assert_eq!(used, encoding_rs::WINDOWS_1252);
+
+        let (bytes, used) = encode_contents("Ω", UTF_16LE, false);
+        assert_eq!(bytes, vec![0xFF, 0xFE, 0xA9, 0x03]);
+        assert_eq!(used, UTF_16LE);
+
+        let (bytes, used) = encode_contents("Ω", UTF_8, true);
+        assert_eq!(bytes, b"\xEF\xBB\xBF\xCE\xA9");
+        assert_eq!(used, UTF_8);
+        assert_eq!(encode_contents("Ω", UTF_8, false).0, "Ω".as_bytes());
+
+        // Characters outside the encoding fall back to UTF-8
+        let (bytes, used) = encode_contents("Ω", encoding_rs::WINDOWS_1252, false);
+        assert_eq!(bytes, "Ω".as_bytes());
+        assert_eq!(used, UTF_8);
+    }
+
+    #[test]
+    fn test_document_stats() {
+        let text = r"\section{Intro} Hello world, see \cite{a, b} and \citep[p.~2]{a}.
+% \begin{figure} commented out \cite{c}
+\begin{figure}\end{figure}
+\begin{table*}\end{table*}
+\begin{equation} x \end{equation}
+\[ y \]
+Costs 100\% more.";
+        let stats = document_stats(text);
+        assert_eq!(stats.sections, 1);
+        assert_eq!(stats.figures, 1);
+        assert_eq!(stats.tables, 1);
+        assert_eq!(stats.equations, 2);
+        assert_eq!(stats.citations, 3);
+        assert_eq!(stats.unique_citations, 2);
+        // Intro Hello world see and Costs more
+        assert_eq!(stats.words, 7);
+        assert_eq!(stats.characters, 30);
+    }
+
+    #[test]
+    fn test_find_todos() {
+        let keywords: Vec<String> = ["TODO", "FIXME"].iter().map(|k| k.to_string()).collect();
+        let text = "Intro % TODO: cite Knuth\n%% FIXME wrong figure\n50\\% TODO not a comment\n% TODOS are plural\n% todo lowercase";
+        let todos = find_todos(text, &keywords);
+        assert_eq!(
+            todos,
+            vec![
+                TodoItem {
+                    line: 0,
+                    column: 6,
+                    keyword: "TODO".to_string(),
+                    text: "cite Knuth".to_string(),
+                },
+                TodoItem {
+                    line: 1,
+                    column: 0,
+                    keyword: "FIXME".to_string(),
+                    text: "wrong figure".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_estimate_page() {
+        let body: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let text = format!(
+            "\\documentclass{{article}}\n% \\begin{{document}}\n\\begin{{document}}\n{}\\end{{document}}\n",
+            body
+        );
+        // Preamble lines count as the first page, the last body line as the last
+        assert_eq!(estimate_page(&text, 0, 4), 1);
+        assert_eq!(estimate_page(&text, 3, 4), 1);
+        assert_eq!(estimate_page(&text, 3 + 50, 4), 3);
+        assert_eq!(estimate_page(&text, 3 + 99, 4), 4);
+        assert_eq!(estimate_page(&text, 500, 4), 4);
+        assert_eq!(estimate_page(&text, 60, 1), 1);
+        assert_eq!(estimate_page("a\nb\nc\nd", 2, 2), 2);
+        assert_eq!(estimate_page("", 0, 3), 1);
+    }
 }